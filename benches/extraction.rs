@@ -0,0 +1,37 @@
+use bitcoin::consensus::Decodable;
+use bitcoin::Transaction;
+use bitcoin_da::service::{extract_blobs, extract_blobs_with_proof, extraction_proof};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+// relevant txs are on 6, 8, 10, 12 indices
+fn get_mock_txs() -> Vec<Transaction> {
+    let txs = std::fs::read_to_string("test_data/mock_txs.txt").unwrap();
+
+    txs.lines()
+        .map(|tx| Transaction::consensus_decode(&mut &hex::decode(tx).unwrap()[..]).unwrap())
+        .collect()
+}
+
+fn extraction(c: &mut Criterion) {
+    let txs = get_mock_txs();
+
+    c.bench_function("extract_blobs", |b| {
+        b.iter(|| extract_blobs(black_box(&txs), black_box("sov-btc")))
+    });
+
+    // the blobs and the proof of the block, in two passes and in one
+    c.bench_function("extract_blobs_then_proof", |b| {
+        b.iter(|| {
+            (
+                extract_blobs(black_box(&txs), black_box("sov-btc")),
+                extraction_proof(black_box(&txs)),
+            )
+        })
+    });
+    c.bench_function("extract_blobs_with_proof", |b| {
+        b.iter(|| extract_blobs_with_proof(black_box(&txs), black_box("sov-btc")))
+    });
+}
+
+criterion_group!(benches, extraction);
+criterion_main!(benches);
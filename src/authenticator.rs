@@ -0,0 +1,23 @@
+use core::fmt::Debug;
+
+use crate::helpers::parsers::verify_blob_hash_signature;
+
+// Authenticates the sequencer of the blobs inscribed for the rollup.
+// Extraction drops the blobs whose signature is rejected.
+pub trait BlobAuthenticator: Debug + Send + Sync {
+    // Returns whether the signature over the message of an inscription is valid for the public key.
+    // The message is the blob hash of the inscribed, thus compressed, body bound to the signed
+    // envelope fields, see `signed_message`.
+    fn authenticate(&self, message: &[u8; 32], signature: &[u8], public_key: &[u8]) -> bool;
+}
+
+// Default authenticator: ECDSA or Schnorr signature over the message, as told by the version byte
+// of the signature
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EcdsaAuthenticator;
+
+impl BlobAuthenticator for EcdsaAuthenticator {
+    fn authenticate(&self, message: &[u8; 32], signature: &[u8], public_key: &[u8]) -> bool {
+        verify_blob_hash_signature(message, signature, public_key)
+    }
+}
@@ -0,0 +1,103 @@
+use std::collections::{HashMap, VecDeque};
+
+use bitcoin::BlockHash;
+
+use crate::spec::block::BitcoinBlock;
+
+// Least recently used blocks by hash, so that a block asked for again is not fetched and parsed
+// again. Only finalized blocks go there: they do not change, whatever the node says later.
+#[derive(Debug)]
+pub struct BlockCache {
+    capacity: usize,
+    blocks: HashMap<BlockHash, BitcoinBlock>,
+    // least recently used first
+    order: VecDeque<BlockHash>,
+}
+
+impl BlockCache {
+    // A capacity of 0 disables the cache
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            blocks: HashMap::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn get(&mut self, hash: &BlockHash) -> Option<BitcoinBlock> {
+        let block = self.blocks.get(hash)?.clone();
+        self.touch(hash);
+        Some(block)
+    }
+
+    // Adds the block, evicting the least recently used one when full
+    pub fn insert(&mut self, block: BitcoinBlock) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let hash = block.header.header.block_hash();
+        if self.blocks.insert(hash, block).is_some() {
+            self.touch(&hash);
+            return;
+        }
+
+        self.order.push_back(hash);
+        if self.order.len() > self.capacity {
+            if let Some(evicted) = self.order.pop_front() {
+                self.blocks.remove(&evicted);
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+        self.order.clear();
+    }
+
+    // Marks the block as the most recently used, the cache is small enough for a linear search
+    fn touch(&mut self, hash: &BlockHash) {
+        if let Some(position) = self.order.iter().position(|cached| cached == hash) {
+            self.order.remove(position);
+            self.order.push_back(*hash);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::Network;
+
+    use super::BlockCache;
+    use crate::spec::block::BitcoinBlock;
+
+    fn get_block(nonce: u32) -> BitcoinBlock {
+        let mut header = genesis_block(Network::Regtest).header;
+        header.nonce = nonce;
+        BitcoinBlock::from_txs(header, nonce as u64, vec![], "sov-btc")
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let blocks = (0..4).map(get_block).collect::<Vec<_>>();
+        let hash = |index: usize| blocks[index].header.header.block_hash();
+        let mut cache = BlockCache::new(2);
+
+        cache.insert(blocks[0].clone());
+        cache.insert(blocks[1].clone());
+        // block 0 is used again, block 1 is evicted first
+        assert_eq!(cache.get(&hash(0)), Some(blocks[0].clone()));
+        cache.insert(blocks[2].clone());
+        assert_eq!(cache.get(&hash(1)), None);
+        assert!(cache.get(&hash(0)).is_some());
+        assert!(cache.get(&hash(2)).is_some());
+
+        cache.clear();
+        assert_eq!(cache.get(&hash(0)), None);
+
+        let mut disabled = BlockCache::new(0);
+        disabled.insert(blocks[3].clone());
+        assert_eq!(disabled.get(&hash(3)), None);
+    }
+}
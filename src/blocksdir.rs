@@ -0,0 +1,331 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use bitcoin::block::Header;
+use bitcoin::consensus::encode;
+use bitcoin::{Block, BlockHash, Network};
+
+use crate::spec::block::BitcoinBlock;
+
+// Name of the file holding the key the block files are obfuscated with (Bitcoin Core 28+)
+const XOR_KEY_FILE: &str = "xor.dat";
+
+// Length of the record preceding every block in the block files: the network magic and the size
+const RECORD_HEADER_LEN: u64 = 8;
+
+// Reads raw `blkNNNNN.dat` files from the blocks directory of a node.
+// Much faster than RPC for historical sync when the service runs next to the node.
+#[derive(Debug, Clone)]
+pub struct BlocksDir {
+    path: PathBuf,
+    network: Network,
+}
+
+// Where a block is stored in the block files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BlockLocation {
+    // index of the file in `BlocksIndex::files`
+    file: usize,
+    // offset of the serialized block in the file, past its record header
+    offset: u64,
+    size: u32,
+}
+
+// Locations of the blocks stored in the block files by block hash. Blocks are only read when
+// looked up, so the index stays small whatever the size of the chain.
+#[derive(Debug)]
+pub struct BlocksIndex {
+    xor_key: Option<[u8; 8]>,
+    files: Vec<PathBuf>,
+    blocks: HashMap<BlockHash, BlockLocation>,
+}
+
+impl BlocksDir {
+    pub fn new(path: impl Into<PathBuf>, network: Network) -> Self {
+        Self {
+            path: path.into(),
+            network,
+        }
+    }
+
+    // Indexes every block stored in the block files by block hash, reading only their headers.
+    // Block files are not ordered by height and may contain stale blocks, callers look blocks up by hash.
+    pub fn index_blocks(&self) -> Result<BlocksIndex, anyhow::Error> {
+        let xor_key = read_xor_key(&self.path)?;
+
+        let mut files = fs::read_dir(&self.path)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| is_block_file(path))
+            .collect::<Vec<_>>();
+        files.sort();
+
+        let mut blocks = HashMap::new();
+        for (index, path) in files.iter().enumerate() {
+            for (block_hash, offset, size) in index_block_file(path, self.network, xor_key)? {
+                blocks.insert(
+                    block_hash,
+                    BlockLocation {
+                        file: index,
+                        offset,
+                        size,
+                    },
+                );
+            }
+        }
+
+        Ok(BlocksIndex {
+            xor_key,
+            files,
+            blocks,
+        })
+    }
+}
+
+impl BlocksIndex {
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    // Reads the block with the given hash from its block file, none if it is not stored
+    pub fn read_block(&self, block_hash: &BlockHash) -> Result<Option<Block>, anyhow::Error> {
+        let location = match self.blocks.get(block_hash) {
+            Some(location) => *location,
+            None => return Ok(None),
+        };
+
+        let mut file = File::open(&self.files[location.file])?;
+        let data = read_at(
+            &mut file,
+            location.offset,
+            location.size as usize,
+            self.xor_key,
+        )?;
+
+        Ok(Some(encode::deserialize(&data)?))
+    }
+}
+
+// Returns the hash, offset and size of every block of a block file, reading only the record and
+// block headers. Files are preallocated so the blocks end at the first record without magic.
+fn index_block_file(
+    path: &Path,
+    network: Network,
+    xor_key: Option<[u8; 8]>,
+) -> Result<Vec<(BlockHash, u64, u32)>, anyhow::Error> {
+    let magic = network.magic().to_bytes();
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut blocks = Vec::new();
+    let mut offset = 0;
+
+    while offset + RECORD_HEADER_LEN <= len {
+        let record = read_at(&mut file, offset, RECORD_HEADER_LEN as usize, xor_key)?;
+        if record[..4] != magic {
+            break;
+        }
+
+        let size = u32::from_le_bytes(record[4..].try_into().unwrap());
+        let start = offset + RECORD_HEADER_LEN;
+        let end = start + size as u64;
+        if end > len {
+            return Err(anyhow::anyhow!(
+                "Block at offset {} of {} is truncated",
+                offset,
+                path.display()
+            ));
+        }
+
+        let header: Header = encode::deserialize(&read_at(&mut file, start, 80, xor_key)?)?;
+        blocks.push((header.block_hash(), start, size));
+        offset = end;
+    }
+
+    Ok(blocks)
+}
+
+// Reads `len` bytes of the file from the offset, deobfuscated with the key if any
+fn read_at(
+    file: &mut File,
+    offset: u64,
+    len: usize,
+    xor_key: Option<[u8; 8]>,
+) -> Result<Vec<u8>, anyhow::Error> {
+    let mut data = vec![0; len];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut data)?;
+    if let Some(key) = xor_key {
+        apply_xor_key(&mut data, &key, offset);
+    }
+
+    Ok(data)
+}
+
+fn is_block_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map(|name| name.starts_with("blk") && name.ends_with(".dat"))
+        .unwrap_or(false)
+}
+
+// Returns the obfuscation key of the block files, if any
+fn read_xor_key(path: &Path) -> Result<Option<[u8; 8]>, anyhow::Error> {
+    let xor_key_path = path.join(XOR_KEY_FILE);
+    if !xor_key_path.exists() {
+        return Ok(None);
+    }
+
+    let key: [u8; 8] = fs::read(xor_key_path)?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("{} must hold an 8 byte key", XOR_KEY_FILE))?;
+
+    // an all zero key means the files are not obfuscated
+    Ok(key.iter().any(|byte| *byte != 0).then_some(key))
+}
+
+// Deobfuscates data read from the given offset of a block file, the key repeats from the start
+// of the file
+fn apply_xor_key(data: &mut [u8], key: &[u8; 8], offset: u64) {
+    data.iter_mut().enumerate().for_each(|(index, byte)| {
+        *byte ^= key[((offset + index as u64) % key.len() as u64) as usize]
+    });
+}
+
+// Parses the blocks of a block file. Every block is preceded by the network magic and its size,
+// files are preallocated so the data ends at the first record without magic.
+pub fn parse_block_file(data: &[u8], network: Network) -> Result<Vec<Block>, anyhow::Error> {
+    let magic = network.magic().to_bytes();
+    let mut blocks = Vec::new();
+    let mut offset = 0;
+
+    while offset + 8 <= data.len() {
+        if data[offset..offset + 4] != magic {
+            break;
+        }
+
+        let size = u32::from_le_bytes(data[offset + 4..offset + 8].try_into().unwrap()) as usize;
+        let start = offset + 8;
+        let end = start
+            .checked_add(size)
+            .filter(|end| *end <= data.len())
+            .ok_or_else(|| anyhow::anyhow!("Block at offset {} is truncated", offset))?;
+
+        blocks.push(encode::deserialize::<Block>(&data[start..end])?);
+        offset = end;
+    }
+
+    Ok(blocks)
+}
+
+// Converts a block read from the block files to the block type of the service,
+// recovering the sender of every inscription relevant to the rollup
+pub fn to_bitcoin_block(block: Block, height: u64, rollup_name: &str) -> BitcoinBlock {
+    BitcoinBlock::from_txs(block.header, height, block.txdata, rollup_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::block::{Header, Version};
+    use bitcoin::consensus::encode;
+    use bitcoin::hash_types::TxMerkleNode;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{Block, BlockHash, CompactTarget, Network};
+
+    use super::{parse_block_file, to_bitcoin_block, BlocksDir};
+    use crate::helpers::builders::EnvelopeFields;
+    use crate::helpers::parsers::parse_transaction;
+    use crate::helpers::test_utils::get_mock_reveal_tx;
+
+    fn get_sample_block(nonce: u32) -> Block {
+        Block {
+            header: Header {
+                version: Version::from_consensus(536870912),
+                prev_blockhash: BlockHash::all_zeros(),
+                merkle_root: TxMerkleNode::all_zeros(),
+                time: 1694177029,
+                bits: CompactTarget::from_consensus(0x207fffff),
+                nonce,
+            },
+            txdata: vec![get_mock_reveal_tx(
+                "sov-btc",
+                b"blob from a block file",
+                &EnvelopeFields::default(),
+            )],
+        }
+    }
+
+    // Serializes the blocks the way bitcoind stores them, followed by preallocated space
+    fn get_sample_block_file(blocks: &[Block]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for block in blocks {
+            let serialized = encode::serialize(block);
+            data.extend(Network::Regtest.magic().to_bytes());
+            data.extend((serialized.len() as u32).to_le_bytes());
+            data.extend(serialized);
+        }
+        data.extend([0u8; 64]);
+        data
+    }
+
+    #[test]
+    fn parse_sample_block_file() {
+        let blocks = vec![get_sample_block(0), get_sample_block(1)];
+        let data = get_sample_block_file(&blocks);
+
+        let parsed = parse_block_file(&data, Network::Regtest).unwrap();
+        assert_eq!(parsed, blocks);
+
+        let block = to_bitcoin_block(parsed[0].clone(), 7, "sov-btc");
+        assert_eq!(block.header.height, 7);
+        assert!(block.txdata[0].sender.is_some());
+
+        let inscription = parse_transaction(&block.txdata[0].transaction, "sov-btc").unwrap();
+        assert!(!inscription.body.is_empty());
+
+        // a truncated file is an error
+        assert!(parse_block_file(&data[..data.len() - 100], Network::Regtest).is_err());
+    }
+
+    #[test]
+    fn read_obfuscated_blocks_dir() {
+        let path =
+            std::env::temp_dir().join(format!("bitcoin-da-blocksdir-{}", std::process::id()));
+        std::fs::create_dir_all(&path).unwrap();
+
+        // the key repeats from the start of every file, not of every block
+        let blocks = (0..4).map(get_sample_block).collect::<Vec<_>>();
+        let key = [1, 2, 3, 4, 5, 6, 7, 8];
+        for (name, blocks) in [
+            ("blk00000.dat", &blocks[..1]),
+            ("blk00001.dat", &blocks[1..]),
+        ] {
+            let mut data = get_sample_block_file(blocks);
+            super::apply_xor_key(&mut data, &key, 0);
+            std::fs::write(path.join(name), data).unwrap();
+        }
+        std::fs::write(path.join("xor.dat"), key).unwrap();
+
+        let index = BlocksDir::new(&path, Network::Regtest).index_blocks();
+        let read_blocks = index.as_ref().map(|index| {
+            blocks
+                .iter()
+                .map(|block| index.read_block(&block.block_hash()).unwrap())
+                .collect::<Vec<_>>()
+        });
+        std::fs::remove_dir_all(&path).unwrap();
+
+        let index = index.unwrap();
+        assert_eq!(index.len(), 4);
+        assert_eq!(
+            read_blocks.unwrap(),
+            blocks.into_iter().map(Some).collect::<Vec<_>>()
+        );
+        assert_eq!(index.read_block(&BlockHash::all_zeros()).unwrap(), None);
+    }
+}
@@ -0,0 +1,98 @@
+use core::str::FromStr;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use bitcoin::bip32::{ChildNumber, ExtendedPubKey};
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::{Address, Network};
+
+// Index of the change branch under an account xpub (BIP44 and its descendants)
+const CHANGE_BRANCH: u32 = 1;
+
+// Derives change addresses (P2WPKH) from an account xpub, so that the wallet of the node
+// does not decide where the change goes. Addresses are `<xpub>/1/<index>`, the index
+// starting at `start_index` and increasing with every derived address.
+#[derive(Debug)]
+pub struct ChangeDeriver {
+    xpub: ExtendedPubKey,
+    network: Network,
+    next_index: AtomicU32,
+}
+
+impl ChangeDeriver {
+    pub fn new(xpub: &str, network: Network, start_index: u32) -> Result<Self, anyhow::Error> {
+        let xpub = ExtendedPubKey::from_str(xpub)?;
+
+        // xpubs only tell mainnet from the test networks
+        let expected_network = match network {
+            Network::Bitcoin => Network::Bitcoin,
+            _ => Network::Testnet,
+        };
+        if xpub.network != expected_network {
+            return Err(anyhow::anyhow!(
+                "Change xpub is for {}, expected {}",
+                xpub.network,
+                network
+            ));
+        }
+
+        Ok(Self {
+            xpub,
+            network,
+            next_index: AtomicU32::new(start_index),
+        })
+    }
+
+    // Returns the change address at the given index
+    pub fn derive(&self, index: u32) -> Result<Address, anyhow::Error> {
+        let path = [
+            ChildNumber::from_normal_idx(CHANGE_BRANCH)?,
+            ChildNumber::from_normal_idx(index)?,
+        ];
+        let child = self
+            .xpub
+            .derive_pub(&Secp256k1::verification_only(), &path)?;
+
+        Ok(Address::p2wpkh(&child.to_pub(), self.network)?)
+    }
+
+    // Returns the two next unused change addresses, same as `BitcoinNode::get_change_addresses`
+    pub fn next_addresses(&self) -> Result<[Address; 2], anyhow::Error> {
+        let index = self.next_index.fetch_add(2, Ordering::SeqCst);
+        Ok([self.derive(index)?, self.derive(index + 1)?])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::Network;
+
+    use super::ChangeDeriver;
+
+    // Account xpub of the BIP84 test vector, m/84'/0'/0'
+    const XPUB: &str = "xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V";
+
+    #[test]
+    fn derive_change_addresses() {
+        let deriver = ChangeDeriver::new(XPUB, Network::Bitcoin, 0).unwrap();
+
+        let [first, second] = deriver.next_addresses().unwrap();
+        assert_eq!(
+            first.to_string(),
+            "bc1q8c6fshw2dlwun7ekn9qwf37cu2rn755upcp6el"
+        );
+        assert_eq!(
+            second.to_string(),
+            "bc1qggnasd834t54yulsep6fta8lpjekv4zj6gv5rf"
+        );
+
+        // the next call does not reuse them
+        let [third, _] = deriver.next_addresses().unwrap();
+        assert_eq!(
+            third.to_string(),
+            "bc1qn8alfh45rlsj44pcdt0f2cadtztgnz4gq3h3uf"
+        );
+
+        // a mainnet xpub is rejected on the test networks
+        assert!(ChangeDeriver::new(XPUB, Network::Regtest, 0).is_err());
+    }
+}
@@ -0,0 +1,31 @@
+use core::fmt::Debug;
+use core::time::Duration;
+use std::time::Instant;
+
+use async_trait::async_trait;
+
+// Source of time of the wait loops of the service, polling the node until a block is available
+// or final. Tests inject a virtual clock to drive these loops without real delays.
+#[async_trait]
+pub trait Clock: Debug + Send + Sync {
+    // Waits for the given duration before the next poll
+    async fn sleep(&self, duration: Duration);
+
+    // Current time, moved forward by `sleep`, to measure the waits against
+    fn now(&self) -> Instant;
+}
+
+// Default clock: the timer of the tokio runtime, thus following `tokio::time::pause`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioClock;
+
+#[async_trait]
+impl Clock for TokioClock {
+    async fn sleep(&self, duration: Duration) {
+        tokio::time::sleep(duration).await
+    }
+
+    fn now(&self) -> Instant {
+        tokio::time::Instant::now().into_std()
+    }
+}
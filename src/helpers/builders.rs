@@ -1,30 +1,42 @@
 use core::result::Result::Ok;
 use core::str::FromStr;
 use std::collections::BTreeMap;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
 
 use anyhow::Context;
 use bitcoin::absolute::LockTime;
 use bitcoin::blockdata::opcodes::all::{OP_CHECKSIG, OP_ENDIF, OP_IF};
 use bitcoin::blockdata::opcodes::OP_FALSE;
 use bitcoin::blockdata::script;
-use bitcoin::hashes::{sha256d, Hash};
+use bitcoin::consensus::encode;
+use bitcoin::hashes::{sha256, Hash};
 use bitcoin::key::{TapTweak, TweakedPublicKey, UntweakedKeyPair};
 use bitcoin::psbt::Prevouts;
 use bitcoin::script::PushBytesBuf;
 use bitcoin::secp256k1::constants::SCHNORR_SIGNATURE_SIZE;
 use bitcoin::secp256k1::schnorr::Signature;
 use bitcoin::secp256k1::{self, Secp256k1, XOnlyPublicKey};
-use bitcoin::sighash::SighashCache;
-use bitcoin::taproot::{ControlBlock, LeafVersion, TapLeafHash, TaprootBuilder};
+use bitcoin::sighash::{Annex, SighashCache};
+use bitcoin::taproot::{
+    ControlBlock, LeafVersion, TapLeafHash, TaprootBuilder, TAPROOT_ANNEX_PREFIX,
+};
 use bitcoin::{
-    Address, Amount, Network, OutPoint, Script, Sequence, Transaction, TxIn, TxOut, Witness,
+    Address, Amount, Network, OutPoint, Script, ScriptBuf, Sequence, Transaction, TxIn, TxOut,
+    VarInt, Witness,
 };
 use brotli::{CompressorWriter, DecompressorWriter};
 use ord::{FeeRate, SatPoint, TransactionBuilder};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
-use crate::helpers::{BODY_TAG, PUBLICKEY_TAG, RANDOM_TAG, ROLLUP_NAME_TAG, SIGNATURE_TAG};
+use crate::helpers::{
+    blob_hash, metadata_len, signed_message, ChunkInfo, Compression, SignatureScheme, BODY_TAG,
+    CHUNK_TAG, COMPRESSION_TAG, LABEL_TAG, MAX_LABEL_LEN, MAX_METADATA_LEN, METADATA_TAG,
+    NAMESPACE_ID_LEN, NAMESPACE_TAG, PUBLICKEY_TAG, RANDOM_TAG, ROLLUP_NAME_TAG,
+    SCHNORR_SIGNATURE_VERSION, SIGNATURE_TAG,
+};
 use crate::spec::utxo::UTXO;
 
 pub fn get_satpoint_to_inscribe(utxo: &UTXO) -> SatPoint {
@@ -32,33 +44,167 @@ pub fn get_satpoint_to_inscribe(utxo: &UTXO) -> SatPoint {
     SatPoint::from_str(&satpoint_str).unwrap()
 }
 
+// Returns the satpoint of the sat the commit output at `vout` starts with, which the inscription is
+// on, given the values of the inputs of the commit tx in order
+pub fn get_commit_satpoint(
+    commit_tx: &Transaction,
+    vout: u32,
+    input_values: &[u64],
+) -> Option<SatPoint> {
+    let mut position = commit_tx
+        .output
+        .iter()
+        .take(vout as usize)
+        .map(|output| output.value)
+        .sum::<u64>();
+
+    for (input, value) in commit_tx.input.iter().zip(input_values) {
+        if position < *value {
+            return Some(SatPoint {
+                outpoint: input.previous_output,
+                offset: position,
+            });
+        }
+        position -= value;
+    }
+
+    None
+}
+
 pub fn compress_blob(blob: &[u8]) -> Vec<u8> {
     let mut writer = CompressorWriter::new(Vec::new(), 4096, 11, 22);
     writer.write_all(blob).unwrap();
     writer.into_inner()
 }
 
-pub fn decompress_blob(blob: &[u8]) -> Vec<u8> {
+// Bodies are read off-chain, anyone can inscribe a corrupt or truncated brotli stream
+pub fn decompress_blob(blob: &[u8]) -> Result<Vec<u8>, DecompressError> {
     let mut writer = DecompressorWriter::new(Vec::new(), 4096);
-    writer.write_all(blob).unwrap();
-    writer.into_inner().expect("decompression failed")
+    writer
+        .write_all(blob)
+        .map_err(|_| DecompressError::Corrupt)?;
+    writer.into_inner().map_err(|_| DecompressError::Truncated)
+}
+
+// Decompresses an inscribed body according to its compression header, brotli if there is none
+pub fn decompress_body(
+    body: &[u8],
+    compression: Option<Compression>,
+) -> Result<Vec<u8>, DecompressError> {
+    match compression.unwrap_or(Compression::Brotli) {
+        Compression::None => Ok(body.to_vec()),
+        Compression::Brotli => decompress_blob(body),
+    }
 }
 
-// Signs a message with a private key
+// Signs a blob with a private key, with ECDSA as the legacy inscriptions. The signature is only
+// valid for an envelope without the fields covered by `signed_message`.
 pub fn sign_blob_with_private_key(
     blob: &[u8],
     private_key: &str,
 ) -> Result<(Vec<u8>, Vec<u8>), ()> {
-    let message = sha256d::Hash::hash(blob).to_byte_array();
+    sign_message_with_scheme(&blob_hash(blob), private_key, SignatureScheme::Ecdsa)
+}
+
+// Same as `sign_blob_with_private_key`, under the scheme
+pub fn sign_blob_with_scheme(
+    blob: &[u8],
+    private_key: &str,
+    scheme: SignatureScheme,
+) -> Result<(Vec<u8>, Vec<u8>), ()> {
+    sign_message_with_scheme(&blob_hash(blob), private_key, scheme)
+}
+
+// Signs the message of an inscription, see `signed_message`, with a private key under the scheme.
+// Schnorr signatures are deterministic, so that a reveal can be rebuilt, and come with the version
+// byte and the x-only public key.
+pub fn sign_message_with_scheme(
+    message: &[u8; 32],
+    private_key: &str,
+    scheme: SignatureScheme,
+) -> Result<(Vec<u8>, Vec<u8>), ()> {
     let secp = Secp256k1::new();
-    let key = secp256k1::SecretKey::from_str(private_key).unwrap();
-    let public_key = secp256k1::PublicKey::from_secret_key(&secp, &key);
-    let msg = secp256k1::Message::from_slice(&message).unwrap();
-    let sig = secp.sign_ecdsa(&msg, &key);
-    Ok((
-        sig.serialize_compact().to_vec(),
-        public_key.serialize().to_vec(),
-    ))
+    let msg = secp256k1::Message::from_slice(message).unwrap();
+    match scheme {
+        SignatureScheme::Ecdsa => {
+            let key = secp256k1::SecretKey::from_str(private_key).map_err(|_| ())?;
+            let public_key = secp256k1::PublicKey::from_secret_key(&secp, &key);
+            let sig = secp.sign_ecdsa(&msg, &key);
+            Ok((
+                sig.serialize_compact().to_vec(),
+                public_key.serialize().to_vec(),
+            ))
+        }
+        SignatureScheme::Schnorr => {
+            let key_pair = UntweakedKeyPair::from_seckey_str(&secp, private_key).map_err(|_| ())?;
+            let sig = secp.sign_schnorr_no_aux_rand(&msg, &key_pair);
+            let (public_key, _parity) = key_pair.x_only_public_key();
+            Ok((
+                [&[SCHNORR_SIGNATURE_VERSION][..], &sig.as_ref()[..]].concat(),
+                public_key.serialize().to_vec(),
+            ))
+        }
+    }
+}
+
+// Maximum weight of a transaction relayed by default by Bitcoin Core
+pub const MAX_STANDARD_TX_WEIGHT: u64 = 400_000;
+
+// Size of the chunks of the compressed blobs too large for a single reveal transaction,
+// leaving room for the rest of the reveal within the standard weight
+pub const DEFAULT_CHUNK_SIZE: usize = 390_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum DecompressError {
+    #[error("the body is not a valid brotli stream")]
+    Corrupt,
+    #[error("the brotli stream of the body is truncated")]
+    Truncated,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum BuilderError {
+    #[error("reveal transaction weight {weight} exceeds the standard limit of {limit}, split the blob in chunks")]
+    RevealTooLarge { weight: u64, limit: u64 },
+    #[error("label is {len} bytes long, the limit is {limit}")]
+    LabelTooLong { len: usize, limit: usize },
+    #[error("metadata is {len} bytes long, the limit is {limit}")]
+    MetadataTooLarge { len: usize, limit: usize },
+    #[error("annex must start with 0x50")]
+    InvalidAnnex,
+    #[error("utxo {outpoint} is not available to the commit transaction")]
+    UnknownUtxo { outpoint: OutPoint },
+    #[error("utxo {outpoint} holds {value} sat, not enough for a commit output of {needed} sat")]
+    InsufficientValue {
+        outpoint: OutPoint,
+        value: u64,
+        needed: u64,
+    },
+    #[error("no additional utxo can pay the commit fee of {fee} sat")]
+    NoFeeInput { fee: u64 },
+    #[error("utxos hold {available} sat, {shortfall} sat short of the {needed} sat needed")]
+    InsufficientFunds {
+        available: u64,
+        needed: u64,
+        shortfall: u64,
+    },
+    #[error("the commit fee can only be paid by an additional input when inscribing the first sat of a utxo, got offset {offset}")]
+    UnalignedSatpoint { offset: u64 },
+    #[error("change of {value} sat is below the dust limit, donating it to the fee is disabled")]
+    DustChange { value: u64 },
+}
+
+// Checks that the reveal transaction, with its witness filled, can be relayed and returns its weight
+pub fn check_reveal_weight(reveal_tx: &Transaction) -> Result<u64, BuilderError> {
+    let weight = reveal_tx.weight().to_wu();
+    if weight > MAX_STANDARD_TX_WEIGHT {
+        return Err(BuilderError::RevealTooLarge {
+            weight,
+            limit: MAX_STANDARD_TX_WEIGHT,
+        });
+    }
+
+    Ok(weight)
 }
 
 // Builds the inscription reveal transaction
@@ -68,7 +214,8 @@ fn build_reveal_transaction(
     input: OutPoint,
     output: TxOut,
     script: &Script,
-) -> (Transaction, Amount) {
+    annex: Option<&[u8]>,
+) -> Result<(Transaction, Amount), BuilderError> {
     let reveal_tx = Transaction {
         input: vec![TxIn {
             previous_output: input,
@@ -91,11 +238,536 @@ fn build_reveal_transaction(
         );
         reveal_tx.input[0].witness.push(script);
         reveal_tx.input[0].witness.push(&control_block.serialize());
+        if let Some(annex) = annex {
+            reveal_tx.input[0].witness.push(annex);
+        }
+
+        check_reveal_weight(&reveal_tx)?;
 
         Amount::from_sat((fee_rate * reveal_tx.vsize() as f64).round() as u64)
     };
 
-    (reveal_tx, fee)
+    Ok((reveal_tx, fee))
+}
+
+// Value of the output created by the reveal transaction
+pub(crate) const REVEAL_OUTPUT_AMOUNT: u64 = 546;
+
+// Random number the estimates assume, the expected number of tries for a txid starting with 0000
+const ESTIMATED_REVEAL_RANDOM: i64 = 1 << 16;
+
+// Virtual sizes of the commit and reveal transactions of an inscription
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InscriptionVsizes {
+    pub commit: u64,
+    pub reveal: u64,
+}
+
+// Estimates the vsizes the fees of the commit and reveal transactions are computed from, out of the
+// length of the inscribed (compressed) body, without building the transactions. The commit tx is
+// assumed to spend a single utxo, two when an additional input pays its fee, with the same dummy
+// witness as the builder. Change outputs are assumed to pay to `change`.
+pub fn estimate_inscription_vsizes(
+    rollup_name: &str,
+    body_len: usize,
+    scheme: SignatureScheme,
+    fields: &EnvelopeFields,
+    fee_mode: CommitFeeMode,
+    destination: &Address,
+    change: &Address,
+) -> InscriptionVsizes {
+    let (signature_len, public_key_len) = match scheme {
+        SignatureScheme::Ecdsa => (64, 33),
+        SignatureScheme::Schnorr => (65, 32),
+    };
+
+    // any valid key does, only the lengths matter
+    let secp256k1 = Secp256k1::new();
+    let key_pair = UntweakedKeyPair::from_secret_key(
+        &secp256k1,
+        &secp256k1::SecretKey::from_slice(&[1; 32]).unwrap(),
+    );
+    let (public_key, _parity) = XOnlyPublicKey::from_keypair(&key_pair);
+
+    // the envelope without its body, the body pushes are added up instead
+    let envelope = build_packed_reveal_script(
+        &public_key,
+        rollup_name,
+        &[(Vec::new(), vec![0; signature_len])],
+        &vec![0; public_key_len],
+        fields,
+        ESTIMATED_REVEAL_RANDOM,
+    );
+    let script_len = envelope.len() + body_pushes_len(body_len);
+
+    let input = |witness: &[&[u8]]| TxIn {
+        previous_output: OutPoint::null(),
+        script_sig: ScriptBuf::new(),
+        witness: Witness::from_slice(witness),
+        sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+    };
+    let output = |script_pubkey: ScriptBuf| TxOut {
+        value: 0,
+        script_pubkey,
+    };
+
+    // signature, empty script standing for the reveal script, control block of a single leaf
+    let mut reveal_witness: Vec<&[u8]> = vec![&[0; SCHNORR_SIGNATURE_SIZE], &[], &[0; 33]];
+    if let Some(annex) = &fields.annex {
+        reveal_witness.push(annex);
+    }
+    let reveal_tx = Transaction {
+        version: 1,
+        lock_time: LockTime::ZERO,
+        input: vec![input(&reveal_witness)],
+        output: vec![output(destination.script_pubkey())],
+    };
+    let reveal_weight =
+        reveal_tx.weight().to_wu() + (VarInt(script_len as u64).len() - 1 + script_len) as u64;
+
+    let commit_output = output(ScriptBuf::new_v1_p2tr_tweaked(
+        TweakedPublicKey::dangerous_assume_tweaked(public_key),
+    ));
+    let (inputs, change_outputs) = match fee_mode {
+        CommitFeeMode::SubtractFromChange => (1, 1),
+        CommitFeeMode::RequireAdditionalInput => (2, 2),
+    };
+    let commit_tx = Transaction {
+        version: 2,
+        lock_time: LockTime::ZERO,
+        input: vec![input(&[&[0; SCHNORR_SIGNATURE_SIZE][..]]); inputs],
+        output: core::iter::once(commit_output)
+            .chain(core::iter::repeat(output(change.script_pubkey())).take(change_outputs))
+            .collect(),
+    };
+
+    InscriptionVsizes {
+        commit: commit_tx.vsize() as u64,
+        reveal: (reveal_weight + 3) / 4,
+    }
+}
+
+// Length of the pushes of a body, in chunks of 520 bytes as `push_envelope` does
+fn body_pushes_len(body_len: usize) -> usize {
+    let push_len = |len: usize| match len {
+        0..=75 => 1 + len,
+        76..=255 => 2 + len,
+        _ => 3 + len,
+    };
+
+    let last_len = body_len % 520;
+    (body_len / 520) * push_len(520) + if last_len > 0 { push_len(last_len) } else { 0 }
+}
+
+// Vsize of a commit transaction without its inputs: the commit output, two change outputs and the
+// overhead. Every input adds at most the vsize of a P2WPKH spend, which outweighs a P2TR key spend.
+const COMMIT_BASE_VSIZE: u64 = 116;
+const COMMIT_INPUT_VSIZE: u64 = 68;
+
+// Picks the utxos funding a commit output of `target_amount` sat at `fee_rate` sat/vB, largest
+// first so that the commit tx has few inputs. Utxos worth less than the fee of spending them are
+// left out, as are the ones the wallet cannot sign for, e.g. watch-only ones. The first selected
+// utxo is the largest one, the inscription goes there.
+pub fn select_utxos(
+    utxos: &[UTXO],
+    target_amount: u64,
+    fee_rate: f64,
+) -> Result<Vec<UTXO>, BuilderError> {
+    let input_fee = (fee_rate * COMMIT_INPUT_VSIZE as f64).ceil() as u64;
+    let mut candidates = utxos
+        .iter()
+        .filter(|utxo| utxo.spendable && utxo.solvable && utxo.amount > input_fee)
+        .collect::<Vec<_>>();
+    candidates.sort_by(|a, b| b.amount.cmp(&a.amount));
+
+    let mut needed = target_amount + (fee_rate * COMMIT_BASE_VSIZE as f64).ceil() as u64;
+    let mut available = 0;
+    let mut selected = Vec::new();
+    for utxo in candidates {
+        needed += input_fee;
+        available += utxo.amount;
+        selected.push(utxo.clone());
+        if available >= needed {
+            return Ok(selected);
+        }
+    }
+
+    Err(BuilderError::InsufficientFunds {
+        available,
+        needed,
+        shortfall: needed - available,
+    })
+}
+
+// Upper bound for the random number grinding the reveal txid, and so for the rebuild search.
+// A txid starts with two zero bytes once in 65536 tries, so the bound is hit about once in ten
+// million inscriptions; the send is then refused rather than left unrebuildable.
+const MAX_REVEAL_RANDOM: i64 = 1 << 20;
+
+// Derives the commit key from the sequencer key and the blob so that inscriptions can be rebuilt deterministically
+pub fn derive_commit_key_pair(
+    sequencer_da_private_key: &str,
+    body: &[u8],
+) -> Result<UntweakedKeyPair, anyhow::Error> {
+    let secp256k1 = Secp256k1::new();
+    let sequencer_key = secp256k1::SecretKey::from_str(sequencer_da_private_key)?;
+    let body_hash = blob_hash(body);
+
+    let seed: Vec<u8> = [
+        b"bitcoin-da/commit".as_slice(),
+        sequencer_key.secret_bytes().as_slice(),
+        body_hash.as_slice(),
+    ]
+    .concat();
+    let commit_key = secp256k1::SecretKey::from_slice(sha256::Hash::hash(&seed).as_byte_array())?;
+
+    Ok(UntweakedKeyPair::from_secret_key(&secp256k1, &commit_key))
+}
+
+// Optional fields written to the inscription envelope. Only the namespace id and the compression
+// header are covered by the sequencer signature, see `signed_message`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EnvelopeFields {
+    pub namespace_id: Option<[u8; NAMESPACE_ID_LEN]>,
+    // short human-readable label, e.g. "batch-1234", for explorers and debugging
+    pub label: Option<String>,
+    // annex appended to the reveal witness, it is not part of the envelope but is committed to by
+    // the reveal signature. Bitcoin Core does not relay a witness with an annex, the reveal tx
+    // needs a miner accepting non-standard txs.
+    pub annex: Option<Vec<u8>>,
+    // compression header of the body, omitted for brotli compressed bodies sent by `send_transaction`
+    pub compression: Option<Compression>,
+    // key-value entries for integrations, e.g. a chain id or an encoder version, in envelope order
+    pub metadata: Vec<(String, Vec<u8>)>,
+    // position of the body in its blob, for the blobs inscribed in chunks
+    pub chunk: Option<ChunkInfo>,
+}
+
+impl EnvelopeFields {
+    // Returns the message the sequencer signs for the body inscribed with these fields
+    pub fn signed_message(&self, body: &[u8]) -> [u8; 32] {
+        let hash = blob_hash(body);
+        signed_message(&hash, self.namespace_id, self.compression, self.chunk)
+    }
+
+    // Checks that the fields fit in the envelope
+    pub fn validate(&self) -> Result<(), BuilderError> {
+        if let Some(label) = &self.label {
+            if label.len() > MAX_LABEL_LEN {
+                return Err(BuilderError::LabelTooLong {
+                    len: label.len(),
+                    limit: MAX_LABEL_LEN,
+                });
+            }
+        }
+
+        if let Some(annex) = &self.annex {
+            if annex.first() != Some(&TAPROOT_ANNEX_PREFIX) {
+                return Err(BuilderError::InvalidAnnex);
+            }
+        }
+
+        let metadata_len = metadata_len(&self.metadata);
+        if metadata_len > MAX_METADATA_LEN {
+            return Err(BuilderError::MetadataTooLarge {
+                len: metadata_len,
+                limit: MAX_METADATA_LEN,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+// Builds the reveal script that carries the inscription
+pub(crate) fn build_reveal_script(
+    commit_public_key: &XOnlyPublicKey,
+    rollup_name: &str,
+    signature: &[u8],
+    sequencer_public_key: &[u8],
+    fields: &EnvelopeFields,
+    random: i64,
+    body: &[u8],
+) -> ScriptBuf {
+    build_packed_reveal_script(
+        commit_public_key,
+        rollup_name,
+        &[(body.to_vec(), signature.to_vec())],
+        sequencer_public_key,
+        fields,
+        random,
+    )
+}
+
+// Builds a reveal script carrying several inscriptions, one envelope per (body, signature) pair.
+// The optional fields and the random number are repeated in every envelope.
+pub(crate) fn build_packed_reveal_script(
+    commit_public_key: &XOnlyPublicKey,
+    rollup_name: &str,
+    inscriptions: &[(Vec<u8>, Vec<u8>)],
+    sequencer_public_key: &[u8],
+    fields: &EnvelopeFields,
+    random: i64,
+) -> ScriptBuf {
+    let mut reveal_script_builder = script::Builder::new()
+        .push_slice(commit_public_key.serialize())
+        .push_opcode(OP_CHECKSIG);
+
+    for (body, signature) in inscriptions {
+        reveal_script_builder = push_envelope(
+            reveal_script_builder,
+            rollup_name,
+            signature,
+            sequencer_public_key,
+            fields,
+            random,
+            body,
+        );
+    }
+
+    reveal_script_builder.into_script()
+}
+
+// Pushes the envelope of an inscription, from OP_FALSE OP_IF to OP_ENDIF
+fn push_envelope(
+    reveal_script_builder: script::Builder,
+    rollup_name: &str,
+    signature: &[u8],
+    sequencer_public_key: &[u8],
+    fields: &EnvelopeFields,
+    random: i64,
+    body: &[u8],
+) -> script::Builder {
+    // start creating inscription content
+    let mut reveal_script_builder = reveal_script_builder
+        .push_opcode(OP_FALSE)
+        .push_opcode(OP_IF)
+        .push_slice(PushBytesBuf::try_from(ROLLUP_NAME_TAG.to_vec()).unwrap())
+        .push_slice(PushBytesBuf::try_from(rollup_name.as_bytes().to_vec()).unwrap())
+        .push_slice(PushBytesBuf::try_from(SIGNATURE_TAG.to_vec()).unwrap())
+        .push_slice(PushBytesBuf::try_from(signature.to_vec()).unwrap())
+        .push_slice(PushBytesBuf::try_from(PUBLICKEY_TAG.to_vec()).unwrap())
+        .push_slice(PushBytesBuf::try_from(sequencer_public_key.to_vec()).unwrap());
+
+    // push optional fields
+    if let Some(namespace_id) = fields.namespace_id {
+        reveal_script_builder = reveal_script_builder
+            .push_slice(PushBytesBuf::try_from(NAMESPACE_TAG.to_vec()).unwrap())
+            .push_slice(namespace_id);
+    }
+    if let Some(label) = &fields.label {
+        reveal_script_builder = reveal_script_builder
+            .push_slice(PushBytesBuf::try_from(LABEL_TAG.to_vec()).unwrap())
+            .push_slice(PushBytesBuf::try_from(label.as_bytes().to_vec()).unwrap());
+    }
+    if let Some(compression) = fields.compression {
+        reveal_script_builder = reveal_script_builder
+            .push_slice(PushBytesBuf::try_from(COMPRESSION_TAG.to_vec()).unwrap())
+            .push_slice([compression.to_byte()]);
+    }
+    for (key, value) in &fields.metadata {
+        reveal_script_builder = reveal_script_builder
+            .push_slice(PushBytesBuf::try_from(METADATA_TAG.to_vec()).unwrap())
+            .push_slice(PushBytesBuf::try_from(key.as_bytes().to_vec()).unwrap())
+            .push_slice(PushBytesBuf::try_from(value.clone()).unwrap());
+    }
+    if let Some(chunk) = fields.chunk {
+        reveal_script_builder = reveal_script_builder
+            .push_slice(PushBytesBuf::try_from(CHUNK_TAG.to_vec()).unwrap())
+            .push_slice(chunk.to_bytes());
+    }
+
+    reveal_script_builder = reveal_script_builder
+        .push_slice(PushBytesBuf::try_from(RANDOM_TAG.to_vec()).unwrap())
+        .push_int(random)
+        .push_slice(PushBytesBuf::try_from(BODY_TAG.to_vec()).unwrap());
+
+    // push body in chunks of 520 bytes
+    for chunk in body.chunks(520) {
+        reveal_script_builder =
+            reveal_script_builder.push_slice(PushBytesBuf::try_from(chunk.to_vec()).unwrap());
+    }
+
+    // push end if to close the envelope
+    reveal_script_builder.push_opcode(OP_ENDIF)
+}
+
+// Builds the part of the reveal script that follows the commit key and is the same in every
+// inscription of the rollup: the opening of the envelope and the rollup name
+pub fn build_envelope_prefix(rollup_name: &str) -> ScriptBuf {
+    script::Builder::new()
+        .push_opcode(OP_CHECKSIG)
+        .push_opcode(OP_FALSE)
+        .push_opcode(OP_IF)
+        .push_slice(PushBytesBuf::try_from(ROLLUP_NAME_TAG.to_vec()).unwrap())
+        .push_slice(PushBytesBuf::try_from(rollup_name.as_bytes().to_vec()).unwrap())
+        .into_script()
+}
+
+// Signs the reveal transaction and fills its witness
+fn sign_reveal_transaction(
+    reveal_tx: &mut Transaction,
+    output_to_reveal: TxOut,
+    reveal_script: ScriptBuf,
+    control_block: &ControlBlock,
+    key_pair: &UntweakedKeyPair,
+    annex: Option<&[u8]>,
+) {
+    let secp256k1 = Secp256k1::new();
+    let mut sighash_cache = SighashCache::new(reveal_tx);
+
+    // create data to sign, the annex is committed to by the signature
+    let signature_hash = sighash_cache
+        .taproot_signature_hash(
+            0,
+            &Prevouts::All(&[output_to_reveal]),
+            annex.map(|annex| Annex::new(annex).expect("annex is validated")),
+            Some((
+                TapLeafHash::from_script(&reveal_script, LeafVersion::TapScript),
+                0xFFFFFFFF,
+            )),
+            bitcoin::sighash::TapSighashType::Default,
+        )
+        .unwrap();
+
+    // sign reveal tx data, without auxiliary randomness so that the reveal can be rebuilt byte for byte
+    let signature = secp256k1.sign_schnorr_no_aux_rand(
+        &secp256k1::Message::from_slice(signature_hash.as_byte_array())
+            .expect("should be cryptographically secure hash"),
+        key_pair,
+    );
+
+    // add signature to witness and finalize reveal tx
+    let witness = sighash_cache.witness_mut(0).unwrap();
+    witness.push(signature.as_ref());
+    witness.push(reveal_script);
+    witness.push(&control_block.serialize());
+    if let Some(annex) = annex {
+        witness.push(annex);
+    }
+}
+
+// How the fee of the commit transaction is paid
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CommitFeeMode {
+    // the fee is subtracted from the change of the inputs, more inputs are added when it is too small
+    #[default]
+    SubtractFromChange,
+    // the change of the inscribed utxo is kept whole, an additional input pays the fee
+    RequireAdditionalInput,
+}
+
+// Builds a commit transaction whose fee is paid by an additional input, with its own change output.
+// The inscribed utxo only funds the commit output and its change.
+fn build_commit_transaction_with_additional_input(
+    satpoint: SatPoint,
+    amounts: &BTreeMap<OutPoint, Amount>,
+    recipient: &Address,
+    change: &[Address; 2],
+    donate_dust_change: bool,
+    fee_rate: f64,
+    output_value: Amount,
+) -> Result<Transaction, BuilderError> {
+    let inscribed = satpoint.outpoint;
+    let inscribed_value = *amounts.get(&inscribed).ok_or(BuilderError::UnknownUtxo {
+        outpoint: inscribed,
+    })?;
+
+    // change outputs below the dust limit are dropped once the tx is built
+    let inscribed_change =
+        inscribed_value
+            .checked_sub(output_value)
+            .ok_or(BuilderError::InsufficientValue {
+                outpoint: inscribed,
+                value: inscribed_value.to_sat(),
+                needed: output_value.to_sat(),
+            })?;
+
+    let input = |previous_output| TxIn {
+        previous_output,
+        script_sig: ScriptBuf::new(),
+        witness: Witness::new(),
+        sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+    };
+    let mut commit_tx = Transaction {
+        version: 2,
+        lock_time: LockTime::ZERO,
+        input: vec![input(inscribed), input(OutPoint::null())],
+        output: vec![
+            TxOut {
+                script_pubkey: recipient.script_pubkey(),
+                value: output_value.to_sat(),
+            },
+            TxOut {
+                script_pubkey: change[0].script_pubkey(),
+                value: inscribed_change.to_sat(),
+            },
+            TxOut {
+                script_pubkey: change[1].script_pubkey(),
+                value: 0,
+            },
+        ],
+    };
+
+    // estimate the fee with a key path signature in every input, as ord does
+    let fee = {
+        let mut commit_tx = commit_tx.clone();
+        for input in commit_tx.input.iter_mut() {
+            input.witness = Witness::from_slice(&[&[0; SCHNORR_SIGNATURE_SIZE]]);
+        }
+        Amount::from_sat((fee_rate * commit_tx.vsize() as f64).round() as u64)
+    };
+
+    let (fee_input, fee_input_value) = amounts
+        .iter()
+        .find(|(outpoint, value)| **outpoint != inscribed && **value >= fee)
+        .ok_or(BuilderError::NoFeeInput { fee: fee.to_sat() })?;
+
+    commit_tx.input[1].previous_output = *fee_input;
+    commit_tx.output[2].value = (*fee_input_value - fee).to_sat();
+    donate_dust_change_to_fee(&mut commit_tx, change, donate_dust_change)?;
+
+    Ok(commit_tx)
+}
+
+// Removes the outputs paying to the change addresses whose value is below the dust limit of their
+// script (294 sat for P2WPKH, 330 sat for P2TR), which would make the tx non-standard. Their value
+// goes to the fee. Returns the value of the removed outputs.
+pub fn drop_dust_change(tx: &mut Transaction, change: &[Address; 2]) -> u64 {
+    let change_scripts = change
+        .iter()
+        .map(Address::script_pubkey)
+        .collect::<Vec<_>>();
+    let is_dust_change = |output: &TxOut| {
+        change_scripts.contains(&output.script_pubkey)
+            && output.value < output.script_pubkey.dust_value().to_sat()
+    };
+
+    let dropped = tx
+        .output
+        .iter()
+        .filter(|output| is_dust_change(output))
+        .map(|output| output.value)
+        .sum();
+    tx.output.retain(|output| !is_dust_change(output));
+
+    dropped
+}
+
+// Drops the dust change outputs when `donate` is set, refuses a tx that has some otherwise
+fn donate_dust_change_to_fee(
+    tx: &mut Transaction,
+    change: &[Address; 2],
+    donate: bool,
+) -> Result<(), BuilderError> {
+    let mut donated = tx.clone();
+    let value = drop_dust_change(&mut donated, change);
+    if value > 0 && !donate {
+        return Err(BuilderError::DustChange { value });
+    }
+
+    *tx = donated;
+    Ok(())
 }
 
 // Creates the inscription transactions (commit and reveal)
@@ -104,18 +776,69 @@ pub fn create_inscription_transactions(
     body: Vec<u8>,
     signature: Vec<u8>,
     sequencer_public_key: Vec<u8>,
+    fields: &EnvelopeFields,
+    commit_key_pair: UntweakedKeyPair,
     satpoint: SatPoint,
     utxos: Vec<UTXO>,
     change: [Address; 2],
+    fee_mode: CommitFeeMode,
+    enable_rbf: bool,
+    donate_dust_change: bool,
     destination: Address,
     commit_fee_rate: f64,
     reveal_fee_rate: f64,
     network: Network,
 ) -> Result<(Transaction, Transaction), anyhow::Error> {
-    // Create commit key
+    create_packed_inscription_transactions(
+        rollup_name,
+        &[(body, signature)],
+        sequencer_public_key,
+        fields,
+        commit_key_pair,
+        satpoint,
+        utxos,
+        change,
+        fee_mode,
+        enable_rbf,
+        donate_dust_change,
+        destination,
+        commit_fee_rate,
+        reveal_fee_rate,
+        network,
+    )
+}
+
+// Same as `create_inscription_transactions`, with a reveal tx carrying several inscriptions
+// Each (body, signature) pair gets its own envelope, all of them share the commit and reveal fees.
+pub fn create_packed_inscription_transactions(
+    rollup_name: &str,
+    inscriptions: &[(Vec<u8>, Vec<u8>)],
+    sequencer_public_key: Vec<u8>,
+    fields: &EnvelopeFields,
+    commit_key_pair: UntweakedKeyPair,
+    satpoint: SatPoint,
+    utxos: Vec<UTXO>,
+    change: [Address; 2],
+    fee_mode: CommitFeeMode,
+    enable_rbf: bool,
+    donate_dust_change: bool,
+    destination: Address,
+    commit_fee_rate: f64,
+    reveal_fee_rate: f64,
+    network: Network,
+) -> Result<(Transaction, Transaction), anyhow::Error> {
+    fields.validate()?;
+
+    // the additional input mode has no output to carry the sats before the satpoint
+    if fee_mode == CommitFeeMode::RequireAdditionalInput && satpoint.offset != 0 {
+        return Err(BuilderError::UnalignedSatpoint {
+            offset: satpoint.offset,
+        }
+        .into());
+    }
+
     let secp256k1 = Secp256k1::new();
-    let key_pair = UntweakedKeyPair::new(&secp256k1, &mut rand::thread_rng());
-    let (public_key, _parity) = XOnlyPublicKey::from_keypair(&key_pair);
+    let (public_key, _parity) = XOnlyPublicKey::from_keypair(&commit_key_pair);
 
     let mut amounts: BTreeMap<OutPoint, Amount> = BTreeMap::new();
 
@@ -129,44 +852,25 @@ pub fn create_inscription_transactions(
         );
     }
 
-    // start creating inscription content
-    let reveal_script_builder = script::Builder::new()
-        .push_slice(public_key.serialize())
-        .push_opcode(OP_CHECKSIG)
-        .push_opcode(OP_FALSE)
-        .push_opcode(OP_IF)
-        .push_slice(PushBytesBuf::try_from(ROLLUP_NAME_TAG.to_vec()).unwrap())
-        .push_slice(PushBytesBuf::try_from(rollup_name.as_bytes().to_vec()).unwrap())
-        .push_slice(PushBytesBuf::try_from(SIGNATURE_TAG.to_vec()).unwrap())
-        .push_slice(PushBytesBuf::try_from(signature).unwrap())
-        .push_slice(PushBytesBuf::try_from(PUBLICKEY_TAG.to_vec()).unwrap())
-        .push_slice(PushBytesBuf::try_from(sequencer_public_key).unwrap())
-        .push_slice(PushBytesBuf::try_from(RANDOM_TAG.to_vec()).unwrap());
-    // This envelope is not finished yet. The random number will be added later and followed by the body
+    // also rejects NaN and negative rates, which ord cannot build a tx at
+    let commit_fee_rate_per_vbyte = FeeRate::try_from(commit_fee_rate)
+        .map_err(|_| anyhow::anyhow!("invalid commit fee rate of {} sat/vB", commit_fee_rate))?;
 
     // Start loop to find a random number that makes the first two bytes of the reveal tx hash 0
     let mut random: i64 = 0;
     loop {
         // ownerships are moved to the loop
-        let mut reveal_script_builder = reveal_script_builder.clone();
         let change = change.clone();
         let amounts = amounts.clone();
 
-        // push first random number and body tag
-        reveal_script_builder = reveal_script_builder
-            .push_int(random)
-            .push_slice(PushBytesBuf::try_from(BODY_TAG.to_vec()).unwrap());
-
-        // push body in chunks of 520 bytes
-        for chunk in body.chunks(520) {
-            reveal_script_builder =
-                reveal_script_builder.push_slice(PushBytesBuf::try_from(chunk.to_vec()).unwrap());
-        }
-        // push end if
-        reveal_script_builder = reveal_script_builder.push_opcode(OP_ENDIF);
-
-        // finalize reveal script
-        let reveal_script = reveal_script_builder.into_script();
+        let reveal_script = build_packed_reveal_script(
+            &public_key,
+            rollup_name,
+            inscriptions,
+            &sequencer_public_key,
+            fields,
+            random,
+        );
 
         // create spend info for tapscript
         let taproot_spend_info = TaprootBuilder::new()
@@ -193,21 +897,57 @@ pub fn create_inscription_transactions(
                 value: 0,
             },
             &reveal_script,
-        );
+            fields.annex.as_deref(),
+        )?;
 
         // build commit tx
-        let unsigned_commit_tx = TransactionBuilder::build_transaction_with_value(
-            satpoint,
-            BTreeMap::new(),
-            amounts,
-            commit_tx_address.clone(),
-            change,
-            FeeRate::try_from(commit_fee_rate).unwrap(),
-            reveal_fee + Amount::from_sat(546),
-        )
-        .unwrap();
+        let commit_output_value = reveal_fee + Amount::from_sat(REVEAL_OUTPUT_AMOUNT);
+        let mut unsigned_commit_tx = match fee_mode {
+            CommitFeeMode::SubtractFromChange => {
+                let mut commit_tx = TransactionBuilder::build_transaction_with_value(
+                    satpoint,
+                    BTreeMap::new(),
+                    amounts,
+                    commit_tx_address.clone(),
+                    change.clone(),
+                    commit_fee_rate_per_vbyte,
+                    commit_output_value,
+                )
+                .map_err(|error| {
+                    anyhow::anyhow!("cannot fund the commit transaction: {}", error)
+                })?;
+                donate_dust_change_to_fee(&mut commit_tx, &change, donate_dust_change)?;
+                commit_tx
+            }
+            CommitFeeMode::RequireAdditionalInput => {
+                build_commit_transaction_with_additional_input(
+                    satpoint,
+                    &amounts,
+                    &commit_tx_address,
+                    &change,
+                    donate_dust_change,
+                    commit_fee_rate,
+                    commit_output_value,
+                )?
+            }
+        };
+
+        // BIP-125 replaceability, so that the fee can be bumped when the commit tx is stuck
+        let sequence = match enable_rbf {
+            true => Sequence::ENABLE_RBF_NO_LOCKTIME,
+            false => Sequence::ENABLE_LOCKTIME_NO_RBF,
+        };
+        for input in unsigned_commit_tx.input.iter_mut() {
+            input.sequence = sequence;
+        }
 
-        let output_to_reveal = unsigned_commit_tx.output[0].clone();
+        // the commit output is first, unless the sats before the satpoint are sent back before it
+        let commit_vout = unsigned_commit_tx
+            .output
+            .iter()
+            .position(|output| output.script_pubkey == commit_tx_address.script_pubkey())
+            .context("commit transaction does not pay to the commit address")?;
+        let output_to_reveal = unsigned_commit_tx.output[commit_vout].clone();
 
         // build reveal tx
         let (mut reveal_tx, fee) = build_reveal_transaction(
@@ -215,20 +955,20 @@ pub fn create_inscription_transactions(
             reveal_fee_rate,
             OutPoint {
                 txid: unsigned_commit_tx.txid(),
-                vout: 0,
+                vout: commit_vout as u32,
             },
             TxOut {
                 script_pubkey: destination.clone().script_pubkey(),
                 value: output_to_reveal.value,
             },
             &reveal_script,
-        );
+            fields.annex.as_deref(),
+        )?;
 
         reveal_tx.output[0].value = reveal_tx.output[0]
             .value
             .checked_sub(fee.to_sat())
-            .context("commit transaction output value insufficient to pay transaction fee")
-            .unwrap();
+            .context("commit transaction output value insufficient to pay transaction fee")?;
 
         if reveal_tx.output[0].value < reveal_tx.output[0].script_pubkey.dust_value().to_sat() {
             return Err(anyhow::anyhow!(
@@ -240,35 +980,18 @@ pub fn create_inscription_transactions(
 
         // check if first two bytes are 0
         if reveal_hash.starts_with(&[0, 0]) {
-            // start signing reveal tx
-            let mut sighash_cache = SighashCache::new(&mut reveal_tx);
-
-            // create data to sign
-            let signature_hash = sighash_cache
-                .taproot_script_spend_signature_hash(
-                    0,
-                    &Prevouts::All(&[output_to_reveal]),
-                    TapLeafHash::from_script(&reveal_script, LeafVersion::TapScript),
-                    bitcoin::sighash::TapSighashType::Default,
-                )
-                .unwrap();
-
-            // sign reveal tx data
-            let signature = secp256k1.sign_schnorr(
-                &secp256k1::Message::from_slice(signature_hash.as_byte_array())
-                    .expect("should be cryptographically secure hash"),
-                &key_pair,
+            sign_reveal_transaction(
+                &mut reveal_tx,
+                output_to_reveal,
+                reveal_script,
+                &control_block,
+                &commit_key_pair,
+                fields.annex.as_deref(),
             );
 
-            // add signature to witness and finalize reveal tx
-            let witness = sighash_cache.witness_mut(0).unwrap();
-            witness.push(signature.as_ref());
-            witness.push(reveal_script);
-            witness.push(&control_block.serialize());
-
             // check if inscription locked to the correct address
             let recovery_key_pair =
-                key_pair.tap_tweak(&secp256k1, taproot_spend_info.merkle_root());
+                commit_key_pair.tap_tweak(&secp256k1, taproot_spend_info.merkle_root());
             let (x_only_pub_key, _parity) = recovery_key_pair.to_inner().x_only_public_key();
             assert_eq!(
                 Address::p2tr_tweaked(
@@ -282,18 +1005,477 @@ pub fn create_inscription_transactions(
         }
 
         random += 1;
+        if random >= MAX_REVEAL_RANDOM {
+            return Err(anyhow::anyhow!(
+                "no reveal txid starting with two zero bytes within {} tries, retry the send",
+                MAX_REVEAL_RANDOM
+            ));
+        }
+    }
+}
+
+// Rebuilds the reveal transaction of an already broadcasted commit transaction.
+// The commit key and the signatures are deterministic, so the result is identical to the original reveal.
+// Only single inscriptions can be rebuilt: packed reveals carry several bodies, and chunked ones
+// split the blob over several commits, so both are recovered from the kept reveal or the mempool.
+pub fn rebuild_reveal_transaction(
+    rollup_name: &str,
+    body: Vec<u8>,
+    signature: Vec<u8>,
+    sequencer_public_key: Vec<u8>,
+    fields: &EnvelopeFields,
+    commit_key_pair: UntweakedKeyPair,
+    commit_tx: &Transaction,
+    destination: Address,
+    network: Network,
+) -> Result<Transaction, anyhow::Error> {
+    fields.validate()?;
+
+    // no need to search when nothing in the commit transaction can be a commit output
+    let is_taproot = |output: &TxOut| output.script_pubkey.is_v1_p2tr();
+    if !commit_tx.output.iter().any(is_taproot) {
+        return Err(anyhow::anyhow!(
+            "commit transaction {} has no taproot output",
+            commit_tx.txid()
+        ));
+    }
+
+    let secp256k1 = Secp256k1::new();
+    let (public_key, _parity) = XOnlyPublicKey::from_keypair(&commit_key_pair);
+
+    // find the random number the commit transaction was built with, and the output it pays to
+    for random in 0..MAX_REVEAL_RANDOM {
+        let reveal_script = build_reveal_script(
+            &public_key,
+            rollup_name,
+            &signature,
+            &sequencer_public_key,
+            fields,
+            random,
+            &body,
+        );
+
+        let taproot_spend_info = TaprootBuilder::new()
+            .add_leaf(0, reveal_script.clone())
+            .unwrap()
+            .finalize(&secp256k1, public_key)
+            .unwrap();
+
+        let commit_tx_address = Address::p2tr_tweaked(taproot_spend_info.output_key(), network);
+        let (commit_vout, output_to_reveal) = match commit_tx
+            .output
+            .iter()
+            .enumerate()
+            .find(|(_, output)| output.script_pubkey == commit_tx_address.script_pubkey())
+        {
+            Some((vout, output)) => (vout, output.clone()),
+            None => continue,
+        };
+
+        let control_block = taproot_spend_info
+            .control_block(&(reveal_script.clone(), LeafVersion::TapScript))
+            .unwrap();
+
+        let (mut reveal_tx, _fee) = build_reveal_transaction(
+            &control_block,
+            0.0,
+            OutPoint {
+                txid: commit_tx.txid(),
+                vout: commit_vout as u32,
+            },
+            TxOut {
+                script_pubkey: destination.script_pubkey(),
+                value: REVEAL_OUTPUT_AMOUNT,
+            },
+            &reveal_script,
+            fields.annex.as_deref(),
+        )?;
+
+        sign_reveal_transaction(
+            &mut reveal_tx,
+            output_to_reveal,
+            reveal_script,
+            &control_block,
+            &commit_key_pair,
+            fields.annex.as_deref(),
+        );
+
+        return Ok(reveal_tx);
+    }
+
+    Err(anyhow::anyhow!(
+        "commit transaction {} does not commit to the given blob",
+        commit_tx.txid()
+    ))
+}
+
+// Builds a transaction spending the commit output of the reveal transaction back to the destination
+// through the key path, recovering the funds of an inscription that will not be revealed.
+// The script of the reveal is needed to recompute the tweak of the commit key.
+pub fn build_abandon_transaction(
+    reveal_tx: &Transaction,
+    commit_output: &TxOut,
+    commit_key_pair: UntweakedKeyPair,
+    destination: Address,
+    fee_rate: f64,
+) -> Result<Transaction, anyhow::Error> {
+    let secp256k1 = Secp256k1::new();
+    let (public_key, _parity) = XOnlyPublicKey::from_keypair(&commit_key_pair);
+
+    let reveal_input = reveal_tx
+        .input
+        .first()
+        .context("reveal transaction has no inputs")?;
+    let reveal_script = reveal_input
+        .witness
+        .tapscript()
+        .context("reveal witness is not a script path spend")?;
+
+    let taproot_spend_info = TaprootBuilder::new()
+        .add_leaf(0, reveal_script.to_owned())?
+        .finalize(&secp256k1, public_key)
+        .map_err(|_| anyhow::anyhow!("failed to finalize the taproot tree of the reveal"))?;
+    if ScriptBuf::new_v1_p2tr_tweaked(taproot_spend_info.output_key())
+        != commit_output.script_pubkey
+    {
+        return Err(anyhow::anyhow!(
+            "commit output is not locked to the commit key and the reveal script"
+        ));
+    }
+
+    let mut abandon_tx = Transaction {
+        input: vec![TxIn {
+            previous_output: reveal_input.previous_output,
+            script_sig: script::Builder::new().into_script(),
+            witness: Witness::new(),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+        }],
+        output: vec![TxOut {
+            script_pubkey: destination.script_pubkey(),
+            value: commit_output.value,
+        }],
+        lock_time: LockTime::ZERO,
+        version: 2,
+    };
+
+    // a key path spend only carries the signature
+    let fee = {
+        let mut abandon_tx = abandon_tx.clone();
+        abandon_tx.input[0].witness.push(
+            Signature::from_slice(&[0; SCHNORR_SIGNATURE_SIZE])
+                .unwrap()
+                .as_ref(),
+        );
+
+        (fee_rate * abandon_tx.vsize() as f64).round() as u64
+    };
+
+    abandon_tx.output[0].value = commit_output
+        .value
+        .checked_sub(fee)
+        .context("commit output value insufficient to pay the abandon fee")?;
+    if abandon_tx.output[0].value < abandon_tx.output[0].script_pubkey.dust_value().to_sat() {
+        return Err(anyhow::anyhow!("abandon transaction output would be dust"));
     }
+
+    let mut sighash_cache = SighashCache::new(&mut abandon_tx);
+    let signature_hash = sighash_cache.taproot_key_spend_signature_hash(
+        0,
+        &Prevouts::All(&[commit_output.clone()]),
+        bitcoin::sighash::TapSighashType::Default,
+    )?;
+
+    let tweaked_key_pair = commit_key_pair.tap_tweak(&secp256k1, taproot_spend_info.merkle_root());
+    let signature = secp256k1.sign_schnorr_no_aux_rand(
+        &secp256k1::Message::from_slice(signature_hash.as_byte_array())
+            .expect("should be cryptographically secure hash"),
+        &tweaked_key_pair.to_inner(),
+    );
+
+    sighash_cache
+        .witness_mut(0)
+        .unwrap()
+        .push(signature.as_ref());
+
+    Ok(abandon_tx)
 }
 
-pub fn write_reveal_tx(tx: &[u8], tx_id: String) {
-    let reveal_tx_file = File::create("reveal_".to_string() + &tx_id + ".tx").unwrap();
+// Checks that the reveal transaction spends its commit output through the claimed inscription script.
+// Recomputes the tapleaf hash of the script and the merkle root of the control block in the witness,
+// and compares the tweaked internal key with the witness program of the commit output.
+pub fn verify_reveal_commitment(
+    reveal_tx: &Transaction,
+    commit_output: &TxOut,
+    inscription_script: &Script,
+) -> Result<(), anyhow::Error> {
+    let witness = &reveal_tx
+        .input
+        .first()
+        .context("reveal transaction has no inputs")?
+        .witness;
+
+    // the witness is the signature, the script, the control block and the optional annex
+    let mut elements = witness.to_vec();
+    if elements.len() >= 2
+        && elements.last().and_then(|element| element.first()) == Some(&TAPROOT_ANNEX_PREFIX)
+    {
+        elements.pop();
+    }
+    let (control_block, script) = match elements.as_slice() {
+        [.., script, control_block] => (control_block, script),
+        _ => return Err(anyhow::anyhow!("reveal witness is not a script path spend")),
+    };
+
+    if script.as_slice() != inscription_script.as_bytes() {
+        return Err(anyhow::anyhow!(
+            "reveal witness does not carry the claimed inscription script"
+        ));
+    }
+
+    if !commit_output.script_pubkey.is_v1_p2tr() {
+        return Err(anyhow::anyhow!("commit output is not a taproot output"));
+    }
+    let output_key = XOnlyPublicKey::from_slice(&commit_output.script_pubkey.as_bytes()[2..])?;
+
+    let control_block = ControlBlock::decode(control_block)?;
+    if !control_block.verify_taproot_commitment(
+        &Secp256k1::verification_only(),
+        output_key,
+        inscription_script,
+    ) {
+        return Err(anyhow::anyhow!(
+            "commit output does not commit to the inscription script"
+        ));
+    }
+
+    Ok(())
+}
+
+// Creates the reveal directory if needed and checks that files can be written to it, so that a
+// misconfigured directory is reported when the service starts rather than after a commit tx is sent
+pub fn ensure_reveal_dir(reveal_dir: &Path) -> Result<(), anyhow::Error> {
+    fs::create_dir_all(reveal_dir).with_context(|| {
+        format!(
+            "Cannot create the reveal directory {}",
+            reveal_dir.display()
+        )
+    })?;
+
+    // a unique name, services sharing the directory may be checking it at the same time
+    let probe = reveal_dir.join(format!(".probe_{:016x}", rand::random::<u64>()));
+    fs::write(&probe, b"")
+        .with_context(|| format!("Reveal directory {} is not writable", reveal_dir.display()))?;
+    fs::remove_file(&probe)?;
+
+    Ok(())
+}
+
+pub fn write_reveal_tx(reveal_dir: &Path, tx: &[u8], tx_id: String) -> Result<(), anyhow::Error> {
+    let reveal_tx_file = File::create(reveal_dir.join("reveal_".to_string() + &tx_id + ".tx"))?;
     let mut reveal_tx_writer = BufWriter::new(reveal_tx_file);
-    reveal_tx_writer.write_all(tx).unwrap();
+    reveal_tx_writer.write_all(tx)?;
+    // dropping the writer would ignore the errors of the last write
+    reveal_tx_writer.flush()?;
+    Ok(())
+}
+
+// Removes the reveal tx written by `write_reveal_tx`, once it is not needed for recovery anymore
+pub fn remove_reveal_tx(reveal_dir: &Path, tx_id: String) -> Result<(), anyhow::Error> {
+    fs::remove_file(reveal_dir.join("reveal_".to_string() + &tx_id + ".tx"))?;
+    Ok(())
+}
+
+// Reads the reveal tx written by `write_reveal_tx` for the given commit tx
+pub fn read_reveal_tx(reveal_dir: &Path, tx_id: String) -> Result<Transaction, anyhow::Error> {
+    let path = reveal_dir.join("reveal_".to_string() + &tx_id + ".tx");
+
+    encode::deserialize::<Transaction>(&fs::read(&path)?)
+        .with_context(|| format!("invalid reveal tx in {}", path.display()))
+}
+
+// Reads the reveal txs written by `write_reveal_tx`, ordered by the txid of their commit tx
+pub fn read_reveal_txs(reveal_dir: &Path) -> Result<Vec<Transaction>, anyhow::Error> {
+    let mut reveal_tx_files = fs::read_dir(reveal_dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .map(|name| name.starts_with("reveal_") && name.ends_with(".tx"))
+                .unwrap_or(false)
+        })
+        .collect::<Vec<_>>();
+    reveal_tx_files.sort();
+
+    reveal_tx_files
+        .iter()
+        .map(|path| {
+            encode::deserialize::<Transaction>(&fs::read(path)?)
+                .with_context(|| format!("invalid reveal tx in {}", path.display()))
+        })
+        .collect()
+}
+
+// Replaces the record of the inscriptions sent under the correlation id. It lives next to the
+// reveal txs but is never removed with them, so that it outlives their sending.
+pub fn write_correlation_record(
+    reveal_dir: &Path,
+    correlation_id: &[u8; 16],
+    record: &[u8],
+) -> Result<(), anyhow::Error> {
+    fs::write(correlation_record_path(reveal_dir, correlation_id), record)?;
+    Ok(())
+}
+
+// Reads the record written by `write_correlation_record`, if any
+pub fn read_correlation_record(
+    reveal_dir: &Path,
+    correlation_id: &[u8; 16],
+) -> Result<Option<Vec<u8>>, anyhow::Error> {
+    match fs::read(correlation_record_path(reveal_dir, correlation_id)) {
+        Ok(record) => Ok(Some(record)),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
+
+fn correlation_record_path(reveal_dir: &Path, correlation_id: &[u8; 16]) -> PathBuf {
+    reveal_dir.join(format!("correlation_{}.json", hex::encode(correlation_id)))
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::helpers::builders::{compress_blob, decompress_blob};
+    use core::str::FromStr;
+
+    use bitcoin::absolute::LockTime;
+    use bitcoin::consensus::encode;
+    use bitcoin::key::TweakedPublicKey;
+    use bitcoin::secp256k1::XOnlyPublicKey;
+    use bitcoin::{Address, Network, ScriptBuf, Transaction, TxOut, Txid, Witness};
+    use ord::SatPoint;
+
+    use crate::helpers::builders::{
+        build_reveal_script, compress_blob, create_inscription_transactions, decompress_blob,
+        derive_commit_key_pair, drop_dust_change, estimate_inscription_vsizes,
+        rebuild_reveal_transaction, select_utxos, sign_blob_with_private_key,
+        verify_reveal_commitment, BuilderError, CommitFeeMode, DecompressError, EnvelopeFields,
+        MAX_STANDARD_TX_WEIGHT,
+    };
+    use crate::helpers::parsers::{parse_transaction, recover_sender_and_hash_from_tx};
+    use crate::helpers::test_utils::get_mock_reveal_tx;
+    use crate::helpers::{blob_hash, SignatureScheme, MAX_LABEL_LEN};
+    use crate::spec::utxo::UTXO;
+
+    const SEQUENCER_DA_PRIVATE_KEY: &str =
+        "E9873D79C6D87DC0FB6A5778633389F4453213303DA61F20BD67FC233AA33262"; // Test key, safe to publish
+    const ADDRESS: &str = "bcrt1qxuds94z3pqwqea2p4f4ev4f25s6uu7y3avljrl";
+
+    fn get_address() -> Address {
+        Address::from_str(ADDRESS)
+            .unwrap()
+            .require_network(Network::Regtest)
+            .unwrap()
+    }
+
+    fn get_mock_utxos() -> Vec<UTXO> {
+        vec![UTXO {
+            tx_id: Txid::from_str(
+                "4cfbec13cf1510545f285cceceb6229bd7b6a918a8f6eba1dbee64d26226a3b7",
+            )
+            .unwrap(),
+            vout: 0,
+            address: ADDRESS.to_string(),
+            script_pubkey: String::new(),
+            amount: 100_000_000,
+            confirmations: 100,
+            spendable: true,
+            solvable: true,
+        }]
+    }
+
+    // Builds an inscription for the blob out of the mock utxos and returns the compressed blob along with it
+    fn get_mock_inscription(blob: &[u8]) -> (Vec<u8>, bitcoin::Transaction, bitcoin::Transaction) {
+        get_mock_inscription_with(blob, get_mock_utxos(), CommitFeeMode::default())
+    }
+
+    // Same as `get_mock_inscription`, out of the given utxos and paying the commit fee as told
+    fn get_mock_inscription_with(
+        blob: &[u8],
+        utxos: Vec<UTXO>,
+        fee_mode: CommitFeeMode,
+    ) -> (Vec<u8>, bitcoin::Transaction, bitcoin::Transaction) {
+        let body = compress_blob(blob);
+        let (signature, public_key) =
+            sign_blob_with_private_key(&body, SEQUENCER_DA_PRIVATE_KEY).unwrap();
+        let satpoint = SatPoint::from_str(&format!("{}:0:0", utxos[0].tx_id)).unwrap();
+
+        let (commit_tx, reveal_tx) = create_inscription_transactions(
+            "sov-btc",
+            body.clone(),
+            signature,
+            public_key,
+            &EnvelopeFields::default(),
+            derive_commit_key_pair(SEQUENCER_DA_PRIVATE_KEY, &body).unwrap(),
+            satpoint,
+            utxos,
+            [get_address(), get_address()],
+            fee_mode,
+            true,
+            true,
+            get_address(),
+            1.0,
+            1.0,
+            Network::Regtest,
+        )
+        .unwrap();
+
+        (body, commit_tx, reveal_tx)
+    }
+
+    #[test]
+    fn rebuild_reveal_transaction_from_blob() {
+        let (body, commit_tx, reveal_tx) = get_mock_inscription(b"rebuild me");
+
+        let (signature, public_key) =
+            sign_blob_with_private_key(&body, SEQUENCER_DA_PRIVATE_KEY).unwrap();
+        let rebuilt_reveal_tx = rebuild_reveal_transaction(
+            "sov-btc",
+            body.clone(),
+            signature.clone(),
+            public_key.clone(),
+            &EnvelopeFields::default(),
+            derive_commit_key_pair(SEQUENCER_DA_PRIVATE_KEY, &body).unwrap(),
+            &commit_tx,
+            get_address(),
+            Network::Regtest,
+        )
+        .unwrap();
+
+        assert_eq!(
+            encode::serialize(&rebuilt_reveal_tx),
+            encode::serialize(&reveal_tx)
+        );
+
+        // a commit tx without a taproot output is refused without searching
+        let mut not_a_commit_tx = commit_tx.clone();
+        for output in not_a_commit_tx.output.iter_mut() {
+            output.script_pubkey = get_address().script_pubkey();
+        }
+        let started = std::time::Instant::now();
+        assert!(rebuild_reveal_transaction(
+            "sov-btc",
+            body.clone(),
+            signature,
+            public_key,
+            &EnvelopeFields::default(),
+            derive_commit_key_pair(SEQUENCER_DA_PRIVATE_KEY, &body).unwrap(),
+            &not_a_commit_tx,
+            get_address(),
+            Network::Regtest,
+        )
+        .is_err());
+        assert!(started.elapsed() < std::time::Duration::from_secs(1));
+    }
 
     #[test]
     fn compression_decompression() {
@@ -306,11 +1488,17 @@ mod tests {
 
         // decompress and measure time
         let time = std::time::Instant::now();
-        let decompressed_blob = decompress_blob(&compressed_blob);
+        let decompressed_blob = decompress_blob(&compressed_blob).unwrap();
         println!("decompression time: {:?}", time.elapsed());
 
         assert_eq!(blob, decompressed_blob);
 
+        // a truncated stream is an error, not a panic
+        assert_eq!(
+            decompress_blob(&compressed_blob[..compressed_blob.len() / 2]),
+            Err(DecompressError::Truncated)
+        );
+
         // size
         println!("blob size: {}", blob.len());
         println!("compressed blob size: {}", compressed_blob.len());
@@ -319,4 +1507,487 @@ mod tests {
             (blob.len() as f64) / (compressed_blob.len() as f64)
         );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn reveal_too_large() {
+        // incompressible body bigger than what a standard transaction can carry
+        let body = (0..410_000u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 24) as u8)
+            .collect::<Vec<_>>();
+        let utxos = get_mock_utxos();
+        let satpoint = SatPoint::from_str(&format!("{}:0:0", utxos[0].tx_id)).unwrap();
+
+        let error = create_inscription_transactions(
+            "sov-btc",
+            body.clone(),
+            vec![0; 64],
+            vec![2; 33],
+            &EnvelopeFields::default(),
+            derive_commit_key_pair(SEQUENCER_DA_PRIVATE_KEY, &body).unwrap(),
+            satpoint,
+            utxos,
+            [get_address(), get_address()],
+            CommitFeeMode::default(),
+            true,
+            true,
+            get_address(),
+            1.0,
+            1.0,
+            Network::Regtest,
+        )
+        .expect_err("Reveal transaction should be too large");
+
+        match error.downcast_ref::<BuilderError>() {
+            Some(BuilderError::RevealTooLarge { weight, limit }) => {
+                assert_eq!(*limit, MAX_STANDARD_TX_WEIGHT);
+                assert!(*weight > MAX_STANDARD_TX_WEIGHT);
+            }
+            _ => panic!("unexpected error: {}", error),
+        }
+    }
+
+    #[test]
+    fn label_round_trip() {
+        let fields = EnvelopeFields {
+            label: Some("batch-1234".to_string()),
+            ..Default::default()
+        };
+        let tx = get_mock_reveal_tx("sov-btc", b"labelled blob", &fields);
+        let unlabelled_tx =
+            get_mock_reveal_tx("sov-btc", b"labelled blob", &EnvelopeFields::default());
+
+        let inscription = parse_transaction(&tx, "sov-btc").unwrap();
+        assert_eq!(inscription.label, Some("batch-1234".to_string()));
+        assert_eq!(
+            decompress_blob(&inscription.body).unwrap(),
+            b"labelled blob"
+        );
+
+        // the label is not part of the signed data
+        assert_eq!(
+            recover_sender_and_hash_from_tx(&tx, "sov-btc").unwrap(),
+            recover_sender_and_hash_from_tx(&unlabelled_tx, "sov-btc").unwrap()
+        );
+    }
+
+    #[test]
+    fn label_too_long() {
+        let body = compress_blob(b"labelled blob");
+        let utxos = get_mock_utxos();
+        let satpoint = SatPoint::from_str(&format!("{}:0:0", utxos[0].tx_id)).unwrap();
+        let fields = EnvelopeFields {
+            label: Some("x".repeat(MAX_LABEL_LEN + 1)),
+            ..Default::default()
+        };
+
+        let error = create_inscription_transactions(
+            "sov-btc",
+            body.clone(),
+            vec![0; 64],
+            vec![2; 33],
+            &fields,
+            derive_commit_key_pair(SEQUENCER_DA_PRIVATE_KEY, &body).unwrap(),
+            satpoint,
+            utxos,
+            [get_address(), get_address()],
+            CommitFeeMode::default(),
+            true,
+            true,
+            get_address(),
+            1.0,
+            1.0,
+            Network::Regtest,
+        )
+        .expect_err("Over-long label should be rejected");
+
+        assert_eq!(
+            error.downcast_ref::<BuilderError>(),
+            Some(&BuilderError::LabelTooLong {
+                len: MAX_LABEL_LEN + 1,
+                limit: MAX_LABEL_LEN
+            })
+        );
+    }
+
+    #[test]
+    fn annex_round_trip() {
+        let body = compress_blob(b"blob with annex");
+        let (signature, public_key) =
+            sign_blob_with_private_key(&body, SEQUENCER_DA_PRIVATE_KEY).unwrap();
+        let utxos = get_mock_utxos();
+        let satpoint = SatPoint::from_str(&format!("{}:0:0", utxos[0].tx_id)).unwrap();
+        let fields = EnvelopeFields {
+            annex: Some(vec![0x50, 1, 2, 3]),
+            ..Default::default()
+        };
+
+        let (_commit_tx, reveal_tx) = create_inscription_transactions(
+            "sov-btc",
+            body.clone(),
+            signature,
+            public_key,
+            &fields,
+            derive_commit_key_pair(SEQUENCER_DA_PRIVATE_KEY, &body).unwrap(),
+            satpoint,
+            utxos,
+            [get_address(), get_address()],
+            CommitFeeMode::default(),
+            true,
+            true,
+            get_address(),
+            1.0,
+            1.0,
+            Network::Regtest,
+        )
+        .unwrap();
+
+        // the annex is the last witness element
+        let witness = &reveal_tx.input[0].witness;
+        assert_eq!(witness.len(), 4);
+        assert_eq!(witness.last().unwrap(), &[0x50, 1, 2, 3]);
+
+        // and the inscription is still found
+        let inscription = parse_transaction(&reveal_tx, "sov-btc").unwrap();
+        assert_eq!(inscription.body, body);
+        assert!(recover_sender_and_hash_from_tx(&reveal_tx, "sov-btc").is_ok());
+    }
+
+    #[test]
+    fn invalid_annex() {
+        let fields = EnvelopeFields {
+            annex: Some(vec![0x51, 1, 2, 3]),
+            ..Default::default()
+        };
+
+        assert_eq!(fields.validate(), Err(BuilderError::InvalidAnnex));
+    }
+
+    #[test]
+    fn blob_hash_is_consistent() {
+        let blob = b"hash me";
+        let body = compress_blob(blob);
+        let expected_hash = blob_hash(&body);
+
+        // the hash is taken over the compressed body, not the blob itself
+        assert_ne!(expected_hash, blob_hash(blob));
+
+        // the sequencer signs the blob hash
+        let (signature, public_key) =
+            sign_blob_with_private_key(&body, SEQUENCER_DA_PRIVATE_KEY).unwrap();
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        assert!(secp
+            .verify_ecdsa(
+                &bitcoin::secp256k1::Message::from_slice(&expected_hash).unwrap(),
+                &bitcoin::secp256k1::ecdsa::Signature::from_compact(&signature).unwrap(),
+                &bitcoin::secp256k1::PublicKey::from_slice(&public_key).unwrap(),
+            )
+            .is_ok());
+
+        // the parser recovers the same hash, which ends up in the extracted blob
+        let tx = get_mock_reveal_tx("sov-btc", blob, &EnvelopeFields::default());
+        let (_sender, recovered_hash) = recover_sender_and_hash_from_tx(&tx, "sov-btc").unwrap();
+        assert_eq!(recovered_hash, expected_hash);
+        assert_eq!(
+            blob_hash(&parse_transaction(&tx, "sov-btc").unwrap().body),
+            expected_hash
+        );
+    }
+
+    #[test]
+    fn reveal_commitment() {
+        let (body, commit_tx, reveal_tx) = get_mock_inscription(b"audit me");
+
+        let inscription_script = reveal_tx.input[0].witness.tapscript().unwrap().to_owned();
+        verify_reveal_commitment(&reveal_tx, &commit_tx.output[0], &inscription_script).unwrap();
+
+        // a reveal carrying another script is not bound to the commit output
+        let commit_public_key =
+            XOnlyPublicKey::from_slice(&inscription_script.as_bytes()[1..33]).unwrap();
+        let swapped_script = build_reveal_script(
+            &commit_public_key,
+            "sov-btc",
+            &[0; 64],
+            &[0; 33],
+            &EnvelopeFields::default(),
+            0,
+            &body,
+        );
+        let mut witness = reveal_tx.input[0].witness.to_vec();
+        witness[1] = swapped_script.to_bytes();
+        let mut swapped_reveal_tx = reveal_tx.clone();
+        swapped_reveal_tx.input[0].witness = Witness::from_slice(&witness);
+
+        assert!(verify_reveal_commitment(
+            &swapped_reveal_tx,
+            &commit_tx.output[0],
+            &swapped_script
+        )
+        .is_err());
+        // and the original script is not the one it carries
+        assert!(verify_reveal_commitment(
+            &swapped_reveal_tx,
+            &commit_tx.output[0],
+            &inscription_script
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn commit_fee_modes() {
+        let mut utxos = get_mock_utxos();
+        utxos.push(UTXO {
+            tx_id: Txid::from_str(
+                "9f6e4b5a2c8d7e1f0a3b6c9d2e5f8a1b4c7d0e3f6a9b2c5d8e1f4a7b0c3d6e9f",
+            )
+            .unwrap(),
+            vout: 1,
+            amount: 50_000,
+            ..utxos[0].clone()
+        });
+
+        // the fee comes out of the change of the inscribed utxo
+        let (_, commit_tx, _) = get_mock_inscription_with(
+            b"fee from change",
+            utxos.clone(),
+            CommitFeeMode::SubtractFromChange,
+        );
+        let commit_output = commit_tx.output[0].value;
+        assert_eq!(commit_tx.input.len(), 1);
+        assert_eq!(commit_tx.output.len(), 2);
+        assert!(commit_tx.output[1].value < 100_000_000 - commit_output);
+
+        // the change of the inscribed utxo is whole, the second utxo pays the fee.
+        // 2 inputs with a 64 byte witness, a taproot and two P2WPKH outputs weigh 922 WU, 231 vB at 1 sat/vB
+        let (_, commit_tx, _) = get_mock_inscription_with(
+            b"fee from an additional input",
+            utxos.clone(),
+            CommitFeeMode::RequireAdditionalInput,
+        );
+        let commit_output = commit_tx.output[0].value;
+        assert_eq!(commit_tx.input[0].previous_output.txid, utxos[0].tx_id);
+        assert_eq!(commit_tx.input[1].previous_output.txid, utxos[1].tx_id);
+        assert_eq!(commit_tx.output[1].value, 100_000_000 - commit_output);
+        assert_eq!(commit_tx.output[2].value, 50_000 - 231);
+
+        // the change of the additional input would be dust, it is donated to the fee
+        utxos[1].amount = 231 + 100;
+        let (_, commit_tx, _) = get_mock_inscription_with(
+            b"fee from an additional input",
+            utxos.clone(),
+            CommitFeeMode::RequireAdditionalInput,
+        );
+        assert_eq!(commit_tx.input.len(), 2);
+        assert_eq!(commit_tx.output.len(), 2);
+        assert!(commit_tx
+            .output
+            .iter()
+            .all(|output| output.value >= output.script_pubkey.dust_value().to_sat()));
+        let paid_out = commit_tx
+            .output
+            .iter()
+            .map(|output| output.value)
+            .sum::<u64>();
+        assert_eq!(100_000_000 + utxos[1].amount - paid_out, 231 + 100);
+
+        // unless donating it is disabled
+        let body = compress_blob(b"fee from an additional input");
+        let satpoint = SatPoint::from_str(&format!("{}:0:0", utxos[0].tx_id)).unwrap();
+        let error = create_inscription_transactions(
+            "sov-btc",
+            body.clone(),
+            vec![0; 64],
+            vec![2; 33],
+            &EnvelopeFields::default(),
+            derive_commit_key_pair(SEQUENCER_DA_PRIVATE_KEY, &body).unwrap(),
+            satpoint,
+            utxos,
+            [get_address(), get_address()],
+            CommitFeeMode::RequireAdditionalInput,
+            true,
+            false,
+            get_address(),
+            1.0,
+            1.0,
+            Network::Regtest,
+        )
+        .expect_err("Dust change should be refused");
+        assert_eq!(
+            error.downcast_ref::<BuilderError>(),
+            Some(&BuilderError::DustChange { value: 100 })
+        );
+    }
+
+    #[test]
+    fn drop_dust_change_outputs() {
+        let taproot = Address::p2tr_tweaked(
+            TweakedPublicKey::dangerous_assume_tweaked(
+                XOnlyPublicKey::from_str(
+                    "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+                )
+                .unwrap(),
+            ),
+            Network::Regtest,
+        );
+        let segwit = get_address();
+        let change = [segwit.clone(), taproot.clone()];
+        let output = |address: &Address, value: u64| TxOut {
+            script_pubkey: address.script_pubkey(),
+            value,
+        };
+
+        // the dust limit depends on the script of the change address
+        let mut tx = Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![output(&segwit, 294), output(&taproot, 330)],
+        };
+        assert_eq!(drop_dust_change(&mut tx, &change), 0);
+        assert_eq!(tx.output.len(), 2);
+
+        // outputs that do not pay to a change address are kept, however small
+        let other = Address::p2wsh(&ScriptBuf::new(), Network::Regtest);
+        tx.output = vec![
+            output(&other, 100),
+            output(&segwit, 293),
+            output(&taproot, 329),
+        ];
+        assert_eq!(drop_dust_change(&mut tx, &change), 293 + 329);
+        assert_eq!(tx.output, vec![output(&other, 100)]);
+    }
+
+    #[test]
+    fn estimate_vsizes() {
+        let mut utxos = get_mock_utxos();
+        utxos.push(UTXO {
+            tx_id: Txid::from_str(
+                "9f6e4b5a2c8d7e1f0a3b6c9d2e5f8a1b4c7d0e3f6a9b2c5d8e1f4a7b0c3d6e9f",
+            )
+            .unwrap(),
+            vout: 1,
+            amount: 50_000,
+            ..utxos[0].clone()
+        });
+
+        for fee_mode in [
+            CommitFeeMode::SubtractFromChange,
+            CommitFeeMode::RequireAdditionalInput,
+        ] {
+            for blob_len in [0, 50, 600, 10_000, 150_000] {
+                // hashes do not compress, so the body lengths spread as the blob lengths do
+                let blob = (0..blob_len / 32 + 1)
+                    .flat_map(|i: usize| blob_hash(&i.to_le_bytes()))
+                    .take(blob_len)
+                    .collect::<Vec<_>>();
+                let (body, mut commit_tx, reveal_tx) =
+                    get_mock_inscription_with(&blob, utxos.clone(), fee_mode);
+
+                let estimate = estimate_inscription_vsizes(
+                    "sov-btc",
+                    body.len(),
+                    SignatureScheme::Ecdsa,
+                    &EnvelopeFields::default(),
+                    fee_mode,
+                    &get_address(),
+                    &get_address(),
+                );
+
+                // the fees are computed with a 64 byte witness in every input of the commit tx
+                for input in commit_tx.input.iter_mut() {
+                    input.witness = Witness::from_slice(&[&[0; 64]]);
+                }
+                assert_eq!(estimate.commit, commit_tx.vsize() as u64);
+
+                // the random number found only changes the size of its push
+                let reveal_vsize = reveal_tx.vsize() as u64;
+                assert!(estimate.reveal.abs_diff(reveal_vsize) <= 1);
+            }
+        }
+    }
+
+    #[test]
+    fn select_utxos_largest_first() {
+        let utxo = |vout: u32, amount: u64| UTXO {
+            vout,
+            amount,
+            ..get_mock_utxos()[0].clone()
+        };
+        let utxos = vec![
+            utxo(0, 500),
+            utxo(1, 20_000),
+            utxo(2, 100_000),
+            utxo(3, 60_000),
+        ];
+
+        // the largest utxo alone covers the target and the commit fee, the inscription goes there
+        let selected = select_utxos(&utxos, 50_000, 1.0).unwrap();
+        assert_eq!(selected, vec![utxos[2].clone()]);
+
+        // otherwise the next largest ones are added, the fee of every input included
+        let selected = select_utxos(&utxos, 150_000, 1.0).unwrap();
+        assert_eq!(selected, vec![utxos[2].clone(), utxos[3].clone()]);
+
+        // utxos not worth the fee of spending them are left out, the shortfall is reported
+        assert_eq!(
+            select_utxos(&utxos, 180_000, 10.0),
+            Err(BuilderError::InsufficientFunds {
+                available: 180_000,
+                needed: 180_000 + 1160 + 3 * 680,
+                shortfall: 1160 + 3 * 680,
+            })
+        );
+        assert_eq!(
+            select_utxos(&[], 1000, 1.0),
+            Err(BuilderError::InsufficientFunds {
+                available: 0,
+                needed: 1116,
+                shortfall: 1116,
+            })
+        );
+
+        // watch-only and unsolvable utxos are never selected, however large
+        let unsignable = [
+            UTXO {
+                spendable: false,
+                ..utxo(4, 1_000_000)
+            },
+            UTXO {
+                solvable: false,
+                ..utxo(5, 1_000_000)
+            },
+        ];
+        let utxos = [utxos.as_slice(), unsignable.as_slice()].concat();
+        let selected = select_utxos(&utxos, 50_000, 1.0).unwrap();
+        assert_eq!(selected, vec![utxos[2].clone()]);
+    }
+
+    #[test]
+    fn body_chunking_is_optimal() {
+        // opcode bytes of a push of the given length
+        let opcode_len = |len: usize| match len {
+            0..=75 => 1,
+            76..=255 => 2,
+            _ => 3,
+        };
+
+        // fewest bytes the pushes of a body can take, whatever the chunk sizes up to 520 bytes
+        let max_len = 4000;
+        let mut best = vec![0; max_len + 1];
+        for len in 1..=max_len {
+            best[len] = (1..=len.min(520))
+                .map(|chunk| best[len - chunk] + chunk + opcode_len(chunk))
+                .min()
+                .unwrap();
+        }
+
+        // fixed 520 byte chunks are never beaten, smaller pushes cost more opcode bytes per byte
+        for (len, best_len) in best.iter().enumerate() {
+            assert_eq!(
+                super::body_pushes_len(len),
+                *best_len,
+                "body of {} bytes",
+                len
+            );
+        }
+    }
+}
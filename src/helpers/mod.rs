@@ -1,9 +1,171 @@
+use bitcoin::hashes::{sha256d, Hash};
+use serde::{Deserialize, Serialize};
+
 // Tags that are used to seperate the different parts of the script
 const ROLLUP_NAME_TAG: &[u8] = &[1];
 const SIGNATURE_TAG: &[u8] = &[2];
 const PUBLICKEY_TAG: &[u8] = &[3];
 const RANDOM_TAG: &[u8] = &[4];
 const BODY_TAG: &[u8] = &[];
+const NAMESPACE_TAG: &[u8] = &[5];
+const LABEL_TAG: &[u8] = &[6];
+const COMPRESSION_TAG: &[u8] = &[7];
+const METADATA_TAG: &[u8] = &[8];
+const CHUNK_TAG: &[u8] = &[9];
+
+// Length of the optional namespace id, used to route blobs within a rollup (epoch, shard...)
+pub const NAMESPACE_ID_LEN: usize = 8;
+
+// Maximum length in bytes of the optional human-readable label
+pub const MAX_LABEL_LEN: usize = 64;
+
+// Maximum length in bytes of the rollup name, far below the push limit of a script
+pub const MAX_ROLLUP_NAME_LEN: usize = 64;
+
+// Maximum total length in bytes of the metadata entries of an envelope, keys and values included
+pub const MAX_METADATA_LEN: usize = 256;
+
+// Compression algorithm of the inscribed body. Bodies without a compression header are brotli compressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Compression {
+    None,
+    Brotli,
+}
+
+impl Compression {
+    pub fn to_byte(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Brotli => 1,
+        }
+    }
+
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Compression::None),
+            1 => Some(Compression::Brotli),
+            _ => None,
+        }
+    }
+}
+
+// Version byte in front of the Schnorr signatures of the sequencer
+const SCHNORR_SIGNATURE_VERSION: u8 = 1;
+
+// Signature scheme of the sequencer over the blob hash. Schnorr signatures are inscribed behind a
+// version byte, legacy ECDSA signatures are the bare 64 bytes of their compact encoding.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SignatureScheme {
+    // ECDSA with the compressed public key, as the first inscriptions. It stays the default, so
+    // that the key the sequencer embeds keeps matching the sequencer keys verifiers are given.
+    #[default]
+    Ecdsa,
+    // BIP-340 Schnorr with the x-only public key, native to taproot scripts. Opting in changes the
+    // embedded key, the authorized sequencer keys of extraction and verifiers must follow.
+    Schnorr,
+}
+
+impl SignatureScheme {
+    // Returns the scheme of an inscribed signature, none if it is neither
+    pub fn of_signature(signature: &[u8]) -> Option<Self> {
+        match signature {
+            [SCHNORR_SIGNATURE_VERSION, signature @ ..] if signature.len() == 64 => {
+                Some(SignatureScheme::Schnorr)
+            }
+            _ if signature.len() == 64 => Some(SignatureScheme::Ecdsa),
+            _ => None,
+        }
+    }
+}
+
+// Position of an inscription in a blob inscribed in chunks, as too large for a single reveal tx
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkInfo {
+    // blob hash of the whole body
+    pub blob_id: [u8; 32],
+    pub index: u16,
+    pub total: u16,
+}
+
+impl ChunkInfo {
+    // Length of the chunk field: the blob id, then the index and the total as big endian u16
+    pub const LEN: usize = 36;
+
+    pub fn to_bytes(self) -> [u8; Self::LEN] {
+        let mut bytes = [0; Self::LEN];
+        bytes[..32].copy_from_slice(&self.blob_id);
+        bytes[32..34].copy_from_slice(&self.index.to_be_bytes());
+        bytes[34..].copy_from_slice(&self.total.to_be_bytes());
+        bytes
+    }
+
+    // Decodes a chunk field, the index must be below the total
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::LEN {
+            return None;
+        }
+
+        let chunk = Self {
+            blob_id: bytes[..32].try_into().unwrap(),
+            index: u16::from_be_bytes([bytes[32], bytes[33]]),
+            total: u16::from_be_bytes([bytes[34], bytes[35]]),
+        };
+        (chunk.index < chunk.total).then_some(chunk)
+    }
+}
+
+// Returns whether the blobs signed with the public key are accepted, any key is if none is set
+pub fn is_authorized_sequencer(authorized_sequencers: &[Vec<u8>], public_key: &[u8]) -> bool {
+    authorized_sequencers.is_empty() || authorized_sequencers.iter().any(|key| key == public_key)
+}
+
+// Total length of metadata entries, as bounded by `MAX_METADATA_LEN`
+pub fn metadata_len(metadata: &[(String, Vec<u8>)]) -> usize {
+    metadata
+        .iter()
+        .map(|(key, value)| key.len() + value.len())
+        .sum()
+}
+
+// Hash identifying a blob: the double sha256 of the inscribed body, that is the *compressed* blob.
+// It is what the sequencer signs and what `BlobWithSender::hash` holds, every call site must use it.
+pub fn blob_hash(body: &[u8]) -> [u8; 32] {
+    sha256d::Hash::hash(body).to_byte_array()
+}
+
+// Message the sequencer signs for an inscription: the blob hash bound to the namespace id and the
+// compression header, so that a signed blob cannot be replayed into another namespace nor read
+// with another compression. A chunk signs the hash of its own body bound to its position in its
+// blob. Envelopes with none of these fields sign the bare blob hash, as the first inscriptions did.
+pub fn signed_message(
+    blob_hash: &[u8; 32],
+    namespace_id: Option<[u8; NAMESPACE_ID_LEN]>,
+    compression: Option<Compression>,
+    chunk: Option<ChunkInfo>,
+) -> [u8; 32] {
+    if namespace_id.is_none() && compression.is_none() && chunk.is_none() {
+        return *blob_hash;
+    }
+
+    // every field is behind its envelope tag and has a fixed length
+    let mut message = blob_hash.to_vec();
+    if let Some(namespace_id) = namespace_id {
+        message.extend(NAMESPACE_TAG);
+        message.extend(namespace_id);
+    }
+    if let Some(compression) = compression {
+        message.extend(COMPRESSION_TAG);
+        message.push(compression.to_byte());
+    }
+    if let Some(chunk) = chunk {
+        message.extend(CHUNK_TAG);
+        message.extend(chunk.to_bytes());
+    }
+
+    sha256d::Hash::hash(&message).to_byte_array()
+}
 
 pub mod builders;
 pub mod parsers;
+#[cfg(test)]
+pub(crate) mod test_utils;
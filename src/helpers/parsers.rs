@@ -1,147 +1,1095 @@
 use core::iter::Peekable;
+use std::collections::{BTreeMap, HashMap};
 
-use bitcoin::blockdata::opcodes::all::{OP_ENDIF, OP_IF};
+use bitcoin::blockdata::opcodes::all::{OP_DROP, OP_ENDIF, OP_IF};
 use bitcoin::blockdata::script::{Instruction, Instructions};
-use bitcoin::hashes::sha256d;
-use bitcoin::secp256k1::{self, ecdsa, Message, Secp256k1};
+use bitcoin::consensus::encode;
+use bitcoin::secp256k1::{self, ecdsa, schnorr, Message, Secp256k1, XOnlyPublicKey};
 use bitcoin::{Script, Transaction};
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
-use super::{BODY_TAG, PUBLICKEY_TAG, RANDOM_TAG, ROLLUP_NAME_TAG, SIGNATURE_TAG};
+use crate::authenticator::BlobAuthenticator;
+use crate::spec::transaction::ExtendedTransaction;
+
+use super::{
+    blob_hash, metadata_len, signed_message, ChunkInfo, Compression, SignatureScheme, BODY_TAG,
+    CHUNK_TAG, COMPRESSION_TAG, LABEL_TAG, MAX_LABEL_LEN, MAX_METADATA_LEN, METADATA_TAG,
+    NAMESPACE_ID_LEN, NAMESPACE_TAG, PUBLICKEY_TAG, RANDOM_TAG, ROLLUP_NAME_TAG, SIGNATURE_TAG,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ParsedInscription {
     pub body: Vec<u8>,
     pub signature: Vec<u8>,
     pub public_key: Vec<u8>,
+    pub namespace_id: Option<[u8; NAMESPACE_ID_LEN]>,
+    pub label: Option<String>,
+    pub compression: Option<Compression>,
+    pub metadata: Vec<(String, Vec<u8>)>,
+    pub chunk: Option<ChunkInfo>,
+    // hash the signature covers instead of the one of the body: that of the first chunk, whose
+    // signature and position a blob reassembled from chunks carries
+    pub signed_hash: Option<[u8; 32]>,
+}
+
+impl ParsedInscription {
+    // Returns the message the sequencer must have signed for the inscription
+    pub fn signed_message(&self) -> [u8; 32] {
+        let hash = self.signed_hash.unwrap_or_else(|| blob_hash(&self.body));
+        signed_message(&hash, self.namespace_id, self.compression, self.chunk)
+    }
+}
+
+// How strictly the envelope layout is enforced
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ParseMode {
+    // every field written by the builder is required
+    #[default]
+    Strict,
+    // the random field may be omitted, as some minimal inscribers do. Without it the reveal txid
+    // cannot be ground to start with zeros, so unless the inscriber grinds it otherwise the blob is
+    // left out of the completeness proof and the proof of its block is rejected. Identical blobs
+    // also yield identical envelopes. The verifier must parse with the same mode as extraction.
+    Lenient,
+}
+
+// Why a transaction carries no inscription for the rollup
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum ParserError {
+    #[error("the first input is not a script path spend")]
+    NoTapscript,
+    #[error("the script has no OP_FALSE OP_IF envelope")]
+    NoEnvelope,
+    #[error("the envelope is missing the tag {expected:?}")]
+    MissingTag { expected: &'static [u8] },
+    #[error("the envelope has an unexpected opcode")]
+    UnexpectedOpcode,
+    #[error("the envelope is for another rollup")]
+    RollupNameMismatch,
+    #[error("the envelope has an invalid {field}")]
+    InvalidField { field: &'static str },
+    #[error("the script ends in the middle of the envelope")]
+    UnexpectedEnd,
+    #[error("the inscription has an invalid public key")]
+    InvalidPublicKey,
+    #[error("the inscription is not signed by its public key")]
+    InvalidSignature,
+}
+
+pub fn parse_transaction(
+    tx: &Transaction,
+    rollup_name: &str,
+) -> Result<ParsedInscription, ParserError> {
+    parse_transaction_with_mode(tx, rollup_name, ParseMode::Strict)
+}
+
+// Same as `parse_transaction`, enforcing the envelope layout as told
+pub fn parse_transaction_with_mode(
+    tx: &Transaction,
+    rollup_name: &str,
+    mode: ParseMode,
+) -> Result<ParsedInscription, ParserError> {
+    parse_inscriptions(tx, rollup_name, mode).map(|mut inscriptions| inscriptions.remove(0))
 }
 
-pub fn parse_transaction(tx: &Transaction, rollup_name: &str) -> Result<ParsedInscription, ()> {
+// Same as `parse_transaction_with_mode`, returning all the inscriptions for the rollup in order.
+// A reveal transaction may pack several blobs, each in its own envelope.
+pub fn parse_inscriptions(
+    tx: &Transaction,
+    rollup_name: &str,
+    mode: ParseMode,
+) -> Result<Vec<ParsedInscription>, ParserError> {
     let script = get_script(tx)?;
     let mut instructions = script.instructions().peekable();
-    parse_relevant_inscriptions(&mut instructions, rollup_name)
+    parse_relevant_inscriptions(&mut instructions, rollup_name, mode)
+}
+
+// Decodes a raw reveal transaction, e.g. the hex returned by `getrawtransaction`, and parses its inscription.
+// Exposes the parser to tooling that has no service at hand.
+pub fn parse_reveal_hex(hex: &str, rollup_name: &str) -> Result<ParsedInscription, anyhow::Error> {
+    let tx: Transaction = encode::deserialize(&hex::decode(hex.trim())?)?;
+
+    parse_transaction(&tx, rollup_name).map_err(|error| {
+        anyhow::anyhow!(
+            "transaction {} has no inscription for rollup {}: {}",
+            tx.txid(),
+            rollup_name,
+            error
+        )
+    })
 }
 
 // Returns the script from the first input of the transaction
-fn get_script(tx: &Transaction) -> Result<&Script, ()> {
-    tx.input[0].witness.tapscript().ok_or(())
+fn get_script(tx: &Transaction) -> Result<&Script, ParserError> {
+    tx.input
+        .first()
+        .and_then(|input| input.witness.tapscript())
+        .ok_or(ParserError::NoTapscript)
 }
 
-// Parses the inscription from script if it is relevant to the rollup
+// Parses the inscriptions from script that are relevant to the rollup, never an empty list.
+// Returns the error of the last envelope found when none is relevant.
 fn parse_relevant_inscriptions(
     instructions: &mut Peekable<Instructions>,
     rollup_name: &str,
-) -> Result<ParsedInscription, ()> {
-    while let Some(instruction) = instructions.next() {
-        let instruction = match instruction {
-            Ok(i) => i,
-            _ => continue,
-        };
+    mode: ParseMode,
+) -> Result<Vec<ParsedInscription>, ParserError> {
+    let (envelopes, error) =
+        parse_envelopes(instructions, |name| name == rollup_name.as_bytes(), mode);
+
+    match envelopes.is_empty() {
+        true => Err(error),
+        false => Ok(envelopes
+            .into_iter()
+            .map(|(_, inscription)| inscription)
+            .collect()),
+    }
+}
 
+// Returns the rollup names of the well-formed envelopes of the transaction, whatever the rollup,
+// in order. Names that are not UTF-8 are skipped.
+pub fn parse_rollup_names(tx: &Transaction, mode: ParseMode) -> Vec<String> {
+    let script = match get_script(tx) {
+        Ok(script) => script,
+        Err(_) => return Vec::new(),
+    };
+
+    let (envelopes, _) = parse_envelopes(&mut script.instructions().peekable(), |_| true, mode);
+    envelopes
+        .into_iter()
+        .filter_map(|(name, _)| String::from_utf8(name.to_vec()).ok())
+        .collect()
+}
+
+// Parses the envelopes of the script whose rollup name is accepted, along with their name.
+// Also returns the error of the last envelope that did not parse, `NoEnvelope` when none failed.
+fn parse_envelopes<'a>(
+    instructions: &mut Peekable<Instructions<'a>>,
+    accept_name: impl Fn(&[u8]) -> bool,
+    mode: ParseMode,
+) -> (Vec<(&'a [u8], ParsedInscription)>, ParserError) {
+    let mut envelopes = Vec::new();
+    let mut error = ParserError::NoEnvelope;
+
+    while let Some(instruction) = instructions.next() {
+        // envelopes start with OP_FALSE OP_IF
         match instruction {
-            Instruction::PushBytes(bytes) if bytes.as_bytes() == BODY_TAG => {}
+            Ok(Instruction::PushBytes(bytes)) if bytes.as_bytes() == BODY_TAG => {}
             _ => continue,
         }
+        if !next_envelope_if(instructions) {
+            continue;
+        }
 
-        let op = match instructions.next() {
-            Some(Ok(Instruction::Op(op))) => op,
-            _ => continue,
-        };
+        // a malformed envelope may be followed by another one
+        match parse_envelope(instructions, &accept_name, mode) {
+            Ok(envelope) => envelopes.push(envelope),
+            Err(envelope_error) => error = envelope_error,
+        }
+    }
 
-        if op != OP_IF {
-            continue;
+    (envelopes, error)
+}
+
+// Consumes the OP_IF following the OP_FALSE of an envelope, skipping the `<push> OP_DROP` pairs
+// that ord-compatible tooling may put between them. Consumes nothing when there is no OP_IF,
+// so the instructions can still open another envelope.
+fn next_envelope_if(instructions: &mut Peekable<Instructions>) -> bool {
+    let mut lookahead = instructions.clone();
+    loop {
+        match lookahead.next() {
+            Some(Ok(Instruction::Op(op))) if op == OP_IF => {
+                *instructions = lookahead;
+                return true;
+            }
+            Some(Ok(Instruction::PushBytes(_))) => match lookahead.next() {
+                Some(Ok(Instruction::Op(op))) if op == OP_DROP => {}
+                _ => return false,
+            },
+            _ => return false,
         }
+    }
+}
+
+// Parses the fields of an envelope, from the instruction following its OP_IF, along with its
+// rollup name
+fn parse_envelope<'a>(
+    instructions: &mut Peekable<Instructions<'a>>,
+    accept_name: impl Fn(&[u8]) -> bool,
+    mode: ParseMode,
+) -> Result<(&'a [u8], ParsedInscription), ParserError> {
+    next_tag(instructions, ROLLUP_NAME_TAG)?;
+    let rollup_name = next_push(instructions, accept_name, ParserError::RollupNameMismatch)?;
 
+    // a relay may inscribe a blob without signature nor key, only a trusted blob hash lets it
+    // through extraction, as an empty signature never authenticates
+    let signature = next_optional_field(instructions, SIGNATURE_TAG)?.unwrap_or_default();
+    let public_key = next_optional_field(instructions, PUBLICKEY_TAG)?.unwrap_or_default();
+
+    // Optional fields come before the random tag
+    let mut namespace_id = None;
+    let mut label = None;
+    let mut compression = None;
+    let mut metadata = Vec::new();
+    let mut chunk = None;
+    let mut has_random = true;
+    loop {
         match instructions.next() {
-            Some(Ok(Instruction::PushBytes(bytes))) if bytes.as_bytes() == ROLLUP_NAME_TAG => bytes,
-            _ => continue,
-        };
+            Some(Ok(Instruction::PushBytes(bytes))) if bytes.as_bytes() == RANDOM_TAG => break,
+            // in lenient mode the body may directly follow the optional fields
+            Some(Ok(Instruction::PushBytes(bytes)))
+                if mode == ParseMode::Lenient && bytes.as_bytes() == BODY_TAG =>
+            {
+                has_random = false;
+                break;
+            }
+            Some(Ok(Instruction::PushBytes(bytes))) if bytes.as_bytes() == NAMESPACE_TAG => {
+                let id = next_push(
+                    instructions,
+                    |bytes| bytes.len() == NAMESPACE_ID_LEN,
+                    ParserError::InvalidField {
+                        field: "namespace id",
+                    },
+                )?;
+                namespace_id = Some(id.try_into().unwrap());
+            }
+            Some(Ok(Instruction::PushBytes(bytes))) if bytes.as_bytes() == LABEL_TAG => {
+                let invalid_label = ParserError::InvalidField { field: "label" };
+                let text = next_push(
+                    instructions,
+                    |bytes| bytes.len() <= MAX_LABEL_LEN,
+                    invalid_label,
+                )?;
+                label = Some(String::from_utf8(text.to_vec()).map_err(|_| invalid_label)?);
+            }
+            Some(Ok(Instruction::PushBytes(bytes))) if bytes.as_bytes() == COMPRESSION_TAG => {
+                let invalid_compression = ParserError::InvalidField {
+                    field: "compression",
+                };
+                let byte = next_push(instructions, |bytes| bytes.len() == 1, invalid_compression)?;
+                compression = Some(Compression::from_byte(byte[0]).ok_or(invalid_compression)?);
+            }
+            Some(Ok(Instruction::PushBytes(bytes))) if bytes.as_bytes() == METADATA_TAG => {
+                let invalid_metadata = ParserError::InvalidField { field: "metadata" };
+                let key = next_push(instructions, |_| true, invalid_metadata)?;
+                let key = String::from_utf8(key.to_vec()).map_err(|_| invalid_metadata)?;
+                let value = next_push(instructions, |_| true, invalid_metadata)?;
+                metadata.push((key, value.to_vec()));
+                if metadata_len(&metadata) > MAX_METADATA_LEN {
+                    return Err(invalid_metadata);
+                }
+            }
+            Some(Ok(Instruction::PushBytes(bytes))) if bytes.as_bytes() == CHUNK_TAG => {
+                let invalid_chunk = ParserError::InvalidField { field: "chunk" };
+                let bytes = next_push(instructions, |_| true, invalid_chunk)?;
+                chunk = Some(ChunkInfo::from_bytes(bytes).ok_or(invalid_chunk)?);
+            }
+            Some(Ok(Instruction::PushBytes(_))) => {
+                return Err(ParserError::MissingTag {
+                    expected: RANDOM_TAG,
+                })
+            }
+            Some(Ok(Instruction::Op(_))) => return Err(ParserError::UnexpectedOpcode),
+            Some(Err(_)) | None => return Err(ParserError::UnexpectedEnd),
+        }
+    }
+
+    if has_random {
+        next_push(instructions, |_| true, ParserError::UnexpectedOpcode)?;
+        // Found random
 
-        let rollup_name_bytes = rollup_name.as_bytes();
+        next_tag(instructions, BODY_TAG)?;
+    }
+
+    let mut body: Vec<u8> = Vec::new();
+    loop {
         match instructions.next() {
-            Some(Ok(Instruction::PushBytes(bytes))) if bytes.as_bytes() == rollup_name_bytes => {
-                bytes
+            Some(Ok(Instruction::PushBytes(bytes))) => {
+                body.extend(bytes.as_bytes());
             }
-            _ => continue,
+            Some(Ok(Instruction::Op(op))) if op == OP_ENDIF => {
+                let inscription = ParsedInscription {
+                    body,
+                    signature: signature.to_vec(),
+                    public_key: public_key.to_vec(),
+                    namespace_id,
+                    label,
+                    compression,
+                    metadata,
+                    chunk,
+                    signed_hash: None,
+                };
+                return Ok((rollup_name, inscription));
+            }
+            Some(Ok(Instruction::Op(_))) => return Err(ParserError::UnexpectedOpcode),
+            Some(Err(_)) | None => return Err(ParserError::UnexpectedEnd),
+        }
+    }
+}
+
+// Takes the next push of the envelope, failing with `error` on a push it does not accept
+fn next_push<'a>(
+    instructions: &mut Peekable<Instructions<'a>>,
+    accept: impl Fn(&[u8]) -> bool,
+    error: ParserError,
+) -> Result<&'a [u8], ParserError> {
+    match instructions.next() {
+        Some(Ok(Instruction::PushBytes(bytes))) if accept(bytes.as_bytes()) => Ok(bytes.as_bytes()),
+        Some(Ok(Instruction::PushBytes(_))) => Err(error),
+        Some(Ok(Instruction::Op(_))) => Err(ParserError::UnexpectedOpcode),
+        Some(Err(_)) | None => Err(ParserError::UnexpectedEnd),
+    }
+}
+
+// Takes the push following the tag when the next push of the envelope is the tag
+fn next_optional_field<'a>(
+    instructions: &mut Peekable<Instructions<'a>>,
+    tag: &'static [u8],
+) -> Result<Option<&'a [u8]>, ParserError> {
+    match instructions.peek() {
+        Some(Ok(Instruction::PushBytes(bytes))) if bytes.as_bytes() == tag => {
+            instructions.next();
+            next_push(instructions, |_| true, ParserError::UnexpectedOpcode).map(Some)
+        }
+        _ => Ok(None),
+    }
+}
+
+// Takes the next push of the envelope, which must be the given tag
+fn next_tag(
+    instructions: &mut Peekable<Instructions>,
+    tag: &'static [u8],
+) -> Result<(), ParserError> {
+    next_push(
+        instructions,
+        |bytes| bytes == tag,
+        ParserError::MissingTag { expected: tag },
+    )?;
+    Ok(())
+}
+
+// Recovers the sequencer public key from the transaction
+pub fn recover_sender_and_hash_from_tx(
+    tx: &Transaction,
+    rollup_name: &str,
+) -> Result<(Vec<u8>, [u8; 32]), ParserError> {
+    let script = get_script(tx)?;
+    let mut instructions = script.instructions().peekable();
+    let parsed_inscription =
+        parse_relevant_inscriptions(&mut instructions, rollup_name, ParseMode::Strict)?.remove(0);
+
+    // the key is compressed for ECDSA signatures and x-only for Schnorr ones
+    let public_key = match SignatureScheme::of_signature(&parsed_inscription.signature) {
+        Some(SignatureScheme::Ecdsa) => {
+            secp256k1::PublicKey::from_slice(&parsed_inscription.public_key)
+                .map(|public_key| public_key.serialize().to_vec())
+        }
+        Some(SignatureScheme::Schnorr) => {
+            XOnlyPublicKey::from_slice(&parsed_inscription.public_key)
+                .map(|public_key| public_key.serialize().to_vec())
+        }
+        None => return Err(ParserError::InvalidSignature),
+    }
+    .map_err(|_| ParserError::InvalidPublicKey)?;
+
+    if verify_blob_hash_signature(
+        &parsed_inscription.signed_message(),
+        &parsed_inscription.signature,
+        &parsed_inscription.public_key,
+    ) {
+        Ok((public_key, blob_hash(&parsed_inscription.body)))
+    } else {
+        Err(ParserError::InvalidSignature)
+    }
+}
+
+// Checks the signature of the sequencer over the blob hash of the inscribed body, ECDSA or
+// Schnorr as told by the signature. Only valid for envelopes without the fields covered by
+// `signed_message`, use `verify_blob_hash_signature` with the message of the inscription otherwise.
+pub fn verify_blob_signature(body: &[u8], signature: &[u8], public_key: &[u8]) -> bool {
+    verify_blob_hash_signature(&blob_hash(body), signature, public_key)
+}
+
+// Same as `verify_blob_signature`, for a message already computed with `signed_message`
+pub fn verify_blob_hash_signature(hash: &[u8; 32], signature: &[u8], public_key: &[u8]) -> bool {
+    match SignatureScheme::of_signature(signature) {
+        Some(SignatureScheme::Ecdsa) => verify_ecdsa(hash, signature, public_key),
+        Some(SignatureScheme::Schnorr) => verify_schnorr(hash, &signature[1..], public_key),
+        None => false,
+    }
+}
+
+// Checks a legacy ECDSA signature, in compact encoding, with the compressed public key
+fn verify_ecdsa(hash: &[u8; 32], signature: &[u8], public_key: &[u8]) -> bool {
+    let public_key = match secp256k1::PublicKey::from_slice(public_key) {
+        Ok(public_key) => public_key,
+        Err(_) => return false,
+    };
+    let signature = match ecdsa::Signature::from_compact(signature) {
+        Ok(signature) => signature,
+        Err(_) => return false,
+    };
+    let message = match Message::from_slice(hash) {
+        Ok(message) => message,
+        Err(_) => return false,
+    };
+
+    Secp256k1::new()
+        .verify_ecdsa(&message, &signature, &public_key)
+        .is_ok()
+}
+
+// Checks a BIP-340 signature, without its version byte, with the x-only public key
+fn verify_schnorr(hash: &[u8; 32], signature: &[u8], public_key: &[u8]) -> bool {
+    let public_key = match XOnlyPublicKey::from_slice(public_key) {
+        Ok(public_key) => public_key,
+        Err(_) => return false,
+    };
+    let signature = match schnorr::Signature::from_slice(signature) {
+        Ok(signature) => signature,
+        Err(_) => return false,
+    };
+    let message = match Message::from_slice(hash) {
+        Ok(message) => message,
+        Err(_) => return false,
+    };
+
+    Secp256k1::new()
+        .verify_schnorr(&signature, &message, &public_key)
+        .is_ok()
+}
+
+// Wraps the transaction with the sender and the blob hash recovered from its inscription, if any
+pub fn extend_transaction(transaction: Transaction, rollup_name: &str) -> ExtendedTransaction {
+    match recover_sender_and_hash_from_tx(&transaction, rollup_name) {
+        Ok((sender, blob_hash)) => ExtendedTransaction {
+            transaction,
+            sender: Some(sender),
+            blob_hash: Some(blob_hash),
+        },
+        Err(_) => ExtendedTransaction {
+            transaction,
+            sender: None,
+            blob_hash: None,
+        },
+    }
+}
+
+// Reassembles the blobs inscribed in chunks, fed with the inscriptions of a block. Every chunk is
+// signed by its sender over its body and position, and only authenticated chunks are kept, by
+// sender and blob id, so that nobody else can drop or replace the chunks of a blob. The chunks may
+// come in any order within the block, copies of a chunk already in are ignored.
+#[derive(Debug)]
+pub struct ChunkAssembler<'a> {
+    authenticator: &'a dyn BlobAuthenticator,
+    // chunks received so far, by sender and blob id, then by index
+    pending: HashMap<(Vec<u8>, [u8; 32]), BTreeMap<u16, ParsedInscription>>,
+}
+
+impl<'a> ChunkAssembler<'a> {
+    pub fn new(authenticator: &'a dyn BlobAuthenticator) -> Self {
+        Self {
+            authenticator,
+            pending: HashMap::new(),
+        }
+    }
+
+    // Takes an inscription, returning it at once unless it is a chunk. Returns the whole blob with
+    // the fields and signature of its first chunk once the last chunk is in and the body matches
+    // the blob id.
+    pub fn push(&mut self, inscription: ParsedInscription) -> Option<ParsedInscription> {
+        let chunk = match inscription.chunk {
+            Some(chunk) => chunk,
+            None => return Some(inscription),
         };
 
-        match instructions.next() {
-            Some(Ok(Instruction::PushBytes(bytes))) if bytes.as_bytes() == SIGNATURE_TAG => bytes,
-            _ => continue,
+        // a chunk not signed by its sender may be anyone's copy with another body
+        if !self.authenticator.authenticate(
+            &inscription.signed_message(),
+            &inscription.signature,
+            &inscription.public_key,
+        ) {
+            return None;
+        }
+
+        let key = (inscription.public_key.clone(), chunk.blob_id);
+        let chunks = self.pending.entry(key.clone()).or_default();
+        // the sender signed the total of every chunk, it can only disagree with itself
+        let same_total = chunks.values().next().map_or(true, |other| {
+            other.chunk.map(|other| other.total) == Some(chunk.total)
+        });
+        if !same_total {
+            return None;
+        }
+        chunks.entry(chunk.index).or_insert(inscription);
+
+        if chunks.len() < chunk.total as usize {
+            return None;
+        }
+        let chunks = self.pending.remove(&key)?;
+        let body = chunks
+            .values()
+            .flat_map(|chunk| chunk.body.iter().copied())
+            .collect::<Vec<_>>();
+        if blob_hash(&body) != chunk.blob_id {
+            return None;
+        }
+
+        let first = chunks.into_values().next()?;
+        Some(ParsedInscription {
+            signed_hash: Some(blob_hash(&first.body)),
+            body,
+            ..first
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::absolute::LockTime;
+    use bitcoin::blockdata::opcodes::all::{OP_CHECKSIG, OP_DROP, OP_ENDIF, OP_IF};
+    use bitcoin::blockdata::opcodes::OP_FALSE;
+    use bitcoin::blockdata::script;
+    use bitcoin::consensus::encode;
+    use bitcoin::script::{Instruction, PushBytes, PushBytesBuf};
+    use bitcoin::taproot::TAPROOT_ANNEX_PREFIX;
+    use bitcoin::{Transaction, TxIn, Witness};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::{
+        extend_transaction, parse_inscriptions, parse_reveal_hex, parse_transaction,
+        parse_transaction_with_mode, recover_sender_and_hash_from_tx, verify_blob_signature,
+        ParseMode, ParserError, RANDOM_TAG,
+    };
+    use crate::helpers::builders::{
+        compress_blob, decompress_body, sign_blob_with_private_key, sign_message_with_scheme,
+        EnvelopeFields,
+    };
+    use crate::helpers::test_utils::{
+        build_mock_reveal_tx, get_mock_reveal_tx, get_mock_reveal_tx_with_scheme,
+        SEQUENCER_DA_PRIVATE_KEY,
+    };
+    use crate::helpers::{blob_hash, Compression, SignatureScheme};
+
+    #[test]
+    fn recover_sender_with_malformed_signature_or_key() {
+        let body = compress_blob(b"malformed");
+        let (signature, public_key) =
+            sign_blob_with_private_key(&body, SEQUENCER_DA_PRIVATE_KEY).unwrap();
+
+        // well tagged envelopes whose signature or key bytes come straight from the witness
+        for (signature, public_key, error) in [
+            (
+                vec![7; 31],
+                public_key.clone(),
+                ParserError::InvalidSignature,
+            ),
+            (
+                vec![1; 66],
+                public_key.clone(),
+                ParserError::InvalidSignature,
+            ),
+            (
+                signature.clone(),
+                vec![2; 31],
+                ParserError::InvalidPublicKey,
+            ),
+            (
+                signature.clone(),
+                vec![0; 33],
+                ParserError::InvalidPublicKey,
+            ),
+            (
+                [&[1][..], &signature].concat(),
+                public_key.clone(),
+                ParserError::InvalidPublicKey,
+            ),
+        ] {
+            let tx = build_mock_reveal_tx(
+                "sov-btc",
+                body.clone(),
+                &signature,
+                &public_key,
+                &EnvelopeFields::default(),
+                SEQUENCER_DA_PRIVATE_KEY,
+            );
+            assert!(parse_transaction(&tx, "sov-btc").is_ok());
+            assert_eq!(recover_sender_and_hash_from_tx(&tx, "sov-btc"), Err(error));
+            assert!(!verify_blob_signature(&body, &signature, &public_key));
+            assert_eq!(extend_transaction(tx, "sov-btc").sender, None);
+        }
+    }
+
+    #[test]
+    fn recover_sender_under_both_schemes() {
+        for (scheme, signature_len, public_key_len) in [
+            (SignatureScheme::Ecdsa, 64, 33),
+            (SignatureScheme::Schnorr, 65, 32),
+        ] {
+            let tx = get_mock_reveal_tx_with_scheme(
+                "sov-btc",
+                b"signed blob",
+                &EnvelopeFields::default(),
+                scheme,
+            );
+            let inscription = parse_transaction(&tx, "sov-btc").unwrap();
+            assert_eq!(inscription.signature.len(), signature_len);
+            assert_eq!(
+                SignatureScheme::of_signature(&inscription.signature),
+                Some(scheme)
+            );
+
+            let (sender, hash) = recover_sender_and_hash_from_tx(&tx, "sov-btc").unwrap();
+            assert_eq!(sender.len(), public_key_len);
+            assert_eq!(sender, inscription.public_key);
+            assert_eq!(hash, blob_hash(&inscription.body));
+
+            // the signature does not hold for another body
+            assert!(!verify_blob_signature(
+                b"another body",
+                &inscription.signature,
+                &inscription.public_key
+            ));
+        }
+
+        // without its version byte, a Schnorr signature is taken for an ECDSA one and rejected
+        let tx = get_mock_reveal_tx_with_scheme(
+            "sov-btc",
+            b"signed blob",
+            &EnvelopeFields::default(),
+            SignatureScheme::Schnorr,
+        );
+        let inscription = parse_transaction(&tx, "sov-btc").unwrap();
+        assert!(verify_blob_signature(
+            &inscription.body,
+            &inscription.signature,
+            &inscription.public_key
+        ));
+        assert!(!verify_blob_signature(
+            &inscription.body,
+            &inscription.signature[1..],
+            &inscription.public_key
+        ));
+    }
+
+    #[test]
+    fn signature_covers_namespace() {
+        let body = compress_blob(b"namespaced");
+        let shard_0 = EnvelopeFields {
+            namespace_id: Some([0; 8]),
+            ..Default::default()
+        };
+        let shard_1 = EnvelopeFields {
+            namespace_id: Some([1; 8]),
+            ..Default::default()
+        };
+        let (signature, public_key) = sign_message_with_scheme(
+            &shard_0.signed_message(&body),
+            SEQUENCER_DA_PRIVATE_KEY,
+            SignatureScheme::Ecdsa,
+        )
+        .unwrap();
+        let reveal_tx = |fields: &EnvelopeFields| {
+            build_mock_reveal_tx(
+                "sov-btc",
+                body.clone(),
+                &signature,
+                &public_key,
+                fields,
+                SEQUENCER_DA_PRIVATE_KEY,
+            )
         };
 
-        let signature = match instructions.next() {
-            Some(Ok(Instruction::PushBytes(bytes))) => bytes.as_bytes(),
-            _ => continue,
+        assert!(recover_sender_and_hash_from_tx(&reveal_tx(&shard_0), "sov-btc").is_ok());
+
+        // the signed body replayed into another namespace, or out of any, is rejected
+        for fields in [shard_1, EnvelopeFields::default()] {
+            let tx = reveal_tx(&fields);
+            assert!(parse_transaction(&tx, "sov-btc").is_ok());
+            assert_eq!(
+                recover_sender_and_hash_from_tx(&tx, "sov-btc"),
+                Err(ParserError::InvalidSignature)
+            );
+        }
+    }
+
+    #[test]
+    fn signature_covers_compression() {
+        let body = compress_blob(b"compressed");
+        let brotli = EnvelopeFields {
+            compression: Some(Compression::Brotli),
+            ..Default::default()
+        };
+        let (signature, public_key) = sign_message_with_scheme(
+            &brotli.signed_message(&body),
+            SEQUENCER_DA_PRIVATE_KEY,
+            SignatureScheme::Ecdsa,
+        )
+        .unwrap();
+        let reveal_tx = |fields: &EnvelopeFields| {
+            build_mock_reveal_tx(
+                "sov-btc",
+                body.clone(),
+                &signature,
+                &public_key,
+                fields,
+                SEQUENCER_DA_PRIVATE_KEY,
+            )
         };
-        // Found signature
 
-        match instructions.next() {
-            Some(Ok(Instruction::PushBytes(bytes))) if bytes.as_bytes() == PUBLICKEY_TAG => bytes,
-            _ => continue,
+        assert!(recover_sender_and_hash_from_tx(&reveal_tx(&brotli), "sov-btc").is_ok());
+
+        // the signed body re-inscribed to be read raw, or without the header, is rejected
+        let raw = EnvelopeFields {
+            compression: Some(Compression::None),
+            ..Default::default()
         };
+        for fields in [raw, EnvelopeFields::default()] {
+            let tx = reveal_tx(&fields);
+            assert!(parse_transaction(&tx, "sov-btc").is_ok());
+            assert_eq!(
+                recover_sender_and_hash_from_tx(&tx, "sov-btc"),
+                Err(ParserError::InvalidSignature)
+            );
+        }
+    }
 
-        let public_key = match instructions.next() {
-            Some(Ok(Instruction::PushBytes(bytes))) => bytes.as_bytes(),
-            _ => continue,
+    #[test]
+    fn parse_reveal_from_hex() {
+        let fields = EnvelopeFields {
+            namespace_id: Some([7; 8]),
+            label: Some("batch-42".to_string()),
+            ..Default::default()
         };
-        // Found public key
+        let reveal_tx = get_mock_reveal_tx("sov-btc", b"decode me", &fields);
+        let reveal_hex = hex::encode(encode::serialize(&reveal_tx));
 
-        match instructions.next() {
-            Some(Ok(Instruction::PushBytes(bytes))) if bytes.as_bytes() == RANDOM_TAG => bytes,
-            _ => continue,
+        let inscription = parse_reveal_hex(&reveal_hex, "sov-btc").unwrap();
+        assert_eq!(inscription.namespace_id, Some([7; 8]));
+        assert_eq!(inscription.label.as_deref(), Some("batch-42"));
+        assert_eq!(inscription.compression, None);
+        assert_eq!(inscription.public_key.len(), 33);
+        assert_eq!(
+            decompress_body(&inscription.body, inscription.compression).unwrap(),
+            b"decode me"
+        );
+
+        // a relevant tx of the mock block
+        let mock_txs = std::fs::read_to_string("test_data/mock_txs.txt").unwrap();
+        let mock_txs = mock_txs.lines().collect::<Vec<_>>();
+        assert!(parse_reveal_hex(mock_txs[6], "sov-btc").is_ok());
+
+        // no inscription, another rollup and invalid hex
+        assert!(parse_reveal_hex(mock_txs[0], "sov-btc").is_err());
+        assert!(parse_reveal_hex(&reveal_hex, "other-rollup").is_err());
+        assert!(parse_reveal_hex("not hex", "sov-btc").is_err());
+    }
+
+    #[test]
+    fn envelope_is_unexecuted() {
+        // the envelope starts with OP_FALSE (an empty push) OP_IF like ordinals inscriptions,
+        // so it is never executed
+        let reveal_tx = get_mock_reveal_tx("sov-btc", b"ord envelope", &EnvelopeFields::default());
+        let instructions = reveal_tx.input[0]
+            .witness
+            .tapscript()
+            .unwrap()
+            .instructions()
+            .map(|instruction| instruction.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(instructions[1], Instruction::Op(OP_CHECKSIG));
+        assert_eq!(instructions[2], Instruction::PushBytes(PushBytes::empty()));
+        assert_eq!(instructions[3], Instruction::Op(OP_IF));
+        assert_eq!(instructions.last(), Some(&Instruction::Op(OP_ENDIF)));
+
+        let inscription = parse_transaction(&reveal_tx, "sov-btc").unwrap();
+        assert_eq!(
+            decompress_body(&inscription.body, inscription.compression).unwrap(),
+            b"ord envelope"
+        );
+    }
+
+    #[test]
+    fn parse_ord_style_envelope() {
+        let reveal_tx = get_mock_reveal_tx("sov-btc", b"ord style", &EnvelopeFields::default());
+        let reveal_script = reveal_tx.input[0].witness.tapscript().unwrap().to_bytes();
+        let tx_with_script = |script: Vec<u8>| Transaction {
+            input: vec![TxIn {
+                witness: Witness::from_slice(&[vec![0; 64], script, vec![0xc0; 33]]),
+                ..Default::default()
+            }],
+            ..reveal_tx.clone()
+        };
+        let dropped_push = |data: &[u8]| {
+            script::Builder::new()
+                .push_slice(<&PushBytes>::try_from(data).unwrap())
+                .push_opcode(OP_DROP)
+                .into_script()
+                .to_bytes()
         };
 
-        match instructions.next() {
-            Some(Ok(Instruction::PushBytes(bytes))) => bytes.as_bytes(),
-            _ => continue,
+        // a dropped push before the commit key, and dropped pushes between OP_FALSE and OP_IF.
+        // The commit key and OP_CHECKSIG take 34 bytes, OP_FALSE follows.
+        let script = [
+            dropped_push(b"ord"),
+            reveal_script[..35].to_vec(),
+            dropped_push(b"text/plain"),
+            dropped_push(&[]),
+            reveal_script[35..].to_vec(),
+        ]
+        .concat();
+        let tx = tx_with_script(script);
+        let inscription = parse_transaction(&tx, "sov-btc").unwrap();
+        assert_eq!(
+            decompress_body(&inscription.body, inscription.compression).unwrap(),
+            b"ord style"
+        );
+        assert_eq!(
+            recover_sender_and_hash_from_tx(&tx, "sov-btc").unwrap(),
+            recover_sender_and_hash_from_tx(&reveal_tx, "sov-btc").unwrap()
+        );
+
+        // an empty push right before the envelope does not hide it
+        let script = [
+            reveal_script[..35].to_vec(),
+            vec![OP_FALSE.to_u8()],
+            reveal_script[35..].to_vec(),
+        ]
+        .concat();
+        assert!(parse_transaction(&tx_with_script(script), "sov-btc").is_ok());
+
+        // a push that is not dropped is not part of the framing
+        let script = [
+            reveal_script[..35].to_vec(),
+            vec![1, 0xff],
+            reveal_script[35..].to_vec(),
+        ]
+        .concat();
+        assert_eq!(
+            parse_transaction(&tx_with_script(script), "sov-btc"),
+            Err(ParserError::NoEnvelope)
+        );
+    }
+
+    #[test]
+    fn parse_envelope_without_random() {
+        // a minimal envelope, the body directly follows the public key
+        let reveal_script = script::Builder::new()
+            .push_slice([2; 32])
+            .push_opcode(OP_CHECKSIG)
+            .push_opcode(OP_FALSE)
+            .push_opcode(OP_IF)
+            .push_slice([1])
+            .push_slice(b"sov-btc")
+            .push_slice([2])
+            .push_slice([3; 64])
+            .push_slice([3])
+            .push_slice([2; 33])
+            .push_slice(PushBytesBuf::new())
+            .push_slice(b"minimal body")
+            .push_opcode(OP_ENDIF)
+            .into_script();
+        let tx = Transaction {
+            version: 1,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                witness: Witness::from_slice(&[
+                    vec![0; 64],
+                    reveal_script.to_bytes(),
+                    vec![0xc0; 33],
+                ]),
+                ..Default::default()
+            }],
+            output: vec![],
         };
-        // Found random
 
-        match instructions.next() {
-            Some(Ok(Instruction::PushBytes(bytes))) if bytes.as_bytes() == BODY_TAG => bytes,
-            _ => continue,
+        assert_eq!(
+            parse_transaction(&tx, "sov-btc"),
+            Err(ParserError::MissingTag {
+                expected: RANDOM_TAG
+            })
+        );
+
+        let inscription = parse_transaction_with_mode(&tx, "sov-btc", ParseMode::Lenient).unwrap();
+        assert_eq!(inscription.body, b"minimal body");
+        assert_eq!(inscription.signature, vec![3; 64]);
+        assert_eq!(inscription.public_key, vec![2; 33]);
+
+        // envelopes with the random field still parse in lenient mode
+        let tx = get_mock_reveal_tx("sov-btc", b"decode me", &EnvelopeFields::default());
+        assert_eq!(
+            parse_transaction_with_mode(&tx, "sov-btc", ParseMode::Lenient)
+                .unwrap()
+                .body,
+            parse_transaction(&tx, "sov-btc").unwrap().body
+        );
+    }
+
+    #[test]
+    fn fuzz_witness_shapes() {
+        let mut rng = StdRng::seed_from_u64(251);
+        let tx_with_witness = |witness: &[Vec<u8>]| Transaction {
+            version: 1,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                witness: Witness::from_slice(witness),
+                ..Default::default()
+            }],
+            output: vec![],
         };
 
-        let mut body: Vec<u8> = Vec::new();
-        loop {
-            match instructions.next() {
-                Some(Ok(Instruction::PushBytes(bytes))) => {
-                    body.extend(bytes.as_bytes());
-                }
-                Some(Ok(Instruction::Op(op))) if op == OP_ENDIF => {
-                    return Ok(ParsedInscription {
-                        body,
-                        signature: signature.to_vec(),
-                        public_key: public_key.to_vec(),
-                    });
+        // no inputs at all
+        let tx = Transaction {
+            input: vec![],
+            ..tx_with_witness(&[])
+        };
+        assert!(parse_transaction(&tx, "sov-btc").is_err());
+        assert!(recover_sender_and_hash_from_tx(&tx, "sov-btc").is_err());
+
+        // empty witnesses, lone signatures, annexes and garbage scripts are not inscriptions
+        for _ in 0..2000 {
+            let mut witness = (0..rng.gen_range(0..5))
+                .map(|_| {
+                    let len = rng.gen_range(0..200);
+                    (0..len).map(|_| rng.gen()).collect::<Vec<u8>>()
+                })
+                .collect::<Vec<_>>();
+            if rng.gen_bool(0.2) {
+                if let Some(last) = witness.last_mut() {
+                    last.insert(0, TAPROOT_ANNEX_PREFIX);
                 }
-                _ => break,
             }
+
+            let tx = tx_with_witness(&witness);
+            assert!(parse_transaction(&tx, "sov-btc").is_err());
+            assert!(parse_transaction_with_mode(&tx, "sov-btc", ParseMode::Lenient).is_err());
+            assert!(recover_sender_and_hash_from_tx(&tx, "sov-btc").is_err());
+        }
+
+        // truncated and corrupted envelopes may or may not parse, they must not panic
+        let reveal_tx = get_mock_reveal_tx("sov-btc", b"fuzz me", &EnvelopeFields::default());
+        let reveal_script = reveal_tx.input[0].witness.tapscript().unwrap().to_bytes();
+        for _ in 0..2000 {
+            let mut script = reveal_script.clone();
+            let position = rng.gen_range(0..script.len());
+            match rng.gen_range(0..3) {
+                0 => script.truncate(position),
+                1 => script[position] = rng.gen(),
+                _ => script.insert(position, rng.gen()),
+            }
+
+            let tx = tx_with_witness(&[vec![0; 64], script, vec![0xc0; 33]]);
+            let _ = parse_transaction(&tx, "sov-btc");
+            let _ = parse_transaction_with_mode(&tx, "sov-btc", ParseMode::Lenient);
+            let _ = extend_transaction(tx, "sov-btc");
         }
     }
 
-    // return error
-    Err(())
-}
+    #[test]
+    fn parser_errors() {
+        let tx_with_script = |script: Vec<u8>| Transaction {
+            version: 1,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                witness: Witness::from_slice(&[vec![0; 64], script, vec![0xc0; 33]]),
+                ..Default::default()
+            }],
+            output: vec![],
+        };
 
-// Recovers the sequencer public key from the transaction
-pub fn recover_sender_and_hash_from_tx(tx: &Transaction, rollup_name: &str) -> Result<(Vec<u8>, [u8; 32]), ()> {
-    let script = get_script(tx)?;
-    let mut instructions = script.instructions().peekable();
-    let parsed_inscription = parse_relevant_inscriptions(&mut instructions, rollup_name)?;
-    let public_key = secp256k1::PublicKey::from_slice(&parsed_inscription.public_key).unwrap();
-    let signature = ecdsa::Signature::from_compact(&parsed_inscription.signature).unwrap();
+        let no_input = Transaction {
+            input: vec![],
+            ..tx_with_script(vec![])
+        };
+        assert_eq!(
+            parse_transaction(&no_input, "sov-btc"),
+            Err(ParserError::NoTapscript)
+        );
 
-    let message = Message::from_hashed_data::<sha256d::Hash>(&parsed_inscription.body);
+        // a plain key spend script
+        let plain_script = script::Builder::new()
+            .push_slice([2; 32])
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        assert_eq!(
+            parse_transaction(&tx_with_script(plain_script.to_bytes()), "sov-btc"),
+            Err(ParserError::NoEnvelope)
+        );
 
-    let secp = Secp256k1::new();
+        let reveal_tx = get_mock_reveal_tx("sov-btc", b"errors", &EnvelopeFields::default());
+        assert_eq!(
+            parse_transaction(&reveal_tx, "other-rollup"),
+            Err(ParserError::RollupNameMismatch)
+        );
 
-    let verified = secp.verify_ecdsa(&message, &signature, &public_key).is_ok();
+        // the envelope is missing its OP_ENDIF
+        let mut truncated = reveal_tx.input[0].witness.tapscript().unwrap().to_bytes();
+        truncated.pop();
+        assert_eq!(
+            parse_transaction(&tx_with_script(truncated), "sov-btc"),
+            Err(ParserError::UnexpectedEnd)
+        );
 
-    if verified {
-        Ok((public_key.serialize().to_vec(), *message.as_ref()))
-    } else {
-        Err(())
+        // the body chunks are followed by an opcode
+        let mut script = reveal_tx.input[0].witness.tapscript().unwrap().to_bytes();
+        script.insert(script.len() - 1, OP_CHECKSIG.to_u8());
+        assert_eq!(
+            parse_transaction(&tx_with_script(script), "sov-btc"),
+            Err(ParserError::UnexpectedOpcode)
+        );
+
+        // the error tells why in the hex helper too
+        let reveal_hex = hex::encode(encode::serialize(&reveal_tx));
+        let error = parse_reveal_hex(&reveal_hex, "other-rollup").unwrap_err();
+        assert!(error.to_string().contains("another rollup"));
+    }
+
+    #[test]
+    fn parse_packed_inscriptions() {
+        let tapscript = |tx: &Transaction| tx.input[0].witness.tapscript().unwrap().to_bytes();
+        let first = get_mock_reveal_tx("sov-btc", b"first", &EnvelopeFields::default());
+        let other = get_mock_reveal_tx("other-rollup", b"other", &EnvelopeFields::default());
+        let second = get_mock_reveal_tx("sov-btc", b"second", &EnvelopeFields::default());
+
+        // the envelopes follow the commit key and OP_CHECKSIG, 34 bytes
+        let packed = [
+            tapscript(&first),
+            tapscript(&other)[34..].to_vec(),
+            tapscript(&second)[34..].to_vec(),
+        ]
+        .concat();
+        let tx = Transaction {
+            input: vec![TxIn {
+                witness: Witness::from_slice(&[vec![0; 64], packed, vec![0xc0; 33]]),
+                ..Default::default()
+            }],
+            ..first.clone()
+        };
+
+        // the envelopes of the rollup in order, the one of the other rollup is skipped
+        let inscriptions = parse_inscriptions(&tx, "sov-btc", ParseMode::Strict).unwrap();
+        let bodies = inscriptions
+            .iter()
+            .map(|inscription| decompress_body(&inscription.body, inscription.compression).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(bodies, [b"first".to_vec(), b"second".to_vec()]);
+        assert_eq!(
+            parse_inscriptions(&tx, "other-rollup", ParseMode::Strict)
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // the single inscription parsers return the first one
+        assert_eq!(
+            parse_transaction(&tx, "sov-btc").unwrap().body,
+            parse_transaction(&first, "sov-btc").unwrap().body
+        );
+        assert_eq!(
+            recover_sender_and_hash_from_tx(&tx, "sov-btc").unwrap(),
+            recover_sender_and_hash_from_tx(&first, "sov-btc").unwrap()
+        );
     }
 }
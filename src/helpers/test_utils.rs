@@ -0,0 +1,202 @@
+use bitcoin::absolute::LockTime;
+use bitcoin::blockdata::opcodes::all::{OP_CHECKSIG, OP_ENDIF, OP_IF};
+use bitcoin::blockdata::opcodes::OP_FALSE;
+use bitcoin::blockdata::script;
+use bitcoin::script::{PushBytes, PushBytesBuf};
+use bitcoin::secp256k1::XOnlyPublicKey;
+use bitcoin::{ScriptBuf, Transaction, TxIn, TxOut, Witness};
+
+use crate::helpers::builders::{
+    build_reveal_script, compress_blob, derive_commit_key_pair, sign_blob_with_private_key,
+    sign_message_with_scheme, EnvelopeFields,
+};
+use crate::helpers::parsers::extend_transaction;
+use crate::helpers::SignatureScheme;
+use crate::spec::transaction::ExtendedTransaction;
+
+pub(crate) const SEQUENCER_DA_PRIVATE_KEY: &str =
+    "E9873D79C6D87DC0FB6A5778633389F4453213303DA61F20BD67FC233AA33262"; // Test key, safe to publish
+
+// Builds a transaction carrying the inscription of the blob in its witness, the same way a reveal does.
+// It skips the search for a random number making the txid start with zeros, so it is cheap to build.
+pub(crate) fn get_mock_reveal_tx(
+    rollup_name: &str,
+    blob: &[u8],
+    fields: &EnvelopeFields,
+) -> Transaction {
+    get_mock_reveal_tx_signed_by(rollup_name, blob, fields, SEQUENCER_DA_PRIVATE_KEY)
+}
+
+// Same as `get_mock_reveal_tx`, with the blob signed by the given private key
+pub(crate) fn get_mock_reveal_tx_signed_by(
+    rollup_name: &str,
+    blob: &[u8],
+    fields: &EnvelopeFields,
+    private_key: &str,
+) -> Transaction {
+    let body = compress_blob(blob);
+    let (signature, public_key) = sign_message_with_scheme(
+        &fields.signed_message(&body),
+        private_key,
+        SignatureScheme::Ecdsa,
+    )
+    .unwrap();
+
+    build_mock_reveal_tx(
+        rollup_name,
+        body,
+        &signature,
+        &public_key,
+        fields,
+        private_key,
+    )
+}
+
+// Same as `get_mock_reveal_tx`, with the blob signed under the given scheme
+pub(crate) fn get_mock_reveal_tx_with_scheme(
+    rollup_name: &str,
+    blob: &[u8],
+    fields: &EnvelopeFields,
+    scheme: SignatureScheme,
+) -> Transaction {
+    let body = compress_blob(blob);
+    let (signature, public_key) = sign_message_with_scheme(
+        &fields.signed_message(&body),
+        SEQUENCER_DA_PRIVATE_KEY,
+        scheme,
+    )
+    .unwrap();
+
+    build_mock_reveal_tx(
+        rollup_name,
+        body,
+        &signature,
+        &public_key,
+        fields,
+        SEQUENCER_DA_PRIVATE_KEY,
+    )
+}
+
+// Same as `get_mock_reveal_tx`, with the body inscribed as is instead of the compressed blob
+pub(crate) fn get_mock_reveal_tx_with_body(
+    rollup_name: &str,
+    body: Vec<u8>,
+    fields: &EnvelopeFields,
+) -> Transaction {
+    let (signature, public_key) = sign_message_with_scheme(
+        &fields.signed_message(&body),
+        SEQUENCER_DA_PRIVATE_KEY,
+        SignatureScheme::Ecdsa,
+    )
+    .unwrap();
+
+    build_mock_reveal_tx(
+        rollup_name,
+        body,
+        &signature,
+        &public_key,
+        fields,
+        SEQUENCER_DA_PRIVATE_KEY,
+    )
+}
+
+// Same as `get_mock_reveal_tx`, with a signature of the sequencer over another blob
+pub(crate) fn get_mock_forged_reveal_tx(rollup_name: &str, blob: &[u8]) -> Transaction {
+    let body = compress_blob(blob);
+    let (signature, public_key) =
+        sign_blob_with_private_key(&compress_blob(b"another blob"), SEQUENCER_DA_PRIVATE_KEY)
+            .unwrap();
+
+    build_mock_reveal_tx(
+        rollup_name,
+        body,
+        &signature,
+        &public_key,
+        &EnvelopeFields::default(),
+        SEQUENCER_DA_PRIVATE_KEY,
+    )
+}
+
+// Builds the mock reveal tx of a body with the signature and public key inscribed as given
+pub(crate) fn build_mock_reveal_tx(
+    rollup_name: &str,
+    body: Vec<u8>,
+    signature: &[u8],
+    public_key: &[u8],
+    fields: &EnvelopeFields,
+    private_key: &str,
+) -> Transaction {
+    let commit_key_pair = derive_commit_key_pair(private_key, &body).unwrap();
+    let (commit_public_key, _parity) = XOnlyPublicKey::from_keypair(&commit_key_pair);
+
+    let reveal_script = build_reveal_script(
+        &commit_public_key,
+        rollup_name,
+        signature,
+        public_key,
+        fields,
+        0,
+        &body,
+    );
+
+    // signature, script and control block
+    let mut witness = Witness::new();
+    witness.push([0u8; 64]);
+    witness.push(reveal_script);
+    witness.push([0xc0u8; 33]);
+    if let Some(annex) = &fields.annex {
+        witness.push(annex);
+    }
+
+    Transaction {
+        version: 1,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            witness,
+            ..Default::default()
+        }],
+        output: vec![TxOut {
+            value: 546,
+            script_pubkey: ScriptBuf::new(),
+        }],
+    }
+}
+
+// Same as `get_mock_reveal_tx`, with an envelope that has neither signature nor public key, as a
+// relay may inscribe
+pub(crate) fn get_mock_unsigned_reveal_tx(rollup_name: &str, blob: &[u8]) -> Transaction {
+    let body = compress_blob(blob);
+
+    let mut builder = script::Builder::new()
+        .push_slice([2; 32])
+        .push_opcode(OP_CHECKSIG)
+        .push_opcode(OP_FALSE)
+        .push_opcode(OP_IF)
+        .push_slice([1])
+        .push_slice(<&PushBytes>::try_from(rollup_name.as_bytes()).unwrap())
+        .push_slice([4])
+        .push_slice(0u64.to_le_bytes())
+        .push_slice(PushBytesBuf::new());
+    for chunk in body.chunks(520) {
+        builder = builder.push_slice(<&PushBytes>::try_from(chunk).unwrap());
+    }
+    let reveal_script = builder.push_opcode(OP_ENDIF).into_script();
+
+    Transaction {
+        version: 1,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            witness: Witness::from_slice(&[vec![0; 64], reveal_script.to_bytes(), vec![0xc0; 33]]),
+            ..Default::default()
+        }],
+        output: vec![TxOut {
+            value: 546,
+            script_pubkey: ScriptBuf::new(),
+        }],
+    }
+}
+
+// Wraps the transaction with the sender and the blob hash recovered from its inscription
+pub(crate) fn get_extended_tx(transaction: Transaction, rollup_name: &str) -> ExtendedTransaction {
+    extend_transaction(transaction, rollup_name)
+}
@@ -1,8 +1,26 @@
 #![cfg_attr(not(feature = "native"), no_std)]
+pub mod authenticator;
+#[cfg(feature = "native")]
+mod block_cache;
+#[cfg(feature = "native")]
+mod blocksdir;
+#[cfg(feature = "native")]
+mod change;
+#[cfg(feature = "native")]
+pub mod clock;
 mod helpers;
 mod rpc;
 pub mod spec;
+#[cfg(feature = "metrics")]
+mod telemetry;
 
 #[cfg(feature = "native")]
 pub mod service;
 pub mod verifier;
+#[cfg(feature = "zmq")]
+mod zmq;
+
+pub use helpers::builders::CommitFeeMode;
+pub use helpers::parsers::{parse_reveal_hex, ParseMode, ParsedInscription, ParserError};
+pub use helpers::{Compression, SignatureScheme};
+pub use rpc::{RPCError, RPCErrorKind};
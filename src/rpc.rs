@@ -1,16 +1,20 @@
 use core::fmt::Display;
 use core::str::FromStr;
+use core::time::Duration;
+use std::path::{Path, PathBuf};
 
 use bitcoin::block::{Header, Version};
+use bitcoin::blockdata::constants::genesis_block;
 use bitcoin::consensus::Decodable;
 use bitcoin::hash_types::TxMerkleNode;
-use bitcoin::{Address, BlockHash, CompactTarget, Network, Transaction};
+use bitcoin::{Address, BlockHash, CompactTarget, Network, OutPoint, Target, Transaction, Txid};
 use reqwest::header::HeaderMap;
 use serde::{Deserialize, Serialize};
 use serde_json::value::RawValue;
 use serde_json::{json, to_value};
+use tracing::warn;
 
-use crate::helpers::parsers::recover_sender_and_hash_from_tx;
+use crate::helpers::parsers::extend_transaction;
 use crate::spec::block::BitcoinBlock;
 use crate::spec::header::HeaderWrapper;
 use crate::spec::transaction::ExtendedTransaction;
@@ -22,12 +26,95 @@ pub struct RPCError {
     pub code: i32,
     pub message: String,
 }
+impl RPCError {
+    pub fn kind(&self) -> RPCErrorKind {
+        match self.code {
+            -5 => RPCErrorKind::InvalidAddressOrKey,
+            -8 => RPCErrorKind::InvalidParameter,
+            -25 => RPCErrorKind::VerifyError,
+            -26 => RPCErrorKind::VerifyRejected,
+            -27 => RPCErrorKind::AlreadyInChain,
+            -28 => RPCErrorKind::InWarmup,
+            -32601 => RPCErrorKind::MethodNotFound,
+            code => RPCErrorKind::Other(code),
+        }
+    }
+}
 impl Display for RPCError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "RPCError {}: {}", self.code, self.message)
     }
 }
 
+// Well-known error codes of Bitcoin Core, see `src/rpc/protocol.h` there
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RPCErrorKind {
+    // -5, e.g. an invalid address or an unknown transaction
+    InvalidAddressOrKey,
+    // -8, e.g. a block height out of range, i.e. not mined yet
+    InvalidParameter,
+    // -25, e.g. missing or already spent inputs
+    VerifyError,
+    // -26, the transaction is rejected by the mempool policy, the message tells why
+    VerifyRejected,
+    // -27, the transaction is already in the chain
+    AlreadyInChain,
+    // -28, the node is still starting
+    InWarmup,
+    // -32601
+    MethodNotFound,
+    Other(i32),
+}
+
+// Returned when the node answers with something that is not a JSON-RPC response of the expected
+// shape, e.g. the HTML error page of a misconfigured proxy. The start of the body tells why.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnexpectedRpcResponse {
+    pub method: String,
+    pub status: u16,
+    pub body: String,
+}
+impl UnexpectedRpcResponse {
+    fn new(method: &str, status: u16, body: &str) -> Self {
+        Self {
+            method: method.to_string(),
+            status,
+            body: body.chars().take(MAX_RESPONSE_SNIPPET_LEN).collect(),
+        }
+    }
+}
+impl Display for UnexpectedRpcResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Unexpected response to {} (HTTP {}): {}",
+            self.method, self.status, self.body
+        )
+    }
+}
+impl std::error::Error for UnexpectedRpcResponse {}
+
+// Number of characters of an unexpected response body kept in the error
+const MAX_RESPONSE_SNIPPET_LEN: usize = 256;
+
+pub const DEFAULT_RPC_ATTEMPTS: u32 = 5;
+pub const DEFAULT_RPC_RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
+// Whether the request may succeed if sent again: it did not reach the node or got no answer, or
+// a proxy or the node failed with a 5xx status without a JSON-RPC answer. Errors of the node, such
+// as -8 for a block not found, are answers and are not retried.
+fn is_transient(error: &anyhow::Error) -> bool {
+    // a request that cannot be built fails the same way every time
+    if let Some(error) = error.downcast_ref::<reqwest::Error>() {
+        return !error.is_builder();
+    }
+
+    matches!(
+        error.downcast_ref::<UnexpectedRpcResponse>(),
+        Some(response) if response.status >= 500
+    )
+}
+
 // Response is a struct that represents a response returned by the Bitcoin RPC
 // It is generic over the type of the result field, which is usually a String in Bitcoin Core
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
@@ -37,12 +124,99 @@ struct Response<R = String> {
     pub id: String,
 }
 
+// Fee rates outside of this range in sat/vB are most likely a unit mismatch with the node
+const MIN_SANE_FEE_RATE: f64 = 1.0;
+const MAX_SANE_FEE_RATE: f64 = 10_000.0;
+
+// Fee rate in sat/vB used when the node cannot estimate one
+pub const DEFAULT_FALLBACK_FEE_RATE: f64 = 1.0;
+
+// Minimum relay fee rate of the default node policy in sat/vB, txs paying less are not relayed
+pub const MIN_RELAY_FEE_RATE: f64 = 1.0;
+
+// Highest target, in compact form, a block can have on each network
+fn max_target_bits(network: Network) -> u32 {
+    match network {
+        Network::Regtest => 0x207fffff,
+        Network::Signet => 0x1e0377ae,
+        _ => 0x1d00ffff,
+    }
+}
+
+// Catches blocks that clearly belong to another network than the configured one, e.g. when the
+// service is pointed at the wrong node. Blocks whose difficulty is too low for the network, and the
+// first two blocks when they do not match the genesis of the network, are rejected.
+fn check_block_network(
+    header: &Header,
+    height: u64,
+    network: Network,
+) -> Result<(), anyhow::Error> {
+    let genesis_hash = genesis_block(network).block_hash();
+    let links_to_genesis = match height {
+        0 => header.block_hash() == genesis_hash,
+        1 => header.prev_blockhash == genesis_hash,
+        _ => true,
+    };
+    if !links_to_genesis {
+        return Err(anyhow::anyhow!(
+            "Block {} at height {} is not on the {} chain",
+            header.block_hash(),
+            height,
+            network
+        ));
+    }
+
+    let max_target = CompactTarget::from_consensus(max_target_bits(network));
+    if header.target() > Target::from_compact(max_target) {
+        return Err(anyhow::anyhow!(
+            "Block {} has a target above the limit {:08x} of the {} chain",
+            header.block_hash(),
+            max_target.to_consensus(),
+            network
+        ));
+    }
+
+    Ok(())
+}
+
+// Reads the `<user>:<password>` credentials bitcoind writes to the `.cookie` file of its datadir
+fn read_cookie(path: &Path) -> Result<(String, String), anyhow::Error> {
+    let cookie = std::fs::read_to_string(path).map_err(|error| {
+        anyhow::anyhow!("Failed to read cookie file {}: {}", path.display(), error)
+    })?;
+
+    match cookie.trim().split_once(':') {
+        Some((user, password)) if !user.is_empty() && !password.is_empty() => {
+            Ok((user.to_string(), password.to_string()))
+        }
+        _ => Err(anyhow::anyhow!(
+            "Cookie file {} is not in the <user>:<password> format",
+            path.display()
+        )),
+    }
+}
+
 // BitcoinNode is a struct that represents a connection to a Bitcoin RPC node
 #[derive(Debug, Clone)]
 pub struct BitcoinNode {
     url: String,
     client: reqwest::Client,
+    // authentication and content type headers, kept to rebuild the client
+    headers: HeaderMap,
     network: Network,
+    fallback_fee_rate: f64,
+    // timeout of every request, none by default
+    timeout: Option<Duration>,
+    // timeout of block fetches, defaults to `timeout`
+    long_timeout: Option<Duration>,
+    // timeout of lightweight calls, defaults to `timeout`
+    short_timeout: Option<Duration>,
+    // cookie file authenticating the requests instead of the username and password
+    cookie_file: Option<PathBuf>,
+    // attempts of every request failing at the connection level or with a 5xx error
+    max_attempts: u32,
+    // delay before the first retry of a request, doubled after every failed attempt
+    retry_backoff: Duration,
 }
 impl BitcoinNode {
     pub fn new(url: String, username: String, password: String, network: Network) -> Self {
@@ -58,15 +232,106 @@ impl BitcoinNode {
         );
         headers.insert("Content-Type", "application/json".parse().unwrap());
         let client = reqwest::Client::builder()
-            .default_headers(headers)
+            .default_headers(headers.clone())
             .build()
             .unwrap();
 
         Self {
             url,
             client,
+            headers,
             network,
+            fallback_fee_rate: DEFAULT_FALLBACK_FEE_RATE,
+            timeout: None,
+            long_timeout: None,
+            short_timeout: None,
+            cookie_file: None,
+            max_attempts: DEFAULT_RPC_ATTEMPTS,
+            retry_backoff: DEFAULT_RPC_RETRY_BACKOFF,
+        }
+    }
+
+    // Authenticates with the cookie file of the node instead of the username and password.
+    // The cookie is read before every request, as bitcoind writes a new one whenever it restarts.
+    pub fn with_cookie_file(self, cookie_file: PathBuf) -> Result<Self, anyhow::Error> {
+        read_cookie(&cookie_file)?;
+
+        Ok(Self {
+            cookie_file: Some(cookie_file),
+            ..self
+        })
+    }
+
+    // Sets the timeout of every request, unless overridden for block fetches or lightweight calls
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    // Sets the timeout of block fetches, which legitimately take longer on big blocks
+    pub fn with_long_timeout(self, long_timeout: Duration) -> Self {
+        Self {
+            long_timeout: Some(long_timeout),
+            ..self
+        }
+    }
+
+    // Sets the timeout of lightweight calls such as fee estimation or the block count
+    pub fn with_short_timeout(self, short_timeout: Duration) -> Self {
+        Self {
+            short_timeout: Some(short_timeout),
+            ..self
+        }
+    }
+
+    // Returns the timeout of the given method
+    fn timeout_for(&self, method: &str) -> Option<Duration> {
+        match method {
+            "getblock" => self.long_timeout.or(self.timeout),
+            "getblockcount" | "getblockhash" | "estimatesmartfee" | "getrawchangeaddress" => {
+                self.short_timeout.or(self.timeout)
+            }
+            _ => self.timeout,
+        }
+    }
+
+    // Sets how often requests are retried when the node cannot be reached or answers with a 5xx
+    // error, e.g. while it restarts. Errors of the node itself, like a block not found, are not.
+    pub fn with_retries(self, max_attempts: u32, retry_backoff: Duration) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            retry_backoff,
+            ..self
+        }
+    }
+
+    // Sets the fee rate in sat/vB used when the estimation of the node is missing or not sane
+    pub fn with_fallback_fee_rate(self, fallback_fee_rate: f64) -> Self {
+        Self {
+            fallback_fee_rate,
+            ..self
+        }
+    }
+
+    // Routes the RPC traffic through a SOCKS5 proxy, e.g. `socks5h://127.0.0.1:9050` for Tor.
+    // With `socks5h` the host name of the node is resolved by the proxy, which onion addresses need.
+    #[cfg(feature = "socks")]
+    pub fn with_socks5_proxy(self, proxy: &str) -> Result<Self, anyhow::Error> {
+        if !proxy.starts_with("socks5://") && !proxy.starts_with("socks5h://") {
+            return Err(anyhow::anyhow!(
+                "SOCKS5 proxy must be a socks5:// or socks5h:// url, got {}",
+                proxy
+            ));
         }
+
+        let client = reqwest::Client::builder()
+            .default_headers(self.headers.clone())
+            .proxy(reqwest::Proxy::all(proxy)?)
+            .build()?;
+
+        Ok(Self { client, ..self })
     }
 
     async fn call<T: serde::de::DeserializeOwned>(
@@ -74,25 +339,73 @@ impl BitcoinNode {
         method: &str,
         params: Vec<serde_json::Value>,
     ) -> Result<T, anyhow::Error> {
-        let response: Response<T> = self
-            .client
-            .post(&self.url)
-            .json(&json!({
-                "jsonrpc": "1.0",
-                "id": method,
-                "method": method,
-                "params": params
-            }))
-            .send()
-            .await?
-            .json::<Response<T>>()
-            .await?;
+        self.call_retrying(method, params, |_| None).await
+    }
+
+    // Same as `call`, a failure of an attempt following a transient one is first handed to
+    // `on_retried_error`: the lost attempt may have reached the node, which non-idempotent
+    // methods must account for
+    async fn call_retrying<T: serde::de::DeserializeOwned>(
+        &self,
+        method: &str,
+        params: Vec<serde_json::Value>,
+        on_retried_error: impl Fn(&anyhow::Error) -> Option<T>,
+    ) -> Result<T, anyhow::Error> {
+        let mut backoff = self.retry_backoff;
+        let mut attempt = 1;
+        loop {
+            match self.call_once(method, &params).await {
+                Err(error) if attempt < self.max_attempts && is_transient(&error) => {
+                    warn!(
+                        "RPC {} failed on attempt {}: {}, retrying in {:?}",
+                        method, attempt, error, backoff
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                Err(error) if attempt > 1 => return on_retried_error(&error).ok_or(error),
+                result => return result,
+            }
+        }
+    }
+
+    async fn call_once<T: serde::de::DeserializeOwned>(
+        &self,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Result<T, anyhow::Error> {
+        let mut request = self.client.post(&self.url).json(&json!({
+            "jsonrpc": "1.0",
+            "id": method,
+            "method": method,
+            "params": params
+        }));
+        if let Some(timeout) = self.timeout_for(method) {
+            request = request.timeout(timeout);
+        }
+        // replaces the default authorization header
+        if let Some(cookie_file) = &self.cookie_file {
+            let (user, password) = read_cookie(cookie_file)?;
+            request = request.basic_auth(user, Some(password));
+        }
+
+        let response = request.send().await?;
+        let status = response.status().as_u16();
+        let body = response.text().await?;
+        let response: Response<T> = serde_json::from_str(&body)
+            .map_err(|_| UnexpectedRpcResponse::new(method, status, &body))?;
 
         if let Some(error) = response.error {
             return Err(anyhow::anyhow!(error));
         }
 
-        Ok(response.result.unwrap())
+        // a null result is only valid for the callers expecting an option
+        match response.result {
+            Some(result) => Ok(result),
+            None => serde_json::from_value(serde_json::Value::Null)
+                .map_err(|_| anyhow::anyhow!("RPC {} returned no result", method)),
+        }
     }
 
     // get_block_count returns the current block height
@@ -106,6 +419,22 @@ impl BitcoinNode {
             .await
     }
 
+    // get_median_time_past returns the median time past, in seconds since the epoch, of the block at the given height
+    pub async fn get_median_time_past(&self, height: u64) -> Result<u64, anyhow::Error> {
+        let block_hash = self.get_block_hash(height).await?;
+        let header: serde_json::Value = self
+            .call(
+                "getblockheader",
+                vec![to_value(block_hash).unwrap(), to_value(true).unwrap()],
+            )
+            .await?;
+
+        header
+            .get("mediantime")
+            .and_then(|median_time| median_time.as_u64())
+            .ok_or_else(|| anyhow::anyhow!("Block header at height {} has no median time", height))
+    }
+
     // get_block returns the block at the given hash
     pub async fn get_block(
         &self,
@@ -155,25 +484,14 @@ impl BitcoinNode {
                 let transaction =
                     Transaction::consensus_decode(&mut &hex::decode(tx_hex).unwrap()[..]).unwrap();
 
-                let extended_tx = match recover_sender_and_hash_from_tx(&transaction, rollup_name) {
-                    Ok((sender, blob_hash)) => ExtendedTransaction {
-                        transaction,
-                        sender: Some(sender),
-                        blob_hash: Some(blob_hash),
-                    },
-                    Err(_) => ExtendedTransaction {
-                            transaction,
-                            sender: None,
-                            blob_hash: None,
-                        }
-                };
-                
-                extended_tx
+                extend_transaction(transaction, rollup_name)
             })
             .collect();
 
         let height = full_block.get("height").unwrap().as_u64().unwrap();
 
+        check_block_network(&header, height, self.network)?;
+
         Ok(BitcoinBlock {
             header: HeaderWrapper {
                 header,
@@ -184,20 +502,122 @@ impl BitcoinNode {
         })
     }
 
-    // get_utxos returns all unspent transaction outputs for the wallets of bitcoind
-    pub async fn get_utxos(&self) -> Result<Vec<UTXO>, anyhow::Error> {
-        let utxos = self
-            .call::<Vec<UTXO>>(
-                "listunspent",
-                vec![to_value(0).unwrap(), to_value(9999999).unwrap()],
+    // get_raw_block returns the serialized block with the given hash, as stored by the node
+    pub async fn get_raw_block(&self, hash: BlockHash) -> Result<Vec<u8>, anyhow::Error> {
+        let block_hex = self
+            .call::<String>(
+                "getblock",
+                vec![to_value(hash.to_string()).unwrap(), to_value(0).unwrap()],
             )
             .await?;
 
-        if utxos.is_empty() {
-            return Err(anyhow::anyhow!("No UTXOs found"));
-        }
+        Ok(hex::decode(block_hex)?)
+    }
+
+    // get_raw_transaction returns the transaction with the given txid
+    pub async fn get_raw_transaction(&self, txid: Txid) -> Result<Transaction, anyhow::Error> {
+        let tx_hex = self
+            .call::<String>(
+                "getrawtransaction",
+                vec![to_value(txid.to_string()).unwrap()],
+            )
+            .await?;
+
+        let transaction = Transaction::consensus_decode(&mut &hex::decode(tx_hex)?[..])?;
 
-        Ok(utxos)
+        Ok(transaction)
+    }
+
+    // get_tx_spending_prevout returns the txid of the mempool transaction spending the output, if
+    // any. Needs Bitcoin Core 24 or later.
+    pub async fn get_tx_spending_prevout(
+        &self,
+        outpoint: OutPoint,
+    ) -> Result<Option<Txid>, anyhow::Error> {
+        let spends = self
+            .call::<Vec<serde_json::Value>>(
+                "gettxspendingprevout",
+                vec![json!([{ "txid": outpoint.txid.to_string(), "vout": outpoint.vout }])],
+            )
+            .await?;
+
+        spends
+            .first()
+            .and_then(|spend| spend.get("spendingtxid"))
+            .and_then(|txid| txid.as_str())
+            .map(Txid::from_str)
+            .transpose()
+            .map_err(Into::into)
+    }
+
+    // get_tx_out_value returns the value in satoshis of the output if it is in the UTXO set.
+    // Without the mempool, outputs spent by unconfirmed transactions are still returned.
+    pub async fn get_tx_out_value(
+        &self,
+        outpoint: OutPoint,
+        include_mempool: bool,
+    ) -> Result<Option<u64>, anyhow::Error> {
+        let tx_out = self
+            .call::<Option<serde_json::Value>>(
+                "gettxout",
+                vec![
+                    to_value(outpoint.txid.to_string()).unwrap(),
+                    to_value(outpoint.vout).unwrap(),
+                    to_value(include_mempool).unwrap(),
+                ],
+            )
+            .await?;
+
+        tx_out
+            .map(|tx_out| {
+                tx_out
+                    .get("value")
+                    .and_then(|value| value.as_f64())
+                    .map(|value| (value * 100_000_000.0).round() as u64)
+                    .ok_or_else(|| anyhow::anyhow!("Output {} has no value", outpoint))
+            })
+            .transpose()
+    }
+
+    // get_transaction_confirmations returns the number of confirmations of the transaction, 0 while in the mempool.
+    // Returns None if the node does not know the transaction, looking up confirmed transactions needs -txindex.
+    pub async fn get_transaction_confirmations(
+        &self,
+        txid: Txid,
+    ) -> Result<Option<u64>, anyhow::Error> {
+        let result = self
+            .call::<Box<RawValue>>(
+                "getrawtransaction",
+                vec![to_value(txid.to_string()).unwrap(), to_value(true).unwrap()],
+            )
+            .await;
+
+        let result = match result {
+            Ok(result) => result.to_string(),
+            Err(error) => {
+                return match error.downcast_ref::<RPCError>() {
+                    Some(error) if error.kind() == RPCErrorKind::InvalidAddressOrKey => Ok(None),
+                    _ => Err(error),
+                }
+            }
+        };
+
+        let tx: serde_json::Value = serde_json::from_str(&result)?;
+
+        Ok(Some(
+            tx.get("confirmations")
+                .and_then(|confirmations| confirmations.as_u64())
+                .unwrap_or(0),
+        ))
+    }
+
+    // get_utxos returns all unspent transaction outputs for the wallets of bitcoind, it may be empty
+    pub async fn get_utxos(&self) -> Result<Vec<UTXO>, anyhow::Error> {
+        self.call::<Vec<UTXO>>(
+            "listunspent",
+            vec![to_value(0).unwrap(), to_value(9999999).unwrap()],
+        )
+        .await
     }
 
     // get_change_address returns a change address for the wallet of bitcoind
@@ -225,14 +645,27 @@ impl BitcoinNode {
         let result_map: serde_json::Value = serde_json::from_str(&result)?;
 
         // Issue: https://github.com/chainwayxyz/bitcoin-da/issues/3
-        let btc_vkb = result_map
+        let btc_vkb = match result_map
             .get("feerate")
-            .unwrap_or(&serde_json::Value::from_str("0.00001").unwrap())
-            .as_f64()
-            .unwrap();
+            .and_then(|feerate| feerate.as_f64())
+        {
+            Some(btc_vkb) => btc_vkb,
+            None => return Ok(self.fallback_fee_rate),
+        };
 
         // convert to sat/vB and round up
-        Ok((btc_vkb * 100_000_000.0 / 1000.0).ceil())
+        let fee_rate = (btc_vkb * 100_000_000.0 / 1000.0).ceil();
+
+        // some versions and forks report other units, which would make the fee off by orders of magnitude
+        if !(MIN_SANE_FEE_RATE..=MAX_SANE_FEE_RATE).contains(&fee_rate) {
+            warn!(
+                "Estimated fee rate {} sat/vB is out of range, using fallback fee rate {} sat/vB",
+                fee_rate, self.fallback_fee_rate
+            );
+            return Ok(self.fallback_fee_rate);
+        }
+
+        Ok(fee_rate)
     }
 
     // sign_raw_transaction_with_wallet signs a raw transaction with the wallet of bitcoind
@@ -250,9 +683,27 @@ impl BitcoinNode {
         Ok(signed_tx.get("hex").unwrap().as_str().unwrap().to_string())
     }
 
-    // send_raw_transaction sends a raw transaction to the network
+    // send_raw_transaction sends a raw transaction to the network. When a retry finds the
+    // transaction already known, the lost attempt did send it, its txid is returned
     pub async fn send_raw_transaction(&self, tx: String) -> Result<String, anyhow::Error> {
-        self.call::<String>("sendrawtransaction", vec![to_value(tx).unwrap()])
+        let already_sent = |error: &anyhow::Error| {
+            let error = error.downcast_ref::<RPCError>()?;
+            let known = match error.kind() {
+                RPCErrorKind::AlreadyInChain => true,
+                RPCErrorKind::VerifyRejected => {
+                    error.message.contains("txn-already-in-mempool")
+                        || error.message.contains("txn-already-known")
+                }
+                _ => false,
+            };
+            if !known {
+                return None;
+            }
+            let transaction = Transaction::consensus_decode(&mut &hex::decode(&tx).ok()?[..]);
+            Some(transaction.ok()?.txid().to_string())
+        };
+        let params = vec![to_value(&tx).unwrap()];
+        self.call_retrying("sendrawtransaction", params, already_sent)
             .await
     }
 }
@@ -269,6 +720,426 @@ mod tests {
         )
     }
 
+    #[tokio::test]
+    async fn send_raw_transaction_rejects_unexpected_responses() {
+        use super::mock::start_raw_mock_node;
+        use super::{RPCError, RPCErrorKind, UnexpectedRpcResponse};
+
+        let send = |body: String| async move {
+            let node_url = start_raw_mock_node(move |_method, _params| body.clone());
+            BitcoinNode::new(
+                node_url,
+                "chainway".to_string(),
+                "topsecret".to_string(),
+                bitcoin::Network::Regtest,
+            )
+            .send_raw_transaction("00".to_string())
+            .await
+        };
+
+        // a proxy answering with its error page, only the start of it is kept
+        let page = format!(
+            "<html><body>502 Bad Gateway{}</body></html>",
+            " ".repeat(1000)
+        );
+        let error = send(page.clone()).await.unwrap_err();
+        let error = error.downcast_ref::<UnexpectedRpcResponse>().unwrap();
+        assert_eq!(error.method, "sendrawtransaction");
+        assert_eq!(error.status, 200);
+        assert_eq!(error.body, page[..256]);
+
+        // a result that is not a txid
+        let body = r#"{"result":{"txid":"00"},"error":null,"id":"sendrawtransaction"}"#;
+        let error = send(body.to_string()).await.unwrap_err();
+        assert!(error.downcast_ref::<UnexpectedRpcResponse>().is_some());
+
+        // errors of the node are still reported as such
+        let body = r#"{"result":null,"error":{"code":-26,"message":"dust"},"id":"send"}"#;
+        let error = send(body.to_string()).await.unwrap_err();
+        let error = error.downcast_ref::<RPCError>().unwrap();
+        assert_eq!(error.code, -26);
+        assert_eq!(error.kind(), RPCErrorKind::VerifyRejected);
+
+        let body = r#"{"result":"00ff","error":null,"id":"sendrawtransaction"}"#;
+        assert_eq!(send(body.to_string()).await.unwrap(), "00ff");
+    }
+
+    #[test]
+    fn rpc_error_kinds() {
+        use super::{RPCError, RPCErrorKind};
+
+        let kind = |code| {
+            RPCError {
+                code,
+                message: String::new(),
+            }
+            .kind()
+        };
+        assert_eq!(kind(-5), RPCErrorKind::InvalidAddressOrKey);
+        assert_eq!(kind(-8), RPCErrorKind::InvalidParameter);
+        assert_eq!(kind(-25), RPCErrorKind::VerifyError);
+        assert_eq!(kind(-26), RPCErrorKind::VerifyRejected);
+        assert_eq!(kind(-27), RPCErrorKind::AlreadyInChain);
+        assert_eq!(kind(-28), RPCErrorKind::InWarmup);
+        assert_eq!(kind(-32601), RPCErrorKind::MethodNotFound);
+        assert_eq!(kind(-4), RPCErrorKind::Other(-4));
+    }
+
+    #[tokio::test]
+    async fn retries_transient_failures() {
+        use core::time::Duration;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        use super::mock::start_http_mock_node;
+        use super::RPCError;
+
+        // a proxy failing the first two requests while the node restarts
+        let start_node = |body: &'static str| {
+            let requests = Arc::new(AtomicUsize::new(0));
+            let counter = requests.clone();
+            let node_url = start_http_mock_node(move |_method, _params| {
+                match counter.fetch_add(1, Ordering::SeqCst) {
+                    0 | 1 => (503, "Service Unavailable".to_string()),
+                    _ => (200, body.to_string()),
+                }
+            });
+            let node = BitcoinNode::new(
+                node_url,
+                "chainway".to_string(),
+                "topsecret".to_string(),
+                bitcoin::Network::Regtest,
+            )
+            .with_retries(3, Duration::from_millis(1));
+            (node, requests)
+        };
+
+        let (node, requests) = start_node(r#"{"result":7,"error":null,"id":"getblockcount"}"#);
+        assert_eq!(node.get_block_count().await.unwrap(), 7);
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+
+        // errors of the node are answers, they are not retried
+        let (node, requests) =
+            start_node(r#"{"result":null,"error":{"code":-8,"message":"out of range"},"id":"x"}"#);
+        assert!(node.get_block_count().await.is_err());
+        let error = node.get_block_hash(100).await.unwrap_err();
+        assert_eq!(error.downcast_ref::<RPCError>().unwrap().code, -8);
+        assert_eq!(requests.load(Ordering::SeqCst), 4);
+
+        // the last failure is returned once the attempts are exhausted
+        let (node, requests) = start_node("{}");
+        let node = node.with_retries(2, Duration::from_millis(1));
+        assert!(node.get_block_count().await.is_err());
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn send_raw_transaction_survives_lost_answers() {
+        use core::time::Duration;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        use bitcoin::consensus::encode::serialize_hex;
+
+        use super::mock::start_http_mock_node;
+        use super::RPCError;
+
+        let tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![bitcoin::TxOut {
+                value: 1000,
+                script_pubkey: bitcoin::ScriptBuf::new(),
+            }],
+        };
+        let raw = serialize_hex(&tx);
+
+        // a proxy losing the answer of the first request, which the node did accept
+        let start_node = |body: &'static str| {
+            let requests = Arc::new(AtomicUsize::new(0));
+            let counter = requests.clone();
+            let node_url = start_http_mock_node(move |_method, _params| {
+                match counter.fetch_add(1, Ordering::SeqCst) {
+                    0 => (502, "Bad Gateway".to_string()),
+                    _ => (200, body.to_string()),
+                }
+            });
+            let node = BitcoinNode::new(
+                node_url,
+                "chainway".to_string(),
+                "topsecret".to_string(),
+                bitcoin::Network::Regtest,
+            )
+            .with_retries(3, Duration::from_millis(1));
+            (node, requests)
+        };
+
+        let in_chain =
+            r#"{"result":null,"error":{"code":-27,"message":"already in chain"},"id":"x"}"#;
+        for body in [
+            in_chain,
+            r#"{"result":null,"error":{"code":-26,"message":"txn-already-in-mempool"},"id":"x"}"#,
+            r#"{"result":null,"error":{"code":-26,"message":"txn-already-known"},"id":"x"}"#,
+        ] {
+            let (node, requests) = start_node(body);
+            let txid = node.send_raw_transaction(raw.clone()).await.unwrap();
+            assert_eq!(txid, tx.txid().to_string());
+            assert_eq!(requests.load(Ordering::SeqCst), 2);
+        }
+
+        // other rejections are still errors
+        let (node, _) = start_node(
+            r#"{"result":null,"error":{"code":-26,"message":"min relay fee not met"},"id":"x"}"#,
+        );
+        let error = node.send_raw_transaction(raw.clone()).await.unwrap_err();
+        assert_eq!(error.downcast_ref::<RPCError>().unwrap().code, -26);
+
+        // without a lost answer, a known transaction is reported as such
+        let node_url = start_http_mock_node(move |_method, _params| (200, in_chain.to_string()));
+        let node = BitcoinNode::new(
+            node_url,
+            "chainway".to_string(),
+            "topsecret".to_string(),
+            bitcoin::Network::Regtest,
+        );
+        let error = node.send_raw_transaction(raw).await.unwrap_err();
+        assert_eq!(error.downcast_ref::<RPCError>().unwrap().code, -27);
+    }
+
+    #[tokio::test]
+    async fn estimate_smart_fee_falls_back_when_out_of_range() {
+        use serde_json::json;
+
+        use super::mock::start_mock_node;
+
+        let get_mock_node = |feerate: serde_json::Value| {
+            let node_url =
+                start_mock_node(move |_method, _params| Ok(json!({ "feerate": feerate })));
+            BitcoinNode::new(
+                node_url,
+                "chainway".to_string(),
+                "topsecret".to_string(),
+                bitcoin::Network::Regtest,
+            )
+            .with_fallback_fee_rate(3.0)
+        };
+
+        // 0.0002 BTC/kvB is 20 sat/vB
+        let node = get_mock_node(json!(0.0002));
+        assert_eq!(node.estimate_smart_fee().await.unwrap(), 20.0);
+
+        // a fee rate reported in sat/vB instead of BTC/kvB is way out of range
+        let node = get_mock_node(json!(20));
+        assert_eq!(node.estimate_smart_fee().await.unwrap(), 3.0);
+
+        let node = get_mock_node(json!(-0.0002));
+        assert_eq!(node.estimate_smart_fee().await.unwrap(), 3.0);
+
+        // no estimation
+        let node = get_mock_node(json!(null));
+        assert_eq!(node.estimate_smart_fee().await.unwrap(), 3.0);
+    }
+
+    #[tokio::test]
+    async fn cookie_file_auth() {
+        use serde_json::json;
+
+        use super::mock::start_mock_node;
+        use super::read_cookie;
+
+        let cookie_file =
+            std::env::temp_dir().join(format!("bitcoin-da-cookie-{}", std::process::id()));
+        let node_url = start_mock_node(|_method, _params| Ok(json!(42)));
+        let node = BitcoinNode::new(
+            node_url,
+            "".to_string(),
+            "".to_string(),
+            bitcoin::Network::Regtest,
+        );
+
+        // missing and malformed cookies are rejected upfront
+        assert!(node.clone().with_cookie_file(cookie_file.clone()).is_err());
+        std::fs::write(&cookie_file, "no separator").unwrap();
+        assert!(node.clone().with_cookie_file(cookie_file.clone()).is_err());
+        std::fs::write(&cookie_file, "__cookie__:").unwrap();
+        assert!(node.clone().with_cookie_file(cookie_file.clone()).is_err());
+
+        std::fs::write(&cookie_file, "__cookie__:first\n").unwrap();
+        let node = node.with_cookie_file(cookie_file.clone()).unwrap();
+        assert_eq!(
+            read_cookie(&cookie_file).unwrap(),
+            ("__cookie__".to_string(), "first".to_string())
+        );
+        assert_eq!(node.get_block_count().await.unwrap(), 42);
+
+        // the node restarted with a new cookie
+        std::fs::write(&cookie_file, "__cookie__:second").unwrap();
+        assert_eq!(read_cookie(&cookie_file).unwrap().1, "second");
+        assert_eq!(node.get_block_count().await.unwrap(), 42);
+
+        // the node stopped and removed its cookie
+        std::fs::remove_file(&cookie_file).unwrap();
+        let error = node.get_block_count().await.unwrap_err();
+        assert!(error.to_string().contains("cookie file"));
+    }
+
+    #[tokio::test]
+    async fn get_raw_block() {
+        use bitcoin::blockdata::constants::genesis_block;
+        use bitcoin::consensus::encode;
+        use serde_json::json;
+
+        use super::mock::start_mock_node;
+
+        let block = genesis_block(bitcoin::Network::Regtest);
+        let block_hex = encode::serialize_hex(&block);
+        let node_url = start_mock_node(move |method, params| {
+            assert_eq!(method, "getblock");
+            assert_eq!(params[1], json!(0));
+            Ok(json!(block_hex))
+        });
+        let node = BitcoinNode::new(
+            node_url,
+            "chainway".to_string(),
+            "topsecret".to_string(),
+            bitcoin::Network::Regtest,
+        );
+
+        let raw_block = node.get_raw_block(block.block_hash()).await.unwrap();
+        assert_eq!(
+            encode::deserialize::<bitcoin::Block>(&raw_block).unwrap(),
+            block
+        );
+    }
+
+    #[tokio::test]
+    async fn long_timeout_applies_to_block_fetches() {
+        use core::time::Duration;
+
+        use bitcoin::blockdata::constants::genesis_block;
+        use serde_json::json;
+
+        use super::mock::start_mock_node;
+
+        // a node answering every call after 300ms
+        let genesis = genesis_block(bitcoin::Network::Regtest).header;
+        let node_url = start_mock_node(move |method, _params| {
+            std::thread::sleep(Duration::from_millis(300));
+            match method {
+                "getblock" => Ok(json!({
+                    "bits": format!("{:08x}", genesis.bits.to_consensus()),
+                    "merkleroot": genesis.merkle_root.to_string(),
+                    "nonce": genesis.nonce,
+                    "previousblockhash": genesis.prev_blockhash.to_string(),
+                    "time": genesis.time,
+                    "version": genesis.version.to_consensus(),
+                    "height": 0,
+                    "tx": [],
+                })),
+                _ => Ok(json!(0)),
+            }
+        });
+        let node = BitcoinNode::new(
+            node_url,
+            "chainway".to_string(),
+            "topsecret".to_string(),
+            bitcoin::Network::Regtest,
+        )
+        .with_timeout(Duration::from_millis(100))
+        .with_long_timeout(Duration::from_secs(5));
+
+        // the block fetch waits for the slow node, the lightweight call does not
+        let block = node
+            .get_block(genesis.block_hash().to_string(), "sov-btc")
+            .await
+            .unwrap();
+        assert_eq!(block.header.header, genesis);
+        assert!(node.get_block_count().await.is_err());
+
+        // a short timeout overrides the global one for lightweight calls
+        let node = node
+            .with_timeout(Duration::from_millis(100))
+            .with_short_timeout(Duration::from_secs(5));
+        assert_eq!(node.get_block_count().await.unwrap(), 0);
+    }
+
+    #[cfg(feature = "socks")]
+    #[tokio::test]
+    async fn requests_go_through_socks5_proxy() {
+        use std::io::Read;
+        use std::net::TcpListener;
+        use std::sync::mpsc;
+
+        // a proxy recording the greeting of the client, then closing the connection
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let proxy = format!("socks5h://{}", listener.local_addr().unwrap());
+        let (greeting_sender, greeting_receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut greeting = [0u8; 2];
+            stream.read_exact(&mut greeting).unwrap();
+            greeting_sender.send(greeting).unwrap();
+        });
+
+        let node = get_bitcoin_node().with_socks5_proxy(&proxy).unwrap();
+        assert!(node.get_block_count().await.is_err());
+
+        // SOCKS version 5
+        let greeting = greeting_receiver.recv().unwrap();
+        assert_eq!(greeting[0], 5);
+
+        // only SOCKS5 proxies are accepted
+        assert!(get_bitcoin_node()
+            .with_socks5_proxy("http://127.0.0.1:8080")
+            .is_err());
+    }
+
+    #[test]
+    fn detect_block_of_another_network() {
+        use bitcoin::blockdata::constants::genesis_block;
+        use bitcoin::Network;
+
+        use super::check_block_network;
+
+        // the genesis blocks and the blocks following them
+        for network in [
+            Network::Bitcoin,
+            Network::Testnet,
+            Network::Signet,
+            Network::Regtest,
+        ] {
+            let genesis = genesis_block(network).header;
+            let next = bitcoin::block::Header {
+                prev_blockhash: genesis.block_hash(),
+                ..genesis
+            };
+            assert!(check_block_network(&genesis, 0, network).is_ok());
+            assert!(check_block_network(&next, 1, network).is_ok());
+        }
+
+        // a regtest block is caught on the other networks by its difficulty
+        let regtest_genesis = genesis_block(Network::Regtest).header;
+        for network in [Network::Bitcoin, Network::Testnet, Network::Signet] {
+            assert!(check_block_network(&regtest_genesis, 1000, network).is_err());
+        }
+        let error = check_block_network(&regtest_genesis, 1000, Network::Bitcoin).unwrap_err();
+        assert!(error
+            .to_string()
+            .ends_with("has a target above the limit 1d00ffff of the bitcoin chain"));
+
+        // a mainnet block is caught on testnet when it does not follow the genesis of testnet
+        let mainnet_next = bitcoin::block::Header {
+            prev_blockhash: genesis_block(Network::Bitcoin).block_hash(),
+            ..genesis_block(Network::Bitcoin).header
+        };
+        assert!(check_block_network(&mainnet_next, 1, Network::Testnet).is_err());
+        assert!(
+            check_block_network(&genesis_block(Network::Bitcoin).header, 0, Network::Testnet)
+                .is_err()
+        );
+    }
+
     #[tokio::test]
     async fn get_utxos() {
         let node = get_bitcoin_node();
@@ -280,3 +1151,104 @@ mod tests {
         });
     }
 }
+
+// A minimal JSON-RPC server for tests that cannot rely on a running bitcoind
+#[cfg(test)]
+pub(crate) mod mock {
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::Arc;
+
+    use serde_json::{json, Value};
+
+    use super::RPCError;
+
+    type Handler = Arc<dyn Fn(&str, &[Value]) -> (u16, String) + Send + Sync>;
+
+    // Starts a server answering every request with the result of `handler` and returns its url
+    pub fn start_mock_node(
+        handler: impl Fn(&str, &[Value]) -> Result<Value, RPCError> + Send + Sync + 'static,
+    ) -> String {
+        start_raw_mock_node(move |method, params| {
+            match handler(method, params) {
+                Ok(result) => json!({ "result": result, "error": null, "id": method }),
+                Err(error) => json!({ "result": null, "error": error, "id": method }),
+            }
+            .to_string()
+        })
+    }
+
+    // Starts a server answering every request with the raw body returned by `handler`, e.g. to
+    // act as a proxy answering in place of the node
+    pub fn start_raw_mock_node(
+        handler: impl Fn(&str, &[Value]) -> String + Send + Sync + 'static,
+    ) -> String {
+        start_http_mock_node(move |method, params| (200, handler(method, params)))
+    }
+
+    // Same as `start_raw_mock_node`, with the HTTP status of the answer, e.g. a 503 of a proxy
+    pub fn start_http_mock_node(
+        handler: impl Fn(&str, &[Value]) -> (u16, String) + Send + Sync + 'static,
+    ) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handler: Handler = Arc::new(handler);
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let handler = handler.clone();
+                std::thread::spawn(move || handle_connection(stream, handler));
+            }
+        });
+
+        url
+    }
+
+    fn handle_connection(mut stream: TcpStream, handler: Handler) {
+        let mut buffer = Vec::new();
+        let mut chunk = [0u8; 4096];
+
+        // read until the end of the headers
+        let (header_end, content_length) = loop {
+            let read = stream.read(&mut chunk).unwrap_or(0);
+            if read == 0 {
+                return;
+            }
+            buffer.extend_from_slice(&chunk[..read]);
+
+            if let Some(position) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+                let headers = String::from_utf8_lossy(&buffer[..position]).to_lowercase();
+                let content_length = headers
+                    .lines()
+                    .find_map(|line| line.strip_prefix("content-length:"))
+                    .and_then(|value| value.trim().parse::<usize>().ok())
+                    .unwrap_or(0);
+                break (position + 4, content_length);
+            }
+        };
+
+        // read the rest of the body
+        while buffer.len() < header_end + content_length {
+            let read = stream.read(&mut chunk).unwrap_or(0);
+            if read == 0 {
+                return;
+            }
+            buffer.extend_from_slice(&chunk[..read]);
+        }
+
+        let request: Value =
+            serde_json::from_slice(&buffer[header_end..header_end + content_length]).unwrap();
+        let method = request["method"].as_str().unwrap_or_default().to_string();
+        let params = request["params"].as_array().cloned().unwrap_or_default();
+
+        let (status, body) = handler(&method, &params);
+
+        let _ = write!(
+            stream,
+            "HTTP/1.1 {} Status\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        );
+    }
+}
@@ -1,30 +1,65 @@
+use core::future::Future;
 use core::result::Result::Ok;
 use core::str::FromStr;
 use core::time::Duration;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
+use anyhow::Context;
 use async_trait::async_trait;
+use bitcoin::blockdata::constants::genesis_block;
 use bitcoin::consensus::encode;
 use bitcoin::hashes::Hash;
-use bitcoin::Address;
+use bitcoin::secp256k1::{self, Secp256k1};
+use bitcoin::taproot::TAPROOT_ANNEX_PREFIX;
+use bitcoin::{Address, BlockHash, OutPoint, Script, ScriptBuf, Transaction, TxOut, Txid};
+use futures::stream::{self, StreamExt, TryStreamExt};
 use hex::ToHex;
 use ord::SatPoint;
 use serde::{Deserialize, Serialize};
 use sov_rollup_interface::services::da::DaService;
-use tracing::info;
+use thiserror::Error;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
 
+use crate::authenticator::{BlobAuthenticator, EcdsaAuthenticator};
+use crate::block_cache::BlockCache;
+use crate::blocksdir::{to_bitcoin_block, BlocksDir};
+use crate::change::ChangeDeriver;
+use crate::clock::{Clock, TokioClock};
 use crate::helpers::builders::{
-    create_inscription_transactions, get_satpoint_to_inscribe, sign_blob_with_private_key,
-    write_reveal_tx, compress_blob, decompress_blob,
+    build_abandon_transaction, build_envelope_prefix, compress_blob,
+    create_packed_inscription_transactions, decompress_body, derive_commit_key_pair,
+    ensure_reveal_dir, estimate_inscription_vsizes, get_commit_satpoint, get_satpoint_to_inscribe,
+    read_correlation_record, read_reveal_tx, read_reveal_txs, rebuild_reveal_transaction,
+    remove_reveal_tx, select_utxos, sign_message_with_scheme, write_correlation_record,
+    write_reveal_tx, CommitFeeMode, EnvelopeFields, DEFAULT_CHUNK_SIZE, REVEAL_OUTPUT_AMOUNT,
+};
+use crate::helpers::parsers::{
+    parse_inscriptions, parse_rollup_names, ChunkAssembler, ParseMode, ParsedInscription,
+};
+use crate::helpers::{
+    blob_hash, is_authorized_sequencer, ChunkInfo, Compression, SignatureScheme,
+    MAX_ROLLUP_NAME_LEN, NAMESPACE_ID_LEN,
+};
+use crate::rpc::{
+    BitcoinNode, RPCError, RPCErrorKind, DEFAULT_FALLBACK_FEE_RATE, DEFAULT_RPC_ATTEMPTS,
+    DEFAULT_RPC_RETRY_BACKOFF, MIN_RELAY_FEE_RATE,
 };
-use crate::helpers::parsers::parse_transaction;
-use crate::rpc::{BitcoinNode, RPCError};
 use crate::spec::address::AddressWrapper;
 use crate::spec::blob::BlobWithSender;
 use crate::spec::block::BitcoinBlock;
-use crate::spec::proof::InclusionMultiProof;
+use crate::spec::proof::{ExtractionProof, InclusionMultiProof};
+use crate::spec::transaction::ExtendedTransaction;
 use crate::spec::utxo::UTXO;
-use crate::spec::{BitcoinSpec, RollupParams};
+use crate::spec::{rollup_names_at, AuthorizedRollupName, BitcoinSpec, RollupParams};
+#[cfg(feature = "metrics")]
+use crate::telemetry;
 use crate::verifier::BitcoinVerifier;
+#[cfg(feature = "zmq")]
+use crate::zmq::BlockNotifications;
 
 /// A service that provides data and data availability proofs for Bitcoin
 #[derive(Debug, Clone)]
@@ -32,15 +67,79 @@ pub struct BitcoinService {
     client: BitcoinNode,
     rollup_name: String,
     network: bitcoin::Network,
-    address: String,
+    // checked against the network at construction, none when the service does not send blobs
+    address: Option<Address>,
     sequencer_da_private_key: String,
+    finality_depth: u64,
+    finality_rule: FinalityRule,
+    // delay between two checks of the node while waiting for a block
+    polling_interval: Duration,
+    reveal_retry_policy: RetryPolicy,
+    block_count_retry_policy: RetryPolicy,
+    prefetch_concurrency: usize,
+    checkpoints: HashMap<u64, BlockHash>,
+    blocksdir: Option<BlocksDir>,
+    max_reorg_depth: u64,
+    // hashes of the latest blocks served, by height, used to measure the depth of reorgs
+    tracked_blocks: Arc<Mutex<BTreeMap<u64, BlockHash>>>,
+    // hashes of the last finalized blocks served, by height
+    finalized_hashes: Arc<Mutex<BTreeMap<u64, BlockHash>>>,
+    // finalized blocks served recently, not to fetch them again
+    block_cache: Arc<Mutex<BlockCache>>,
+    authenticator: Arc<dyn BlobAuthenticator>,
+    // sleeps between the polls of the wait loops
+    clock: Arc<dyn Clock>,
+    // directory the reveal txs are written to before being sent
+    reveal_dir: PathBuf,
+    // number of independent inscriptions of every blob sent
+    redundancy: u8,
+    // whether reveal txs stay on disk once sent, for `pending_statuses`
+    keep_sent_reveals: bool,
+    // whether the commit tx must be seen in the mempool before its reveal tx is persisted
+    check_commit_in_mempool: bool,
+    // confirmations of the commit tx to wait for before sending its reveal tx, none when 0
+    commit_confirmations: u32,
+    // derives change addresses in-crate instead of asking the wallet of the node
+    change_deriver: Option<Arc<ChangeDeriver>>,
+    commit_fee_mode: CommitFeeMode,
+    // whether commit txs signal BIP-125 replaceability, which `bump_fee` needs
+    enable_rbf: bool,
+    // whether change below the dust limit goes to the fee, or the commit tx is refused
+    donate_dust_change: bool,
+    // whether reveal txs may carry an annex, which Bitcoin Core does not relay
+    allow_nonstandard_annex: bool,
+    signature_verification: SignatureVerification,
+    parse_mode: ParseMode,
+    // names accepted besides the rollup name, e.g. during a rename
+    authorized_rollup_names: Vec<AuthorizedRollupName>,
+    // minimum time between the start of two sends
+    min_send_interval: Option<Duration>,
+    send_rate_limit: SendRateLimit,
+    // start of the latest send, locked for the whole wait so that concurrent sends queue up
+    last_send: Arc<tokio::sync::Mutex<Option<Instant>>>,
+    duplicate_blob_policy: DuplicateBlobPolicy,
+    // compressed blobs larger than this are inscribed in chunks of this size
+    chunk_size: usize,
+    // public keys the blobs must be signed with, any key when empty
+    authorized_sequencers: Vec<Vec<u8>>,
+    // blobs accepted by hash without any signature, see `trusted_relay_blob_hashes` in the config
+    trusted_relay_blob_hashes: HashSet<[u8; 32]>,
+    // scheme the sequencer signs its blobs with
+    signature_scheme: SignatureScheme,
+    // fee rate in sat/vB used instead of asking the node for an estimation
+    fee_rate_override: Option<f64>,
+    // factors applied to the fee rate for the commit and the reveal txs
+    commit_fee_multiplier: f64,
+    reveal_fee_multiplier: f64,
+    // ZMQ endpoint the node publishes new blocks on, polled when unset
+    zmq_block_endpoint: Option<String>,
 }
 impl BitcoinService {
     pub fn with_client(
         client: BitcoinNode,
         rollup_name: String,
         network: bitcoin::Network,
-        address: String,
+        address: Option<Address>,
         sequencer_da_private_key: String,
     ) -> Self {
         Self {
@@ -49,449 +148,6878 @@ impl BitcoinService {
             network,
             address,
             sequencer_da_private_key,
+            finality_depth: default_finality_depth(network),
+            finality_rule: FinalityRule::BlockDepth(default_finality_depth(network)),
+            polling_interval: Duration::from_secs(POLLING_INTERVAL),
+            reveal_retry_policy: RetryPolicy::default(),
+            block_count_retry_policy: RetryPolicy::default(),
+            prefetch_concurrency: DEFAULT_PREFETCH_CONCURRENCY,
+            checkpoints: HashMap::new(),
+            blocksdir: None,
+            max_reorg_depth: default_finality_depth(network),
+            tracked_blocks: Arc::new(Mutex::new(BTreeMap::new())),
+            finalized_hashes: Arc::new(Mutex::new(BTreeMap::new())),
+            block_cache: Arc::new(Mutex::new(BlockCache::new(DEFAULT_BLOCK_CACHE_CAPACITY))),
+            authenticator: Arc::new(EcdsaAuthenticator),
+            clock: Arc::new(TokioClock),
+            reveal_dir: PathBuf::from(DEFAULT_REVEAL_DIR),
+            redundancy: 1,
+            keep_sent_reveals: false,
+            check_commit_in_mempool: false,
+            commit_confirmations: 0,
+            change_deriver: None,
+            commit_fee_mode: CommitFeeMode::default(),
+            enable_rbf: true,
+            donate_dust_change: true,
+            allow_nonstandard_annex: false,
+            signature_verification: SignatureVerification::default(),
+            parse_mode: ParseMode::default(),
+            authorized_rollup_names: Vec::new(),
+            min_send_interval: None,
+            send_rate_limit: SendRateLimit::default(),
+            last_send: Arc::new(tokio::sync::Mutex::new(None)),
+            duplicate_blob_policy: DuplicateBlobPolicy::default(),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            authorized_sequencers: Vec::new(),
+            trusted_relay_blob_hashes: HashSet::new(),
+            signature_scheme: SignatureScheme::default(),
+            fee_rate_override: None,
+            commit_fee_multiplier: 1.0,
+            reveal_fee_multiplier: 1.0,
+            zmq_block_endpoint: None,
+        }
+    }
+
+    // Replaces the check of the sequencer signature, for rollups using another scheme (BLS, multisig...)
+    pub fn with_authenticator(self, authenticator: impl BlobAuthenticator + 'static) -> Self {
+        Self {
+            authenticator: Arc::new(authenticator),
+            ..self
+        }
+    }
+
+    // Replaces the tokio timer of the wait loops, e.g. by a virtual clock in tests
+    pub fn with_clock(self, clock: impl Clock + 'static) -> Self {
+        Self {
+            clock: Arc::new(clock),
+            ..self
         }
     }
 }
 
-/// Runtime configuration for the DA service
-#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
-pub struct DaServiceConfig {
-    /// The URL of the Bitcoin node to connect to
-    pub node_url: String,
-    pub node_username: String,
-    pub node_password: String,
+// Extracts the blobs relevant to the rollup from the transactions, authenticated by the default signature check.
+// It is the extraction of the DA service decoupled from the block type, for benchmarks and fuzzing.
+pub fn extract_blobs(txs: &[Transaction], rollup_name: &str) -> Vec<BlobWithSender> {
+    let mut chunks = ChunkAssembler::new(&EcdsaAuthenticator);
+    dedup_blobs(txs.iter().flat_map(|tx| {
+        blobs_from_transaction(
+            tx,
+            &[rollup_name],
+            Some(&EcdsaAuthenticator),
+            &[],
+            &HashSet::new(),
+            ParseMode::Strict,
+            &mut chunks,
+        )
+    }))
+}
 
-    // network of the bitcoin node
-    pub network: Option<String>,
+// Builds the inclusion and completeness proofs of the transactions of a block, in block order
+pub fn extraction_proof<'a>(
+    txs: impl IntoIterator<Item = &'a Transaction>,
+) -> (InclusionMultiProof, Vec<Transaction>) {
+    let mut completeness_proof = Vec::new();
 
-    // taproot address that holds the funds of the sequencer
-    pub address: Option<String>,
+    let block_txs = txs
+        .into_iter()
+        .map(|tx| {
+            let tx_hash = tx.txid().to_raw_hash().to_byte_array();
 
-    // da private key of the sequencer
-    pub sequencer_da_private_key: Option<String>,
+            // if tx_hash has two leading zeros, it is in the completeness proof
+            if tx_hash[0..2] == [0, 0] {
+                completeness_proof.push(tx.clone());
+            }
+
+            tx_hash
+        })
+        .collect::<Vec<_>>();
+
+    (InclusionMultiProof { txs: block_txs }, completeness_proof)
 }
 
-const FINALITY_DEPTH: u64 = 4; // blocks
-const POLLING_INTERVAL: u64 = 10; // seconds
+// Same as `extract_blobs` followed by `extraction_proof`, iterating over the transactions once
+pub fn extract_blobs_with_proof(
+    txs: &[Transaction],
+    rollup_name: &str,
+) -> (Vec<BlobWithSender>, InclusionMultiProof, Vec<Transaction>) {
+    extract_blobs_and_proof(
+        txs,
+        &[rollup_name],
+        &EcdsaAuthenticator,
+        &[],
+        &HashSet::new(),
+        ParseMode::Strict,
+    )
+}
 
-impl BitcoinService {
-    // Create a new instance of the DA service from the given configuration.
-    pub fn new(config: DaServiceConfig, chain_params: RollupParams) -> Self {
-        let network =
-            bitcoin::Network::from_str(&config.network.unwrap_or("regtest".to_owned())).unwrap(); // default to regtest (?)
+// Extracts the relevant blobs and builds the extraction proof in a single pass over the transactions
+// with the envelopes parsed in the mode the verifier uses
+fn extract_blobs_and_proof<'a>(
+    txs: impl IntoIterator<Item = &'a Transaction>,
+    rollup_names: &[&str],
+    authenticator: &dyn BlobAuthenticator,
+    authorized_sequencers: &[Vec<u8>],
+    trusted_blob_hashes: &HashSet<[u8; 32]>,
+    mode: ParseMode,
+) -> (Vec<BlobWithSender>, InclusionMultiProof, Vec<Transaction>) {
+    let mut blobs = Vec::new();
+    let mut chunks = ChunkAssembler::new(authenticator);
+    let mut completeness_proof = Vec::new();
 
-        let client = BitcoinNode::new(
-            config.node_url,
-            config.node_username,
-            config.node_password,
-            network,
-        );
+    let block_txs = txs
+        .into_iter()
+        .map(|tx| {
+            blobs.extend(blobs_from_transaction(
+                tx,
+                rollup_names,
+                Some(authenticator),
+                authorized_sequencers,
+                trusted_blob_hashes,
+                mode,
+                &mut chunks,
+            ));
 
-        Self::with_client(
-            client,
-            chain_params.rollup_name,
-            network,
-            config.address.unwrap_or("".to_owned()),
-            config.sequencer_da_private_key.unwrap_or("".to_owned()),
-        )
-    }
+            let tx_hash = tx.txid().to_raw_hash().to_byte_array();
+            if tx_hash[0..2] == [0, 0] {
+                completeness_proof.push(tx.clone());
+            }
+
+            tx_hash
+        })
+        .collect::<Vec<_>>();
+
+    (
+        dedup_blobs(blobs),
+        InclusionMultiProof { txs: block_txs },
+        completeness_proof,
+    )
 }
 
-#[async_trait]
-impl DaService for BitcoinService {
-    type Spec = BitcoinSpec;
+// Collapses the copies of a blob inscribed more than once for redundancy, keeping the first one.
+// Only copies of the same sender collapse: a body re-signed by another key mined first must not
+// displace the blob of its sequencer.
+fn dedup_blobs(blobs: impl IntoIterator<Item = BlobWithSender>) -> Vec<BlobWithSender> {
+    let mut seen_blobs = HashSet::new();
+    blobs
+        .into_iter()
+        .filter(|blob| seen_blobs.insert((blob.sender.0.clone(), blob.hash)))
+        .collect()
+}
 
-    type Verifier = BitcoinVerifier;
+// Returns the hashes of the blobs found more than once, in the order of their first copy
+fn duplicate_hashes(blobs: &[BlobWithSender]) -> Vec<[u8; 32]> {
+    let mut counts = HashMap::new();
+    for blob in blobs {
+        let key = (blob.sender.0.clone(), blob.hash);
+        *counts.entry(key).or_insert(0) += 1;
+    }
 
-    type FilteredBlock = BitcoinBlock;
+    dedup_blobs(blobs.iter().cloned())
+        .into_iter()
+        .filter(|blob| counts[&(blob.sender.0.clone(), blob.hash)] > 1)
+        .map(|blob| blob.hash)
+        .collect()
+}
 
-    type Error = anyhow::Error;
+// Returns the blobs inscribed in the transaction that are relevant to the rollup and authenticated,
+// in script order. Most transactions carry a single blob, `send_transactions` packs several.
+// The inscriptions may be under any of the rollup names, the first one is usually the only one.
+// Without authenticator the blobs are returned unauthenticated, see `SignatureVerification::Lazy`.
+// Chunks are kept in `chunks` until the last one of their blob, the blob is returned with it.
+fn blobs_from_transaction(
+    tx: &Transaction,
+    rollup_names: &[&str],
+    authenticator: Option<&dyn BlobAuthenticator>,
+    authorized_sequencers: &[Vec<u8>],
+    trusted_blob_hashes: &HashSet<[u8; 32]>,
+    mode: ParseMode,
+    chunks: &mut ChunkAssembler<'_>,
+) -> Vec<BlobWithSender> {
+    // check if the inscriptions in script are relevant to the rollup
+    let inscriptions = rollup_names
+        .iter()
+        .find_map(|rollup_name| parse_inscriptions(tx, rollup_name, mode).ok())
+        .unwrap_or_default();
 
-    // Make an RPC call to the node to get the finalized block at the given height, if one exists.
-    // If no such block exists, block until one does.
-    async fn get_finalized_at(&self, height: u64) -> Result<Self::FilteredBlock, Self::Error> {
-        let client = self.client.clone();
-        let rollup_name = self.rollup_name.clone();
-        info!("Getting finalized block at height {}", height);
-        loop {
-            let block_count = client.get_block_count().await?;
+    inscriptions
+        .into_iter()
+        .filter_map(|inscription| chunks.push(inscription))
+        .filter_map(|inscription| {
+            blob_from_inscription(
+                tx,
+                inscription,
+                authenticator,
+                authorized_sequencers,
+                trusted_blob_hashes,
+            )
+        })
+        .collect()
+}
 
-            // if at least `FINALITY_DEPTH` blocks are mined, we can be sure that the block is finalized
-            if block_count >= height + FINALITY_DEPTH {
-                break;
+// Returns the blob of an inscription of the transaction if it is authenticated and decompresses.
+// Blobs whose hash is trusted are not authenticated at all.
+fn blob_from_inscription(
+    tx: &Transaction,
+    inscription: ParsedInscription,
+    authenticator: Option<&dyn BlobAuthenticator>,
+    authorized_sequencers: &[Vec<u8>],
+    trusted_blob_hashes: &HashSet<[u8; 32]>,
+) -> Option<BlobWithSender> {
+    if !trusted_blob_hashes.contains(&blob_hash(&inscription.body)) {
+        // drop the blobs not signed by the sequencer
+        if let Some(authenticator) = authenticator {
+            if !authenticator.authenticate(
+                &inscription.signed_message(),
+                &inscription.signature,
+                &inscription.public_key,
+            ) {
+                return None;
             }
+        }
 
-            info!("Block not finalized, waiting");
-            tokio::time::sleep(Duration::from_secs(POLLING_INTERVAL)).await;
+        // a valid signature only proves the embedded key signed, it must also be a sequencer key
+        if !is_authorized_sequencer(authorized_sequencers, &inscription.public_key) {
+            return None;
         }
+    }
 
-        let block_hash = client.get_block_hash(height).await?;
-        let block: BitcoinBlock = client.get_block(block_hash, &rollup_name).await?;
+    // Decompress the blob, skipping corrupt bodies rather than failing the whole block
+    let decompressed_blob = match decompress_body(&inscription.body, inscription.compression) {
+        Ok(decompressed_blob) => decompressed_blob,
+        Err(error) => {
+            warn!(
+                "Skipping the blob {} of tx {}: {}",
+                hex::encode(blob_hash(&inscription.body)),
+                tx.txid(),
+                error
+            );
+            return None;
+        }
+    };
 
-        Ok(block)
-    }
+    Some(BlobWithSender {
+        namespace_id: inscription.namespace_id,
+        compression: inscription.compression,
+        label: inscription.label,
+        metadata: inscription.metadata,
+        // trusted blobs may come without signature
+        signature: (!inscription.signature.is_empty()).then_some(inscription.signature),
+        chunk: inscription.chunk,
+        signed_hash: inscription.signed_hash,
+        ..BlobWithSender::new(
+            decompressed_blob,
+            Some(inscription.public_key),
+            Some(blob_hash(&inscription.body)),
+        )
+    })
+}
 
-    // Make an RPC call to the node to get the block at the given height
-    // If no such block exists, block until one does.
-    async fn get_block_at(&self, height: u64) -> Result<Self::FilteredBlock, Self::Error> {
-        let client = self.client.clone();
-        let rollup_name = self.rollup_name.clone();
-        info!("Getting block at height {}", height);
+/// When extraction checks the sequencer signature of the blobs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SignatureVerification {
+    /// Blobs whose signature is rejected by the authenticator are dropped
+    #[default]
+    Eager,
+    /// Every inscribed blob is returned, anyone can inscribe one under the rollup name with any sender.
+    /// Only for consumers not needing authenticity (e.g. archivers): they must call
+    /// `BlobWithSender::verify_signature` with the authenticator of the service before trusting a
+    /// blob. Extraction with proof stays eager, as the verifier rejects unauthenticated blobs.
+    /// The chunks of the blobs inscribed in chunks are still authenticated before reassembly.
+    Lazy,
+}
 
-        let block_hash;
-        loop {
-            block_hash = match client.get_block_hash(height).await {
-                Ok(block_hash_response) => block_hash_response,
-                Err(error) => {
-                    match error.downcast_ref::<RPCError>() {
-                        Some(error) => {
-                            if error.code == -8 {
-                                info!("Block not found, waiting");
-                                tokio::time::sleep(Duration::from_secs(POLLING_INTERVAL)).await;
-                                continue;
-                            } else {
-                                // other error, return message
-                                return Err(anyhow::anyhow!(error.message.clone()));
-                            }
-                        }
-                        None => {
-                            return Err(anyhow::anyhow!(error));
-                        }
-                    }
-                }
-            };
+/// What a send does when it comes sooner than the minimum send interval after the previous one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SendRateLimit {
+    /// The send waits until the interval has elapsed
+    #[default]
+    Wait,
+    /// The send fails with `BitcoinServiceError::SendTooSoon`
+    Reject,
+}
 
-            break;
-        }
-        let block = client.get_block(block_hash, &rollup_name).await?;
+/// What `extract_relevant_txs_checked` does with blobs found more than once in a block
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DuplicateBlobPolicy {
+    /// Copies are collapsed silently, as `extract_relevant_txs` does
+    #[default]
+    Permit,
+    /// Copies are collapsed and their hashes reported in the `DuplicateBlobs` diagnostic
+    Flag,
+    /// Extraction fails with `BitcoinServiceError::DuplicateBlobs`.
+    /// Incompatible with a redundancy above 1, which inscribes every blob more than once.
+    Reject,
+}
 
-        Ok(block)
+/// Blobs of a block along with what was scanned, so that a block without relevant transactions
+/// can be told apart from a block not processed yet
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScannedBlock {
+    pub height: u64,
+    pub hash: BlockHash,
+    /// Number of transactions scanned, all the transactions of the block
+    pub tx_count: usize,
+    pub blobs: Vec<BlobWithSender>,
+}
+
+impl ScannedBlock {
+    /// Whether the block was scanned and holds no blob for the rollup
+    pub fn is_empty(&self) -> bool {
+        self.blobs.is_empty()
     }
+}
 
-    // Extract the blob transactions relevant to a particular rollup from a block.
-    fn extract_relevant_txs(
-        &self,
-        block: &Self::FilteredBlock,
-    ) -> Vec<<Self::Spec as sov_rollup_interface::da::DaSpec>::BlobTransaction> {
-        let mut txs = Vec::new();
+/// Blobs found more than once in a block, which may be a replay or a bug of the sequencer
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DuplicateBlobs {
+    /// Hashes of the duplicated blobs, in block order
+    pub hashes: Vec<[u8; 32]>,
+}
 
-        info!(
-            "Extracting relevant txs from block {:?}",
-            block.header.header.block_hash()
-        );
+impl DuplicateBlobs {
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+}
 
-        // iterate over all transactions in the block
-        for tx in block.txdata.iter() {
-            // check if the inscription in script is relevant to the rollup
-            let parsed_inscription = parse_transaction(&tx.transaction, &self.rollup_name);
+/// When `get_finalized_at` considers a block final
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FinalityRule {
+    /// Once the tip is this many blocks above it
+    BlockDepth(u64),
+    /// Once the median time past of the tip is this far ahead of the one of the block
+    TimeElapsed(Duration),
+}
 
-            if let Ok(inscription) = parsed_inscription {
-                let blob = inscription.body;
+/// Confirmation status of a reveal tx
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfStatus {
+    Mempool,
+    Confirmed(u64),
+    // neither in the mempool nor in the chain, it must be sent again
+    Dropped,
+}
 
-                // Decompress the blob
-                let decompressed_blob = decompress_blob(&blob);
+/// Transactions of a blob sent to the network
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubmittedInscription {
+    pub commit_txid: Txid,
+    pub reveal_txid: Txid,
+}
 
-                let relevant_tx = BlobWithSender::new(
-                    decompressed_blob,
-                    tx.sender.clone(),
-                    tx.blob_hash,
-                );
+// Outputs the copies of a send must not be funded with, so that they do not depend on each other
+#[derive(Debug, Default)]
+struct UsedOutputs {
+    // utxos spent by the commit txs already sent
+    spent: HashSet<OutPoint>,
+    // txs already sent, their unconfirmed outputs, e.g. the change, would chain the copies
+    sent_txids: HashSet<Txid>,
+}
 
-                txs.push(relevant_tx);
-            }
-        }
-        txs
+impl UsedOutputs {
+    fn contains(&self, utxo: &UTXO) -> bool {
+        self.sent_txids.contains(&utxo.tx_id)
+            || self.spent.contains(&OutPoint::new(utxo.tx_id, utxo.vout))
     }
 
-    async fn get_extraction_proof(
-        &self,
-        block: &Self::FilteredBlock,
-        _blobs: &[<Self::Spec as sov_rollup_interface::da::DaSpec>::BlobTransaction],
-    ) -> (
-        <Self::Spec as sov_rollup_interface::da::DaSpec>::InclusionMultiProof,
-        <Self::Spec as sov_rollup_interface::da::DaSpec>::CompletenessProof,
-    ) {
-        info!(
-            "Getting extraction proof for block {:?}",
-            block.header.header.block_hash()
-        );
+    fn record(&mut self, commit_tx: &Transaction, submitted: &SubmittedInscription) {
+        let spent = commit_tx.input.iter().map(|input| input.previous_output);
+        self.spent.extend(spent);
+        self.sent_txids
+            .extend([submitted.commit_txid, submitted.reveal_txid]);
+    }
+}
 
-        let mut completeness_proof = Vec::with_capacity(block.txdata.len());
+/// What external tooling, e.g. BIP158 block filters or wallet scan descriptors, can match to find
+/// the inscriptions of the rollup. The blobs live in the witness of the reveal txs, which filters
+/// do not cover. Commit outputs pay to a key derived from each blob, only their shape is known.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterHints {
+    /// Output script of the sequencer address, paid by every reveal tx
+    pub reveal_output_script: ScriptBuf,
+    /// Reveal tapscript after the 32 byte commit key: the envelope opening and the rollup name
+    pub envelope_prefix: ScriptBuf,
+}
 
-        let block_txs = block
-            .txdata
-            .iter()
-            .map(|tx| {
-                let tx_hash = tx.transaction.txid().to_raw_hash().to_byte_array();
+impl FilterHints {
+    /// Whether the output has the shape of a commit output, a taproot output (witness v1, 32 bytes)
+    pub fn matches_commit_output(&self, output: &TxOut) -> bool {
+        output.script_pubkey.is_v1_p2tr()
+    }
 
-                // if tx_hash has two leading zeros, it is in the completeness proof
-                if tx_hash[0..2] == [0, 0] {
-                    completeness_proof.push(tx.transaction.clone());
-                }
+    /// Whether the tapscript of a reveal input carries an envelope of the rollup
+    pub fn matches_reveal_script(&self, tapscript: &Script) -> bool {
+        // the commit key push: OP_PUSHBYTES_32 and the key
+        tapscript.as_bytes().get(33..).map_or(false, |rest| {
+            rest.starts_with(self.envelope_prefix.as_bytes())
+        })
+    }
+}
 
-                tx_hash
+/// What `preflight` checks before the service is put to use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreflightCheck {
+    /// The node answers RPC calls
+    Connection,
+    /// The node is on the configured network
+    Network,
+    /// The node has a loaded wallet
+    Wallet,
+    /// The wallet holds spendable utxos
+    Balance,
+    /// The sequencer private key is valid
+    SequencerKey,
+    /// The sequencer address is valid on the configured network
+    Address,
+}
+
+/// Outcome of `preflight`: every check, with the error it failed with if any
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreflightReport {
+    pub results: Vec<(PreflightCheck, Result<(), String>)>,
+    /// Value of the spendable utxos of the wallet in sat, when they could be listed
+    pub spendable_balance: Option<u64>,
+}
+
+impl PreflightReport {
+    /// Whether every check passed
+    pub fn is_ok(&self) -> bool {
+        self.results.iter().all(|(_, result)| result.is_ok())
+    }
+
+    /// The checks that failed, with their error
+    pub fn failures(&self) -> Vec<(PreflightCheck, &str)> {
+        self.results
+            .iter()
+            .filter_map(|(check, result)| match result {
+                Ok(()) => None,
+                Err(error) => Some((*check, error.as_str())),
             })
-            .collect::<Vec<_>>();
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum BitcoinServiceError {
+    #[error("reorg of depth {depth} from height {fork_height} exceeds the maximum of {max_depth}, operator intervention required")]
+    DeepReorg {
+        fork_height: u64,
+        depth: u64,
+        max_depth: u64,
+    },
+    #[error("block {new_hash} at height {height} replaces the finalized block {old_hash}")]
+    ReorgDetected {
+        height: u64,
+        old_hash: BlockHash,
+        new_hash: BlockHash,
+    },
+    #[error("the wallet has no spendable utxos, fund the sequencer address")]
+    NoSpendableUtxos,
+    #[error("invalid rollup name {name:?}: {reason}")]
+    InvalidRollupName { name: String, reason: String },
+    #[error("send attempted {remaining_ms} ms before the end of the minimum send interval")]
+    SendTooSoon { remaining_ms: u64 },
+    #[error("{} blobs are found more than once in the block", .0.hashes.len())]
+    DuplicateBlobs(DuplicateBlobs),
+    #[error("cannot inscribe on satpoint {satpoint}: {reason}")]
+    InvalidSatpoint { satpoint: SatPoint, reason: String },
+    #[error("reveal txs with an annex are non-standard and not relayed, set allow_nonstandard_annex to send them to a miner accepting them")]
+    NonStandardAnnex,
+}
+
+/// How a failing call to the node is retried, e.g. the reveal broadcast after its commit tx was sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Number of attempts before giving up
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled after every failed attempt
+    pub backoff: Duration,
+}
 
-        let inclusion_proof = InclusionMultiProof { txs: block_txs };
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            backoff: Duration::from_secs(1),
+        }
+    }
+}
 
-        (inclusion_proof, completeness_proof)
+impl RetryPolicy {
+    // Builds the policy from the optional configuration values, missing ones are defaulted
+    fn from_config(max_attempts: Option<u32>, backoff_ms: Option<u64>) -> Self {
+        let default_policy = Self::default();
+        Self {
+            max_attempts: max_attempts.unwrap_or(default_policy.max_attempts).max(1),
+            backoff: backoff_ms
+                .map(Duration::from_millis)
+                .unwrap_or(default_policy.backoff),
+        }
     }
 
-    // Extract the list blob transactions relevant to a particular rollup from a block, along with inclusion and
-    // completeness proofs for that set of transactions. The output of this method will be passed to the verifier.
-    async fn extract_relevant_txs_with_proof(
+    // Runs the operation until it succeeds, retrying with exponential backoff.
+    // Once the attempts are exhausted the last error is returned.
+    async fn retry<T, F, Fut>(
         &self,
-        block: &Self::FilteredBlock,
-    ) -> (
-        Vec<<Self::Spec as sov_rollup_interface::da::DaSpec>::BlobTransaction>,
-        <Self::Spec as sov_rollup_interface::da::DaSpec>::InclusionMultiProof,
-        <Self::Spec as sov_rollup_interface::da::DaSpec>::CompletenessProof,
-    ) {
-        info!(
-            "Extracting relevant txs with proof from block {:?}",
-            block.header.header.block_hash()
-        );
+        clock: &dyn Clock,
+        operation: &str,
+        mut f: F,
+    ) -> Result<T, anyhow::Error>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, anyhow::Error>>,
+    {
+        let mut backoff = self.backoff;
+        let mut attempt = 1;
+        loop {
+            match f().await {
+                Ok(value) => return Ok(value),
+                Err(error) if attempt < self.max_attempts => {
+                    warn!(
+                        "Failed to {} (attempt {}/{}): {}, retrying in {:?}",
+                        operation, attempt, self.max_attempts, error, backoff
+                    );
+                    clock.sleep(backoff).await;
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+}
 
-        let txs = self.extract_relevant_txs(block);
-        let (inclusion_proof, completeness_proof) =
-            self.get_extraction_proof(block, txs.as_slice()).await;
+impl BitcoinService {
+    // Returns the names the blobs of the block at the height may be inscribed under
+    fn rollup_names_at(&self, height: u64) -> Vec<&str> {
+        rollup_names_at(&self.rollup_name, &self.authorized_rollup_names, height)
+    }
+
+    // Returns the blobs inscribed in the transactions that are relevant and authentic,
+    // with the index of their transaction, that of the last chunk for the blobs inscribed in chunks
+    fn blobs_from_txs(
+        &self,
+        txs: &[ExtendedTransaction],
+        rollup_names: &[&str],
+    ) -> Vec<(usize, BlobWithSender)> {
+        let authenticator = match self.signature_verification {
+            SignatureVerification::Eager => Some(self.authenticator.as_ref()),
+            SignatureVerification::Lazy => None,
+        };
 
-        (txs, inclusion_proof, completeness_proof)
+        // chunks are authenticated whatever the mode, so that nobody else can drop them
+        let mut chunks = ChunkAssembler::new(self.authenticator.as_ref());
+        txs.iter()
+            .enumerate()
+            .flat_map(|(index, tx)| {
+                blobs_from_transaction(
+                    &tx.transaction,
+                    rollup_names,
+                    authenticator,
+                    &self.authorized_sequencers,
+                    &self.trusted_relay_blob_hashes,
+                    self.parse_mode,
+                    &mut chunks,
+                )
+                .into_iter()
+                .map(move |blob| (index, blob))
+            })
+            .collect()
     }
 
-    async fn send_transaction(&self, blob: &[u8]) -> Result<(), Self::Error> {
-        let client = self.client.clone();
+    // Same as `blobs_from_txs`, without the indices
+    fn blobs_from_block(&self, block: &BitcoinBlock) -> Vec<BlobWithSender> {
+        let rollup_names = self.rollup_names_at(block.header.height);
 
-        let blob = blob.to_vec();
-        let network = self.network;
-        let address = self.address.clone();
-        let rollup_name = self.rollup_name.clone();
-        let sequencer_da_private_key = self.sequencer_da_private_key.clone();
+        self.blobs_from_txs(&block.txdata, &rollup_names)
+            .into_iter()
+            .map(|(_, blob)| blob)
+            .collect()
+    }
 
-        // Compress the blob
-        let blob = compress_blob(&blob);
+    // Extract the blob transactions relevant to a particular rollup from a block, keeping only
+    // the ones inscribed under the given namespace id
+    pub fn extract_relevant_txs_in_namespace(
+        &self,
+        block: &BitcoinBlock,
+        namespace_id: [u8; NAMESPACE_ID_LEN],
+    ) -> Vec<BlobWithSender> {
+        dedup_blobs(
+            self.blobs_from_block(block)
+                .into_iter()
+                .filter(|blob| blob.namespace_id == Some(namespace_id)),
+        )
+    }
 
-        // get two change addresses that are necessary for the inscribe transaction
-        let change_addresses: [Address; 2] = client.get_change_addresses().await?;
+    // Fetches the block with the given hash, the one at the height, and makes sure the node
+    // returned the requested block. Returns `None` on mismatch, which happens when the chain
+    // changes while the block is being fetched.
+    async fn get_block_matching_hash(
+        &self,
+        height: u64,
+        block_hash: String,
+    ) -> Result<Option<BitcoinBlock>, anyhow::Error> {
+        let cached = BlockHash::from_str(&block_hash)
+            .ok()
+            .and_then(|hash| self.block_cache.lock().unwrap().get(&hash));
+        let block = match cached {
+            Some(block) => block,
+            None => {
+                self.client
+                    .get_block(block_hash.clone(), &self.rollup_name)
+                    .await?
+            }
+        };
 
-        // get all available utxos
-        let utxos: Vec<UTXO> = client.get_utxos().await?;
+        let fetched_hash = block.header.header.block_hash().to_string();
+        if fetched_hash != block_hash {
+            warn!(
+                "Requested block {} but got block {}, retrying",
+                block_hash, fetched_hash
+            );
+            return Ok(None);
+        }
 
-        let satpoint: SatPoint = get_satpoint_to_inscribe(&utxos[0]);
+        self.check_checkpoint(height, &block)?;
+        self.track_reorg(&block).await?;
 
-        // return funds to sequencer address
-        let destination_address = Address::from_str(&address.clone())?.require_network(network)?;
+        Ok(Some(block))
+    }
 
-        // sign the blob for authentication of the sequencer
-        let (signature, public_key) = sign_blob_with_private_key(&blob, &sequencer_da_private_key)
-            .expect("Sequencer sign the blob");
+    // Makes sure the block matches the pinned hash if the requested height is a checkpoint.
+    // This guards against a compromised node serving a fake chain, so the height the node reports
+    // for the block is not trusted.
+    fn check_checkpoint(&self, height: u64, block: &BitcoinBlock) -> Result<(), anyhow::Error> {
+        match self.checkpoints.get(&height) {
+            Some(checkpoint) if *checkpoint != block.header.header.block_hash() => {
+                Err(anyhow::anyhow!(
+                    "Block {} at height {} does not match checkpoint {}",
+                    block.header.header.block_hash(),
+                    height,
+                    checkpoint
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
 
-        // get fee rate from node
-        let fee_sat_per_vbyte: f64 = client.estimate_smart_fee().await?;
+    // Records the block and, if it does not extend the blocks served so far, measures the depth of the reorg.
+    // A reorg deeper than `max_reorg_depth` is fatal: the rollup must not silently re-extract past it.
+    async fn track_reorg(&self, block: &BitcoinBlock) -> Result<(), anyhow::Error> {
+        let height = block.header.height;
+        let block_hash = block.header.header.block_hash();
 
-        // create inscribe transactions
-        let (unsigned_commit_tx, reveal_tx) = create_inscription_transactions(
-            &rollup_name,
-            blob,
-            signature,
-            public_key,
-            satpoint,
-            utxos,
-            change_addresses,
-            destination_address,
-            fee_sat_per_vbyte,
-            fee_sat_per_vbyte,
-            network,
-        )?;
+        let tracked_blocks = self.tracked_blocks.lock().unwrap().clone();
+        let replaced = tracked_blocks
+            .get(&height)
+            .map_or(false, |tracked_hash| *tracked_hash != block_hash);
+        let disconnected = height
+            .checked_sub(1)
+            .and_then(|parent_height| tracked_blocks.get(&parent_height))
+            .map_or(false, |parent_hash| {
+                *parent_hash != block.header.header.prev_blockhash
+            });
 
-        // sign inscribe transactions
-        let serialized_unsigned_commit_tx = &encode::serialize(&unsigned_commit_tx);
-        let signed_raw_commit_tx = client
-            .sign_raw_transaction_with_wallet(serialized_unsigned_commit_tx.encode_hex())
-            .await?;
+        if !replaced && !disconnected {
+            self.record_block(height, block_hash);
+            return Ok(());
+        }
 
-        // send inscribe transactions
-        client.send_raw_transaction(signed_raw_commit_tx).await?;
+        // the fork point is the lowest tracked block that is no longer in the chain of the node
+        let mut fork_height = None;
+        let mut current_blocks = BTreeMap::new();
+        for (tracked_height, tracked_hash) in tracked_blocks.range(..height) {
+            let current_hash =
+                BlockHash::from_str(&self.client.get_block_hash(*tracked_height).await?)?;
+            if current_hash != *tracked_hash && fork_height.is_none() {
+                fork_height = Some(*tracked_height);
+            }
+            current_blocks.insert(*tracked_height, current_hash);
+        }
+        // the block replaces the one served at its height, or the node still agrees with every tracked block
+        let fork_height = match fork_height.or(replaced.then_some(height)) {
+            Some(fork_height) => fork_height,
+            None => {
+                self.record_block(height, block_hash);
+                return Ok(());
+            }
+        };
 
-        // serialize reveal tx
-        let serialized_reveal_tx = &encode::serialize(&reveal_tx);
+        let tip_height = tracked_blocks.keys().next_back().copied().unwrap_or(height);
+        let depth = (tip_height + 1).saturating_sub(fork_height);
+        if depth > self.max_reorg_depth {
+            return Err(BitcoinServiceError::DeepReorg {
+                fork_height,
+                depth,
+                max_depth: self.max_reorg_depth,
+            }
+            .into());
+        }
 
-        // write reveal tx to file, it can be used to continue revealing blob if something goes wrong
-        write_reveal_tx(
-            serialized_reveal_tx,
-            unsigned_commit_tx.txid().to_raw_hash().to_string(),
+        warn!(
+            "Reorg of depth {} detected from height {}",
+            depth, fork_height
         );
 
-        // send reveal tx
-        let reveal_tx_hash = client
-            .send_raw_transaction(serialized_reveal_tx.encode_hex())
-            .await?;
-
-        info!("Blob inscribe tx sent. Hash: {}", reveal_tx_hash);
+        // continue tracking the new chain
+        *self.tracked_blocks.lock().unwrap() = current_blocks;
+        self.record_block(height, block_hash);
 
         Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::collections::HashSet;
+    // Remembers the hash of the finalized block served at its height. Another block served there
+    // later means that the chain reorganized past the finality depth, the rollup must react to it.
+    fn check_finalized_hash(&self, block: &BitcoinBlock) -> Result<(), BitcoinServiceError> {
+        let height = block.header.height;
+        let block_hash = block.header.header.block_hash();
 
-    use bitcoin::hashes::Hash;
-    use bitcoin::{merkle_tree, Txid};
-    use sov_rollup_interface::services::da::DaService;
+        let mut finalized_hashes = self.finalized_hashes.lock().unwrap();
+        match finalized_hashes.get(&height) {
+            Some(old_hash) if *old_hash != block_hash => {
+                return Err(BitcoinServiceError::ReorgDetected {
+                    height,
+                    old_hash: *old_hash,
+                    new_hash: block_hash,
+                })
+            }
+            Some(_) => return Ok(()),
+            None => {}
+        }
 
-    use super::BitcoinService;
-    use crate::helpers::parsers::parse_transaction;
-    use crate::service::DaServiceConfig;
-    use crate::spec::RollupParams;
+        // the lowest heights are the least likely to be asked for again
+        finalized_hashes.insert(height, block_hash);
+        while finalized_hashes.len() > MAX_FINALIZED_HASHES {
+            finalized_hashes.pop_first();
+        }
 
-    async fn get_service() -> BitcoinService {
-        let runtime_config = DaServiceConfig {
-            node_url: "http://localhost:38332".to_string(),
-            node_username: "chainway".to_string(),
-            node_password: "topsecret".to_string(),
-            network: Some("regtest".to_string()),
-            address: Some("bcrt1qxuds94z3pqwqea2p4f4ev4f25s6uu7y3avljrl".to_string()),
+        Ok(())
+    }
+
+    // Caches the block once it is final, a block that may still be reorganized away is not cached.
+    // The block is simply not cached when its finality cannot be checked.
+    async fn cache_if_finalized(&self, block: &BitcoinBlock) {
+        let finalized = match self.client.get_block_count().await {
+            Ok(block_count) => self.is_finalized(block.header.height, block_count).await,
+            Err(error) => Err(error),
+        };
+        if let Ok(true) = finalized {
+            self.block_cache.lock().unwrap().insert(block.clone());
+        }
+    }
+
+    // Empties the cache of finalized blocks, e.g. to count the fetches from the node in tests
+    pub fn clear_cache(&self) {
+        self.block_cache.lock().unwrap().clear();
+    }
+
+    // Records the block, keeping only the blocks that a reorg within the maximum depth can replace.
+    // A reorg reaching below them replaces all of them, which is deeper than the maximum anyway.
+    fn record_block(&self, height: u64, block_hash: BlockHash) {
+        let mut tracked_blocks = self.tracked_blocks.lock().unwrap();
+        tracked_blocks.insert(height, block_hash);
+
+        let tip_height = *tracked_blocks.keys().next_back().unwrap();
+        *tracked_blocks =
+            tracked_blocks.split_off(&tip_height.saturating_sub(self.max_reorg_depth));
+    }
+
+    // Returns what external tooling can match to find the inscriptions of the rollup
+    pub fn filter_hints(&self) -> Result<FilterHints, anyhow::Error> {
+        Ok(FilterHints {
+            reveal_output_script: self.sequencer_address()?.script_pubkey(),
+            envelope_prefix: build_envelope_prefix(&self.rollup_name),
+        })
+    }
+
+    // Returns the public key of the sequencer, as embedded in its inscriptions: x-only for Schnorr
+    // signatures, compressed for ECDSA ones.
+    // It is what verifiers need to be configured with to authenticate the sequencer.
+    pub fn sequencer_public_key(&self) -> Result<Vec<u8>, anyhow::Error> {
+        let secret_key = secp256k1::SecretKey::from_str(&self.sequencer_da_private_key)?;
+        let public_key = secp256k1::PublicKey::from_secret_key(&Secp256k1::new(), &secret_key);
+
+        Ok(match self.signature_scheme {
+            SignatureScheme::Ecdsa => public_key.serialize().to_vec(),
+            SignatureScheme::Schnorr => public_key.x_only_public_key().0.serialize().to_vec(),
+        })
+    }
+
+    // Returns the sequencer address, the reveal txs and recovered funds pay to
+    fn sequencer_address(&self) -> Result<&Address, anyhow::Error> {
+        self.address
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("No sequencer address is configured"))
+    }
+
+    // Checks that the service is usable with its node: reachable and on the configured network,
+    // with a loaded and funded wallet, a valid sequencer key and address. All the checks run even
+    // when one fails, so that operators see every problem at once.
+    pub async fn preflight(&self) -> Result<PreflightReport, anyhow::Error> {
+        let to_string = |error: anyhow::Error| error.to_string();
+        let mut results = Vec::new();
+
+        let block_count = self.client.get_block_count().await;
+        results.push((
+            PreflightCheck::Connection,
+            block_count.map(|_| ()).map_err(to_string),
+        ));
+
+        let genesis_hash = genesis_block(self.network).block_hash().to_string();
+        let network = match self.client.get_block_hash(0).await {
+            Ok(hash) if hash == genesis_hash => Ok(()),
+            Ok(hash) => Err(format!(
+                "the node is not on {}, its genesis block is {}",
+                self.network, hash
+            )),
+            Err(error) => Err(error.to_string()),
+        };
+        results.push((PreflightCheck::Network, network));
+
+        let utxos = self.client.get_utxos().await;
+        let spendable_balance = utxos.as_ref().ok().map(|utxos| {
+            utxos
+                .iter()
+                .filter(|utxo| utxo.spendable)
+                .map(|utxo| utxo.amount)
+                .sum::<u64>()
+        });
+        results.push((PreflightCheck::Wallet, utxos.map(|_| ()).map_err(to_string)));
+        let balance = match spendable_balance {
+            Some(0) => Err(BitcoinServiceError::NoSpendableUtxos.to_string()),
+            Some(_) => Ok(()),
+            None => Err("the utxos of the wallet cannot be listed".to_string()),
+        };
+        results.push((PreflightCheck::Balance, balance));
+
+        let sequencer_key = self.sequencer_public_key().map(|_| ());
+        results.push((
+            PreflightCheck::SequencerKey,
+            sequencer_key.map_err(to_string),
+        ));
+
+        let address = self.sequencer_address().map(|_| ());
+        results.push((PreflightCheck::Address, address.map_err(to_string)));
+
+        Ok(PreflightReport {
+            results,
+            spendable_balance,
+        })
+    }
+
+    // Fee rate in sat/vB of the txs sent, the configured override or the estimation of the node
+    async fn fee_rate(&self) -> Result<f64, anyhow::Error> {
+        match self.fee_rate_override {
+            Some(fee_rate) => Ok(fee_rate),
+            None => self.client.estimate_smart_fee().await,
+        }
+    }
+
+    // Signs a compressed blob inscribed with the fields with the sequencer key, returning the
+    // signature and the public key
+    fn sign_blob(
+        &self,
+        blob: &[u8],
+        fields: &EnvelopeFields,
+    ) -> Result<(Vec<u8>, Vec<u8>), anyhow::Error> {
+        let message = fields.signed_message(blob);
+        sign_message_with_scheme(
+            &message,
+            &self.sequencer_da_private_key,
+            self.signature_scheme,
+        )
+        .map_err(|_| anyhow::anyhow!("Failed to sign the blob"))
+    }
+
+    // Extract the blob transactions relevant to a particular rollup from a block, tagged with the index
+    // of their transaction in the block. The blobs are returned in block order.
+    pub fn extract_relevant_txs_with_indices(
+        &self,
+        block: &BitcoinBlock,
+    ) -> Vec<(usize, BlobWithSender)> {
+        let rollup_names = self.rollup_names_at(block.header.height);
+
+        self.blobs_from_txs(&block.txdata, &rollup_names)
+    }
+
+    // Same as `extract_relevant_txs`, telling how many transactions of which block were scanned.
+    // Lets rollups advance their watermark past blocks confirmed to hold no blob.
+    pub fn extract_relevant_txs_scanned(&self, block: &BitcoinBlock) -> ScannedBlock {
+        ScannedBlock {
+            height: block.header.height,
+            hash: block.header.header.block_hash(),
+            tx_count: block.txdata.len(),
+            blobs: self.extract_relevant_txs(block),
+        }
+    }
+
+    // Same as `extract_relevant_txs`, applying the duplicate blob policy to the blobs found more than once.
+    // The diagnostic is empty unless the policy flags duplicates.
+    pub fn extract_relevant_txs_checked(
+        &self,
+        block: &BitcoinBlock,
+    ) -> Result<(Vec<BlobWithSender>, DuplicateBlobs), BitcoinServiceError> {
+        let blobs = self.blobs_from_block(block);
+
+        let duplicates = match self.duplicate_blob_policy {
+            DuplicateBlobPolicy::Permit => DuplicateBlobs::default(),
+            _ => DuplicateBlobs {
+                hashes: duplicate_hashes(&blobs),
+            },
+        };
+        if !duplicates.is_empty() {
+            warn!(
+                "{} blobs are found more than once in block {}",
+                duplicates.hashes.len(),
+                block.header.header.block_hash()
+            );
+            if self.duplicate_blob_policy == DuplicateBlobPolicy::Reject {
+                return Err(BitcoinServiceError::DuplicateBlobs(duplicates));
+            }
+        }
+
+        Ok((dedup_blobs(blobs), duplicates))
+    }
+
+    // Distinct rollup names inscribed in the block, whatever the rollup, sorted. Meant for
+    // explorers and debugging, the inscriptions are neither authenticated nor checked against
+    // the sequencers.
+    pub fn rollup_names_in_block(&self, block: &BitcoinBlock) -> Vec<String> {
+        block
+            .txdata
+            .iter()
+            .flat_map(|tx| parse_rollup_names(&tx.transaction, self.parse_mode))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    // Same as `get_extraction_proof`, with named proofs that can be checked against the header
+    pub fn extraction_proof_of(&self, block: &BitcoinBlock) -> ExtractionProof {
+        let (inclusion_proof, completeness_proof) =
+            extraction_proof(block.txdata.iter().map(|tx| &tx.transaction));
+
+        ExtractionProof {
+            inclusion: inclusion_proof,
+            completeness: self.with_height_proof(block, completeness_proof),
+        }
+    }
+
+    // Puts the coinbase of the block first in the completeness proof when some authorized names
+    // have height bounds, for the verifier to read the height of the block from. The block hash
+    // does not commit to the height of the header, a prover could pick one in the bounds otherwise.
+    fn with_height_proof(
+        &self,
+        block: &BitcoinBlock,
+        mut completeness_proof: Vec<Transaction>,
+    ) -> Vec<Transaction> {
+        let proves_height = self
+            .authorized_rollup_names
+            .iter()
+            .any(AuthorizedRollupName::has_height_bounds);
+
+        if let Some(coinbase) = block.txdata.first().filter(|_| proves_height) {
+            // a coinbase whose txid starts with 00 bytes is already there
+            if completeness_proof.first() != Some(&coinbase.transaction) {
+                completeness_proof.insert(0, coinbase.transaction.clone());
+            }
+        }
+
+        completeness_proof
+    }
+
+    // Sends the reveal tx, retrying with exponential backoff according to the reveal retry policy.
+    // Once the attempts are exhausted the error is returned, the reveal tx is left on disk for manual recovery.
+    async fn send_reveal_with_retry(&self, reveal_tx: String) -> Result<String, anyhow::Error> {
+        self.reveal_retry_policy
+            .retry(self.clock.as_ref(), "send reveal tx", || {
+                self.client.send_raw_transaction(reveal_tx.clone())
+            })
+            .await
+            .map_err(|error| {
+                error.context("Failed to send reveal tx, it is kept on disk for manual recovery")
+            })
+    }
+
+    // Fetches the block at a height the node already has, without waiting for it
+    async fn get_existing_block_at(&self, height: u64) -> Result<BitcoinBlock, anyhow::Error> {
+        for _ in 0..MAX_BLOCK_FETCH_ATTEMPTS {
+            let block_hash = self.client.get_block_hash(height).await?;
+            if let Some(block) = self.get_block_matching_hash(height, block_hash).await? {
+                return Ok(block);
+            }
+        }
+
+        Err(anyhow::anyhow!(
+            "Block at height {} kept changing while being fetched",
+            height
+        ))
+    }
+
+    // Fetches the blocks in the given range of heights concurrently, returned in height order, e.g.
+    // to backfill an indexer. Blocks up to the tip are fetched without waiting, only the ones above
+    // it are waited for like `get_block_at` does. At most `prefetch_concurrency` blocks are fetched
+    // at the same time, the ones in flight are cancelled on the first error.
+    pub async fn get_block_range(
+        &self,
+        start: u64,
+        end: u64,
+    ) -> Result<Vec<BitcoinBlock>, anyhow::Error> {
+        let block_count = self
+            .block_count_retry_policy
+            .retry(self.clock.as_ref(), "get block count", || {
+                self.client.get_block_count()
+            })
+            .await?;
+
+        stream::iter(start..end)
+            .map(|height| async move {
+                match height <= block_count {
+                    true => self.get_existing_block_at(height).await,
+                    false => self.get_block_at(height).await,
+                }
+            })
+            .buffered(self.prefetch_concurrency.max(1))
+            .try_collect()
+            .await
+    }
+
+    // Streams the blocks mined from now on, in height order, until the receiver is dropped. With a
+    // `zmq_block_endpoint` new blocks are waited for on the notifications of the node, otherwise
+    // the node is polled like `get_block_at` does. Blocks are not checked for finality.
+    pub async fn subscribe_blocks(&self) -> Result<mpsc::Receiver<BitcoinBlock>, anyhow::Error> {
+        let block_count = self
+            .block_count_retry_policy
+            .retry(self.clock.as_ref(), "get block count", || {
+                self.client.get_block_count()
+            })
+            .await?;
+        let (sender, receiver) = mpsc::channel(BLOCK_SUBSCRIPTION_BUFFER);
+
+        let service = self.clone();
+        tokio::spawn(async move { service.forward_blocks(block_count + 1, sender).await });
+
+        Ok(receiver)
+    }
+
+    async fn forward_blocks(&self, mut height: u64, sender: mpsc::Sender<BitcoinBlock>) {
+        #[cfg(feature = "zmq")]
+        let mut notifications = self.zmq_block_endpoint.clone().map(BlockNotifications::new);
+
+        loop {
+            #[cfg(feature = "zmq")]
+            if let Some(notifications) = notifications.as_mut() {
+                // subscribed before checking the tip, so that a block mined in between is announced
+                notifications.connect().await;
+                match self.client.get_block_count().await {
+                    Ok(block_count) if block_count >= height => {}
+                    _ => notifications.next().await,
+                }
+            }
+
+            let block = match self.get_block_at(height).await {
+                Ok(block) => block,
+                Err(error) => {
+                    warn!(
+                        "Failed to get block {} for the subscription: {}",
+                        height, error
+                    );
+                    self.clock.sleep(self.polling_interval).await;
+                    continue;
+                }
+            };
+
+            // the receiver was dropped
+            if sender.send(block).await.is_err() {
+                return;
+            }
+            height += 1;
+        }
+    }
+
+    // Streams the blobs extracted from the finalized blocks in the given range of heights, with the
+    // height of their block, in height order. Blocks are fetched `prefetch_concurrency` at a time
+    // and no further than the receiver consumes, so that a backfill holds a bounded number of
+    // blocks in memory. The stream ends after the first error, or early if the receiver is dropped.
+    pub fn stream_blobs_in_range(
+        &self,
+        start: u64,
+        end: u64,
+    ) -> mpsc::Receiver<Result<(u64, BlobWithSender), anyhow::Error>> {
+        let (sender, receiver) = mpsc::channel(BLOB_STREAM_BUFFER);
+
+        let service = self.clone();
+        tokio::spawn(async move { service.forward_blobs(start, end, sender).await });
+
+        receiver
+    }
+
+    async fn forward_blobs(
+        &self,
+        start: u64,
+        end: u64,
+        sender: mpsc::Sender<Result<(u64, BlobWithSender), anyhow::Error>>,
+    ) {
+        let mut heights = start..end;
+        let mut pending = VecDeque::with_capacity(self.prefetch_concurrency);
+        loop {
+            // keep `prefetch_concurrency` blocks in flight, the next one is only started once the
+            // blobs of the oldest one are handed over
+            while pending.len() < self.prefetch_concurrency.max(1) {
+                let Some(height) = heights.next() else {
+                    break;
+                };
+                let service = self.clone();
+                pending.push_back((
+                    height,
+                    tokio::spawn(async move { service.get_finalized_at(height).await }),
+                ));
+            }
+
+            let Some((height, handle)) = pending.pop_front() else {
+                return;
+            };
+            let blobs = match handle.await {
+                Ok(Ok(block)) => Ok(self.extract_relevant_txs(&block)),
+                Ok(Err(error)) => Err(error),
+                Err(error) => Err(error.into()),
+            };
+
+            let blobs = match blobs {
+                Ok(blobs) => blobs,
+                Err(error) => {
+                    let _ = sender.send(Err(error)).await;
+                    break;
+                }
+            };
+            for blob in blobs {
+                // the receiver was dropped
+                if sender.send(Ok((height, blob))).await.is_err() {
+                    break;
+                }
+            }
+            if sender.is_closed() {
+                break;
+            }
+        }
+
+        for (_, handle) in pending {
+            handle.abort();
+        }
+    }
+
+    // Fetches the finalized blocks in the given range of heights concurrently, returned in height
+    // order. At most `prefetch_concurrency` blocks are fetched at the same time so that the node is
+    // not overwhelmed, the ones in flight are cancelled on the first error.
+    pub async fn prefetch_finalized(
+        &self,
+        start: u64,
+        end: u64,
+    ) -> Result<Vec<BitcoinBlock>, anyhow::Error> {
+        stream::iter(start..end)
+            .map(|height| self.get_finalized_at(height))
+            .buffered(self.prefetch_concurrency.max(1))
+            .try_collect()
+            .await
+    }
+
+    // Returns the serialized block with the given hash without decoding it, e.g. to archive it verbatim
+    pub async fn get_raw_block(&self, hash: BlockHash) -> Result<Vec<u8>, anyhow::Error> {
+        self.client.get_raw_block(hash).await
+    }
+
+    // Whether the block at the height is final under the finality rule, given the current block count
+    async fn is_finalized(&self, height: u64, block_count: u64) -> Result<bool, anyhow::Error> {
+        match self.finality_rule {
+            FinalityRule::BlockDepth(depth) => Ok(block_count >= height + depth),
+            FinalityRule::TimeElapsed(elapsed) => {
+                if block_count < height {
+                    return Ok(false);
+                }
+
+                let block_time = self.client.get_median_time_past(height).await?;
+                let tip_time = self.client.get_median_time_past(block_count).await?;
+                Ok(tip_time >= block_time + elapsed.as_secs())
+            }
+        }
+    }
+
+    // Fetches the finalized blocks in the given range of heights, returned in height order.
+    // Blocks are read from the configured blocks directory, only their hashes are fetched over RPC.
+    // Blocks that are not found there, like recent ones not flushed to disk yet, are fetched over RPC.
+    pub async fn backfill_from_blocksdir(
+        &self,
+        start: u64,
+        end: u64,
+    ) -> Result<Vec<BitcoinBlock>, anyhow::Error> {
+        // only the locations of the stored blocks are kept in memory, blocks are read one at a time
+        let stored_blocks = match self.blocksdir.clone() {
+            Some(blocksdir) => Some(Arc::new(
+                tokio::task::spawn_blocking(move || blocksdir.index_blocks()).await??,
+            )),
+            None => None,
+        };
+
+        let block_count = self.client.get_block_count().await?;
+
+        let mut blocks = Vec::with_capacity(end.saturating_sub(start) as usize);
+        for height in start..end {
+            // only finalized blocks are read from disk, the others are waited for like over RPC
+            let stored_block = match &stored_blocks {
+                Some(stored_blocks) if self.is_finalized(height, block_count).await? => {
+                    let block_hash =
+                        BlockHash::from_str(&self.client.get_block_hash(height).await?)?;
+                    let stored_blocks = stored_blocks.clone();
+                    tokio::task::spawn_blocking(move || stored_blocks.read_block(&block_hash))
+                        .await??
+                }
+                _ => None,
+            };
+
+            let block = match stored_block {
+                Some(block) => {
+                    let block = to_bitcoin_block(block, height, &self.rollup_name);
+                    self.check_checkpoint(height, &block)?;
+                    self.track_reorg(&block).await?;
+                    block
+                }
+                None => self.get_finalized_at(height).await?,
+            };
+            blocks.push(block);
+        }
+
+        Ok(blocks)
+    }
+
+    // Returns the confirmation status of every reveal tx kept in the reveal directory, for monitoring.
+    // Sent reveal txs are only kept there with `keep_sent_reveals`, otherwise the ones that failed to be sent are.
+    pub async fn pending_statuses(&self) -> Result<Vec<(Txid, ConfStatus)>, anyhow::Error> {
+        let reveal_txs = read_reveal_txs(&self.reveal_dir)?;
+
+        let mut statuses = Vec::with_capacity(reveal_txs.len());
+        for reveal_tx in reveal_txs {
+            let txid = reveal_tx.txid();
+            let status = match self.client.get_transaction_confirmations(txid).await? {
+                Some(0) => ConfStatus::Mempool,
+                Some(confirmations) => ConfStatus::Confirmed(confirmations),
+                None => ConfStatus::Dropped,
+            };
+            statuses.push((txid, status));
+        }
+
+        Ok(statuses)
+    }
+
+    // Computes the fee paid by the transaction by looking up the values of its prevouts
+    async fn get_transaction_fee(&self, tx: &Transaction) -> Result<u64, anyhow::Error> {
+        let mut input_value = 0;
+        for input in tx.input.iter() {
+            let prev_tx = self
+                .client
+                .get_raw_transaction(input.previous_output.txid)
+                .await?;
+
+            let prevout = prev_tx
+                .output
+                .get(input.previous_output.vout as usize)
+                .ok_or_else(|| anyhow::anyhow!("prevout {} not found", input.previous_output))?;
+
+            input_value += prevout.value;
+        }
+
+        let output_value: u64 = tx.output.iter().map(|output| output.value).sum();
+
+        input_value
+            .checked_sub(output_value)
+            .ok_or_else(|| anyhow::anyhow!("transaction {} spends more than its inputs", tx.txid()))
+    }
+
+    // Extract the blob transactions relevant to a particular rollup from a block, along with the fee
+    // (in sats) paid by the transaction carrying each blob. This lets a rollup attribute DA costs per batch.
+    pub async fn extract_relevant_txs_with_fees(
+        &self,
+        block: &BitcoinBlock,
+    ) -> Result<Vec<(BlobWithSender, u64)>, anyhow::Error> {
+        let rollup_names = self.rollup_names_at(block.header.height);
+        let mut txs = Vec::new();
+
+        // the blobs packed in a transaction are each attributed its whole fee,
+        // the blobs inscribed in chunks the fee of the transaction of their last chunk
+        let mut last_fee = None;
+        for (index, relevant_tx) in self.blobs_from_txs(&block.txdata, &rollup_names) {
+            let fee = match last_fee {
+                Some((fee_index, fee)) if fee_index == index => fee,
+                _ => {
+                    self.get_transaction_fee(&block.txdata[index].transaction)
+                        .await?
+                }
+            };
+            last_fee = Some((index, fee));
+            txs.push((relevant_tx, fee));
+        }
+
+        Ok(txs)
+    }
+}
+
+impl BitcoinService {
+    // Estimates the fee in satoshis of inscribing the blob with the fields at the fee rate of the
+    // node, chunks and redundant copies included, without building any transaction.
+    // The change is assumed to go to an address of the same type as the sequencer address.
+    pub async fn estimate_inscription_fee(
+        &self,
+        blob: &[u8],
+        fields: &EnvelopeFields,
+    ) -> Result<u64, anyhow::Error> {
+        let address = self.sequencer_address()?;
+        let body_len = compress_blob(blob).len();
+        let fee_rate = self.fee_rate().await?;
+
+        let fee = |body_len: usize, fields: &EnvelopeFields| {
+            let vsizes = estimate_inscription_vsizes(
+                &self.rollup_name,
+                body_len,
+                self.signature_scheme,
+                fields,
+                self.commit_fee_mode,
+                address,
+                address,
+            );
+            (fee_rate * self.commit_fee_multiplier * vsizes.commit as f64).round() as u64
+                + (fee_rate * self.reveal_fee_multiplier * vsizes.reveal as f64).round() as u64
+        };
+
+        // blobs too large for a single reveal tx are inscribed in chunks, see `inscribe_chunks`
+        let fee = if body_len > self.chunk_size {
+            let fields = EnvelopeFields {
+                chunk: Some(ChunkInfo {
+                    blob_id: [0; 32],
+                    index: 0,
+                    total: 1,
+                }),
+                ..fields.clone()
+            };
+            (0..body_len)
+                .step_by(self.chunk_size)
+                .map(|start| fee((body_len - start).min(self.chunk_size), &fields))
+                .sum()
+        } else {
+            fee(body_len, fields)
+        };
+
+        Ok(fee * self.redundancy as u64)
+    }
+
+    // Same as `send_transaction`, returning the commit and reveal txids of every inscription of the
+    // blob, e.g. to track their confirmation. There are several with redundancy or chunking.
+    pub async fn send_transaction_with_ids(
+        &self,
+        blob: &[u8],
+    ) -> Result<Vec<SubmittedInscription>, anyhow::Error> {
+        self.send_transaction_with_fields(blob, EnvelopeFields::default())
+            .await
+    }
+
+    // Inscribes the blob along with the given optional envelope fields
+    pub async fn send_transaction_with_fields(
+        &self,
+        blob: &[u8],
+        fields: EnvelopeFields,
+    ) -> Result<Vec<SubmittedInscription>, anyhow::Error> {
+        let result = self.inscribe_blob(blob, fields).await;
+        #[cfg(feature = "metrics")]
+        telemetry::record_send(result.is_ok());
+
+        result
+    }
+
+    async fn inscribe_blob(
+        &self,
+        blob: &[u8],
+        fields: EnvelopeFields,
+    ) -> Result<Vec<SubmittedInscription>, anyhow::Error> {
+        // a service built without an address only reads, fail before touching the wallet
+        self.sequencer_address()?;
+        self.wait_for_send_slot().await?;
+
+        // Compress the blob
+        let blob = compress_blob(blob);
+
+        // blobs too large for a single reveal tx are inscribed in chunks
+        if blob.len() > self.chunk_size {
+            return self.inscribe_chunks(blob, fields).await;
+        }
+
+        // every copy is inscribed from different utxos, so that a single eviction does not lose the blob
+        let mut used_outputs = UsedOutputs::default();
+        let mut inscriptions = Vec::with_capacity(self.redundancy as usize);
+        for _ in 0..self.redundancy {
+            inscriptions.push(
+                self.inscribe_body(blob.clone(), fields.clone(), &mut used_outputs)
+                    .await?,
+            );
+        }
+
+        Ok(inscriptions)
+    }
+
+    // Same as `send_transaction_with_fields`, also recording the inscriptions under the correlation
+    // id for `find_by_correlation_id`, e.g. to reconcile the batch ids of the sequencer with txids.
+    // The id is kept in the reveal directory, not on-chain. Sending again under the same id, e.g.
+    // when a batch is retried, adds to the inscriptions already recorded.
+    pub async fn send_transaction_with_correlation_id(
+        &self,
+        blob: &[u8],
+        fields: EnvelopeFields,
+        correlation_id: Option<[u8; 16]>,
+    ) -> Result<Vec<SubmittedInscription>, anyhow::Error> {
+        let inscriptions = self.send_transaction_with_fields(blob, fields).await?;
+
+        // the blob is sent whatever happens to the record, failing would only make it sent again
+        if let Some(correlation_id) = correlation_id {
+            if let Err(error) = self.record_correlation(&correlation_id, &inscriptions) {
+                warn!(
+                    "Failed to record the inscriptions {:?} under the correlation id {}: {}",
+                    inscriptions,
+                    hex::encode(correlation_id),
+                    error
+                );
+            }
+        }
+
+        Ok(inscriptions)
+    }
+
+    // Returns the inscriptions sent under the correlation id, none if it is unknown
+    pub fn find_by_correlation_id(
+        &self,
+        correlation_id: &[u8; 16],
+    ) -> Result<Vec<SubmittedInscription>, anyhow::Error> {
+        match read_correlation_record(&self.reveal_dir, correlation_id)? {
+            Some(record) => serde_json::from_slice(&record).with_context(|| {
+                format!(
+                    "Invalid record of the correlation id {}",
+                    hex::encode(correlation_id)
+                )
+            }),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn record_correlation(
+        &self,
+        correlation_id: &[u8; 16],
+        inscriptions: &[SubmittedInscription],
+    ) -> Result<(), anyhow::Error> {
+        let mut record = self.find_by_correlation_id(correlation_id)?;
+        record.extend_from_slice(inscriptions);
+        write_correlation_record(
+            &self.reveal_dir,
+            correlation_id,
+            &serde_json::to_vec(&record)?,
+        )
+    }
+
+    // Inscribes a compressed blob in chunks of `chunk_size` bytes, one commit and reveal pair each.
+    // Every chunk is signed over its own body and position in the blob. The commit tx of every
+    // chunk but the first spends the output of the reveal tx of the previous chunk, so that each
+    // copy of the blob is a single chain of txs mined in index order. Extraction reassembles the
+    // chunks mined in the same block into the blob.
+    async fn inscribe_chunks(
+        &self,
+        blob: Vec<u8>,
+        fields: EnvelopeFields,
+    ) -> Result<Vec<SubmittedInscription>, anyhow::Error> {
+        let bodies = blob.chunks(self.chunk_size).collect::<Vec<_>>();
+        let total = u16::try_from(bodies.len()).map_err(|_| {
+            anyhow::anyhow!(
+                "Blob of {} bytes needs more than {} chunks",
+                blob.len(),
+                u16::MAX
+            )
+        })?;
+        let blob_id = blob_hash(&blob);
+
+        let mut used_outputs = UsedOutputs::default();
+        let mut inscriptions = Vec::with_capacity(self.redundancy as usize * bodies.len());
+        for _ in 0..self.redundancy {
+            // every copy is a chain of its own, so that the copies do not depend on each other
+            let mut previous_reveal = None;
+            for (index, body) in bodies.iter().enumerate() {
+                let fields = EnvelopeFields {
+                    chunk: Some(ChunkInfo {
+                        blob_id,
+                        index: index as u16,
+                        total,
+                    }),
+                    ..fields.clone()
+                };
+                let (signature, _) = self.sign_blob(body, &fields)?;
+                let submitted = self
+                    .inscribe_signed(
+                        vec![(body.to_vec(), signature)],
+                        fields,
+                        &mut used_outputs,
+                        None,
+                        previous_reveal,
+                    )
+                    .await?;
+                previous_reveal = Some(submitted.reveal_txid);
+                inscriptions.push(submitted);
+            }
+        }
+
+        Ok(inscriptions)
+    }
+
+    // Inscribes several blobs in a single reveal tx, each in its own envelope signed by the
+    // sequencer, paying for one commit and one reveal instead of a pair per blob. Extraction yields
+    // one blob per envelope, in the given order. The reveal tx must not exceed the standard weight.
+    pub async fn send_transactions(
+        &self,
+        blobs: &[&[u8]],
+    ) -> Result<Vec<SubmittedInscription>, anyhow::Error> {
+        if blobs.is_empty() {
+            return Err(anyhow::anyhow!("No blob to send"));
+        }
+
+        self.wait_for_send_slot().await?;
+
+        // Compress the blobs
+        let blobs = blobs
+            .iter()
+            .map(|blob| compress_blob(blob))
+            .collect::<Vec<_>>();
+
+        let mut used_outputs = UsedOutputs::default();
+        let mut inscriptions = Vec::with_capacity(self.redundancy as usize);
+        for _ in 0..self.redundancy {
+            inscriptions.push(
+                self.inscribe_bodies(blobs.clone(), EnvelopeFields::default(), &mut used_outputs)
+                    .await?,
+            );
+        }
+
+        Ok(inscriptions)
+    }
+
+    // Inscribes the blob on the given sat instead of the first sat of the largest utxo, e.g. to
+    // inscribe on a rare sat. The satpoint must be in a spendable utxo of the wallet, and past its
+    // first sat only when the commit fee is subtracted from the change. The blob is inscribed
+    // once whatever the redundancy, and must fit in a single reveal tx.
+    pub async fn send_transaction_on_satpoint(
+        &self,
+        blob: &[u8],
+        fields: EnvelopeFields,
+        satpoint: SatPoint,
+    ) -> Result<SubmittedInscription, anyhow::Error> {
+        self.wait_for_send_slot().await?;
+
+        let blob = compress_blob(blob);
+        if blob.len() > self.chunk_size {
+            return Err(anyhow::anyhow!(
+                "Blob of {} bytes compressed does not fit in a single reveal tx",
+                blob.len()
+            ));
+        }
+        let (signature, _) = self.sign_blob(&blob, &fields)?;
+
+        self.inscribe_signed(
+            vec![(blob, signature)],
+            fields,
+            &mut UsedOutputs::default(),
+            Some(satpoint),
+            None,
+        )
+        .await
+    }
+
+    // Returns the size of the body `send_transaction` would inscribe for the blob, once compressed.
+    // Lets callers with their own batching policy decide whether to split the blob before sending it.
+    pub fn compressed_size(&self, blob: &[u8]) -> usize {
+        compress_blob(blob).len()
+    }
+
+    // Inscribes already compressed data, with a header telling extraction how to decompress it.
+    // Use `Compression::None` to inscribe data that must not be compressed.
+    pub async fn send_raw_blob(
+        &self,
+        compressed: &[u8],
+        algorithm: Compression,
+    ) -> Result<SubmittedInscription, anyhow::Error> {
+        let fields = EnvelopeFields {
+            compression: Some(algorithm),
+            ..Default::default()
+        };
+
+        self.wait_for_send_slot().await?;
+
+        self.inscribe_body(compressed.to_vec(), fields, &mut UsedOutputs::default())
+            .await
+    }
+
+    // Enforces the minimum send interval, waiting for the end of it or failing as configured
+    async fn wait_for_send_slot(&self) -> Result<(), anyhow::Error> {
+        let min_send_interval = match self.min_send_interval {
+            Some(min_send_interval) => min_send_interval,
+            None => return Ok(()),
+        };
+
+        let mut last_send = self.last_send.lock().await;
+        if let Some(last_send) = *last_send {
+            let elapsed = self.clock.now().saturating_duration_since(last_send);
+            if elapsed < min_send_interval {
+                let remaining = min_send_interval - elapsed;
+                match self.send_rate_limit {
+                    SendRateLimit::Wait => self.clock.sleep(remaining).await,
+                    SendRateLimit::Reject => {
+                        return Err(BitcoinServiceError::SendTooSoon {
+                            remaining_ms: remaining.as_millis() as u64,
+                        }
+                        .into())
+                    }
+                }
+            }
+        }
+        *last_send = Some(self.clock.now());
+
+        Ok(())
+    }
+
+    // Inscribes the body as is, it must already be compressed as told by the envelope fields.
+    // Utxos in `used_outputs` are not used, the commit and reveal txs sent are added to it.
+    async fn inscribe_body(
+        &self,
+        blob: Vec<u8>,
+        fields: EnvelopeFields,
+        used_outputs: &mut UsedOutputs,
+    ) -> Result<SubmittedInscription, anyhow::Error> {
+        self.inscribe_bodies(vec![blob], fields, used_outputs).await
+    }
+
+    // Same as `inscribe_body`, packing the bodies in a single reveal tx, one envelope each
+    async fn inscribe_bodies(
+        &self,
+        blobs: Vec<Vec<u8>>,
+        fields: EnvelopeFields,
+        used_outputs: &mut UsedOutputs,
+    ) -> Result<SubmittedInscription, anyhow::Error> {
+        // sign every blob for authentication of the sequencer
+        let mut inscriptions = Vec::with_capacity(blobs.len());
+        for blob in blobs {
+            let (signature, _) = self.sign_blob(&blob, &fields)?;
+            inscriptions.push((blob, signature));
+        }
+
+        self.inscribe_signed(inscriptions, fields, used_outputs, None, None)
+            .await
+    }
+
+    // Inscribes (body, signature) pairs already signed by the sequencer in a single reveal tx,
+    // on the given satpoint or else on the first sat of the largest funding utxo. With a previous
+    // reveal tx, it inscribes on the first sat of its output instead, chaining the inscriptions.
+    async fn inscribe_signed(
+        &self,
+        inscriptions: Vec<(Vec<u8>, Vec<u8>)>,
+        fields: EnvelopeFields,
+        used_outputs: &mut UsedOutputs,
+        satpoint: Option<SatPoint>,
+        previous_reveal: Option<Txid>,
+    ) -> Result<SubmittedInscription, anyhow::Error> {
+        // refused before anything is sent, the commit output would be stuck behind the reveal tx
+        self.check_annex(&fields)?;
+
+        let client = self.client.clone();
+
+        let network = self.network;
+        let rollup_name = self.rollup_name.clone();
+        let sequencer_da_private_key = self.sequencer_da_private_key.clone();
+
+        // get two change addresses that are necessary for the inscribe transaction
+        let change_addresses: [Address; 2] = match &self.change_deriver {
+            Some(change_deriver) => change_deriver.next_addresses()?,
+            None => client.get_change_addresses().await?,
+        };
+
+        // get all available utxos, an unfunded wallet has none
+        let mut utxos: Vec<UTXO> = client
+            .get_utxos()
+            .await?
+            .into_iter()
+            .filter(|utxo| !used_outputs.contains(utxo))
+            .collect();
+        if utxos.is_empty() {
+            return Err(BitcoinServiceError::NoSpendableUtxos.into());
+        }
+
+        // return funds to sequencer address
+        let destination_address = self.sequencer_address()?.clone();
+
+        // the output of the previous reveal tx pays to the sequencer address, the node may not list
+        // it yet as it is unconfirmed, and it is excluded with the other outputs sent if listed
+        let satpoint = match previous_reveal {
+            Some(txid) => {
+                let outpoint = OutPoint::new(txid, 0);
+                let listed = |utxo: &UTXO| OutPoint::new(utxo.tx_id, utxo.vout) == outpoint;
+                if !utxos.iter().any(listed) {
+                    utxos.push(UTXO {
+                        tx_id: txid,
+                        vout: 0,
+                        address: destination_address.to_string(),
+                        script_pubkey: hex::encode(destination_address.script_pubkey().as_bytes()),
+                        amount: REVEAL_OUTPUT_AMOUNT,
+                        confirmations: 0,
+                        spendable: true,
+                        solvable: true,
+                    });
+                }
+                Some(SatPoint {
+                    outpoint,
+                    offset: 0,
+                })
+            }
+            None => satpoint,
+        };
+
+        // get fee rate from node
+        let fee_sat_per_vbyte: f64 = self.fee_rate().await?;
+        let commit_fee_rate = fee_sat_per_vbyte * self.commit_fee_multiplier;
+        let reveal_fee_rate = fee_sat_per_vbyte * self.reveal_fee_multiplier;
+
+        // fund the commit output, which pays the reveal fee, every envelope is counted as its own
+        // reveal tx so that the target stays above the actual fee
+        let reveal_fee = inscriptions
+            .iter()
+            .map(|(body, _)| {
+                let vsizes = estimate_inscription_vsizes(
+                    &rollup_name,
+                    body.len(),
+                    self.signature_scheme,
+                    &fields,
+                    self.commit_fee_mode,
+                    &destination_address,
+                    &change_addresses[0],
+                );
+                (reveal_fee_rate * vsizes.reveal as f64).ceil() as u64
+            })
+            .sum::<u64>();
+        let (satpoint, mut funding_utxos) = match satpoint {
+            Some(satpoint) => {
+                let inscribed = find_satpoint_utxo(&utxos, satpoint)?.clone();
+                // the commit tx takes the value it misses from the other utxos it can sign for
+                let other_utxos = utxos
+                    .iter()
+                    .filter(|utxo| **utxo != inscribed && utxo.spendable && utxo.solvable)
+                    .cloned();
+                (
+                    satpoint,
+                    [inscribed].into_iter().chain(other_utxos).collect(),
+                )
+            }
+            None => {
+                let funding_utxos =
+                    select_utxos(&utxos, reveal_fee + REVEAL_OUTPUT_AMOUNT, commit_fee_rate)?;
+                (get_satpoint_to_inscribe(&funding_utxos[0]), funding_utxos)
+            }
+        };
+
+        // the commit fee is paid by another utxo of the wallet in this mode
+        if self.commit_fee_mode == CommitFeeMode::RequireAdditionalInput {
+            let other_utxos = utxos
+                .into_iter()
+                .filter(|utxo| utxo.spendable && utxo.solvable && !funding_utxos.contains(utxo))
+                .collect::<Vec<_>>();
+            funding_utxos.extend(other_utxos);
+        }
+
+        let public_key = self.sequencer_public_key()?;
+
+        #[cfg(feature = "metrics")]
+        let input_values = funding_utxos
+            .iter()
+            .map(|utxo| (OutPoint::new(utxo.tx_id, utxo.vout), utxo.amount))
+            .collect::<HashMap<_, _>>();
+
+        // derive the commit key, so that the reveal can be rebuilt if something goes wrong
+        let bodies = inscriptions
+            .iter()
+            .flat_map(|(body, _)| body.iter().copied())
+            .collect::<Vec<_>>();
+        let commit_key_pair = derive_commit_key_pair(&sequencer_da_private_key, &bodies)?;
+
+        // create inscribe transactions
+        let (unsigned_commit_tx, reveal_tx) = create_packed_inscription_transactions(
+            &rollup_name,
+            &inscriptions,
+            public_key,
+            &fields,
+            commit_key_pair,
+            satpoint,
+            funding_utxos,
+            change_addresses,
+            self.commit_fee_mode,
+            self.enable_rbf,
+            self.donate_dust_change,
+            destination_address,
+            commit_fee_rate,
+            reveal_fee_rate,
+            network,
+        )?;
+
+        let submitted = self
+            .broadcast_inscription(&unsigned_commit_tx, &reveal_tx)
+            .await?;
+        used_outputs.record(&unsigned_commit_tx, &submitted);
+        #[cfg(feature = "metrics")]
+        telemetry::record_inscription_fee(&unsigned_commit_tx, &reveal_tx, &input_values);
+
+        Ok(submitted)
+    }
+
+    // Signs the commit tx with the wallet of the node and sends it, then sends the reveal tx.
+    // The reveal tx is written to the reveal directory in between, so that it survives a failure.
+    async fn broadcast_inscription(
+        &self,
+        unsigned_commit_tx: &Transaction,
+        reveal_tx: &Transaction,
+    ) -> Result<SubmittedInscription, anyhow::Error> {
+        // sign inscribe transactions
+        let serialized_unsigned_commit_tx = &encode::serialize(unsigned_commit_tx);
+        let signed_raw_commit_tx = self
+            .client
+            .sign_raw_transaction_with_wallet(serialized_unsigned_commit_tx.encode_hex())
+            .await?;
+
+        // send inscribe transactions, the reveal tx is persisted once the commit tx is accepted
+        self.client
+            .send_raw_transaction(signed_raw_commit_tx)
+            .await
+            .context("Failed to send commit tx, its reveal tx is not persisted")?;
+
+        let commit_txid = unsigned_commit_tx.txid();
+        if self.check_commit_in_mempool
+            && self
+                .client
+                .get_transaction_confirmations(commit_txid)
+                .await?
+                .is_none()
+        {
+            return Err(anyhow::anyhow!(
+                "Commit tx {} is accepted but not in the mempool, its reveal tx is not persisted",
+                commit_txid
+            ));
+        }
+
+        // serialize reveal tx
+        let serialized_reveal_tx = &encode::serialize(reveal_tx);
+
+        // write reveal tx to file, `resume_reveal_tx` sends it if something goes wrong
+        let commit_tx_id = unsigned_commit_tx.txid().to_raw_hash().to_string();
+        write_reveal_tx(&self.reveal_dir, serialized_reveal_tx, commit_tx_id.clone())
+            .with_context(|| {
+                format!(
+                    "Commit tx {} is sent but its reveal tx is not persisted",
+                    commit_txid
+                )
+            })?;
+
+        // the reveal tx is withheld until the commit tx is deep enough
+        self.wait_for_commit_confirmations(commit_txid).await?;
+
+        // send reveal tx, retrying as the commit tx is already sent
+        let reveal_tx_hash = self
+            .send_reveal_with_retry(serialized_reveal_tx.encode_hex())
+            .await?;
+
+        info!("Blob inscribe tx sent. Hash: {}", reveal_tx_hash);
+
+        // the reveal tx is in the mempool, the file is only kept for monitoring if asked to
+        if !self.keep_sent_reveals {
+            if let Err(error) = remove_reveal_tx(&self.reveal_dir, commit_tx_id) {
+                warn!("Failed to remove the file of the sent reveal tx: {}", error);
+            }
+        }
+
+        Ok(SubmittedInscription {
+            commit_txid: unsigned_commit_tx.txid(),
+            reveal_txid: Txid::from_str(&reveal_tx_hash)?,
+        })
+    }
+
+    // Waits until the commit tx has `commit_confirmations` confirmations, polling the node.
+    // Fails if the commit tx is dropped meanwhile, its reveal tx is kept for `resume_reveal_tx`.
+    async fn wait_for_commit_confirmations(&self, commit_txid: Txid) -> Result<(), anyhow::Error> {
+        if self.commit_confirmations == 0 {
+            return Ok(());
+        }
+
+        loop {
+            match self
+                .client
+                .get_transaction_confirmations(commit_txid)
+                .await?
+            {
+                Some(confirmations) if confirmations >= self.commit_confirmations as u64 => {
+                    return Ok(())
+                }
+                Some(confirmations) => {
+                    info!(
+                        "Commit tx {} has {} of {} confirmations, waiting to send its reveal tx",
+                        commit_txid, confirmations, self.commit_confirmations
+                    );
+                    self.clock.sleep(self.polling_interval).await;
+                }
+                None => {
+                    return Err(anyhow::anyhow!(
+                        "Commit tx {} was dropped before its reveal tx was sent",
+                        commit_txid
+                    ))
+                }
+            }
+        }
+    }
+
+    // Replaces a commit tx stuck in the mempool, and its reveal tx, by ones paying the given fee rate
+    // from the same utxos. The commit tx must signal replaceability (`enable_rbf`), the rate
+    // must be above its own. The reveal tx is read from the reveal directory, or from the mempool
+    // once it was sent and removed from there, which needs Bitcoin Core 24 or later.
+    pub async fn bump_fee(
+        &self,
+        commit_txid: Txid,
+        fee_sat_per_vbyte: f64,
+    ) -> Result<SubmittedInscription, anyhow::Error> {
+        // also rejects NaN
+        if !(fee_sat_per_vbyte.is_finite() && fee_sat_per_vbyte >= MIN_RELAY_FEE_RATE) {
+            return Err(anyhow::anyhow!(
+                "fee rate of {} sat/vB is below the minimum relay fee rate of {}",
+                fee_sat_per_vbyte,
+                MIN_RELAY_FEE_RATE
+            ));
+        }
+
+        let commit_tx = self.client.get_raw_transaction(commit_txid).await?;
+        if !commit_tx.is_explicitly_rbf() {
+            return Err(anyhow::anyhow!(
+                "Commit tx {} does not signal replaceability",
+                commit_txid
+            ));
+        }
+
+        let commit_tx_id = commit_txid.to_raw_hash().to_string();
+        let (reveal_tx, kept) = match read_reveal_tx(&self.reveal_dir, commit_tx_id.clone()) {
+            Ok(reveal_tx) => (reveal_tx, true),
+            Err(error) => match self.find_mempool_reveal_tx(&commit_tx).await? {
+                Some(reveal_tx) => (reveal_tx, false),
+                None => {
+                    return Err(anyhow::anyhow!(
+                        "Reveal tx of commit {} is neither kept nor in the mempool: {}",
+                        commit_txid,
+                        error
+                    ))
+                }
+            },
+        };
+        let inscriptions = parse_inscriptions(&reveal_tx, &self.rollup_name, ParseMode::Strict)
+            .map_err(|error| {
+                anyhow::anyhow!(
+                    "Reveal tx of commit {} has no inscription: {}",
+                    commit_txid,
+                    error
+                )
+            })?;
+        // the envelopes of a packed reveal tx share their fields and public key
+        let inscription = inscriptions[0].clone();
+
+        // the annex is not part of the envelope, it is the last element of the witness
+        let witness = &reveal_tx.input[0].witness;
+        let annex = witness
+            .last()
+            .filter(|last| witness.len() > 1 && last.first() == Some(&TAPROOT_ANNEX_PREFIX))
+            .map(<[u8]>::to_vec);
+        let fields = EnvelopeFields {
+            namespace_id: inscription.namespace_id,
+            label: inscription.label,
+            annex,
+            compression: inscription.compression,
+            metadata: inscription.metadata,
+            chunk: inscription.chunk,
+        };
+        self.check_annex(&fields)?;
+
+        // the replacement spends the same utxos
+        let mut utxos = Vec::with_capacity(commit_tx.input.len());
+        for input in commit_tx.input.iter() {
+            let outpoint = input.previous_output;
+            let amount = match self.client.get_tx_out_value(outpoint, false).await? {
+                Some(amount) => amount,
+                // outputs of unconfirmed parents are not in the chain state, the parent has them
+                None => self
+                    .client
+                    .get_raw_transaction(outpoint.txid)
+                    .await
+                    .ok()
+                    .and_then(|parent| {
+                        parent
+                            .output
+                            .get(outpoint.vout as usize)
+                            .map(|output| output.value)
+                    })
+                    .ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "Input {} of commit {} is unspendable",
+                            outpoint,
+                            commit_txid
+                        )
+                    })?,
+            };
+            utxos.push(UTXO {
+                tx_id: outpoint.txid,
+                vout: outpoint.vout,
+                address: String::new(),
+                script_pubkey: String::new(),
+                amount,
+                confirmations: 0,
+                spendable: true,
+                solvable: true,
+            });
+        }
+        // a replacement must pay a higher fee rate than the tx it replaces
+        let input_values = utxos.iter().map(|utxo| utxo.amount).collect::<Vec<_>>();
+        let output_value = commit_tx
+            .output
+            .iter()
+            .map(|output| output.value)
+            .sum::<u64>();
+        let commit_fee = input_values
+            .iter()
+            .sum::<u64>()
+            .saturating_sub(output_value);
+        let commit_fee_rate = commit_fee as f64 / commit_tx.vsize() as f64;
+        if fee_sat_per_vbyte <= commit_fee_rate {
+            return Err(anyhow::anyhow!(
+                "fee rate of {} sat/vB is not above the {:.2} sat/vB of commit {}",
+                fee_sat_per_vbyte,
+                commit_fee_rate,
+                commit_txid
+            ));
+        }
+
+        // and inscribes the same sat, the one the commit output starts with
+        let commit_vout = reveal_tx.input[0].previous_output.vout;
+        let satpoint = get_commit_satpoint(&commit_tx, commit_vout, &input_values)
+            .ok_or_else(|| anyhow::anyhow!("Commit {} has no commit output", commit_txid))?;
+
+        let change_addresses: [Address; 2] = match &self.change_deriver {
+            Some(change_deriver) => change_deriver.next_addresses()?,
+            None => self.client.get_change_addresses().await?,
+        };
+        let destination_address = self.sequencer_address()?.clone();
+        let bodies = inscriptions
+            .into_iter()
+            .map(|inscription| (inscription.body, inscription.signature))
+            .collect::<Vec<_>>();
+        let commit_key_pair = derive_commit_key_pair(
+            &self.sequencer_da_private_key,
+            &bodies
+                .iter()
+                .flat_map(|(body, _)| body.clone())
+                .collect::<Vec<_>>(),
+        )?;
+
+        let (unsigned_commit_tx, reveal_tx) = create_packed_inscription_transactions(
+            &self.rollup_name,
+            &bodies,
+            inscription.public_key,
+            &fields,
+            commit_key_pair,
+            satpoint,
+            utxos,
+            change_addresses,
+            self.commit_fee_mode,
+            true,
+            self.donate_dust_change,
+            destination_address,
+            fee_sat_per_vbyte,
+            fee_sat_per_vbyte,
+            self.network,
+        )?;
+
+        let submitted = self
+            .broadcast_inscription(&unsigned_commit_tx, &reveal_tx)
+            .await?;
+
+        // the replaced reveal tx can never confirm
+        if kept {
+            if let Err(error) = remove_reveal_tx(&self.reveal_dir, commit_tx_id) {
+                warn!(
+                    "Failed to remove the file of the replaced reveal tx: {}",
+                    error
+                );
+            }
+        }
+
+        info!(
+            "Commit tx {} replaced by {}",
+            commit_txid, submitted.commit_txid
+        );
+
+        Ok(submitted)
+    }
+
+    // Refuses the fields with an annex unless `allow_nonstandard_annex`
+    fn check_annex(&self, fields: &EnvelopeFields) -> Result<(), BitcoinServiceError> {
+        match fields.annex.is_some() && !self.allow_nonstandard_annex {
+            true => Err(BitcoinServiceError::NonStandardAnnex),
+            false => Ok(()),
+        }
+    }
+
+    // Returns the tx of the mempool revealing an inscription of the rollup from the commit tx
+    async fn find_mempool_reveal_tx(
+        &self,
+        commit_tx: &Transaction,
+    ) -> Result<Option<Transaction>, anyhow::Error> {
+        let commit_txid = commit_tx.txid();
+        for vout in 0..commit_tx.output.len() as u32 {
+            let outpoint = OutPoint::new(commit_txid, vout);
+            if let Some(txid) = self.client.get_tx_spending_prevout(outpoint).await? {
+                let tx = self.client.get_raw_transaction(txid).await?;
+                if parse_inscriptions(&tx, &self.rollup_name, ParseMode::Strict).is_ok() {
+                    return Ok(Some(tx));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    // Sends the reveal tx written to the reveal directory for the commit tx, finishing an inscription
+    // interrupted after its commit tx was sent. The commit tx must be in the mempool or in the chain
+    // and its output must be unspent.
+    pub async fn resume_reveal_tx(&self, commit_txid: &str) -> Result<Txid, anyhow::Error> {
+        let commit_txid = Txid::from_str(commit_txid)?;
+        let commit_tx_id = commit_txid.to_raw_hash().to_string();
+        let reveal_tx = read_reveal_tx(&self.reveal_dir, commit_tx_id.clone())?;
+
+        if self
+            .client
+            .get_transaction_confirmations(commit_txid)
+            .await?
+            .is_none()
+        {
+            return Err(anyhow::anyhow!(
+                "Commit tx {} is neither in the mempool nor in the chain, it must be sent first",
+                commit_txid
+            ));
+        }
+
+        let commit_output = reveal_tx.input[0].previous_output;
+        if self
+            .client
+            .get_tx_out_value(commit_output, true)
+            .await?
+            .is_none()
+        {
+            let reveal_txid = reveal_tx.txid();
+            return Err(
+                match self
+                    .client
+                    .get_transaction_confirmations(reveal_txid)
+                    .await?
+                {
+                    Some(_) => anyhow::anyhow!(
+                        "Commit output {} is already spent by its reveal tx {}",
+                        commit_output,
+                        reveal_txid
+                    ),
+                    None => anyhow::anyhow!(
+                        "Commit output {} is already spent by another tx than the reveal tx {}",
+                        commit_output,
+                        reveal_txid
+                    ),
+                },
+            );
+        }
+
+        let reveal_tx_hash = self
+            .send_reveal_with_retry(encode::serialize(&reveal_tx).encode_hex())
+            .await?;
+
+        info!("Blob reveal tx resumed and sent. Hash: {}", reveal_tx_hash);
+
+        if !self.keep_sent_reveals {
+            if let Err(error) = remove_reveal_tx(&self.reveal_dir, commit_tx_id) {
+                warn!("Failed to remove the file of the sent reveal tx: {}", error);
+            }
+        }
+
+        Ok(Txid::from_str(&reveal_tx_hash)?)
+    }
+
+    // Rebuilds the reveal transaction of an already broadcasted commit transaction from the original blob
+    // and broadcasts it. Useful for disaster recovery when only the commit txid and the blob survived.
+    pub async fn rebuild_and_reveal(
+        &self,
+        commit_txid: Txid,
+        blob: &[u8],
+    ) -> Result<Txid, anyhow::Error> {
+        self.rebuild_and_reveal_with_fields(commit_txid, blob, &EnvelopeFields::default())
+            .await
+    }
+
+    // Same as `rebuild_and_reveal`, for inscriptions sent with optional envelope fields.
+    // Only blobs sent alone can be rebuilt. Chunked blobs and packed sends (`send_transactions`)
+    // derive their commit keys from other bodies, so they are recovered with `resume_reveal_tx`.
+    pub async fn rebuild_and_reveal_with_fields(
+        &self,
+        commit_txid: Txid,
+        blob: &[u8],
+        fields: &EnvelopeFields,
+    ) -> Result<Txid, anyhow::Error> {
+        let blob = compress_blob(blob);
+        if blob.len() > self.chunk_size {
+            return Err(anyhow::anyhow!(
+                "Blob of {} bytes compressed was inscribed in chunks and cannot be rebuilt",
+                blob.len()
+            ));
+        }
+
+        let (signature, public_key) = self.sign_blob(&blob, fields)?;
+        let commit_key_pair = derive_commit_key_pair(&self.sequencer_da_private_key, &blob)?;
+
+        let destination_address = self.sequencer_address()?.clone();
+
+        let commit_tx = self.client.get_raw_transaction(commit_txid).await?;
+
+        let reveal_tx = rebuild_reveal_transaction(
+            &self.rollup_name,
+            blob,
+            signature,
+            public_key,
+            fields,
+            commit_key_pair,
+            &commit_tx,
+            destination_address,
+            self.network,
+        )?;
+
+        let reveal_tx_hash = self
+            .client
+            .send_raw_transaction(encode::serialize(&reveal_tx).encode_hex())
+            .await?;
+
+        info!("Blob reveal tx rebuilt and sent. Hash: {}", reveal_tx_hash);
+
+        Ok(Txid::from_str(&reveal_tx_hash)?)
+    }
+
+    // Spends the commit output of an inscription that will not be revealed, e.g. because its batch is
+    // superseded, back to the sequencer address through the key path of the commit key. The reveal tx
+    // kept in the reveal directory tells the blob the commit key was derived from, it is removed once sent.
+    pub async fn abandon_inscription(&self, commit_txid: Txid) -> Result<Txid, anyhow::Error> {
+        let commit_tx_id = commit_txid.to_raw_hash().to_string();
+        let reveal_tx = read_reveal_tx(&self.reveal_dir, commit_tx_id.clone())?;
+
+        let inscriptions = parse_inscriptions(&reveal_tx, &self.rollup_name, ParseMode::Strict)
+            .map_err(|error| {
+                anyhow::anyhow!(
+                    "Reveal tx of commit {} has no inscription: {}",
+                    commit_txid,
+                    error
+                )
+            })?;
+        // the commit key of a packed reveal tx is derived from all its bodies
+        let bodies = inscriptions
+            .into_iter()
+            .flat_map(|inscription| inscription.body)
+            .collect::<Vec<_>>();
+        let commit_key_pair = derive_commit_key_pair(&self.sequencer_da_private_key, &bodies)?;
+
+        let commit_tx = self.client.get_raw_transaction(commit_txid).await?;
+        let commit_output = commit_tx
+            .output
+            .get(reveal_tx.input[0].previous_output.vout as usize)
+            .context("commit transaction has no commit output")?;
+
+        let destination_address = self.sequencer_address()?.clone();
+        let fee_sat_per_vbyte = self.fee_rate().await?;
+
+        let abandon_tx = build_abandon_transaction(
+            &reveal_tx,
+            commit_output,
+            commit_key_pair,
+            destination_address,
+            fee_sat_per_vbyte,
+        )?;
+
+        let abandon_tx_hash = self
+            .client
+            .send_raw_transaction(encode::serialize(&abandon_tx).encode_hex())
+            .await?;
+
+        info!(
+            "Inscription abandoned, funds recovered. Hash: {}",
+            abandon_tx_hash
+        );
+
+        // the abandon tx is sent, failing here would make the caller retry an abandon that succeeded
+        if let Err(error) = remove_reveal_tx(&self.reveal_dir, commit_tx_id) {
+            warn!(
+                "Failed to remove the file of the abandoned reveal tx: {}",
+                error
+            );
+        }
+
+        Ok(Txid::from_str(&abandon_tx_hash)?)
+    }
+}
+
+/// Runtime configuration for the DA service
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct DaServiceConfig {
+    /// The URL of the Bitcoin node to connect to
+    pub node_url: String,
+    pub node_username: String,
+    pub node_password: String,
+
+    // network of the bitcoin node, one of "bitcoin", "testnet", "signet" or "regtest", defaults to regtest
+    pub network: Option<bitcoin::Network>,
+
+    // taproot address that holds the funds of the sequencer
+    pub address: Option<String>,
+
+    // da private key of the sequencer
+    pub sequencer_da_private_key: Option<String>,
+
+    // number of confirmations after which a block is considered final, defaults depend on the network
+    pub finality_depth: Option<u64>,
+
+    // finality by block depth or by elapsed median time past, defaults to block depth.
+    // A block depth here takes precedence over `finality_depth`.
+    pub finality_rule: Option<FinalityRule>,
+
+    // delay between two checks of the node while waiting for a block in seconds, at least 1, defaults to 10
+    pub polling_interval_secs: Option<u64>,
+
+    // number of attempts to send the reveal tx once the commit tx is sent, defaults to 3
+    pub reveal_retry_attempts: Option<u32>,
+
+    // delay before retrying to send the reveal tx in milliseconds, doubled after every attempt, defaults to 1000
+    pub reveal_retry_backoff_ms: Option<u64>,
+
+    // number of attempts to get the block count, e.g. while the node is starting, defaults to 3
+    pub block_count_retry_attempts: Option<u32>,
+
+    // delay before retrying to get the block count in milliseconds, doubled after every attempt, defaults to 1000
+    pub block_count_retry_backoff_ms: Option<u64>,
+
+    // number of attempts of every RPC request that does not reach the node or fails with a 5xx status, defaults to 5.
+    // Errors returned by the node, e.g. a block not found, are never retried.
+    pub rpc_retry_attempts: Option<u32>,
+
+    // delay before retrying an RPC request in milliseconds, doubled after every attempt, defaults to 100
+    pub rpc_retry_backoff_ms: Option<u64>,
+
+    // maximum number of blocks fetched at the same time by `prefetch_finalized`, defaults to 4
+    pub prefetch_concurrency: Option<usize>,
+
+    // known-good block hashes by height, fetched blocks at these heights must match them
+    pub checkpoints: Option<HashMap<u64, BlockHash>>,
+
+    // blocks directory of the node (`blkNNNNN.dat` files), used by `backfill_from_blocksdir` instead of RPC
+    pub blocksdir: Option<String>,
+
+    // depth of reorg beyond which the service fails instead of re-extracting, defaults to the finality depth
+    pub max_reorg_depth: Option<u64>,
+
+    // directory the reveal txs are written to, created if missing, defaults to the working directory.
+    // The service fails to start if files cannot be written there.
+    pub reveal_dir: Option<PathBuf>,
+
+    // number of independent inscriptions of every blob, from different utxos, defaults to 1
+    pub redundancy: Option<u8>,
+
+    // fee rate in sat/vB used when the node cannot estimate a sane one, defaults to 1
+    pub fallback_fee_rate: Option<f64>,
+
+    // keep the reveal txs on disk once sent instead of only when sending them failed, defaults to false
+    pub keep_sent_reveals: Option<bool>,
+
+    // check that the commit tx is in the mempool before persisting its reveal tx, defaults to false
+    pub check_commit_in_mempool: Option<bool>,
+
+    // confirmations of the commit tx to wait for before sending its reveal tx, defaults to 0 that
+    // sends it right away. More confirmations make a reorg of the commit tx unlikely. Needs -txindex.
+    pub commit_confirmations: Option<u32>,
+
+    // send reveal txs with the annex of the envelope fields, defaults to false. Bitcoin Core
+    // rejects a witness with an annex as non-standard, the reveal tx must reach a miner directly.
+    pub allow_nonstandard_annex: Option<bool>,
+
+    // account xpub to derive change addresses from (`<xpub>/1/<index>`), instead of the wallet of the node
+    pub change_xpub: Option<String>,
+
+    // index of the first change address derived from `change_xpub`, defaults to 0
+    pub change_start_index: Option<u32>,
+
+    // whether the commit fee is subtracted from the change or paid by an additional utxo, defaults to the former
+    pub commit_fee_mode: Option<CommitFeeMode>,
+
+    // whether commit txs signal BIP-125 replaceability, needed by `bump_fee`, defaults to true
+    pub enable_rbf: Option<bool>,
+
+    // whether change below the dust limit is donated to the fee, defaults to true.
+    // When false, a commit tx that would have such change is refused instead.
+    pub donate_dust_change: Option<bool>,
+
+    // whether extraction drops the blobs with an invalid signature or leaves it to the caller, defaults to eager
+    pub signature_verification: Option<SignatureVerification>,
+
+    // SOCKS5 proxy the RPC traffic goes through, e.g. "socks5h://127.0.0.1:9050" for Tor. Needs the `socks` feature.
+    pub socks5_proxy: Option<String>,
+
+    // whether extraction accepts envelopes without the random field, defaults to strict.
+    // The verifier must be built with the same mode, see `BitcoinVerifier::with_parse_mode`.
+    pub envelope_parse_mode: Option<ParseMode>,
+
+    // rollup names accepted besides the one of the rollup within height bounds, e.g. during a rename
+    pub authorized_rollup_names: Option<Vec<AuthorizedRollupName>>,
+
+    // minimum time between two sends in milliseconds, none by default
+    pub min_send_interval_ms: Option<u64>,
+
+    // whether a send coming too soon waits or fails, defaults to waiting
+    pub send_rate_limit: Option<SendRateLimit>,
+
+    // timeout of the RPC requests in milliseconds, none by default
+    pub rpc_timeout_ms: Option<u64>,
+
+    // timeout of the block fetches in milliseconds, defaults to `rpc_timeout_ms`
+    pub rpc_long_timeout_ms: Option<u64>,
+
+    // timeout of the lightweight RPC calls (fee estimation, block count) in milliseconds, defaults to `rpc_timeout_ms`
+    pub rpc_short_timeout_ms: Option<u64>,
+
+    // whether `extract_relevant_txs_checked` permits, flags or rejects duplicate blobs, defaults to permitting
+    pub duplicate_blobs: Option<DuplicateBlobPolicy>,
+
+    // `.cookie` file of the node to authenticate with instead of the username and password
+    pub cookie_file: Option<PathBuf>,
+
+    // size in bytes of the chunks of the compressed blobs too large for a single reveal tx, defaults to 390000
+    pub chunk_size: Option<usize>,
+
+    // hex encoded public keys of the sequencers allowed to sign the blobs, extraction drops the others.
+    // Any key is accepted when unset.
+    pub authorized_sequencers: Option<Vec<String>>,
+
+    // DANGEROUS, off by default. Hex encoded hashes (`blob_hash`) of blobs delivered by a relay the
+    // operator trusts, which extraction accepts without checking their signature nor their sequencer.
+    // Their envelopes may omit the signature and public key, the blobs then have no sender.
+    // Whoever inscribes a blob with one of these hashes gets it accepted, e.g. by replaying it.
+    // The verifier must be given the same hashes, see `BitcoinVerifier::with_trusted_relay_blobs`.
+    pub trusted_relay_blob_hashes: Option<Vec<String>>,
+
+    // scheme the sequencer signs its blobs with, defaults to ecdsa. Both are extracted and
+    // verified. Schnorr embeds the x-only sequencer key, authorized sequencers must list that one.
+    pub signature_scheme: Option<SignatureScheme>,
+
+    // fee rate in sat/vB of the commit and reveal txs, the node is asked for an estimation when unset.
+    // Must be at least the minimum relay fee rate of 1 sat/vB.
+    pub fee_sat_per_vbyte_override: Option<f64>,
+
+    // factor applied to the fee rate of the commit txs, defaults to 1.0
+    pub commit_fee_multiplier: Option<f64>,
+
+    // factor applied to the fee rate of the reveal txs, which carry the blob in a large witness, defaults to 1.0
+    pub reveal_fee_multiplier: Option<f64>,
+
+    // ZMQ endpoint of the `-zmqpubhashblock` notifications of the node, e.g. "tcp://127.0.0.1:28332".
+    // `subscribe_blocks` waits on it instead of polling. Needs the `zmq` feature.
+    pub zmq_block_endpoint: Option<String>,
+
+    // number of finalized blocks kept in memory to serve them again without the node, defaults to 64.
+    // 0 disables the cache.
+    pub block_cache_capacity: Option<usize>,
+}
+
+const POLLING_INTERVAL: u64 = 10; // seconds
+const MAX_BLOCK_FETCH_ATTEMPTS: u32 = 3;
+const DEFAULT_PREFETCH_CONCURRENCY: usize = 4;
+const BLOCK_SUBSCRIPTION_BUFFER: usize = 16;
+const BLOB_STREAM_BUFFER: usize = 64;
+const MAX_FINALIZED_HASHES: usize = 256;
+const DEFAULT_BLOCK_CACHE_CAPACITY: usize = 64;
+const DEFAULT_REVEAL_DIR: &str = ".";
+
+// Returns the finality depth used when none is configured: deep enough for mainnet,
+// shallower for test networks and immediate on regtest
+pub fn default_finality_depth(network: bitcoin::Network) -> u64 {
+    match network {
+        bitcoin::Network::Regtest => 1,
+        bitcoin::Network::Testnet | bitcoin::Network::Signet => 4,
+        _ => 6,
+    }
+}
+
+impl BitcoinService {
+    // Create a new instance of the DA service from the given configuration.
+    // Panics on an invalid configuration, use `try_new` to handle it.
+    pub fn new(config: DaServiceConfig, chain_params: RollupParams) -> Self {
+        Self::try_new(config, chain_params).expect("Invalid DA service configuration")
+    }
+
+    // Create a new instance of the DA service from the given configuration, checking it first.
+    pub fn try_new(
+        config: DaServiceConfig,
+        chain_params: RollupParams,
+    ) -> Result<Self, anyhow::Error> {
+        validate_rollup_name(&chain_params.rollup_name)?;
+        let authorized_rollup_names = config.authorized_rollup_names.unwrap_or_default();
+        for authorized in authorized_rollup_names.iter() {
+            validate_rollup_name(&authorized.name)?;
+        }
+
+        let network = config.network.unwrap_or(bitcoin::Network::Regtest);
+
+        // an address of another network would only be noticed when sending, maybe hours later
+        let address = match config.address.as_deref() {
+            None | Some("") => None,
+            Some(address) => Some(
+                Address::from_str(address)
+                    .with_context(|| format!("Invalid sequencer address {}", address))?
+                    .require_network(network)
+                    .with_context(|| {
+                        format!("Sequencer address {} is not a {} address", address, network)
+                    })?,
+            ),
+        };
+
+        let client = BitcoinNode::new(
+            config.node_url,
+            config.node_username,
+            config.node_password,
+            network,
+        )
+        .with_fallback_fee_rate(
+            config
+                .fallback_fee_rate
+                .unwrap_or(DEFAULT_FALLBACK_FEE_RATE),
+        );
+
+        let client = match config.socks5_proxy {
+            #[cfg(feature = "socks")]
+            Some(proxy) => client.with_socks5_proxy(&proxy)?,
+            #[cfg(not(feature = "socks"))]
+            Some(_) => {
+                return Err(anyhow::anyhow!(
+                    "socks5_proxy is set but the crate is built without the socks feature"
+                ))
+            }
+            None => client,
+        };
+
+        let client = match config.cookie_file {
+            Some(cookie_file) => client.with_cookie_file(cookie_file)?,
+            None => client,
+        };
+
+        let client = match config.rpc_timeout_ms {
+            Some(timeout) => client.with_timeout(Duration::from_millis(timeout)),
+            None => client,
+        };
+        let client = match config.rpc_long_timeout_ms {
+            Some(timeout) => client.with_long_timeout(Duration::from_millis(timeout)),
+            None => client,
+        };
+        let client = match config.rpc_short_timeout_ms {
+            Some(timeout) => client.with_short_timeout(Duration::from_millis(timeout)),
+            None => client,
+        };
+        let client = client.with_retries(
+            config.rpc_retry_attempts.unwrap_or(DEFAULT_RPC_ATTEMPTS),
+            config
+                .rpc_retry_backoff_ms
+                .map(Duration::from_millis)
+                .unwrap_or(DEFAULT_RPC_RETRY_BACKOFF),
+        );
+
+        let reveal_dir = config
+            .reveal_dir
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_REVEAL_DIR));
+        ensure_reveal_dir(&reveal_dir)?;
+
+        let reveal_retry_policy =
+            RetryPolicy::from_config(config.reveal_retry_attempts, config.reveal_retry_backoff_ms);
+        let block_count_retry_policy = RetryPolicy::from_config(
+            config.block_count_retry_attempts,
+            config.block_count_retry_backoff_ms,
+        );
+
+        let finality_depth = match config.finality_rule {
+            Some(FinalityRule::BlockDepth(depth)) => depth,
+            _ => config
+                .finality_depth
+                .unwrap_or_else(|| default_finality_depth(network)),
+        };
+        let finality_rule = config
+            .finality_rule
+            .unwrap_or(FinalityRule::BlockDepth(finality_depth));
+
+        // a zero interval would poll the node in a busy loop
+        let polling_interval_secs = config.polling_interval_secs.unwrap_or(POLLING_INTERVAL);
+        if polling_interval_secs == 0 {
+            warn!("polling_interval_secs is 0, polling every second instead");
+        }
+
+        let change_deriver = config
+            .change_xpub
+            .map(|xpub| {
+                ChangeDeriver::new(&xpub, network, config.change_start_index.unwrap_or(0))
+                    .context("Invalid change xpub")
+            })
+            .transpose()?;
+
+        let authorized_sequencers = config
+            .authorized_sequencers
+            .unwrap_or_default()
+            .iter()
+            .map(|key| hex::decode(key).context("Invalid authorized sequencer key"))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let trusted_relay_blob_hashes = config
+            .trusted_relay_blob_hashes
+            .unwrap_or_default()
+            .iter()
+            .map(|hash| {
+                let hash = hex::decode(hash).context("Invalid trusted relay blob hash")?;
+                <[u8; 32]>::try_from(hash)
+                    .map_err(|_| anyhow::anyhow!("Trusted relay blob hash is not 32 bytes long"))
+            })
+            .collect::<Result<HashSet<_>, _>>()?;
+        if !trusted_relay_blob_hashes.is_empty() {
+            warn!(
+                "{} blobs are accepted without signature from a trusted relay",
+                trusted_relay_blob_hashes.len()
+            );
+        }
+
+        if let Some(fee_rate) = config.fee_sat_per_vbyte_override {
+            // also rejects NaN
+            if !(fee_rate.is_finite() && fee_rate >= MIN_RELAY_FEE_RATE) {
+                return Err(anyhow::anyhow!(
+                    "fee rate override of {} sat/vB is below the minimum relay fee rate of {}",
+                    fee_rate,
+                    MIN_RELAY_FEE_RATE
+                ));
+            }
+        }
+
+        #[cfg(not(feature = "zmq"))]
+        if config.zmq_block_endpoint.is_some() {
+            return Err(anyhow::anyhow!(
+                "zmq_block_endpoint is set but the crate is built without the zmq feature"
+            ));
+        }
+
+        let commit_fee_multiplier = config.commit_fee_multiplier.unwrap_or(1.0);
+        let reveal_fee_multiplier = config.reveal_fee_multiplier.unwrap_or(1.0);
+        for multiplier in [commit_fee_multiplier, reveal_fee_multiplier] {
+            if !(multiplier.is_finite() && multiplier > 0.0) {
+                return Err(anyhow::anyhow!(
+                    "fee multipliers must be positive, got {}",
+                    multiplier
+                ));
+            }
+        }
+
+        Ok(Self {
+            finality_depth,
+            finality_rule,
+            polling_interval: Duration::from_secs(polling_interval_secs.max(1)),
+            max_reorg_depth: config.max_reorg_depth.unwrap_or(finality_depth),
+            reveal_dir,
+            redundancy: config.redundancy.unwrap_or(1).max(1),
+            keep_sent_reveals: config.keep_sent_reveals.unwrap_or(false),
+            check_commit_in_mempool: config.check_commit_in_mempool.unwrap_or(false),
+            commit_confirmations: config.commit_confirmations.unwrap_or(0),
+            commit_fee_mode: config.commit_fee_mode.unwrap_or_default(),
+            enable_rbf: config.enable_rbf.unwrap_or(true),
+            donate_dust_change: config.donate_dust_change.unwrap_or(true),
+            allow_nonstandard_annex: config.allow_nonstandard_annex.unwrap_or(false),
+            signature_verification: config.signature_verification.unwrap_or_default(),
+            parse_mode: config.envelope_parse_mode.unwrap_or_default(),
+            authorized_rollup_names,
+            min_send_interval: config.min_send_interval_ms.map(Duration::from_millis),
+            send_rate_limit: config.send_rate_limit.unwrap_or_default(),
+            duplicate_blob_policy: config.duplicate_blobs.unwrap_or_default(),
+            chunk_size: config.chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE).max(1),
+            authorized_sequencers,
+            trusted_relay_blob_hashes,
+            signature_scheme: config.signature_scheme.unwrap_or_default(),
+            fee_rate_override: config.fee_sat_per_vbyte_override,
+            commit_fee_multiplier,
+            reveal_fee_multiplier,
+            zmq_block_endpoint: config.zmq_block_endpoint,
+            block_cache: Arc::new(Mutex::new(BlockCache::new(
+                config
+                    .block_cache_capacity
+                    .unwrap_or(DEFAULT_BLOCK_CACHE_CAPACITY),
+            ))),
+            change_deriver: change_deriver.map(Arc::new),
+            reveal_retry_policy,
+            block_count_retry_policy,
+            prefetch_concurrency: config
+                .prefetch_concurrency
+                .unwrap_or(DEFAULT_PREFETCH_CONCURRENCY)
+                .max(1),
+            checkpoints: config.checkpoints.unwrap_or_default(),
+            blocksdir: config.blocksdir.map(|path| BlocksDir::new(path, network)),
+            ..Self::with_client(
+                client,
+                chain_params.rollup_name,
+                network,
+                address,
+                config.sequencer_da_private_key.unwrap_or("".to_owned()),
+            )
+        })
+    }
+}
+
+// Returns the utxo holding the sat of the satpoint, which must be spendable
+fn find_satpoint_utxo(utxos: &[UTXO], satpoint: SatPoint) -> Result<&UTXO, BitcoinServiceError> {
+    let invalid = |reason: &str| BitcoinServiceError::InvalidSatpoint {
+        satpoint,
+        reason: reason.to_string(),
+    };
+
+    let utxo = utxos
+        .iter()
+        .find(|utxo| OutPoint::new(utxo.tx_id, utxo.vout) == satpoint.outpoint)
+        .ok_or_else(|| invalid("its utxo is not available in the wallet"))?;
+    if !utxo.spendable {
+        return Err(invalid("its utxo is not spendable"));
+    }
+    if satpoint.offset >= utxo.amount {
+        return Err(invalid(&format!("its utxo holds {} sat", utxo.amount)));
+    }
+
+    Ok(utxo)
+}
+
+// Checks that the rollup name can be pushed in the envelope and matched by the parser.
+// Names are compared byte for byte, so they are restricted to unambiguous ASCII.
+fn validate_rollup_name(name: &str) -> Result<(), BitcoinServiceError> {
+    let invalid = |reason: &str| BitcoinServiceError::InvalidRollupName {
+        name: name.to_string(),
+        reason: reason.to_string(),
+    };
+
+    if name.is_empty() {
+        return Err(invalid("it is empty"));
+    }
+    if name.len() > MAX_ROLLUP_NAME_LEN {
+        return Err(invalid(&format!(
+            "it is longer than {} bytes",
+            MAX_ROLLUP_NAME_LEN
+        )));
+    }
+    if !name
+        .bytes()
+        .all(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.'))
+    {
+        return Err(invalid(
+            "only ASCII letters, digits, '-', '_' and '.' are allowed",
+        ));
+    }
+
+    Ok(())
+}
+
+#[async_trait]
+impl DaService for BitcoinService {
+    type Spec = BitcoinSpec;
+
+    type Verifier = BitcoinVerifier;
+
+    type FilteredBlock = BitcoinBlock;
+
+    type Error = anyhow::Error;
+
+    // Make an RPC call to the node to get the finalized block at the given height, if one exists.
+    // If no such block exists, block until one does.
+    async fn get_finalized_at(&self, height: u64) -> Result<Self::FilteredBlock, Self::Error> {
+        let client = self.client.clone();
+        info!("Getting finalized block at height {}", height);
+        loop {
+            // the node may be briefly unavailable, e.g. at startup
+            let block_count = self
+                .block_count_retry_policy
+                .retry(self.clock.as_ref(), "get block count", || {
+                    client.get_block_count()
+                })
+                .await?;
+
+            // once the block is deep or old enough, we can be sure that it is finalized
+            if self.is_finalized(height, block_count).await? {
+                #[cfg(feature = "metrics")]
+                telemetry::record_finality_lag(block_count, height);
+                break;
+            }
+
+            info!("Block not finalized, waiting");
+            self.clock.sleep(self.polling_interval).await;
+        }
+
+        let block = self.get_existing_block_at(height).await?;
+        self.check_finalized_hash(&block)?;
+        self.block_cache.lock().unwrap().insert(block.clone());
+
+        Ok(block)
+    }
+
+    // Make an RPC call to the node to get the block at the given height
+    // If no such block exists, block until one does.
+    async fn get_block_at(&self, height: u64) -> Result<Self::FilteredBlock, Self::Error> {
+        let client = self.client.clone();
+        info!("Getting block at height {}", height);
+
+        let mut attempts = 0;
+        loop {
+            let block_hash = match client.get_block_hash(height).await {
+                Ok(block_hash_response) => block_hash_response,
+                Err(error) => match error.downcast_ref::<RPCError>().map(RPCError::kind) {
+                    // the height is above the tip
+                    Some(RPCErrorKind::InvalidParameter) => {
+                        info!("Block not found, waiting");
+                        self.clock.sleep(self.polling_interval).await;
+                        continue;
+                    }
+                    // other errors are returned as is, so that callers can match on their kind
+                    _ => return Err(error),
+                },
+            };
+
+            #[cfg(feature = "metrics")]
+            let started = Instant::now();
+            let block = self.get_block_matching_hash(height, block_hash).await?;
+            #[cfg(feature = "metrics")]
+            telemetry::record_get_block_latency(started.elapsed());
+
+            if let Some(block) = block {
+                self.cache_if_finalized(&block).await;
+                return Ok(block);
+            }
+
+            attempts += 1;
+            if attempts >= MAX_BLOCK_FETCH_ATTEMPTS {
+                return Err(anyhow::anyhow!(
+                    "Block at height {} kept changing while being fetched",
+                    height
+                ));
+            }
+        }
+    }
+
+    // Extract the blob transactions relevant to a particular rollup from a block.
+    fn extract_relevant_txs(
+        &self,
+        block: &Self::FilteredBlock,
+    ) -> Vec<<Self::Spec as sov_rollup_interface::da::DaSpec>::BlobTransaction> {
+        info!(
+            "Extracting relevant txs from block {:?}",
+            block.header.header.block_hash()
+        );
+
+        // iterate over all transactions in the block
+        dedup_blobs(self.blobs_from_block(block))
+    }
+
+    async fn get_extraction_proof(
+        &self,
+        block: &Self::FilteredBlock,
+        _blobs: &[<Self::Spec as sov_rollup_interface::da::DaSpec>::BlobTransaction],
+    ) -> (
+        <Self::Spec as sov_rollup_interface::da::DaSpec>::InclusionMultiProof,
+        <Self::Spec as sov_rollup_interface::da::DaSpec>::CompletenessProof,
+    ) {
+        info!(
+            "Getting extraction proof for block {:?}",
+            block.header.header.block_hash()
+        );
+
+        self.extraction_proof_of(block).into_parts()
+    }
+
+    // Extract the list blob transactions relevant to a particular rollup from a block, along with inclusion and
+    // completeness proofs for that set of transactions. The output of this method will be passed to the verifier.
+    async fn extract_relevant_txs_with_proof(
+        &self,
+        block: &Self::FilteredBlock,
+    ) -> (
+        Vec<<Self::Spec as sov_rollup_interface::da::DaSpec>::BlobTransaction>,
+        <Self::Spec as sov_rollup_interface::da::DaSpec>::InclusionMultiProof,
+        <Self::Spec as sov_rollup_interface::da::DaSpec>::CompletenessProof,
+    ) {
+        info!(
+            "Extracting relevant txs with proof from block {:?}",
+            block.header.header.block_hash()
+        );
+
+        // a single pass over the transactions, same result as `extract_relevant_txs` and `get_extraction_proof`
+        // as long as the verifier uses the same parse mode, see `BitcoinVerifier::with_parse_mode`
+        let (blobs, inclusion_proof, completeness_proof) = extract_blobs_and_proof(
+            block.txdata.iter().map(|tx| &tx.transaction),
+            &self.rollup_names_at(block.header.height),
+            self.authenticator.as_ref(),
+            &self.authorized_sequencers,
+            &self.trusted_relay_blob_hashes,
+            self.parse_mode,
+        );
+
+        (
+            blobs,
+            inclusion_proof,
+            self.with_height_proof(block, completeness_proof),
+        )
+    }
+
+    async fn send_transaction(&self, blob: &[u8]) -> Result<(), Self::Error> {
+        self.send_transaction_with_ids(blob).await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+    use core::time::Duration;
+    use std::collections::{HashMap, HashSet};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Instant;
+
+    use async_trait::async_trait;
+    use bitcoin::absolute::LockTime;
+    use bitcoin::block::{Header, Version};
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::consensus::{encode, Decodable};
+    use bitcoin::hash_types::TxMerkleNode;
+    use bitcoin::hashes::Hash;
+    use bitcoin::psbt::Prevouts;
+    use bitcoin::secp256k1::{schnorr, Message, Secp256k1, XOnlyPublicKey};
+    use bitcoin::sighash::{SighashCache, TapSighashType};
+    use bitcoin::string::FromHexStr;
+    use bitcoin::{
+        merkle_tree, Address, BlockHash, CompactTarget, OutPoint, ScriptBuf, Sequence, Transaction,
+        TxIn, TxOut, Txid,
+    };
+    use ord::SatPoint;
+    use serde_json::json;
+    use sov_rollup_interface::da::{BlockHeaderTrait, DaVerifier};
+    use sov_rollup_interface::services::da::DaService;
+
+    use super::{
+        extract_blobs, extract_blobs_with_proof, extraction_proof, BitcoinService,
+        BitcoinServiceError, ConfStatus, DuplicateBlobPolicy, DuplicateBlobs, FinalityRule,
+        PreflightCheck, RetryPolicy, SendRateLimit, SignatureVerification, SubmittedInscription,
+    };
+    use crate::authenticator::{BlobAuthenticator, EcdsaAuthenticator};
+    use crate::blocksdir::BlocksDir;
+    use crate::clock::Clock;
+    use crate::helpers::builders::{
+        compress_blob, get_commit_satpoint, read_reveal_tx, write_reveal_tx, BuilderError,
+        CommitFeeMode, EnvelopeFields,
+    };
+    use crate::helpers::parsers::{
+        parse_inscriptions, parse_transaction, recover_sender_and_hash_from_tx,
+        verify_blob_signature, ParseMode,
+    };
+    use crate::helpers::test_utils::{
+        build_mock_reveal_tx, get_extended_tx, get_mock_forged_reveal_tx, get_mock_reveal_tx,
+        get_mock_reveal_tx_signed_by, get_mock_reveal_tx_with_body, get_mock_unsigned_reveal_tx,
+        SEQUENCER_DA_PRIVATE_KEY,
+    };
+    use crate::helpers::{blob_hash, ChunkInfo, Compression, SignatureScheme, MAX_METADATA_LEN};
+    use crate::rpc::mock::start_mock_node;
+    use crate::rpc::{BitcoinNode, RPCError};
+    use crate::service::DaServiceConfig;
+    use crate::spec::block::BitcoinBlock;
+    use crate::spec::header::HeaderWrapper;
+    use crate::spec::{AuthorizedRollupName, RollupParams};
+    use crate::verifier::BitcoinVerifier;
+
+    // relevant txs are on 6, 8, 10, 12 indices
+    fn get_mock_txs() -> Vec<Transaction> {
+        let txs = std::fs::read_to_string("test_data/mock_txs.txt").unwrap();
+
+        txs.lines()
+            .map(|tx| Transaction::consensus_decode(&mut &hex::decode(tx).unwrap()[..]).unwrap())
+            .collect()
+    }
+
+    fn get_mock_header() -> HeaderWrapper {
+        HeaderWrapper {
+            header: Header {
+                version: Version::from_consensus(536870912),
+                prev_blockhash: BlockHash::from_str(
+                    "6b15a2e4b17b0aabbd418634ae9410b46feaabf693eea4c8621ffe71435d24b0",
+                )
+                .unwrap(),
+                merkle_root: TxMerkleNode::from_str(
+                    "7750076b3b5498aad3e2e7da55618c66394d1368dc08f19f0b13d1e5b83ae056",
+                )
+                .unwrap(),
+                time: 1694177029,
+                bits: CompactTarget::from_hex_str_no_prefix("207fffff").unwrap(),
+                nonce: 0,
+            },
+            tx_count: 13,
+            height: 2,
+        }
+    }
+
+    fn get_mock_block() -> BitcoinBlock {
+        let txdata = get_mock_txs()
+            .into_iter()
+            .map(|transaction| get_extended_tx(transaction, "sov-btc"))
+            .collect();
+
+        BitcoinBlock {
+            header: get_mock_header(),
+            txdata,
+        }
+    }
+
+    // Returns a distinct mock block for the height, the first two on top of the regtest genesis
+    // as the node checks that they belong to the network
+    fn get_mock_block_at(height: u64) -> BitcoinBlock {
+        let mut block = get_mock_block();
+        block.header.header.nonce = height as u32;
+        block.header.height = height;
+        match height {
+            0 => block.header.header = genesis_block(bitcoin::Network::Regtest).header,
+            1 => {
+                block.header.header.prev_blockhash =
+                    genesis_block(bitcoin::Network::Regtest).block_hash()
+            }
+            _ => {}
+        }
+        block
+    }
+
+    // Returns the block in the verbose format of `getblock`
+    fn get_block_json(block: &BitcoinBlock) -> serde_json::Value {
+        let header = &block.header.header;
+        json!({
+            "hash": header.block_hash().to_string(),
+            "bits": format!("{:08x}", header.bits.to_consensus()),
+            "merkleroot": header.merkle_root.to_string(),
+            "nonce": header.nonce,
+            "previousblockhash": header.prev_blockhash.to_string(),
+            "time": header.time,
+            "version": header.version.to_consensus(),
+            "height": block.header.height,
+            "tx": block
+                .txdata
+                .iter()
+                .map(|tx| json!({ "hex": encode::serialize_hex(&tx.transaction) }))
+                .collect::<Vec<_>>(),
+        })
+    }
+
+    fn get_mock_service(node_url: String) -> BitcoinService {
+        BitcoinService::with_client(
+            BitcoinNode::new(
+                node_url,
+                "chainway".to_string(),
+                "topsecret".to_string(),
+                bitcoin::Network::Regtest,
+            ),
+            "sov-btc".to_string(),
+            bitcoin::Network::Regtest,
+            Some(
+                Address::from_str("bcrt1qxuds94z3pqwqea2p4f4ev4f25s6uu7y3avljrl")
+                    .unwrap()
+                    .assume_checked(),
+            ),
+            "E9873D79C6D87DC0FB6A5778633389F4453213303DA61F20BD67FC233AA33262".to_string(),
+        )
+    }
+
+    // Starts a node with a funded wallet, the transactions sent to it are pushed to `sent_txs`
+    fn start_mock_wallet_node(sent_txs: Arc<Mutex<Vec<Transaction>>>) -> String {
+        start_mock_wallet_node_with_limit(sent_txs, usize::MAX)
+    }
+
+    // Same as `start_mock_wallet_node`, rejecting every tx sent after the first `max_sent_txs`
+    fn start_mock_wallet_node_with_limit(
+        sent_txs: Arc<Mutex<Vec<Transaction>>>,
+        max_sent_txs: usize,
+    ) -> String {
+        start_mock_node(move |method, params| {
+            mock_wallet_call(&sent_txs, max_sent_txs, method, params)
+        })
+    }
+
+    // Answers the calls of the service to a funded wallet node, see `start_mock_wallet_node`
+    fn mock_wallet_call(
+        sent_txs: &Mutex<Vec<Transaction>>,
+        max_sent_txs: usize,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Result<serde_json::Value, RPCError> {
+        match method {
+            "getrawchangeaddress" => Ok(json!("bcrt1qxuds94z3pqwqea2p4f4ev4f25s6uu7y3avljrl")),
+            "listunspent" => Ok(json!([
+                {
+                    "txid": "4cfbec13cf1510545f285cceceb6229bd7b6a918a8f6eba1dbee64d26226a3b7",
+                    "vout": 0,
+                    "address": "bcrt1qxuds94z3pqwqea2p4f4ev4f25s6uu7y3avljrl",
+                    "scriptPubKey": "",
+                    "amount": 1.0,
+                    "confirmations": 100,
+                    "spendable": true,
+                    "solvable": true,
+                },
+                {
+                    "txid": "9f6e4b5a2c8d7e1f0a3b6c9d2e5f8a1b4c7d0e3f6a9b2c5d8e1f4a7b0c3d6e9f",
+                    "vout": 1,
+                    "address": "bcrt1qxuds94z3pqwqea2p4f4ev4f25s6uu7y3avljrl",
+                    "scriptPubKey": "",
+                    "amount": 1.0,
+                    "confirmations": 100,
+                    "spendable": true,
+                    "solvable": true,
+                },
+            ])),
+            "estimatesmartfee" => Ok(json!({ "feerate": 0.00001 })),
+            "signrawtransactionwithwallet" => Ok(json!({ "hex": params[0] })),
+            "gettxout" => Ok(json!({ "value": 1.0 })),
+            "getrawtransaction" => sent_txs
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|tx| tx.txid().to_string() == params[0].as_str().unwrap())
+                .map(|tx| json!(encode::serialize_hex(tx)))
+                .ok_or(RPCError {
+                    code: -5,
+                    message: "No such mempool or blockchain transaction".to_string(),
+                }),
+            "gettxspendingprevout" => {
+                let prevout = &params[0][0];
+                let outpoint = OutPoint::new(
+                    Txid::from_str(prevout["txid"].as_str().unwrap()).unwrap(),
+                    prevout["vout"].as_u64().unwrap() as u32,
+                );
+                let spends = |input: &TxIn| input.previous_output == outpoint;
+                let spending_txid = sent_txs
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .find(|tx| tx.input.iter().any(spends))
+                    .map(|tx| tx.txid().to_string());
+                Ok(json!([{
+                    "txid": prevout["txid"],
+                    "vout": prevout["vout"],
+                    "spendingtxid": spending_txid,
+                }]))
+            }
+            "sendrawtransaction" if sent_txs.lock().unwrap().len() >= max_sent_txs => {
+                Err(RPCError {
+                    code: -26,
+                    message: "mempool full".to_string(),
+                })
+            }
+            "sendrawtransaction" => {
+                let tx: Transaction =
+                    encode::deserialize(&hex::decode(params[0].as_str().unwrap()).unwrap())
+                        .unwrap();
+                let txid = tx.txid();
+                sent_txs.lock().unwrap().push(tx);
+                Ok(json!(txid.to_string()))
+            }
+            _ => Err(RPCError {
+                code: -32601,
+                message: "Method not found".to_string(),
+            }),
+        }
+    }
+
+    fn get_config() -> DaServiceConfig {
+        DaServiceConfig {
+            node_url: "http://localhost:38332".to_string(),
+            node_username: "chainway".to_string(),
+            node_password: "topsecret".to_string(),
+            network: Some(bitcoin::Network::Regtest),
+            address: Some("bcrt1qxuds94z3pqwqea2p4f4ev4f25s6uu7y3avljrl".to_string()),
             sequencer_da_private_key: Some(
                 "E9873D79C6D87DC0FB6A5778633389F4453213303DA61F20BD67FC233AA33262".to_string(), // Test key, safe to publish
             ),
+            finality_depth: None,
+            finality_rule: None,
+            polling_interval_secs: None,
+            reveal_retry_attempts: None,
+            reveal_retry_backoff_ms: None,
+            block_count_retry_attempts: None,
+            block_count_retry_backoff_ms: None,
+            rpc_retry_attempts: None,
+            rpc_retry_backoff_ms: None,
+            prefetch_concurrency: None,
+            checkpoints: None,
+            blocksdir: None,
+            max_reorg_depth: None,
+            reveal_dir: None,
+            redundancy: None,
+            fallback_fee_rate: None,
+            keep_sent_reveals: None,
+            check_commit_in_mempool: None,
+            commit_confirmations: None,
+            allow_nonstandard_annex: None,
+            change_xpub: None,
+            change_start_index: None,
+            commit_fee_mode: None,
+            enable_rbf: None,
+            donate_dust_change: None,
+            signature_verification: None,
+            socks5_proxy: None,
+            envelope_parse_mode: None,
+            authorized_rollup_names: None,
+            min_send_interval_ms: None,
+            send_rate_limit: None,
+            rpc_timeout_ms: None,
+            rpc_long_timeout_ms: None,
+            rpc_short_timeout_ms: None,
+            duplicate_blobs: None,
+            cookie_file: None,
+            chunk_size: None,
+            authorized_sequencers: None,
+            trusted_relay_blob_hashes: None,
+            signature_scheme: None,
+            fee_sat_per_vbyte_override: None,
+            commit_fee_multiplier: None,
+            reveal_fee_multiplier: None,
+            zmq_block_endpoint: None,
+            block_cache_capacity: None,
+        }
+    }
+
+    async fn get_service() -> BitcoinService {
+        let runtime_config = get_config();
+
+        BitcoinService::new(
+            runtime_config,
+            RollupParams {
+                rollup_name: "sov-btc".to_string(),
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn get_finalized_at() {
+        let da_service = get_service().await;
+
+        da_service
+            .get_finalized_at(132)
+            .await
+            .expect("Failed to get block");
+    }
+
+    #[tokio::test]
+    async fn get_block_at() {
+        let da_service = get_service().await;
+
+        da_service
+            .get_block_at(132)
+            .await
+            .expect("Failed to get block");
+    }
+
+    #[tokio::test]
+    async fn extract_relevant_txs() {
+        let da_service = get_service().await;
+
+        let block = da_service
+            .get_block_at(132)
+            .await
+            .expect("Failed to get block");
+        // panic!();
+
+        let txs = da_service.extract_relevant_txs(&block);
+
+        for tx in txs {
+            println!("blob: {:?}", tx.blob);
+        }
+    }
+
+    #[tokio::test]
+    async fn extract_relevant_txs_with_proof() {
+        let da_service = get_service().await;
+
+        let block = da_service
+            .get_block_at(142)
+            .await
+            .expect("Failed to get block");
+
+        let (txs, inclusion_proof, completeness_proof) =
+            da_service.extract_relevant_txs_with_proof(&block).await;
+        
+        // completeness proof
+
+        // create hash set of txs
+        let mut txs_to_check = txs.iter().map(|blob| blob.hash).collect::<HashSet<_>>();
+
+        // Check every 00 bytes tx that parsed correctly is in txs
+        let mut completeness_tx_hashes = completeness_proof
+            .iter()
+            .map(|tx| {
+                let tx_hash = tx.txid().to_raw_hash().to_byte_array();
+
+                // it must parsed correctly
+                let parsed_tx = parse_transaction(tx, &da_service.rollup_name);
+                if parsed_tx.is_ok() {
+                    let blob = parsed_tx.unwrap().body;
+                    let blob_hash = blob_hash(&blob);
+                    // it must be in txs
+                    assert!(txs_to_check.remove(&blob_hash));
+                }
+
+                tx_hash
+            })
+            .collect::<HashSet<_>>();
+
+        // assert no extra txs than the ones in the completeness proof are left
+        assert!(txs_to_check.is_empty());
+
+        // no 00 bytes left behind completeness proof
+        inclusion_proof.txs.iter().for_each(|tx_hash| {
+            if tx_hash[0..2] == [0, 0] {
+                assert!(completeness_tx_hashes.remove(tx_hash));
+            }
+        });
+
+        // assert all transactions are included in block
+        assert!(completeness_tx_hashes.is_empty());
+
+        println!("\n--- Completeness proof verified ---\n");
+
+        let tx_root = block
+            .header
+            .header
+            .merkle_root
+            .to_raw_hash()
+            .to_byte_array();
+
+        // Inclusion proof is all the txs in the block.
+        let tx_hashes = inclusion_proof
+            .txs
+            .iter()
+            .map(|tx| Txid::from_slice(tx).unwrap())
+            .collect::<Vec<_>>();
+
+        let root_from_inclusion = merkle_tree::calculate_root(tx_hashes.into_iter())
+            .unwrap()
+            .to_raw_hash()
+            .to_byte_array();
+
+        // Check that the tx root in the block header matches the tx root in the inclusion proof.
+        assert_eq!(root_from_inclusion, tx_root);
+
+        println!("\n--- Inclusion proof verified ---\n");
+
+        println!("\n--- Extracted #{:?} txs ---\n", txs.len());
+    }
+
+    #[tokio::test]
+    async fn send_transaction() {
+        let da_service = get_service().await;
+
+        let blob = "01000000b60000002adbd76606f2bd4125080e6f44df7ba2d728409955c80b8438eb1828ddf23e3c12188eeac7ecf6323be0ed5668e21cc354fca90d8bca513d6c0a240c26afa7007b758bf2e7670fafaf6bf0015ce0ff5aa802306fc7e3f45762853ffc37180fe64a0000000001fea6ac5b8751120fb62fff67b54d2eac66aef307c7dde1d394dea1e09e43dd44c800000000000000135d23aee8cb15c890831ff36db170157acaac31df9bba6cd40e7329e608eabd0000000000000000";
+        da_service
+            .send_transaction(blob.as_bytes())
+            .await
+            .expect("Failed to send transaction");
+    }
+
+    #[tokio::test]
+    async fn extract_relevant_txs_with_fees() {
+        const FEE: u64 = 1_234;
+
+        let block = get_mock_block();
+
+        // every relevant tx spends a single prevout, which is worth exactly its outputs plus `FEE`
+        let mut prevouts = HashMap::new();
+        for tx in block.txdata.iter().filter(|tx| tx.blob_hash.is_some()) {
+            assert_eq!(tx.transaction.input.len(), 1);
+            let output_value: u64 = tx.transaction.output.iter().map(|o| o.value).sum();
+            let outpoint = tx.transaction.input[0].previous_output;
+            prevouts.insert(
+                outpoint.txid.to_string(),
+                (outpoint.vout, output_value + FEE),
+            );
+        }
+
+        let node_url = start_mock_node(move |method, params| match method {
+            "getrawtransaction" => {
+                let (vout, value) = prevouts[params[0].as_str().unwrap()];
+                let prev_tx = Transaction {
+                    version: 2,
+                    lock_time: LockTime::ZERO,
+                    input: vec![TxIn::default()],
+                    output: (0..=vout)
+                        .map(|_| TxOut {
+                            value,
+                            script_pubkey: ScriptBuf::new(),
+                        })
+                        .collect(),
+                };
+                Ok(json!(encode::serialize_hex(&prev_tx)))
+            }
+            _ => Err(RPCError {
+                code: -32601,
+                message: "Method not found".to_string(),
+            }),
+        });
+
+        let da_service = get_mock_service(node_url);
+
+        let txs_with_fees = da_service
+            .extract_relevant_txs_with_fees(&block)
+            .await
+            .expect("Failed to extract txs with fees");
+
+        assert_eq!(txs_with_fees.len(), 4);
+        for (blob, fee) in txs_with_fees {
+            assert_eq!(fee, FEE);
+            assert!(!blob.sender.0.is_empty());
+        }
+    }
+
+    #[test]
+    fn default_finality_depth_per_network() {
+        let finality_depth_for = |network: &str| {
+            let config = DaServiceConfig {
+                network: Some(bitcoin::Network::from_str(network).unwrap()),
+                ..get_config()
+            };
+
+            BitcoinService::new(
+                config,
+                RollupParams {
+                    rollup_name: "sov-btc".to_string(),
+                },
+            )
+            .finality_depth
+        };
+
+        assert_eq!(finality_depth_for("bitcoin"), 6);
+        assert_eq!(finality_depth_for("testnet"), 4);
+        assert_eq!(finality_depth_for("signet"), 4);
+        assert_eq!(finality_depth_for("regtest"), 1);
+    }
+
+    #[test]
+    fn polling_interval() {
+        let polling_interval_for = |polling_interval_secs: Option<u64>| {
+            BitcoinService::new(
+                DaServiceConfig {
+                    polling_interval_secs,
+                    ..get_config()
+                },
+                RollupParams {
+                    rollup_name: "sov-btc".to_string(),
+                },
+            )
+            .polling_interval
+        };
+
+        assert_eq!(polling_interval_for(None), Duration::from_secs(10));
+        assert_eq!(polling_interval_for(Some(30)), Duration::from_secs(30));
+        // a zero interval is raised to a second
+        assert_eq!(polling_interval_for(Some(0)), Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn reveal_dir_is_checked_at_construction() {
+        let root =
+            std::env::temp_dir().join(format!("bitcoin-da-reveal-dir-{}", std::process::id()));
+        let reveal_dir = root.join("reveals");
+
+        // the directory is created, the reveal txs are written under it
+        let sent_txs = Arc::new(Mutex::new(Vec::new()));
+        let da_service = BitcoinService::try_new(
+            DaServiceConfig {
+                node_url: start_mock_wallet_node(sent_txs.clone()),
+                reveal_dir: Some(reveal_dir.clone()),
+                keep_sent_reveals: Some(true),
+                ..get_config()
+            },
+            RollupParams {
+                rollup_name: "sov-btc".to_string(),
+            },
+        )
+        .unwrap();
+        assert_eq!(std::fs::read_dir(&reveal_dir).unwrap().count(), 0);
+        let submitted = da_service.send_transaction_with_ids(b"kept").await.unwrap();
+        let commit_txid = submitted[0].commit_txid.to_raw_hash().to_string();
+        assert!(read_reveal_tx(&reveal_dir, commit_txid).is_ok());
+        assert_eq!(std::fs::read_dir(&reveal_dir).unwrap().count(), 1);
+
+        // a directory that cannot be created is reported, not the first send
+        let file = root.join("file");
+        std::fs::write(&file, b"").unwrap();
+        let error = BitcoinService::try_new(
+            DaServiceConfig {
+                reveal_dir: Some(file.join("reveals")),
+                ..get_config()
+            },
+            RollupParams {
+                rollup_name: "sov-btc".to_string(),
+            },
+        )
+        .unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Cannot create the reveal directory"));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn get_block_at_retries_when_hash_changes() {
+        let stale_block = get_mock_block();
+        let mut new_block = get_mock_block();
+        new_block.header.header.nonce = 1;
+
+        let stale_hash = stale_block.header.header.block_hash().to_string();
+        let new_hash = new_block.header.header.block_hash().to_string();
+        let new_block_json = get_block_json(&new_block);
+
+        // the chain switches to the new block right after the first hash is served
+        let hash_requests = AtomicUsize::new(0);
+        let block_requests = Arc::new(AtomicUsize::new(0));
+        let block_requests_in_node = block_requests.clone();
+        let node_url = start_mock_node(move |method, _params| match method {
+            "getblockhash" => match hash_requests.fetch_add(1, Ordering::SeqCst) {
+                0 => Ok(json!(stale_hash)),
+                _ => Ok(json!(new_hash)),
+            },
+            "getblock" => {
+                block_requests_in_node.fetch_add(1, Ordering::SeqCst);
+                Ok(new_block_json.clone())
+            }
+            _ => Err(RPCError {
+                code: -32601,
+                message: "Method not found".to_string(),
+            }),
+        });
+
+        let da_service = get_mock_service(node_url);
+
+        let block = da_service
+            .get_block_at(2)
+            .await
+            .expect("Failed to get block");
+
+        assert_eq!(
+            block.header.header.block_hash(),
+            new_block.header.header.block_hash()
+        );
+        assert_eq!(block_requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn extract_relevant_txs_with_indices() {
+        let da_service = get_mock_service("http://localhost:38332".to_string());
+        let block = get_mock_block();
+
+        let indexed_txs = da_service.extract_relevant_txs_with_indices(&block);
+
+        let indices = indexed_txs
+            .iter()
+            .map(|(index, _)| *index)
+            .collect::<Vec<_>>();
+        assert_eq!(indices, vec![6, 8, 10, 12]);
+
+        for (index, blob) in indexed_txs {
+            assert_eq!(Some(blob.hash), block.txdata[index].blob_hash);
+        }
+
+        // same blobs, in the same order, as the plain extraction
+        let txs = da_service.extract_relevant_txs(&block);
+        assert_eq!(
+            txs.iter().map(|blob| blob.hash).collect::<Vec<_>>(),
+            block
+                .txdata
+                .iter()
+                .filter_map(|tx| tx.blob_hash)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn send_reveal_with_retry() {
+        // the reveal is rejected twice before being accepted
+        let send_requests = Arc::new(AtomicUsize::new(0));
+        let send_requests_in_node = send_requests.clone();
+        let node_url = start_mock_node(move |method, _params| match method {
+            "sendrawtransaction" => match send_requests_in_node.fetch_add(1, Ordering::SeqCst) {
+                0 | 1 => Err(RPCError {
+                    code: -25,
+                    message: "bad-txns-inputs-missingorspent".to_string(),
+                }),
+                _ => Ok(json!(
+                    "0000e3d0f6b1a9b3b5d0e39b0a8d8d3ef6b8de2c4f3c9b8ad6f9ab5d3b2f0e1c"
+                )),
+            },
+            _ => Err(RPCError {
+                code: -32601,
+                message: "Method not found".to_string(),
+            }),
+        });
+
+        // the backoff goes through the clock
+        let sleeps = Arc::new(Mutex::new(Vec::new()));
+        let da_service = BitcoinService {
+            reveal_retry_policy: RetryPolicy {
+                max_attempts: 3,
+                backoff: Duration::from_secs(1),
+            },
+            ..get_mock_service(node_url)
+        }
+        .with_clock(VirtualClock::new(sleeps.clone()));
+
+        let reveal_tx_hash = da_service
+            .send_reveal_with_retry("00".to_string())
+            .await
+            .expect("Reveal should be sent on the third attempt");
+
+        assert_eq!(
+            reveal_tx_hash,
+            "0000e3d0f6b1a9b3b5d0e39b0a8d8d3ef6b8de2c4f3c9b8ad6f9ab5d3b2f0e1c"
+        );
+        assert_eq!(send_requests.load(Ordering::SeqCst), 3);
+        assert_eq!(
+            *sleeps.lock().unwrap(),
+            vec![Duration::from_secs(1), Duration::from_secs(2)]
+        );
+    }
+
+    #[test]
+    fn extract_relevant_txs_in_namespace() {
+        let da_service = get_mock_service("http://localhost:38332".to_string());
+
+        let shard_0 = EnvelopeFields {
+            namespace_id: Some([0; 8]),
+            ..Default::default()
+        };
+        let shard_1 = EnvelopeFields {
+            namespace_id: Some([1; 8]),
+            ..Default::default()
+        };
+
+        let block = BitcoinBlock {
+            header: get_mock_header(),
+            txdata: vec![
+                get_mock_reveal_tx("sov-btc", b"shard 0, first", &shard_0),
+                get_mock_reveal_tx("sov-btc", b"shard 1", &shard_1),
+                get_mock_reveal_tx("sov-btc", b"no shard", &EnvelopeFields::default()),
+                get_mock_reveal_tx("sov-btc", b"shard 0, second", &shard_0),
+            ]
+            .into_iter()
+            .map(|tx| get_extended_tx(tx, "sov-btc"))
+            .collect(),
+        };
+
+        // all blobs are relevant, with their namespace surfaced
+        let txs = da_service.extract_relevant_txs(&block);
+        assert_eq!(
+            txs.iter().map(|blob| blob.namespace_id).collect::<Vec<_>>(),
+            vec![Some([0; 8]), Some([1; 8]), None, Some([0; 8])]
+        );
+
+        let shard_0_txs = da_service.extract_relevant_txs_in_namespace(&block, [0; 8]);
+        assert_eq!(shard_0_txs.len(), 2);
+        let mut first = shard_0_txs[0].blob.clone();
+        first.advance(first.total_len());
+        assert_eq!(first.accumulator(), b"shard 0, first");
+        let mut second = shard_0_txs[1].blob.clone();
+        second.advance(second.total_len());
+        assert_eq!(second.accumulator(), b"shard 0, second");
+    }
+
+    #[test]
+    fn sequencer_public_key() {
+        let da_service = get_mock_service("http://localhost:38332".to_string());
+
+        // the sequencer signs with ECDSA by default, as the mock inscriptions
+        let tx = get_mock_reveal_tx("sov-btc", b"signed blob", &EnvelopeFields::default());
+        let (sender, _blob_hash) = recover_sender_and_hash_from_tx(&tx, "sov-btc").unwrap();
+        assert_eq!(da_service.sequencer_public_key().unwrap(), sender);
+
+        // Schnorr is opt-in, its signatures come with the x-only key of the same secret key
+        let schnorr_service = BitcoinService {
+            signature_scheme: SignatureScheme::Schnorr,
+            ..da_service
+        };
+        let public_key = schnorr_service.sequencer_public_key().unwrap();
+        assert_eq!(public_key.len(), 32);
+        assert_eq!(public_key, sender[1..]);
+    }
+
+    #[tokio::test]
+    async fn prefetch_finalized_bounds_concurrency() {
+        // one distinct block per height
+        let blocks = (0..8).map(get_mock_block_at).collect::<Vec<_>>();
+        let hashes = blocks
+            .iter()
+            .map(|block| block.header.header.block_hash().to_string())
+            .collect::<Vec<_>>();
+        let blocks_json = blocks
+            .iter()
+            .map(|block| {
+                (
+                    block.header.header.block_hash().to_string(),
+                    get_block_json(block),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+        let (in_flight_in_node, max_in_flight_in_node) = (in_flight.clone(), max_in_flight.clone());
+        let node_url = start_mock_node(move |method, params| match method {
+            "getblockcount" => Ok(json!(100)),
+            "getblockhash" => Ok(json!(hashes[params[0].as_u64().unwrap() as usize])),
+            "getblock" => {
+                let current = in_flight_in_node.fetch_add(1, Ordering::SeqCst) + 1;
+                max_in_flight_in_node.fetch_max(current, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(50));
+                in_flight_in_node.fetch_sub(1, Ordering::SeqCst);
+                Ok(blocks_json[params[0].as_str().unwrap()].clone())
+            }
+            _ => Err(RPCError {
+                code: -32601,
+                message: "Method not found".to_string(),
+            }),
+        });
+
+        let da_service = BitcoinService {
+            prefetch_concurrency: 2,
+            ..get_mock_service(node_url)
+        };
+
+        let fetched_blocks = da_service
+            .prefetch_finalized(0, 8)
+            .await
+            .expect("Failed to prefetch blocks");
+
+        assert_eq!(
+            fetched_blocks
+                .iter()
+                .map(|block| block.header.height)
+                .collect::<Vec<_>>(),
+            (0..8).collect::<Vec<_>>()
+        );
+        assert!(max_in_flight.load(Ordering::SeqCst) <= 2);
+    }
+
+    #[tokio::test]
+    async fn prefetch_finalized_cancels_on_error() {
+        let blocks = (0..10).map(get_mock_block_at).collect::<Vec<_>>();
+        let hashes = blocks
+            .iter()
+            .map(|block| block.header.header.block_hash().to_string())
+            .collect::<Vec<_>>();
+        let blocks_json = blocks
+            .iter()
+            .map(|block| {
+                (
+                    block.header.header.block_hash().to_string(),
+                    get_block_json(block),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+
+        // the blocks up to height 8 are final, the block at height 1 cannot be fetched
+        let block_count_requests = Arc::new(AtomicUsize::new(0));
+        let node_url = {
+            let block_count_requests = block_count_requests.clone();
+            start_mock_node(move |method, params| match method {
+                "getblockcount" => {
+                    block_count_requests.fetch_add(1, Ordering::SeqCst);
+                    Ok(json!(9))
+                }
+                "getblockhash" => match params[0].as_u64().unwrap() {
+                    1 => Err(RPCError {
+                        code: -1,
+                        message: "Block not available".to_string(),
+                    }),
+                    height => Ok(json!(hashes[height as usize])),
+                },
+                "getblock" => Ok(blocks_json[params[0].as_str().unwrap()].clone()),
+                _ => Err(RPCError {
+                    code: -32601,
+                    message: "Method not found".to_string(),
+                }),
+            })
+        };
+        let da_service = BitcoinService {
+            finality_rule: FinalityRule::BlockDepth(1),
+            prefetch_concurrency: 16,
+            polling_interval: Duration::from_millis(5),
+            ..get_mock_service(node_url)
+        };
+
+        assert!(da_service.prefetch_finalized(0, 16).await.is_err());
+
+        // the fetches in flight are cancelled with the error, the ones awaiting finality included
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let requests = block_count_requests.load(Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(block_count_requests.load(Ordering::SeqCst), requests);
+    }
+
+    #[tokio::test]
+    async fn get_block_range_waits_only_above_the_tip() {
+        let blocks = (0..8).map(get_mock_block_at).collect::<Vec<_>>();
+        let hashes = blocks
+            .iter()
+            .map(|block| block.header.header.block_hash().to_string())
+            .collect::<Vec<_>>();
+        let blocks_json = blocks
+            .iter()
+            .map(|block| {
+                (
+                    block.header.header.block_hash().to_string(),
+                    get_block_json(block),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+
+        // the tip is at height 5, the next blocks are mined once the service sleeps
+        let sleeps = Arc::new(Mutex::new(Vec::new()));
+        let node_url = {
+            let sleeps = sleeps.clone();
+            start_mock_node(move |method, params| match method {
+                "getblockcount" => Ok(json!(5)),
+                "getblockhash" => {
+                    let height = params[0].as_u64().unwrap();
+                    match height <= 5 || !sleeps.lock().unwrap().is_empty() {
+                        true => Ok(json!(hashes[height as usize])),
+                        false => Err(RPCError {
+                            code: -8,
+                            message: "Block height out of range".to_string(),
+                        }),
+                    }
+                }
+                "getblock" => Ok(blocks_json[params[0].as_str().unwrap()].clone()),
+                _ => Err(RPCError {
+                    code: -32601,
+                    message: "Method not found".to_string(),
+                }),
+            })
+        };
+        let da_service = get_mock_service(node_url).with_clock(VirtualClock::new(sleeps.clone()));
+        let heights = |blocks: Vec<BitcoinBlock>| {
+            blocks
+                .iter()
+                .map(|block| block.header.height)
+                .collect::<Vec<_>>()
+        };
+
+        let fetched_blocks = da_service.get_block_range(0, 6).await.unwrap();
+        assert_eq!(heights(fetched_blocks), (0..6).collect::<Vec<_>>());
+        assert!(sleeps.lock().unwrap().is_empty());
+
+        let fetched_blocks = da_service.get_block_range(3, 8).await.unwrap();
+        assert_eq!(heights(fetched_blocks), (3..8).collect::<Vec<_>>());
+        assert!(!sleeps.lock().unwrap().is_empty());
+
+        assert!(da_service.get_block_range(4, 4).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn get_block_range_cancels_on_error() {
+        let blocks = (0..4).map(get_mock_block_at).collect::<Vec<_>>();
+        let hashes = blocks
+            .iter()
+            .map(|block| block.header.header.block_hash().to_string())
+            .collect::<Vec<_>>();
+        let blocks_json = blocks
+            .iter()
+            .map(|block| {
+                (
+                    block.header.header.block_hash().to_string(),
+                    get_block_json(block),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+
+        // the tip is at height 3, the block at height 1 cannot be fetched and the blocks above the
+        // tip are never mined
+        let requests_above_tip = Arc::new(AtomicUsize::new(0));
+        let node_url = {
+            let requests_above_tip = requests_above_tip.clone();
+            start_mock_node(move |method, params| match method {
+                "getblockcount" => Ok(json!(3)),
+                "getblockhash" => match params[0].as_u64().unwrap() {
+                    1 => Err(RPCError {
+                        code: -1,
+                        message: "Block not available".to_string(),
+                    }),
+                    height if height > 3 => {
+                        requests_above_tip.fetch_add(1, Ordering::SeqCst);
+                        Err(RPCError {
+                            code: -8,
+                            message: "Block height out of range".to_string(),
+                        })
+                    }
+                    height => Ok(json!(hashes[height as usize])),
+                },
+                "getblock" => Ok(blocks_json[params[0].as_str().unwrap()].clone()),
+                _ => Err(RPCError {
+                    code: -32601,
+                    message: "Method not found".to_string(),
+                }),
+            })
+        };
+        let da_service = BitcoinService {
+            prefetch_concurrency: 8,
+            polling_interval: Duration::from_millis(5),
+            ..get_mock_service(node_url)
+        };
+
+        assert!(da_service.get_block_range(0, 8).await.is_err());
+
+        // the fetches in flight are cancelled with the error, the heights above the tip included
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let requests = requests_above_tip.load(Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(requests_above_tip.load(Ordering::SeqCst), requests);
+    }
+
+    #[tokio::test]
+    async fn subscribe_blocks_polls_without_zmq() {
+        let blocks = (0..8).map(get_mock_block_at).collect::<Vec<_>>();
+        let hashes = blocks
+            .iter()
+            .map(|block| block.header.header.block_hash().to_string())
+            .collect::<Vec<_>>();
+        let blocks_json = blocks
+            .iter()
+            .map(|block| {
+                (
+                    block.header.header.block_hash().to_string(),
+                    get_block_json(block),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+
+        // the tip is at height 3, a block is mined every time the service sleeps
+        let sleeps = Arc::new(Mutex::new(Vec::new()));
+        let node_url = {
+            let sleeps = sleeps.clone();
+            start_mock_node(move |method, params| match method {
+                "getblockcount" => Ok(json!(3)),
+                "getblockhash" => {
+                    let height = params[0].as_u64().unwrap() as usize;
+                    let tip = 3 + sleeps.lock().unwrap().len();
+                    match hashes.get(height) {
+                        Some(hash) if height <= tip => Ok(json!(hash)),
+                        _ => Err(RPCError {
+                            code: -8,
+                            message: "Block height out of range".to_string(),
+                        }),
+                    }
+                }
+                "getblock" => Ok(blocks_json[params[0].as_str().unwrap()].clone()),
+                _ => Err(RPCError {
+                    code: -32601,
+                    message: "Method not found".to_string(),
+                }),
+            })
+        };
+        let da_service = get_mock_service(node_url).with_clock(VirtualClock::new(sleeps.clone()));
+
+        let mut blocks = da_service.subscribe_blocks().await.unwrap();
+        for height in 4..7 {
+            assert_eq!(blocks.recv().await.unwrap().header.height, height);
+        }
+        assert!(sleeps.lock().unwrap().len() >= 3);
+    }
+
+    #[tokio::test]
+    async fn stream_blobs_in_range() {
+        let blocks = (0..10).map(get_mock_block_at).collect::<Vec<_>>();
+        let hashes = blocks
+            .iter()
+            .map(|block| block.header.header.block_hash().to_string())
+            .collect::<Vec<_>>();
+        let blocks_json = blocks
+            .iter()
+            .map(|block| {
+                (
+                    block.header.header.block_hash().to_string(),
+                    get_block_json(block),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+        let node_url = start_mock_node(move |method, params| match method {
+            "getblockcount" => Ok(json!(9)),
+            "getblockhash" => Ok(json!(hashes[params[0].as_u64().unwrap() as usize])),
+            "getblock" => Ok(blocks_json[params[0].as_str().unwrap()].clone()),
+            _ => Err(RPCError {
+                code: -32601,
+                message: "Method not found".to_string(),
+            }),
+        });
+        let da_service = BitcoinService {
+            prefetch_concurrency: 2,
+            ..get_mock_service(node_url)
+        };
+
+        let mut stream = da_service.stream_blobs_in_range(2, 6);
+        let mut streamed = Vec::new();
+        while let Some(blob) = stream.recv().await {
+            streamed.push(blob.unwrap());
+        }
+
+        // the blobs of every block, in height order and block order within a height
+        let expected = (2..6)
+            .flat_map(|height| {
+                da_service
+                    .extract_relevant_txs(&blocks[height as usize])
+                    .into_iter()
+                    .map(move |blob| (height, blob))
+            })
+            .collect::<Vec<_>>();
+        assert!(!expected.is_empty());
+        assert_eq!(streamed, expected);
+    }
+
+    #[tokio::test]
+    async fn finalized_blocks_are_cached() {
+        let blocks = (0..10).map(get_mock_block_at).collect::<Vec<_>>();
+        let hashes = blocks
+            .iter()
+            .map(|block| block.header.header.block_hash().to_string())
+            .collect::<Vec<_>>();
+        let blocks_json = blocks
+            .iter()
+            .map(|block| {
+                (
+                    block.header.header.block_hash().to_string(),
+                    get_block_json(block),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+
+        // the tip is at height 9, blocks up to height 7 are final
+        let block_requests = Arc::new(AtomicUsize::new(0));
+        let node_url = {
+            let block_requests = block_requests.clone();
+            start_mock_node(move |method, params| match method {
+                "getblockcount" => Ok(json!(9)),
+                "getblockhash" => Ok(json!(hashes[params[0].as_u64().unwrap() as usize])),
+                "getblock" => {
+                    block_requests.fetch_add(1, Ordering::SeqCst);
+                    Ok(blocks_json[params[0].as_str().unwrap()].clone())
+                }
+                _ => Err(RPCError {
+                    code: -32601,
+                    message: "Method not found".to_string(),
+                }),
+            })
+        };
+        let da_service = BitcoinService {
+            finality_depth: 2,
+            finality_rule: FinalityRule::BlockDepth(2),
+            ..get_mock_service(node_url.clone())
+        };
+        let block_requests_after = |requests: usize| {
+            assert_eq!(block_requests.swap(0, Ordering::SeqCst), requests);
+        };
+
+        // a finalized block is fetched once, whether asked for as final or not
+        assert_eq!(da_service.get_finalized_at(3).await.unwrap(), blocks[3]);
+        assert_eq!(da_service.get_finalized_at(3).await.unwrap(), blocks[3]);
+        assert_eq!(da_service.get_block_at(3).await.unwrap(), blocks[3]);
+        block_requests_after(1);
+        assert_eq!(da_service.get_block_at(7).await.unwrap(), blocks[7]);
+        assert_eq!(da_service.get_finalized_at(7).await.unwrap(), blocks[7]);
+        block_requests_after(1);
+
+        // blocks that may still be reorganized away are fetched every time
+        assert_eq!(da_service.get_block_at(8).await.unwrap(), blocks[8]);
+        assert_eq!(da_service.get_block_at(8).await.unwrap(), blocks[8]);
+        block_requests_after(2);
+
+        da_service.clear_cache();
+        da_service.get_finalized_at(3).await.unwrap();
+        block_requests_after(1);
+
+        // the cache can be disabled
+        let da_service = BitcoinService::try_new(
+            DaServiceConfig {
+                node_url,
+                block_cache_capacity: Some(0),
+                ..get_config()
+            },
+            RollupParams {
+                rollup_name: "sov-btc".to_string(),
+            },
+        )
+        .unwrap();
+        da_service.get_block_at(3).await.unwrap();
+        da_service.get_block_at(3).await.unwrap();
+        block_requests_after(2);
+    }
+
+    #[tokio::test]
+    async fn get_block_at_checks_checkpoints() {
+        let mut block = get_mock_block();
+        let block_hash = block.header.header.block_hash();
+        let block_json = get_block_json(&block);
+        // the same block, reported by a lying node at a height without checkpoint
+        block.header.height = 7;
+        let misplaced_block_json = get_block_json(&block);
+
+        let node_url = start_mock_node(move |method, _params| match method {
+            "getblockhash" => Ok(json!(block_hash.to_string())),
+            "getblock" => Ok(block_json.clone()),
+            _ => Err(RPCError {
+                code: -32601,
+                message: "Method not found".to_string(),
+            }),
+        });
+
+        // the served block matches the checkpoint
+        let da_service = BitcoinService {
+            checkpoints: HashMap::from([(2, block_hash)]),
+            ..get_mock_service(node_url.clone())
+        };
+        da_service
+            .get_block_at(2)
+            .await
+            .expect("Block matching the checkpoint should be accepted");
+
+        // the served block does not match the checkpoint
+        let da_service = BitcoinService {
+            checkpoints: HashMap::from([(2, BlockHash::all_zeros())]),
+            ..get_mock_service(node_url)
+        };
+        let error = da_service
+            .get_block_at(2)
+            .await
+            .expect_err("Block not matching the checkpoint should be rejected");
+        assert!(error.to_string().contains("does not match checkpoint"));
+
+        // the checkpoint is at the requested height, whatever height the node reports
+        let node_url = start_mock_node(move |method, _params| match method {
+            "getblockhash" => Ok(json!(block_hash.to_string())),
+            "getblock" => Ok(misplaced_block_json.clone()),
+            _ => Err(RPCError {
+                code: -32601,
+                message: "Method not found".to_string(),
+            }),
+        });
+        let da_service = BitcoinService {
+            checkpoints: HashMap::from([(2, BlockHash::all_zeros())]),
+            ..get_mock_service(node_url)
+        };
+        let error = da_service
+            .get_block_at(2)
+            .await
+            .expect_err("Block not matching the checkpoint should be rejected");
+        assert!(error
+            .to_string()
+            .contains("at height 2 does not match checkpoint"));
+    }
+
+    #[tokio::test]
+    async fn backfill_from_blocksdir() {
+        // block 0 is stored in the blocks directory, block 1 is only known by the node
+        let blocks = (0..2).map(get_mock_block_at).collect::<Vec<_>>();
+        let hashes = blocks
+            .iter()
+            .map(|block| block.header.header.block_hash().to_string())
+            .collect::<Vec<_>>();
+
+        let stored_block = bitcoin::Block {
+            header: blocks[0].header.header,
+            txdata: blocks[0]
+                .txdata
+                .iter()
+                .map(|tx| tx.transaction.clone())
+                .collect(),
+        };
+        let serialized = encode::serialize(&stored_block);
+        let mut block_file = bitcoin::Network::Regtest.magic().to_bytes().to_vec();
+        block_file.extend((serialized.len() as u32).to_le_bytes());
+        block_file.extend(serialized);
+
+        let path = std::env::temp_dir().join(format!("bitcoin-da-backfill-{}", std::process::id()));
+        std::fs::create_dir_all(&path).unwrap();
+        std::fs::write(path.join("blk00000.dat"), block_file).unwrap();
+
+        let fetched_over_rpc = Arc::new(Mutex::new(Vec::new()));
+        let fetched_in_node = fetched_over_rpc.clone();
+        let block_json = get_block_json(&blocks[1]);
+        let node_url = start_mock_node(move |method, params| match method {
+            "getblockcount" => Ok(json!(100)),
+            "getblockhash" => Ok(json!(hashes[params[0].as_u64().unwrap() as usize])),
+            "getblock" => {
+                fetched_in_node
+                    .lock()
+                    .unwrap()
+                    .push(params[0].as_str().unwrap().to_string());
+                Ok(block_json.clone())
+            }
+            _ => Err(RPCError {
+                code: -32601,
+                message: "Method not found".to_string(),
+            }),
+        });
+
+        let da_service = BitcoinService {
+            blocksdir: Some(BlocksDir::new(&path, bitcoin::Network::Regtest)),
+            ..get_mock_service(node_url)
+        };
+
+        let fetched_blocks = da_service.backfill_from_blocksdir(0, 2).await;
+        std::fs::remove_dir_all(&path).unwrap();
+        let fetched_blocks = fetched_blocks.expect("Failed to backfill blocks");
+
+        assert_eq!(fetched_blocks, blocks);
+        assert_eq!(
+            *fetched_over_rpc.lock().unwrap(),
+            vec![blocks[1].header.header.block_hash().to_string()]
+        );
+
+        // the known inscriptions are extracted from the block read from disk
+        assert_eq!(
+            da_service.extract_relevant_txs(&fetched_blocks[0]),
+            da_service.extract_relevant_txs(&blocks[0])
+        );
+        assert!(!da_service
+            .extract_relevant_txs(&fetched_blocks[0])
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn deep_reorg_is_fatal() {
+        // two chains of 6 blocks, forked from height 2
+        let get_chain = |fork_nonce: u32| {
+            let mut prev_blockhash = BlockHash::all_zeros();
+            (0..6u64)
+                .map(|height| {
+                    let mut block = get_mock_block();
+                    block.header.header.prev_blockhash = prev_blockhash;
+                    block.header.header.nonce = if height < 2 { 0 } else { fork_nonce };
+                    block.header.height = height;
+                    // the node checks that the chain starts at the regtest genesis
+                    if height == 0 {
+                        block.header.header = genesis_block(bitcoin::Network::Regtest).header;
+                    }
+                    prev_blockhash = block.header.header.block_hash();
+                    block
+                })
+                .collect::<Vec<_>>()
+        };
+        let chains = [get_chain(0), get_chain(1)];
+        let chains_json = chains
+            .iter()
+            .flatten()
+            .map(|block| {
+                (
+                    block.header.header.block_hash().to_string(),
+                    get_block_json(block),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+        let chain_hashes = chains
+            .iter()
+            .map(|chain| {
+                chain
+                    .iter()
+                    .map(|block| block.header.header.block_hash().to_string())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let active_chain = Arc::new(AtomicUsize::new(0));
+        let active_chain_in_node = active_chain.clone();
+        let node_url = start_mock_node(move |method, params| match method {
+            "getblockhash" => Ok(json!(
+                chain_hashes[active_chain_in_node.load(Ordering::SeqCst)]
+                    [params[0].as_u64().unwrap() as usize]
+            )),
+            "getblock" => Ok(chains_json[params[0].as_str().unwrap()].clone()),
+            _ => Err(RPCError {
+                code: -32601,
+                message: "Method not found".to_string(),
+            }),
+        });
+
+        let da_service = BitcoinService {
+            max_reorg_depth: 3,
+            ..get_mock_service(node_url)
+        };
+
+        for height in 0..5 {
+            da_service.get_block_at(height).await.unwrap();
+        }
+
+        // the node switches to the other chain, replacing blocks 2 to 4
+        active_chain.store(1, Ordering::SeqCst);
+        da_service
+            .get_block_at(5)
+            .await
+            .expect("Reorg within the maximum depth should be absorbed");
+
+        // switching back replaces blocks 2 to 5, deeper than the maximum
+        active_chain.store(0, Ordering::SeqCst);
+        let error = da_service
+            .get_block_at(5)
+            .await
+            .expect_err("Reorg deeper than the maximum depth should be fatal");
+        assert_eq!(
+            error.downcast_ref::<BitcoinServiceError>(),
+            Some(&BitcoinServiceError::DeepReorg {
+                fork_height: 2,
+                depth: 4,
+                max_depth: 3,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn reorg_of_finalized_block_is_detected() {
+        // two chains of 6 blocks, forked from height 2
+        let get_chain = |fork_nonce: u32| {
+            let mut prev_blockhash = BlockHash::all_zeros();
+            (0..6u64)
+                .map(|height| {
+                    let mut block = get_mock_block_at(height);
+                    block.header.header.prev_blockhash = prev_blockhash;
+                    if height >= 2 {
+                        block.header.header.nonce += fork_nonce;
+                    }
+                    prev_blockhash = block.header.header.block_hash();
+                    block
+                })
+                .collect::<Vec<_>>()
+        };
+        let chains = [get_chain(0), get_chain(100)];
+        let chains_json = chains
+            .iter()
+            .flatten()
+            .map(|block| {
+                (
+                    block.header.header.block_hash().to_string(),
+                    get_block_json(block),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+        let chain_hashes = chains
+            .iter()
+            .map(|chain| {
+                chain
+                    .iter()
+                    .map(|block| block.header.header.block_hash())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let active_chain = Arc::new(AtomicUsize::new(0));
+        let node_url = {
+            let active_chain = active_chain.clone();
+            let chain_hashes = chain_hashes.clone();
+            start_mock_node(move |method, params| match method {
+                "getblockcount" => Ok(json!(5)),
+                "getblockhash" => Ok(json!(chain_hashes[active_chain.load(Ordering::SeqCst)]
+                    [params[0].as_u64().unwrap() as usize]
+                    .to_string())),
+                "getblock" => Ok(chains_json[params[0].as_str().unwrap()].clone()),
+                _ => Err(RPCError {
+                    code: -32601,
+                    message: "Method not found".to_string(),
+                }),
+            })
+        };
+
+        // a reorg of 4 blocks is absorbed by the reorg tracking, but blocks 2 and 3 are final
+        let da_service = BitcoinService {
+            finality_depth: 2,
+            finality_rule: FinalityRule::BlockDepth(2),
+            max_reorg_depth: 10,
+            ..get_mock_service(node_url)
+        };
+        for height in 1..4 {
+            da_service.get_finalized_at(height).await.unwrap();
+        }
+
+        active_chain.store(1, Ordering::SeqCst);
+        let error = da_service.get_finalized_at(2).await.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<BitcoinServiceError>(),
+            Some(&BitcoinServiceError::ReorgDetected {
+                height: 2,
+                old_hash: chain_hashes[0][2],
+                new_hash: chain_hashes[1][2],
+            })
+        );
+
+        // blocks below the fork are the same, and the new chain links back to them
+        let block = da_service.get_finalized_at(1).await.unwrap();
+        assert_eq!(block.header.header.block_hash(), chain_hashes[1][1]);
+        let block = da_service.get_block_at(2).await.unwrap();
+        assert_eq!(block.prev_hash().0, chain_hashes[1][1]);
+    }
+
+    #[tokio::test]
+    async fn send_raw_blob_round_trip() {
+        let sent_txs = Arc::new(Mutex::new(Vec::new()));
+        let da_service = get_mock_service(start_mock_wallet_node(sent_txs.clone()));
+
+        let blob = b"already compressed by the rollup".to_vec();
+        for (body, algorithm) in [
+            (compress_blob(&blob), Compression::Brotli),
+            (blob.clone(), Compression::None),
+        ] {
+            let inscription = da_service
+                .send_raw_blob(&body, algorithm)
+                .await
+                .expect("Failed to send raw blob");
+
+            let reveal_tx = sent_txs.lock().unwrap().pop().unwrap();
+            assert_eq!(reveal_tx.txid(), inscription.reveal_txid);
+
+            // the body is inscribed as is, with its compression header
+            let parsed = parse_transaction(&reveal_tx, "sov-btc").unwrap();
+            assert_eq!(parsed.body, body);
+            assert_eq!(parsed.compression, Some(algorithm));
+
+            let block = BitcoinBlock {
+                header: get_mock_header(),
+                txdata: vec![get_extended_tx(reveal_tx, "sov-btc")],
+            };
+            let txs = da_service.extract_relevant_txs(&block);
+            assert_eq!(txs.len(), 1);
+            assert_eq!(txs[0].hash, blob_hash(&body));
+            let mut extracted = txs[0].blob.clone();
+            extracted.advance(extracted.total_len());
+            assert_eq!(extracted.accumulator(), blob);
+        }
+    }
+
+    #[tokio::test]
+    async fn send_transaction_without_utxos() {
+        let node_url = start_mock_node(|method, _params| match method {
+            "getrawchangeaddress" => Ok(json!("bcrt1qxuds94z3pqwqea2p4f4ev4f25s6uu7y3avljrl")),
+            "listunspent" => Ok(json!([])),
+            _ => Err(RPCError {
+                code: -32601,
+                message: "Method not found".to_string(),
+            }),
+        });
+        let da_service = get_mock_service(node_url);
+
+        let error = da_service
+            .send_transaction(b"blob")
+            .await
+            .expect_err("Sending without utxos should fail");
+        assert_eq!(
+            error.downcast_ref::<BitcoinServiceError>(),
+            Some(&BitcoinServiceError::NoSpendableUtxos)
+        );
+    }
+
+    // Accepts the blobs of a single sequencer key, whatever their signature
+    #[derive(Debug)]
+    struct SingleKeyAuthenticator(Vec<u8>);
+
+    impl BlobAuthenticator for SingleKeyAuthenticator {
+        fn authenticate(&self, _message: &[u8; 32], _signature: &[u8], public_key: &[u8]) -> bool {
+            public_key == self.0.as_slice()
+        }
+    }
+
+    #[test]
+    fn extract_relevant_txs_with_custom_authenticator() {
+        const OTHER_PRIVATE_KEY: &str =
+            "0C27E8EB3C7D2F4E1F3E6C7E0B1E9A6F7D2C3B4A5968778695A4B3C2D1E0F1A2"; // Test key, safe to publish
+
+        let da_service = get_mock_service("http://localhost:38332".to_string());
+        let other_tx = get_mock_reveal_tx_signed_by(
+            "sov-btc",
+            b"other sequencer",
+            &EnvelopeFields::default(),
+            OTHER_PRIVATE_KEY,
+        );
+        let other_public_key = parse_transaction(&other_tx, "sov-btc").unwrap().public_key;
+
+        let block = BitcoinBlock {
+            header: get_mock_header(),
+            txdata: vec![
+                get_mock_reveal_tx("sov-btc", b"sequencer", &EnvelopeFields::default()),
+                other_tx,
+            ]
+            .into_iter()
+            .map(|tx| get_extended_tx(tx, "sov-btc"))
+            .collect(),
+        };
+
+        // both blobs are validly signed
+        assert_eq!(da_service.extract_relevant_txs(&block).len(), 2);
+
+        let da_service =
+            da_service.with_authenticator(SingleKeyAuthenticator(other_public_key.clone()));
+        let txs = da_service.extract_relevant_txs(&block);
+        assert_eq!(txs.len(), 1);
+        assert_eq!(txs[0].sender.0, other_public_key);
+        let mut blob = txs[0].blob.clone();
+        blob.advance(blob.total_len());
+        assert_eq!(blob.accumulator(), b"other sequencer");
+
+        // blobs verified on demand are checked by the same authenticator
+        let authenticator = SingleKeyAuthenticator(other_public_key);
+        assert!(txs[0].verify_signature(&authenticator));
+        assert!(!txs[0].verify_signature(&SingleKeyAuthenticator(vec![2; 33])));
+    }
+
+    #[tokio::test]
+    async fn extract_unsigned_relay_blobs() {
+        // an envelope without signature nor key, relevant looking so that the verifier checks it
+        let mut tx = get_mock_unsigned_reveal_tx("sov-btc", b"relayed");
+        for lock_time in 1.. {
+            tx.lock_time = LockTime::from_consensus(lock_time);
+            if tx.txid().to_raw_hash().to_byte_array()[0..2] == [0, 0] {
+                break;
+            }
+        }
+        let inscription = parse_transaction(&tx, "sov-btc").unwrap();
+        assert!(inscription.signature.is_empty());
+        assert!(inscription.public_key.is_empty());
+
+        let mut header = get_mock_header();
+        header.header.merkle_root = TxMerkleNode::from_raw_hash(tx.txid().to_raw_hash());
+        let block = BitcoinBlock::from_txs(header.header, 100, vec![tx], "sov-btc");
+
+        // the blob is dropped unless a trusted relay vouched for it, the verifier agrees
+        let da_service = get_mock_service("http://localhost:38332".to_string());
+        let (txs, inclusion_proof, completeness_proof) =
+            da_service.extract_relevant_txs_with_proof(&block).await;
+        assert!(txs.is_empty());
+        assert_eq!(completeness_proof.len(), 1);
+        BitcoinVerifier::new(RollupParams {
+            rollup_name: "sov-btc".to_string(),
+        })
+        .verify_relevant_tx_list(&block.header, &txs, inclusion_proof, completeness_proof)
+        .expect("Unsigned blobs should be dropped");
+
+        let trusted_hash = blob_hash(&inscription.body);
+        let trusting = BitcoinService {
+            trusted_relay_blob_hashes: HashSet::from([trusted_hash]),
+            ..da_service
+        };
+        let (txs, inclusion_proof, completeness_proof) =
+            trusting.extract_relevant_txs_with_proof(&block).await;
+        assert_eq!(txs.len(), 1);
+        assert_eq!(txs[0].hash, trusted_hash);
+        assert!(txs[0].sender.0.is_empty());
+        assert_eq!(txs[0].signature, None);
+        BitcoinVerifier::new(RollupParams {
+            rollup_name: "sov-btc".to_string(),
+        })
+        .with_trusted_relay_blobs(HashSet::from([trusted_hash]))
+        .verify_relevant_tx_list(&block.header, &txs, inclusion_proof, completeness_proof)
+        .expect("Unsigned blobs vouched for by the trusted relay should be verified");
+    }
+
+    #[tokio::test]
+    async fn extract_relevant_txs_from_authorized_sequencers() {
+        const FOREIGN_PRIVATE_KEY: &str =
+            "0C27E8EB3C7D2F4E1F3E6C7E0B1E9A6F7D2C3B4A5968778695A4B3C2D1E0F1A2"; // Test key, safe to publish
+
+        let da_service = get_mock_service("http://localhost:38332".to_string());
+        let sequencer_public_key = da_service.sequencer_public_key().unwrap();
+        let da_service = BitcoinService {
+            authorized_sequencers: vec![sequencer_public_key.clone()],
+            ..da_service
+        };
+
+        // a foreign key signing its own blob, relevant looking so that the verifier checks it
+        let mut txs = vec![
+            get_mock_reveal_tx_signed_by(
+                "sov-btc",
+                b"self-signed",
+                &EnvelopeFields::default(),
+                FOREIGN_PRIVATE_KEY,
+            ),
+            get_mock_reveal_tx("sov-btc", b"sequencer", &EnvelopeFields::default()),
+        ];
+        for tx in txs.iter_mut() {
+            for lock_time in 1.. {
+                tx.lock_time = LockTime::from_consensus(lock_time);
+                if tx.txid().to_raw_hash().to_byte_array()[0..2] == [0, 0] {
+                    break;
+                }
+            }
+        }
+        let foreign = parse_transaction(&txs[0], "sov-btc").unwrap();
+        assert!(verify_blob_signature(
+            &foreign.body,
+            &foreign.signature,
+            &foreign.public_key
+        ));
+
+        let txids = txs.iter().map(|tx| tx.txid()).collect::<Vec<_>>();
+        let mut header = get_mock_header();
+        header.header.merkle_root = TxMerkleNode::from_raw_hash(
+            merkle_tree::calculate_root(txids.into_iter())
+                .unwrap()
+                .to_raw_hash(),
+        );
+        let block = BitcoinBlock::from_txs(header.header, 100, txs, "sov-btc");
+
+        // the validly signed foreign blob is dropped
+        let (txs, inclusion_proof, completeness_proof) =
+            da_service.extract_relevant_txs_with_proof(&block).await;
+        assert_eq!(txs.len(), 1);
+        assert_eq!(txs[0].sender.0, sequencer_public_key);
+        assert_eq!(da_service.extract_relevant_txs(&block), txs);
+        assert_eq!(completeness_proof.len(), 2);
+
+        // the verifier agrees when given the same keys
+        BitcoinVerifier::new(RollupParams {
+            rollup_name: "sov-btc".to_string(),
+        })
+        .with_authorized_sequencers(vec![sequencer_public_key.clone()])
+        .verify_relevant_tx_list(&block.header, &txs, inclusion_proof, completeness_proof)
+        .expect("Blobs of the authorized sequencer should be verified");
+
+        // a blob vouched for by a trusted relay is kept whoever signed it
+        let trusted_hash = blob_hash(&foreign.body);
+        let trusting_service = BitcoinService {
+            trusted_relay_blob_hashes: HashSet::from([trusted_hash]),
+            ..da_service.clone()
+        };
+        let (txs, inclusion_proof, completeness_proof) = trusting_service
+            .extract_relevant_txs_with_proof(&block)
+            .await;
+        assert_eq!(txs.len(), 2);
+        assert_eq!(txs[0].hash, trusted_hash);
+        BitcoinVerifier::new(RollupParams {
+            rollup_name: "sov-btc".to_string(),
+        })
+        .with_authorized_sequencers(vec![sequencer_public_key])
+        .with_trusted_relay_blobs(HashSet::from([trusted_hash]))
+        .verify_relevant_tx_list(&block.header, &txs, inclusion_proof, completeness_proof)
+        .expect("Blobs vouched for by the trusted relay should be verified");
+
+        // without keys, any valid signature is accepted
+        let da_service = BitcoinService {
+            authorized_sequencers: vec![],
+            ..da_service
+        };
+        assert_eq!(da_service.extract_relevant_txs(&block).len(), 2);
+
+        // a custom authenticator only accepting the foreign key, the verifier agrees when given it
+        let custom_service =
+            da_service.with_authenticator(SingleKeyAuthenticator(foreign.public_key.clone()));
+        let (txs, inclusion_proof, completeness_proof) =
+            custom_service.extract_relevant_txs_with_proof(&block).await;
+        assert_eq!(txs.len(), 1);
+        assert_eq!(txs[0].sender.0, foreign.public_key);
+        BitcoinVerifier::new(RollupParams {
+            rollup_name: "sov-btc".to_string(),
+        })
+        .with_authenticator(SingleKeyAuthenticator(foreign.public_key))
+        .verify_relevant_tx_list(&block.header, &txs, inclusion_proof, completeness_proof)
+        .expect("Blobs accepted by the custom authenticator should be verified");
+    }
+
+    #[tokio::test]
+    async fn pending_statuses() {
+        let reveal_dir =
+            std::env::temp_dir().join(format!("bitcoin-da-reveals-{}", std::process::id()));
+        std::fs::create_dir_all(&reveal_dir).unwrap();
+
+        let reveal_txs = [b"confirmed".as_slice(), b"in mempool", b"dropped"]
+            .into_iter()
+            .map(|blob| get_mock_reveal_tx("sov-btc", blob, &EnvelopeFields::default()))
+            .collect::<Vec<_>>();
+        // the mock reveal txs all spend the null outpoint, so they are named after their own txid
+        for reveal_tx in reveal_txs.iter() {
+            write_reveal_tx(
+                &reveal_dir,
+                &encode::serialize(reveal_tx),
+                reveal_tx.txid().to_string(),
+            )
+            .unwrap();
+        }
+
+        let confirmed_txid = reveal_txs[0].txid().to_string();
+        let mempool_txid = reveal_txs[1].txid().to_string();
+        let node_url = start_mock_node(move |method, params| {
+            match (method, params.first().and_then(|txid| txid.as_str())) {
+                ("getrawtransaction", Some(txid)) if txid == confirmed_txid => {
+                    Ok(json!({ "txid": txid, "confirmations": 3 }))
+                }
+                ("getrawtransaction", Some(txid)) if txid == mempool_txid => {
+                    Ok(json!({ "txid": txid }))
+                }
+                ("getrawtransaction", _) => Err(RPCError {
+                    code: -5,
+                    message: "No such mempool or blockchain transaction".to_string(),
+                }),
+                _ => Err(RPCError {
+                    code: -32601,
+                    message: "Method not found".to_string(),
+                }),
+            }
+        });
+
+        let da_service = BitcoinService {
+            reveal_dir: reveal_dir.clone(),
+            ..get_mock_service(node_url)
+        };
+
+        let statuses = da_service.pending_statuses().await;
+        std::fs::remove_dir_all(&reveal_dir).unwrap();
+
+        let statuses = statuses.unwrap().into_iter().collect::<HashMap<_, _>>();
+        assert_eq!(
+            statuses,
+            HashMap::from([
+                (reveal_txs[0].txid(), ConfStatus::Confirmed(3)),
+                (reveal_txs[1].txid(), ConfStatus::Mempool),
+                (reveal_txs[2].txid(), ConfStatus::Dropped),
+            ])
+        );
+    }
+
+    #[test]
+    fn extract_blobs_matches_extract_relevant_txs() {
+        let da_service = get_mock_service("http://localhost:38332".to_string());
+        let block = get_mock_block();
+
+        let txs = block
+            .txdata
+            .iter()
+            .map(|tx| tx.transaction.clone())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            extract_blobs(&txs, "sov-btc"),
+            da_service.extract_relevant_txs(&block)
+        );
+        assert_eq!(extract_blobs(&txs, "sov-btc").len(), 4);
+        assert!(extract_blobs(&txs, "other-rollup").is_empty());
+    }
+
+    #[tokio::test]
+    async fn send_transaction_with_redundancy() {
+        let sent_txs = Arc::new(Mutex::new(Vec::new()));
+
+        // the node lists the unconfirmed outputs of the txs sent, as `listunspent 0` does, larger
+        // than the confirmed utxos so that the selection would pick them first
+        let node_txs = sent_txs.clone();
+        let node_url = start_mock_node(move |method, params| {
+            let mut result = mock_wallet_call(&node_txs, usize::MAX, method, params)?;
+            if method == "listunspent" {
+                let unconfirmed = node_txs
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .flat_map(|tx| {
+                        (0..tx.output.len()).map(move |vout| {
+                            json!({
+                                "txid": tx.txid().to_string(),
+                                "vout": vout,
+                                "address": "bcrt1qxuds94z3pqwqea2p4f4ev4f25s6uu7y3avljrl",
+                                "scriptPubKey": "",
+                                "amount": 5.0,
+                                "confirmations": 0,
+                                "spendable": true,
+                                "solvable": true,
+                            })
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                result.as_array_mut().unwrap().extend(unconfirmed);
+            }
+            Ok(result)
+        });
+        let da_service = BitcoinService {
+            redundancy: 2,
+            ..get_mock_service(node_url)
+        };
+
+        let blob = b"blob worth keeping twice";
+        let inscriptions = da_service
+            .send_transaction_with_fields(blob, EnvelopeFields::default())
+            .await
+            .expect("Failed to send blob");
+        assert_eq!(inscriptions.len(), 2);
+
+        // commit, reveal, commit, reveal
+        let sent_txs = sent_txs.lock().unwrap().clone();
+        assert_eq!(sent_txs.len(), 4);
+        let (commit_txs, reveal_txs): (Vec<_>, Vec<_>) = sent_txs
+            .into_iter()
+            .enumerate()
+            .partition(|(index, _)| index % 2 == 0);
+        let commit_txs = commit_txs.into_iter().map(|(_, tx)| tx).collect::<Vec<_>>();
+        let reveal_txs = reveal_txs.into_iter().map(|(_, tx)| tx).collect::<Vec<_>>();
+
+        // the copies are independent: different utxos, none created by another tx of the send,
+        // different reveal txs
+        assert!(commit_txs[0]
+            .input
+            .iter()
+            .all(|input| !commit_txs[1].input.contains(input)));
+        let sent_txids = commit_txs
+            .iter()
+            .chain(&reveal_txs)
+            .map(|tx| tx.txid())
+            .collect::<HashSet<_>>();
+        assert!(commit_txs
+            .iter()
+            .flat_map(|tx| &tx.input)
+            .all(|input| !sent_txids.contains(&input.previous_output.txid)));
+        assert_ne!(reveal_txs[0].txid(), reveal_txs[1].txid());
+        assert_eq!(
+            reveal_txs.iter().map(|tx| tx.txid()).collect::<Vec<_>>(),
+            inscriptions
+                .iter()
+                .map(|inscription| inscription.reveal_txid)
+                .collect::<Vec<_>>()
+        );
+
+        // both reveals carry the same blob
+        assert_eq!(
+            parse_transaction(&reveal_txs[0], "sov-btc").unwrap().body,
+            parse_transaction(&reveal_txs[1], "sov-btc").unwrap().body
+        );
+
+        // extraction yields it once, and the verifier agrees
+        let txids = reveal_txs.iter().map(|tx| tx.txid()).collect::<Vec<_>>();
+        let mut header = get_mock_header();
+        header.header.merkle_root = TxMerkleNode::from_raw_hash(
+            merkle_tree::calculate_root(txids.into_iter())
+                .unwrap()
+                .to_raw_hash(),
+        );
+        let block = BitcoinBlock {
+            header,
+            txdata: reveal_txs
+                .into_iter()
+                .map(|tx| get_extended_tx(tx, "sov-btc"))
+                .collect(),
+        };
+
+        let (txs, inclusion_proof, completeness_proof) =
+            da_service.extract_relevant_txs_with_proof(&block).await;
+        assert_eq!(txs.len(), 1);
+        let mut extracted = txs[0].blob.clone();
+        extracted.advance(extracted.total_len());
+        assert_eq!(extracted.accumulator(), blob);
+
+        let verifier = BitcoinVerifier::new(RollupParams {
+            rollup_name: "sov-btc".to_string(),
+        });
+        verifier
+            .verify_relevant_tx_list(&block.header, &txs, inclusion_proof, completeness_proof)
+            .expect("Deduplicated blobs should be accepted");
+    }
+
+    #[test]
+    fn compressed_size() {
+        let da_service = get_mock_service("http://localhost:38332".to_string());
+
+        for blob in [
+            Vec::new(),
+            b"small blob".to_vec(),
+            vec![7u8; 100_000],
+            std::fs::read("test_data/blob.txt").unwrap(),
+        ] {
+            assert_eq!(
+                da_service.compressed_size(&blob),
+                compress_blob(&blob).len()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn get_finalized_at_retries_block_count() {
+        let block = get_mock_block();
+        let block_hash = block.header.header.block_hash();
+        let block_json = get_block_json(&block);
+
+        // the node is loading for the first request
+        let block_count_requests = Arc::new(AtomicUsize::new(0));
+        let block_count_requests_in_node = block_count_requests.clone();
+        let node_url = start_mock_node(move |method, _params| match method {
+            "getblockcount" => match block_count_requests_in_node.fetch_add(1, Ordering::SeqCst) {
+                0 => Err(RPCError {
+                    code: -28,
+                    message: "Loading block index...".to_string(),
+                }),
+                _ => Ok(json!(100)),
+            },
+            "getblockhash" => Ok(json!(block_hash.to_string())),
+            "getblock" => Ok(block_json.clone()),
+            _ => Err(RPCError {
+                code: -32601,
+                message: "Method not found".to_string(),
+            }),
+        });
+
+        let da_service = BitcoinService {
+            block_count_retry_policy: RetryPolicy {
+                max_attempts: 3,
+                backoff: Duration::from_millis(10),
+            },
+            ..get_mock_service(node_url)
+        };
+
+        let fetched_block = da_service
+            .get_finalized_at(2)
+            .await
+            .expect("Transient unavailability should be retried");
+        assert_eq!(fetched_block.header.header.block_hash(), block_hash);
+        assert_eq!(block_count_requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn deserialize_network() {
+        let config_with_network = |network: serde_json::Value| {
+            let mut config = serde_json::to_value(get_config()).unwrap();
+            config["network"] = network;
+            serde_json::from_value::<DaServiceConfig>(config)
+        };
+
+        for (name, network) in [
+            ("bitcoin", bitcoin::Network::Bitcoin),
+            ("testnet", bitcoin::Network::Testnet),
+            ("signet", bitcoin::Network::Signet),
+            ("regtest", bitcoin::Network::Regtest),
+        ] {
+            assert_eq!(
+                config_with_network(json!(name)).unwrap().network,
+                Some(network)
+            );
+        }
+        assert_eq!(config_with_network(json!(null)).unwrap().network, None);
+
+        // invalid networks are rejected when the config is read
+        let error = config_with_network(json!("mainnet")).unwrap_err();
+        assert!(error.to_string().contains("unknown variant `mainnet`"));
+        assert!(config_with_network(json!(42)).is_err());
+
+        // a missing network falls back to regtest instead of failing
+        let service = BitcoinService::try_new(
+            DaServiceConfig {
+                network: None,
+                ..get_config()
+            },
+            RollupParams {
+                rollup_name: "sov-btc".to_string(),
+            },
+        )
+        .unwrap();
+        assert_eq!(service.network, bitcoin::Network::Regtest);
+    }
+
+    #[tokio::test]
+    async fn reveal_file_is_only_kept_on_failure() {
+        let reveal_dir =
+            std::env::temp_dir().join(format!("bitcoin-da-reveal-files-{}", std::process::id()));
+        std::fs::create_dir_all(&reveal_dir).unwrap();
+        let reveal_files = || std::fs::read_dir(&reveal_dir).unwrap().count();
+
+        // the reveal tx is sent
+        let sent_txs = Arc::new(Mutex::new(Vec::new()));
+        let da_service = BitcoinService {
+            reveal_dir: reveal_dir.clone(),
+            ..get_mock_service(start_mock_wallet_node(sent_txs.clone()))
+        };
+        da_service.send_transaction(b"sent blob").await.unwrap();
+        assert_eq!(sent_txs.lock().unwrap().len(), 2);
+        assert_eq!(reveal_files(), 0);
+
+        // the reveal tx is rejected, its file is kept to retry it later
+        let sent_txs = Arc::new(Mutex::new(Vec::new()));
+        let da_service = BitcoinService {
+            reveal_dir: reveal_dir.clone(),
+            reveal_retry_policy: RetryPolicy {
+                max_attempts: 1,
+                backoff: Duration::from_millis(10),
+            },
+            ..get_mock_service(start_mock_wallet_node_with_limit(sent_txs.clone(), 1))
+        };
+        assert!(da_service.send_transaction(b"rejected blob").await.is_err());
+        assert_eq!(sent_txs.lock().unwrap().len(), 1);
+        assert_eq!(reveal_files(), 1);
+
+        std::fs::remove_dir_all(&reveal_dir).unwrap();
+    }
+
+    #[test]
+    fn invalid_rollup_names() {
+        let try_new_with_name = |rollup_name: &str| {
+            BitcoinService::try_new(
+                get_config(),
+                RollupParams {
+                    rollup_name: rollup_name.to_string(),
+                },
+            )
+        };
+
+        assert!(try_new_with_name("sov-btc").is_ok());
+        assert!(try_new_with_name(&"a".repeat(64)).is_ok());
+
+        for rollup_name in ["", &"a".repeat(65), "sov btc", "sov\0btc", "sov-btç"] {
+            let error = try_new_with_name(rollup_name).unwrap_err();
+            assert!(matches!(
+                error.downcast_ref::<BitcoinServiceError>(),
+                Some(BitcoinServiceError::InvalidRollupName { .. })
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn extraction_in_one_pass() {
+        let da_service = get_mock_service("http://localhost:38332".to_string());
+        let block = get_mock_block();
+
+        let txs_with_proof = da_service.extract_relevant_txs_with_proof(&block).await;
+        let txs = da_service.extract_relevant_txs(&block);
+        let (inclusion_proof, completeness_proof) =
+            da_service.get_extraction_proof(&block, &txs).await;
+        assert_eq!(txs_with_proof, (txs, inclusion_proof, completeness_proof));
+
+        let txs = block
+            .txdata
+            .iter()
+            .map(|tx| tx.transaction.clone())
+            .collect::<Vec<_>>();
+        let (inclusion_proof, completeness_proof) = extraction_proof(&txs);
+        assert_eq!(
+            extract_blobs_with_proof(&txs, "sov-btc"),
+            (
+                extract_blobs(&txs, "sov-btc"),
+                inclusion_proof,
+                completeness_proof
+            )
+        );
+    }
+
+    #[test]
+    fn lazy_signature_verification() {
+        let block = BitcoinBlock {
+            header: get_mock_header(),
+            txdata: vec![
+                get_mock_reveal_tx("sov-btc", b"signed", &EnvelopeFields::default()),
+                get_mock_forged_reveal_tx("sov-btc", b"forged"),
+            ]
+            .into_iter()
+            .map(|tx| get_extended_tx(tx, "sov-btc"))
+            .collect(),
+        };
+
+        // the forged blob is dropped
+        let da_service = get_mock_service("http://localhost:38332".to_string());
+        let txs = da_service.extract_relevant_txs(&block);
+        assert_eq!(txs.len(), 1);
+        assert!(txs[0].verify_signature(&EcdsaAuthenticator));
+
+        // the forged blob is returned, it is caught when verified on demand
+        let da_service = BitcoinService {
+            signature_verification: SignatureVerification::Lazy,
+            ..da_service
+        };
+        let txs = da_service.extract_relevant_txs(&block);
+        assert_eq!(txs.len(), 2);
+        assert!(txs[0].verify_signature(&EcdsaAuthenticator));
+        assert!(!txs[1].verify_signature(&EcdsaAuthenticator));
+    }
+
+    #[test]
+    fn extract_from_block_built_from_txs() {
+        let da_service = get_mock_service("http://localhost:38332".to_string());
+        let fields = EnvelopeFields {
+            label: Some("second".to_string()),
+            ..Default::default()
+        };
+
+        let block = BitcoinBlock::from_txs(
+            get_mock_header().header,
+            7,
+            vec![
+                get_mock_txs()[0].clone(),
+                get_mock_reveal_tx("sov-btc", b"first blob", &EnvelopeFields::default()),
+                get_mock_reveal_tx("other-rollup", b"not for us", &EnvelopeFields::default()),
+                get_mock_reveal_tx("sov-btc", b"second blob", &fields),
+            ],
+            "sov-btc",
+        );
+        assert_eq!(block.header.tx_count, 4);
+        assert_eq!(block.header.height, 7);
+        assert!(block.txdata[1].sender.is_some());
+        assert!(block.txdata[2].sender.is_none());
+
+        let txs = da_service.extract_relevant_txs(&block);
+        assert_eq!(txs.len(), 2);
+        let blobs = txs
+            .into_iter()
+            .map(|tx| {
+                let mut blob = tx.blob.clone();
+                blob.advance(blob.total_len());
+                (blob.accumulator().to_vec(), tx.label)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            blobs,
+            vec![
+                (b"first blob".to_vec(), None),
+                (b"second blob".to_vec(), Some("second".to_string()))
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn prev_hash_links_to_block_below() {
+        // a chain of 4 blocks, each on top of the previous one
+        let mut prev_blockhash = get_mock_header().header.prev_blockhash;
+        let blocks = (2..6)
+            .map(|height| {
+                let mut block = get_mock_block_at(height);
+                block.header.header.prev_blockhash = prev_blockhash;
+                prev_blockhash = block.header.header.block_hash();
+                block
+            })
+            .collect::<Vec<_>>();
+        let hashes = blocks
+            .iter()
+            .map(|block| block.header.header.block_hash().to_string())
+            .collect::<Vec<_>>();
+        let blocks_json = blocks
+            .iter()
+            .map(|block| {
+                (
+                    block.header.header.block_hash().to_string(),
+                    get_block_json(block),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+        let node_url = start_mock_node(move |method, params| match method {
+            "getblockhash" => Ok(json!(hashes[params[0].as_u64().unwrap() as usize - 2])),
+            "getblock" => Ok(blocks_json[params[0].as_str().unwrap()].clone()),
+            _ => Err(RPCError {
+                code: -32601,
+                message: "Method not found".to_string(),
+            }),
+        });
+        let da_service = get_mock_service(node_url);
+
+        let below = da_service.get_block_at(4).await.unwrap();
+        let block = da_service.get_block_at(5).await.unwrap();
+        assert_eq!(block.prev_hash(), below.header.hash());
+        assert_ne!(block.prev_hash(), block.header.hash());
+    }
+
+    #[tokio::test]
+    async fn abandon_inscription() {
+        let reveal_dir =
+            std::env::temp_dir().join(format!("bitcoin-da-abandon-{}", std::process::id()));
+        std::fs::create_dir_all(&reveal_dir).unwrap();
+
+        // the commit tx is sent, the reveal tx is rejected and kept on disk
+        let sent_txs = Arc::new(Mutex::new(Vec::new()));
+        let da_service = BitcoinService {
+            reveal_dir: reveal_dir.clone(),
+            reveal_retry_policy: RetryPolicy {
+                max_attempts: 1,
+                backoff: Duration::from_millis(10),
+            },
+            ..get_mock_service(start_mock_wallet_node_with_limit(sent_txs.clone(), 1))
+        };
+        assert!(da_service
+            .send_transaction(b"superseded blob")
+            .await
+            .is_err());
+        let commit_tx = sent_txs.lock().unwrap()[0].clone();
+
+        let da_service = BitcoinService {
+            reveal_dir: reveal_dir.clone(),
+            ..get_mock_service(start_mock_wallet_node(sent_txs.clone()))
+        };
+        let abandon_txid = da_service
+            .abandon_inscription(commit_tx.txid())
+            .await
+            .unwrap();
+
+        // the recovery tx spends the commit output back to the sequencer with a key path signature
+        let abandon_tx = sent_txs.lock().unwrap()[1].clone();
+        assert_eq!(abandon_tx.txid(), abandon_txid);
+        assert_eq!(
+            abandon_tx.input[0].previous_output,
+            OutPoint::new(commit_tx.txid(), 0)
+        );
+        assert_eq!(
+            abandon_tx.output[0].script_pubkey,
+            da_service.address.as_ref().unwrap().script_pubkey()
+        );
+        assert!(abandon_tx.output[0].value < commit_tx.output[0].value);
+
+        let signature_hash = SighashCache::new(&abandon_tx)
+            .taproot_key_spend_signature_hash(
+                0,
+                &Prevouts::All(&[commit_tx.output[0].clone()]),
+                TapSighashType::Default,
+            )
+            .unwrap();
+        let output_key = commit_tx.output[0].script_pubkey.as_bytes()[2..].to_vec();
+        let output_key = XOnlyPublicKey::from_slice(&output_key).unwrap();
+        assert!(Secp256k1::verification_only()
+            .verify_schnorr(
+                &schnorr::Signature::from_slice(&abandon_tx.input[0].witness[0]).unwrap(),
+                &Message::from_slice(signature_hash.as_byte_array()).unwrap(),
+                &output_key,
+            )
+            .is_ok());
+
+        // the reveal tx is not needed anymore
+        assert_eq!(std::fs::read_dir(&reveal_dir).unwrap().count(), 0);
+        assert!(da_service
+            .abandon_inscription(commit_tx.txid())
+            .await
+            .is_err());
+
+        std::fs::remove_dir_all(&reveal_dir).unwrap();
+    }
+
+    #[test]
+    fn extract_under_authorized_rollup_names() {
+        let block_at = |height: u64| {
+            BitcoinBlock::from_txs(
+                get_mock_header().header,
+                height,
+                vec![
+                    get_mock_reveal_tx("sov-btc", b"old name", &EnvelopeFields::default()),
+                    get_mock_reveal_tx("sov-btc-v2", b"new name", &EnvelopeFields::default()),
+                    get_mock_reveal_tx("other-rollup", b"not for us", &EnvelopeFields::default()),
+                ],
+                "sov-btc-v2",
+            )
+        };
+
+        // the rollup moved to a new name, the old one stays valid until height 100
+        let da_service = BitcoinService::try_new(
+            DaServiceConfig {
+                authorized_rollup_names: Some(vec![AuthorizedRollupName {
+                    name: "sov-btc".to_string(),
+                    from_height: None,
+                    until_height: Some(100),
+                }]),
+                ..get_config()
+            },
+            RollupParams {
+                rollup_name: "sov-btc-v2".to_string(),
+            },
+        )
+        .unwrap();
+
+        let blobs = |height: u64| {
+            da_service
+                .extract_relevant_txs(&block_at(height))
+                .into_iter()
+                .map(|tx| {
+                    let mut blob = tx.blob.clone();
+                    blob.advance(blob.total_len());
+                    blob.accumulator().to_vec()
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(blobs(100), vec![b"old name".to_vec(), b"new name".to_vec()]);
+        assert_eq!(blobs(101), vec![b"new name".to_vec()]);
+
+        // the first tx of the block stands for the coinbase proving the height to the verifier
+        let block = block_at(100);
+        assert_eq!(
+            da_service.extraction_proof_of(&block).completeness[0],
+            block.txdata[0].transaction
+        );
+
+        // authorized names are checked like the rollup name
+        assert!(BitcoinService::try_new(
+            DaServiceConfig {
+                authorized_rollup_names: Some(vec![AuthorizedRollupName {
+                    name: String::new(),
+                    from_height: None,
+                    until_height: None,
+                }]),
+                ..get_config()
+            },
+            RollupParams {
+                rollup_name: "sov-btc-v2".to_string(),
+            },
+        )
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn extraction_proof_with_named_fields() {
+        let da_service = get_mock_service("http://localhost:38332".to_string());
+        let block = get_mock_block();
+
+        let proof = da_service.extraction_proof_of(&block);
+        assert_eq!(proof.inclusion.txs.len(), 13);
+        // relevant txs are on 6, 8, 10, 12 indices
+        assert_eq!(
+            proof.completeness,
+            [6, 8, 10, 12]
+                .iter()
+                .map(|&index| block.txdata[index].transaction.clone())
+                .collect::<Vec<_>>()
+        );
+        assert!(proof.verify_against(&block.header).is_ok());
+
+        // the trait method returns the same proofs
+        let txs = da_service.extract_relevant_txs(&block);
+        assert_eq!(
+            da_service.get_extraction_proof(&block, &txs).await,
+            proof.clone().into_parts()
+        );
+
+        // a tx missing from either proof is caught
+        let mut missing_inclusion = proof.clone();
+        missing_inclusion.inclusion.txs.pop();
+        assert!(missing_inclusion.verify_against(&block.header).is_err());
+
+        let mut missing_completeness = proof;
+        missing_completeness.completeness.pop();
+        assert!(missing_completeness.verify_against(&block.header).is_err());
+    }
+
+    #[tokio::test]
+    async fn sends_are_rate_limited() {
+        let sent_txs = Arc::new(Mutex::new(Vec::new()));
+        let da_service = BitcoinService {
+            min_send_interval: Some(Duration::from_millis(300)),
+            ..get_mock_service(start_mock_wallet_node(sent_txs.clone()))
+        };
+
+        // the second send waits for the end of the interval
+        let start = std::time::Instant::now();
+        da_service.send_transaction(b"first").await.unwrap();
+        da_service.send_transaction(b"second").await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(300));
+        assert_eq!(sent_txs.lock().unwrap().len(), 4);
+
+        // or fails right away
+        let da_service = BitcoinService {
+            send_rate_limit: SendRateLimit::Reject,
+            ..da_service
+        };
+        let error = da_service.send_transaction(b"third").await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<BitcoinServiceError>(),
+            Some(BitcoinServiceError::SendTooSoon { .. })
+        ));
+        assert_eq!(sent_txs.lock().unwrap().len(), 4);
+
+        // once the interval has elapsed the send goes through
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        da_service.send_transaction(b"third").await.unwrap();
+        assert_eq!(sent_txs.lock().unwrap().len(), 6);
+    }
+
+    #[tokio::test]
+    async fn send_rate_limit_waits_on_clock() {
+        let sent_txs = Arc::new(Mutex::new(Vec::new()));
+        let sleeps = Arc::new(Mutex::new(Vec::new()));
+        let da_service = BitcoinService {
+            min_send_interval: Some(Duration::from_secs(60)),
+            ..get_mock_service(start_mock_wallet_node(sent_txs.clone()))
+        }
+        .with_clock(VirtualClock::new(sleeps.clone()));
+
+        // the wait for the second send goes through the clock instead of blocking for a minute
+        da_service.send_transaction(b"first").await.unwrap();
+        da_service.send_transaction(b"second").await.unwrap();
+        assert_eq!(sent_txs.lock().unwrap().len(), 4);
+
+        // no virtual time passes between the sends, so the whole interval is waited for
+        assert_eq!(*sleeps.lock().unwrap(), vec![Duration::from_secs(60)]);
+
+        // the interval is measured on the clock too, which the wait moved forward
+        let da_service = BitcoinService {
+            send_rate_limit: SendRateLimit::Reject,
+            ..da_service
+        };
+        let error = da_service.send_transaction(b"third").await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<BitcoinServiceError>(),
+            Some(BitcoinServiceError::SendTooSoon {
+                remaining_ms: 60_000
+            })
+        ));
+        da_service.clock.sleep(Duration::from_secs(60)).await;
+        da_service.send_transaction(b"third").await.unwrap();
+        assert_eq!(sent_txs.lock().unwrap().len(), 6);
+    }
+
+    #[tokio::test]
+    async fn get_finalized_at_uses_configured_finality_depth() {
+        let blocks = (0..12).map(get_mock_block_at).collect::<Vec<_>>();
+        let hashes = blocks
+            .iter()
+            .map(|block| block.header.header.block_hash().to_string())
+            .collect::<Vec<_>>();
+        let blocks_json = blocks
+            .iter()
+            .map(|block| {
+                (
+                    block.header.header.block_hash().to_string(),
+                    get_block_json(block),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+        let node_url = start_mock_node(move |method, params| match method {
+            "getblockcount" => Ok(json!(10)),
+            "getblockhash" => Ok(json!(hashes[params[0].as_u64().unwrap() as usize])),
+            "getblock" => Ok(blocks_json[params[0].as_str().unwrap()].clone()),
+            _ => Err(RPCError {
+                code: -32601,
+                message: "Method not found".to_string(),
+            }),
+        });
+
+        let da_service = BitcoinService::new(
+            DaServiceConfig {
+                node_url,
+                finality_depth: Some(3),
+                ..get_config()
+            },
+            RollupParams {
+                rollup_name: "sov-btc".to_string(),
+            },
+        );
+        assert_eq!(da_service.finality_depth, 3);
+
+        // 3 blocks are mined on top of height 7, not yet on top of height 8
+        let block = da_service.get_finalized_at(7).await.unwrap();
+        assert_eq!(block.header.height, 7);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(500), da_service.get_finalized_at(8))
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn get_finalized_at_by_elapsed_time() {
+        let blocks = (0..12).map(get_mock_block_at).collect::<Vec<_>>();
+        let hashes = blocks
+            .iter()
+            .map(|block| block.header.header.block_hash().to_string())
+            .collect::<Vec<_>>();
+        let blocks_json = blocks
+            .iter()
+            .map(|block| {
+                (
+                    block.header.header.block_hash().to_string(),
+                    get_block_json(block),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+        // a block every 10 minutes
+        let median_times = hashes
+            .iter()
+            .enumerate()
+            .map(|(height, hash)| (hash.clone(), 1_700_000_000 + 600 * height as u64))
+            .collect::<HashMap<_, _>>();
+        let node_url = start_mock_node(move |method, params| match method {
+            "getblockcount" => Ok(json!(10)),
+            "getblockhash" => Ok(json!(hashes[params[0].as_u64().unwrap() as usize])),
+            "getblockheader" => Ok(json!({
+                "mediantime": median_times[params[0].as_str().unwrap()],
+            })),
+            "getblock" => Ok(blocks_json[params[0].as_str().unwrap()].clone()),
+            _ => Err(RPCError {
+                code: -32601,
+                message: "Method not found".to_string(),
+            }),
+        });
+
+        let da_service = BitcoinService::new(
+            DaServiceConfig {
+                node_url,
+                finality_rule: Some(FinalityRule::TimeElapsed(Duration::from_secs(30 * 60))),
+                ..get_config()
+            },
+            RollupParams {
+                rollup_name: "sov-btc".to_string(),
+            },
+        );
+
+        // the tip is 30 minutes ahead of height 7, only 20 minutes ahead of height 8
+        let block = da_service.get_finalized_at(7).await.unwrap();
+        assert_eq!(block.header.height, 7);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(500), da_service.get_finalized_at(8))
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn duplicate_blobs_in_block() {
+        let block = BitcoinBlock::from_txs(
+            get_mock_header().header,
+            200,
+            vec![
+                get_mock_reveal_tx("sov-btc", b"replayed", &EnvelopeFields::default()),
+                get_mock_reveal_tx("sov-btc", b"unique", &EnvelopeFields::default()),
+                get_mock_reveal_tx("sov-btc", b"replayed", &EnvelopeFields::default()),
+            ],
+            "sov-btc",
+        );
+        let service_with_policy = |policy| {
+            BitcoinService::try_new(
+                DaServiceConfig {
+                    duplicate_blobs: Some(policy),
+                    ..get_config()
+                },
+                RollupParams {
+                    rollup_name: "sov-btc".to_string(),
+                },
+            )
+            .unwrap()
+        };
+
+        // duplicates are permitted by default
+        let (blobs, duplicates) = get_mock_service("http://localhost:38332".to_string())
+            .extract_relevant_txs_checked(&block)
+            .unwrap();
+        assert_eq!(blobs.len(), 2);
+        assert!(duplicates.is_empty());
+
+        let (flagged_blobs, duplicates) = service_with_policy(DuplicateBlobPolicy::Flag)
+            .extract_relevant_txs_checked(&block)
+            .unwrap();
+        assert_eq!(flagged_blobs, blobs);
+        assert_eq!(
+            duplicates,
+            DuplicateBlobs {
+                hashes: vec![blobs[0].hash]
+            }
+        );
+
+        assert_eq!(
+            service_with_policy(DuplicateBlobPolicy::Reject).extract_relevant_txs_checked(&block),
+            Err(BitcoinServiceError::DuplicateBlobs(duplicates))
+        );
+
+        // a block without duplicates passes under every policy
+        let unique_block = BitcoinBlock {
+            txdata: block.txdata[..2].to_vec(),
+            ..block
+        };
+        let (blobs, duplicates) = service_with_policy(DuplicateBlobPolicy::Reject)
+            .extract_relevant_txs_checked(&unique_block)
+            .unwrap();
+        assert_eq!(blobs.len(), 2);
+        assert!(duplicates.is_empty());
+    }
+
+    #[tokio::test]
+    async fn duplicate_blobs_of_other_senders() {
+        const FOREIGN_PRIVATE_KEY: &str =
+            "0C27E8EB3C7D2F4E1F3E6C7E0B1E9A6F7D2C3B4A5968778695A4B3C2D1E0F1A2"; // Test key, safe to publish
+
+        // a third party re-signs the body of the sequencer and gets it mined first
+        let mut txs = vec![
+            get_mock_reveal_tx_signed_by(
+                "sov-btc",
+                b"replayed",
+                &EnvelopeFields::default(),
+                FOREIGN_PRIVATE_KEY,
+            ),
+            get_mock_reveal_tx("sov-btc", b"replayed", &EnvelopeFields::default()),
+            get_mock_reveal_tx("sov-btc", b"replayed", &EnvelopeFields::default()),
+        ];
+        for tx in txs.iter_mut() {
+            for lock_time in 1.. {
+                tx.lock_time = LockTime::from_consensus(lock_time);
+                if tx.txid().to_raw_hash().to_byte_array()[0..2] == [0, 0] {
+                    break;
+                }
+            }
+        }
+        let foreign_key = parse_transaction(&txs[0], "sov-btc").unwrap().public_key;
+
+        let txids = txs.iter().map(|tx| tx.txid()).collect::<Vec<_>>();
+        let mut header = get_mock_header();
+        header.header.merkle_root = TxMerkleNode::from_raw_hash(
+            merkle_tree::calculate_root(txids.into_iter())
+                .unwrap()
+                .to_raw_hash(),
+        );
+        let block = BitcoinBlock::from_txs(header.header, 100, txs, "sov-btc");
+
+        // without authorized keys both senders are accepted, the copy of the sequencer is kept
+        let da_service = BitcoinService::try_new(
+            DaServiceConfig {
+                duplicate_blobs: Some(DuplicateBlobPolicy::Flag),
+                ..get_config()
+            },
+            RollupParams {
+                rollup_name: "sov-btc".to_string(),
+            },
+        )
+        .unwrap();
+        let sequencer_public_key = da_service.sequencer_public_key().unwrap();
+        let (txs, inclusion_proof, completeness_proof) =
+            da_service.extract_relevant_txs_with_proof(&block).await;
+        assert_eq!(txs.len(), 2);
+        assert_eq!(txs[0].hash, txs[1].hash);
+        assert_eq!(txs[0].sender.0, foreign_key);
+        assert_eq!(txs[1].sender.0, sequencer_public_key);
+
+        // only the copies of the same sender are duplicates
+        let (blobs, duplicates) = da_service.extract_relevant_txs_checked(&block).unwrap();
+        assert_eq!(blobs, txs);
+        assert_eq!(
+            duplicates,
+            DuplicateBlobs {
+                hashes: vec![txs[1].hash]
+            }
+        );
+
+        // the verifier keeps both senders apart too
+        BitcoinVerifier::new(RollupParams {
+            rollup_name: "sov-btc".to_string(),
+        })
+        .verify_relevant_tx_list(&block.header, &txs, inclusion_proof, completeness_proof)
+        .expect("Copies of other senders should be verified");
+    }
+
+    #[tokio::test]
+    async fn send_transaction_with_annex() {
+        let fields = EnvelopeFields {
+            annex: Some(vec![0x50, 1, 2, 3]),
+            ..Default::default()
+        };
+
+        // refused by default, before the commit tx is sent
+        let sent_txs = Arc::new(Mutex::new(Vec::new()));
+        let da_service = get_mock_service(start_mock_wallet_node(sent_txs.clone()));
+        let error = da_service
+            .send_transaction_with_fields(b"annexed", fields.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<BitcoinServiceError>(),
+            Some(&BitcoinServiceError::NonStandardAnnex)
+        );
+        assert!(sent_txs.lock().unwrap().is_empty());
+
+        // sent once opted in, the annex is the last element of the reveal witness
+        let da_service = BitcoinService {
+            allow_nonstandard_annex: true,
+            ..da_service
+        };
+        da_service
+            .send_transaction_with_fields(b"annexed", fields)
+            .await
+            .unwrap();
+        let sent_txs = sent_txs.lock().unwrap().clone();
+        assert_eq!(sent_txs.len(), 2);
+        assert_eq!(
+            sent_txs[1].input[0].witness.last(),
+            Some([0x50, 1, 2, 3].as_slice())
+        );
+    }
+
+    #[tokio::test]
+    async fn send_transaction_without_reveal_dir() {
+        // the reveal directory is removed while the service runs
+        let sent_txs = Arc::new(Mutex::new(Vec::new()));
+        let da_service = BitcoinService {
+            reveal_dir: std::env::temp_dir()
+                .join(format!("bitcoin-da-missing-{}", std::process::id())),
+            ..get_mock_service(start_mock_wallet_node(sent_txs.clone()))
+        };
+
+        // the error tells which commit tx is sent without its reveal tx
+        let error = da_service
+            .send_transaction_with_fields(b"unpersisted", EnvelopeFields::default())
+            .await
+            .unwrap_err();
+        let sent_txs = sent_txs.lock().unwrap().clone();
+        assert_eq!(sent_txs.len(), 1);
+        assert!(error.to_string().contains(&sent_txs[0].txid().to_string()));
+    }
+
+    #[tokio::test]
+    async fn send_transaction_with_ids() {
+        let sent_txs = Arc::new(Mutex::new(Vec::new()));
+        let da_service = get_mock_service(start_mock_wallet_node(sent_txs.clone()));
+
+        let submitted = da_service
+            .send_transaction_with_ids(b"receipt")
+            .await
+            .unwrap();
+
+        // the ids of the commit and reveal txs sent to the node
+        let sent_txs = sent_txs.lock().unwrap().clone();
+        assert_eq!(
+            submitted,
+            vec![SubmittedInscription {
+                commit_txid: sent_txs[0].txid(),
+                reveal_txid: sent_txs[1].txid(),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn find_by_correlation_id() {
+        let reveal_dir =
+            std::env::temp_dir().join(format!("bitcoin-da-correlation-{}", std::process::id()));
+        std::fs::create_dir_all(&reveal_dir).unwrap();
+
+        let sent_txs = Arc::new(Mutex::new(Vec::new()));
+        let da_service = BitcoinService {
+            reveal_dir: reveal_dir.clone(),
+            ..get_mock_service(start_mock_wallet_node(sent_txs.clone()))
+        };
+        let batch_id = [7; 16];
+        assert_eq!(
+            da_service.find_by_correlation_id(&batch_id).unwrap(),
+            vec![]
+        );
+
+        let first = da_service
+            .send_transaction_with_correlation_id(
+                b"batch",
+                EnvelopeFields::default(),
+                Some(batch_id),
+            )
+            .await
+            .unwrap();
+        assert_eq!(first[0].reveal_txid, sent_txs.lock().unwrap()[1].txid());
+        assert_eq!(da_service.find_by_correlation_id(&batch_id).unwrap(), first);
+
+        // a retry of the batch is recorded along with the first attempt, other ids are untouched
+        let retry = da_service
+            .send_transaction_with_correlation_id(
+                b"batch",
+                EnvelopeFields::default(),
+                Some(batch_id),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            da_service.find_by_correlation_id(&batch_id).unwrap(),
+            [first, retry].concat()
+        );
+        assert_eq!(da_service.find_by_correlation_id(&[8; 16]).unwrap(), vec![]);
+
+        // the id is not inscribed, the reveal txs are identical but for their funding
+        let sent_txs = sent_txs.lock().unwrap().clone();
+        assert_eq!(
+            parse_transaction(&sent_txs[1], "sov-btc").unwrap().body,
+            parse_transaction(&sent_txs[3], "sov-btc").unwrap().body
+        );
+
+        std::fs::remove_dir_all(&reveal_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn bump_fee() {
+        let reveal_dir =
+            std::env::temp_dir().join(format!("bitcoin-da-bump-fee-{}", std::process::id()));
+        std::fs::create_dir_all(&reveal_dir).unwrap();
+
+        // the commit tx is sent at 1 sat/vB
+        let sent_txs = Arc::new(Mutex::new(Vec::new()));
+        let da_service = BitcoinService {
+            reveal_dir: reveal_dir.clone(),
+            keep_sent_reveals: true,
+            ..get_mock_service(start_mock_wallet_node(sent_txs.clone()))
+        };
+        let fields = EnvelopeFields {
+            label: Some("stuck".to_string()),
+            ..Default::default()
+        };
+        let submitted = da_service
+            .send_transaction_with_fields(b"stuck blob", fields)
+            .await
+            .unwrap()[0];
+
+        // invalid rates, rates not above the one of the commit tx and rates its utxos cannot pay
+        // are rejected before anything is sent
+        for fee_rate in [f64::NAN, -5.0, 1.0, 10_000_000.0] {
+            assert!(da_service
+                .bump_fee(submitted.commit_txid, fee_rate)
+                .await
+                .is_err());
+        }
+        assert_eq!(sent_txs.lock().unwrap().len(), 2);
+
+        let bumped = da_service
+            .bump_fee(submitted.commit_txid, 5.0)
+            .await
+            .unwrap();
+        let sent_txs = sent_txs.lock().unwrap().clone();
+        assert_eq!(sent_txs.len(), 4);
+        let (commit_tx, replacement_tx) = (&sent_txs[0], &sent_txs[2]);
+        assert_eq!(bumped.commit_txid, replacement_tx.txid());
+        assert_eq!(bumped.reveal_txid, sent_txs[3].txid());
+        assert_eq!(
+            sent_txs[3].input[0].previous_output.txid,
+            replacement_tx.txid()
+        );
+
+        // the replacement spends the same utxos, both signal replaceability
+        let outpoints = |tx: &Transaction| {
+            tx.input
+                .iter()
+                .map(|input| input.previous_output)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(outpoints(commit_tx), outpoints(replacement_tx));
+        assert!(commit_tx
+            .input
+            .iter()
+            .chain(replacement_tx.input.iter())
+            .all(|input| input.sequence == Sequence::ENABLE_RBF_NO_LOCKTIME));
+
+        // every utxo of the mock wallet holds 1 BTC
+        let fee = |tx: &Transaction| {
+            tx.input.len() as u64 * 100_000_000
+                - tx.output.iter().map(|output| output.value).sum::<u64>()
+        };
+        assert!(fee(replacement_tx) > 4 * fee(commit_tx));
+
+        // the inscription is the same, only the file of the replacement is left
+        let inscription = parse_transaction(&sent_txs[3], "sov-btc").unwrap();
+        assert_eq!(inscription.label.as_deref(), Some("stuck"));
+        assert_eq!(
+            inscription.body,
+            parse_transaction(&sent_txs[1], "sov-btc").unwrap().body
+        );
+        let reveal_file_exists = |commit_txid: Txid| {
+            read_reveal_tx(&reveal_dir, commit_txid.to_raw_hash().to_string()).is_ok()
+        };
+        assert!(reveal_file_exists(bumped.commit_txid));
+        assert!(!reveal_file_exists(submitted.commit_txid));
+
+        // the commit tx can also spend outputs of unconfirmed parents, not in the chain state
+        let parent = Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![
+                TxOut {
+                    value: 100_000_000,
+                    script_pubkey: ScriptBuf::new(),
+                };
+                2
+            ],
+        };
+        let mempool_txs = Arc::new(Mutex::new(sent_txs.clone()));
+        let node_txs = mempool_txs.clone();
+        let node_url = start_mock_node(move |method, params| match method {
+            "gettxout" => Ok(json!(null)),
+            "getrawtransaction" => mock_wallet_call(&node_txs, usize::MAX, method, params)
+                .or_else(|_| Ok(json!(encode::serialize_hex(&parent)))),
+            _ => mock_wallet_call(&node_txs, usize::MAX, method, params),
+        });
+        let rebumped = BitcoinService {
+            reveal_dir: reveal_dir.clone(),
+            keep_sent_reveals: true,
+            ..get_mock_service(node_url)
+        }
+        .bump_fee(bumped.commit_txid, 10.0)
+        .await
+        .unwrap();
+        assert_eq!(rebumped.commit_txid, mempool_txs.lock().unwrap()[4].txid());
+
+        // without `keep_sent_reveals` the reveal tx is found in the mempool
+        let sent_txs = Arc::new(Mutex::new(Vec::new()));
+        let da_service = BitcoinService {
+            reveal_dir: reveal_dir.clone(),
+            ..get_mock_service(start_mock_wallet_node(sent_txs.clone()))
+        };
+        let submitted = da_service
+            .send_transaction_with_fields(b"unkept blob", EnvelopeFields::default())
+            .await
+            .unwrap()[0];
+        assert!(!reveal_file_exists(submitted.commit_txid));
+        let bumped = da_service
+            .bump_fee(submitted.commit_txid, 5.0)
+            .await
+            .unwrap();
+        let sent_txs = sent_txs.lock().unwrap().clone();
+        assert_eq!(bumped.commit_txid, sent_txs[2].txid());
+        assert_eq!(
+            parse_transaction(&sent_txs[3], "sov-btc").unwrap().body,
+            parse_transaction(&sent_txs[1], "sov-btc").unwrap().body
+        );
+
+        // commit txs that do not signal replaceability cannot be bumped, whatever the service
+        let da_service = BitcoinService {
+            enable_rbf: false,
+            ..da_service
+        };
+        let submitted = da_service
+            .send_transaction_with_fields(b"final blob", EnvelopeFields::default())
+            .await
+            .unwrap()[0];
+        let error = da_service
+            .bump_fee(submitted.commit_txid, 10.0)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("replaceability"));
+        assert!(BitcoinService {
+            enable_rbf: true,
+            ..da_service
+        }
+        .bump_fee(submitted.commit_txid, 10.0)
+        .await
+        .is_err());
+
+        std::fs::remove_dir_all(&reveal_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn resume_reveal_tx() {
+        let reveal_dir =
+            std::env::temp_dir().join(format!("bitcoin-da-resume-{}", std::process::id()));
+        std::fs::create_dir_all(&reveal_dir).unwrap();
+
+        // the reveal tx of a commit tx sent before a crash
+        let commit_txid = Txid::from_byte_array([7; 32]);
+        let mut reveal_tx = get_mock_reveal_tx("sov-btc", b"resumed", &EnvelopeFields::default());
+        reveal_tx.input[0].previous_output = OutPoint::new(commit_txid, 0);
+        write_reveal_tx(
+            &reveal_dir,
+            &encode::serialize(&reveal_tx),
+            commit_txid.to_raw_hash().to_string(),
+        )
+        .unwrap();
+
+        let get_service = |commit_known: bool, output_unspent: bool| {
+            let commit_txid = commit_txid.to_string();
+            let node_url = start_mock_node(move |method, params| {
+                match (method, params.first().and_then(|txid| txid.as_str())) {
+                    ("getrawtransaction", Some(txid)) if commit_known && txid == commit_txid => {
+                        Ok(json!({ "txid": txid, "confirmations": 1 }))
+                    }
+                    ("getrawtransaction", _) => Err(RPCError {
+                        code: -5,
+                        message: "No such mempool or blockchain transaction".to_string(),
+                    }),
+                    ("gettxout", _) if output_unspent => Ok(json!({ "value": 0.0001 })),
+                    ("gettxout", _) => Ok(json!(null)),
+                    ("sendrawtransaction", Some(tx)) => {
+                        let tx: Transaction =
+                            encode::deserialize(&hex::decode(tx).unwrap()).unwrap();
+                        Ok(json!(tx.txid().to_string()))
+                    }
+                    _ => Err(RPCError {
+                        code: -32601,
+                        message: "Method not found".to_string(),
+                    }),
+                }
+            });
+
+            BitcoinService {
+                reveal_dir: reveal_dir.clone(),
+                ..get_mock_service(node_url)
+            }
+        };
+
+        let commit_txid = commit_txid.to_string();
+        let error = get_service(false, true)
+            .resume_reveal_tx(&commit_txid)
+            .await
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("neither in the mempool nor in the chain"));
+
+        let error = get_service(true, false)
+            .resume_reveal_tx(&commit_txid)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("already spent by another tx"));
+
+        // the reveal tx is sent and its file removed
+        let reveal_txid = get_service(true, true)
+            .resume_reveal_tx(&commit_txid)
+            .await
+            .unwrap();
+        assert_eq!(reveal_txid, reveal_tx.txid());
+        assert_eq!(std::fs::read_dir(&reveal_dir).unwrap().count(), 0);
+        assert!(get_service(true, true)
+            .resume_reveal_tx(&commit_txid)
+            .await
+            .is_err());
+
+        std::fs::remove_dir_all(&reveal_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn reveal_is_not_persisted_without_commit() {
+        let reveal_dir =
+            std::env::temp_dir().join(format!("bitcoin-da-no-commit-{}", std::process::id()));
+        std::fs::create_dir_all(&reveal_dir).unwrap();
+        let reveal_files = || std::fs::read_dir(&reveal_dir).unwrap().count();
+
+        // the commit tx is rejected
+        let sent_txs = Arc::new(Mutex::new(Vec::new()));
+        let da_service = BitcoinService {
+            reveal_dir: reveal_dir.clone(),
+            check_commit_in_mempool: true,
+            ..get_mock_service(start_mock_wallet_node_with_limit(sent_txs.clone(), 0))
         };
+        let error = da_service.send_transaction(b"lost blob").await.unwrap_err();
+        assert!(error.to_string().contains("not persisted"));
+        assert!(sent_txs.lock().unwrap().is_empty());
+        assert_eq!(reveal_files(), 0);
 
-        BitcoinService::new(
-            runtime_config,
-            RollupParams {
-                rollup_name: "sov-btc".to_string(),
-            },
-        )
+        // the commit tx is accepted and found in the mempool
+        let sent_txs = Arc::new(Mutex::new(Vec::new()));
+        let da_service = BitcoinService {
+            reveal_dir: reveal_dir.clone(),
+            keep_sent_reveals: true,
+            check_commit_in_mempool: true,
+            ..get_mock_service(start_mock_wallet_node(sent_txs.clone()))
+        };
+        da_service.send_transaction(b"kept blob").await.unwrap();
+        assert_eq!(sent_txs.lock().unwrap().len(), 2);
+        assert_eq!(reveal_files(), 1);
+
+        std::fs::remove_dir_all(&reveal_dir).unwrap();
     }
 
     #[tokio::test]
-    async fn get_finalized_at() {
-        let da_service = get_service().await;
+    async fn filter_hints() {
+        let sent_txs = Arc::new(Mutex::new(Vec::new()));
+        let da_service = get_mock_service(start_mock_wallet_node(sent_txs.clone()));
+        da_service.send_transaction(b"filtered blob").await.unwrap();
+        let (commit_tx, reveal_tx) = {
+            let sent_txs = sent_txs.lock().unwrap();
+            (sent_txs[0].clone(), sent_txs[1].clone())
+        };
 
-        da_service
-            .get_finalized_at(132)
+        let hints = da_service.filter_hints().unwrap();
+        assert!(hints.matches_commit_output(&commit_tx.output[0]));
+        // the change goes to a P2WPKH address
+        assert!(!hints.matches_commit_output(&commit_tx.output[1]));
+
+        assert_eq!(
+            reveal_tx.output[0].script_pubkey,
+            hints.reveal_output_script
+        );
+        let tapscript = reveal_tx.input[0].witness.tapscript().unwrap();
+        assert!(hints.matches_reveal_script(tapscript));
+
+        // the inscriptions of other rollups do not match
+        let other_tx = get_mock_reveal_tx("other-rollup", b"other", &EnvelopeFields::default());
+        assert!(!hints.matches_reveal_script(other_tx.input[0].witness.tapscript().unwrap()));
+    }
+
+    #[test]
+    fn extract_from_empty_block() {
+        let da_service = get_mock_service("http://localhost:38332".to_string());
+
+        // only the coinbase tx, and an inscription of another rollup
+        let genesis = genesis_block(bitcoin::Network::Regtest);
+        let block = BitcoinBlock::from_txs(
+            genesis.header,
+            0,
+            vec![
+                genesis.txdata[0].clone(),
+                get_mock_reveal_tx("other-rollup", b"not for us", &EnvelopeFields::default()),
+            ],
+            "sov-btc",
+        );
+
+        let scanned = da_service.extract_relevant_txs_scanned(&block);
+        assert!(scanned.is_empty());
+        assert_eq!(scanned.tx_count, 2);
+        assert_eq!(scanned.height, 0);
+        assert_eq!(scanned.hash, genesis.block_hash());
+
+        // the blobs are the ones of `extract_relevant_txs`
+        let block = get_mock_block();
+        let scanned = da_service.extract_relevant_txs_scanned(&block);
+        assert!(!scanned.is_empty());
+        assert_eq!(scanned.tx_count, block.txdata.len());
+        assert_eq!(scanned.blobs, da_service.extract_relevant_txs(&block));
+    }
+
+    #[tokio::test]
+    async fn corrupt_compressed_body_is_skipped() {
+        let da_service = get_mock_service("http://localhost:38332".to_string());
+
+        // signed by the sequencer, with valid tags around a truncated brotli stream
+        let compressed = compress_blob(&std::fs::read("test_data/blob.txt").unwrap());
+        let mut corrupt_tx = get_mock_reveal_tx_with_body(
+            "sov-btc",
+            compressed[..compressed.len() / 2].to_vec(),
+            &EnvelopeFields::default(),
+        );
+        // relevant looking, so that the verifier checks it
+        for lock_time in 1.. {
+            corrupt_tx.lock_time = LockTime::from_consensus(lock_time);
+            if corrupt_tx.txid().to_raw_hash().to_byte_array()[0..2] == [0, 0] {
+                break;
+            }
+        }
+        assert!(parse_transaction(&corrupt_tx, "sov-btc").is_ok());
+
+        let block = BitcoinBlock::from_txs(
+            get_mock_header().header,
+            100,
+            vec![
+                corrupt_tx.clone(),
+                get_mock_reveal_tx("sov-btc", b"sound blob", &EnvelopeFields::default()),
+            ],
+            "sov-btc",
+        );
+        let txs = da_service.extract_relevant_txs(&block);
+        assert_eq!(txs.len(), 1);
+        let mut blob = txs[0].blob.clone();
+        blob.advance(blob.total_len());
+        assert_eq!(blob.accumulator(), b"sound blob");
+        assert_eq!(extract_blobs(&[corrupt_tx.clone()], "sov-btc"), vec![]);
+
+        // the verifier skips it too
+        let mut header = get_mock_header();
+        header.header.merkle_root = TxMerkleNode::from_raw_hash(corrupt_tx.txid().to_raw_hash());
+        let block = BitcoinBlock::from_txs(header.header, 100, vec![corrupt_tx], "sov-btc");
+        let (txs, inclusion_proof, completeness_proof) =
+            da_service.extract_relevant_txs_with_proof(&block).await;
+        assert!(txs.is_empty());
+        assert_eq!(completeness_proof.len(), 1);
+        BitcoinVerifier::new(RollupParams {
+            rollup_name: "sov-btc".to_string(),
+        })
+        .verify_relevant_tx_list(&block.header, &txs, inclusion_proof, completeness_proof)
+        .expect("Corrupt blobs should be skipped by the verifier");
+    }
+
+    // Virtual clock: sleeps return at once, are recorded and move its time forward
+    #[derive(Debug, Clone)]
+    struct VirtualClock(Arc<Mutex<Vec<Duration>>>, Instant);
+
+    impl VirtualClock {
+        fn new(sleeps: Arc<Mutex<Vec<Duration>>>) -> Self {
+            Self(sleeps, Instant::now())
+        }
+    }
+
+    impl Default for VirtualClock {
+        fn default() -> Self {
+            Self::new(Arc::default())
+        }
+    }
+
+    #[async_trait]
+    impl Clock for VirtualClock {
+        async fn sleep(&self, duration: Duration) {
+            self.0.lock().unwrap().push(duration);
+        }
+
+        fn now(&self) -> Instant {
+            self.1 + self.0.lock().unwrap().iter().sum::<Duration>()
+        }
+    }
+
+    #[tokio::test]
+    async fn wait_loops_with_virtual_clock() {
+        let blocks = (0..12).map(get_mock_block_at).collect::<Vec<_>>();
+        let hashes = blocks
+            .iter()
+            .map(|block| block.header.header.block_hash().to_string())
+            .collect::<Vec<_>>();
+        let blocks_json = blocks
+            .iter()
+            .map(|block| {
+                (
+                    block.header.header.block_hash().to_string(),
+                    get_block_json(block),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+
+        // a block is mined at every poll of the block count, from height 5,
+        // and the block at height 11 is only found at the third request
+        let block_count_requests = Arc::new(AtomicUsize::new(0));
+        let block_count_requests_in_node = block_count_requests.clone();
+        let hash_requests = AtomicUsize::new(0);
+        let node_url = start_mock_node(move |method, params| match method {
+            "getblockcount" => Ok(json!(
+                5 + block_count_requests_in_node.fetch_add(1, Ordering::SeqCst)
+            )),
+            "getblockhash" => {
+                let height = params[0].as_u64().unwrap() as usize;
+                if height == 11 && hash_requests.fetch_add(1, Ordering::SeqCst) < 2 {
+                    return Err(RPCError {
+                        code: -8,
+                        message: "Block height out of range".to_string(),
+                    });
+                }
+                Ok(json!(hashes[height]))
+            }
+            "getblock" => Ok(blocks_json[params[0].as_str().unwrap()].clone()),
+            _ => Err(RPCError {
+                code: -32601,
+                message: "Method not found".to_string(),
+            }),
+        });
+
+        let clock = VirtualClock::default();
+        let da_service = BitcoinService {
+            finality_rule: FinalityRule::BlockDepth(3),
+            polling_interval: Duration::from_secs(60),
+            ..get_mock_service(node_url)
+        }
+        .with_clock(clock.clone());
+
+        // block counts 5 to 10 are polled, with a sleep between two polls
+        let block = da_service.get_finalized_at(7).await.unwrap();
+        assert_eq!(block.header.height, 7);
+        assert_eq!(block_count_requests.load(Ordering::SeqCst), 6);
+        assert_eq!(*clock.0.lock().unwrap(), vec![Duration::from_secs(60); 5]);
+
+        // two sleeps while the block is not found
+        clock.0.lock().unwrap().clear();
+        let block = da_service.get_block_at(11).await.unwrap();
+        assert_eq!(block.header.height, 11);
+        assert_eq!(*clock.0.lock().unwrap(), vec![Duration::from_secs(60); 2]);
+    }
+
+    #[tokio::test]
+    async fn reveal_waits_for_commit_confirmations() {
+        let sent_txs = Arc::new(Mutex::new(Vec::new()));
+        let sleeps = Arc::new(Mutex::new(Vec::new()));
+        // txs sent when the commit tx is polled
+        let polls = Arc::new(Mutex::new(Vec::new()));
+
+        // a block is mined on top of the commit tx every time the service sleeps
+        let node_url = {
+            let sent_txs = sent_txs.clone();
+            let sleeps = sleeps.clone();
+            let polls = polls.clone();
+            start_mock_node(move |method, params| match method {
+                "getrawtransaction" => {
+                    polls.lock().unwrap().push(sent_txs.lock().unwrap().len());
+                    Ok(json!({ "confirmations": sleeps.lock().unwrap().len() }))
+                }
+                _ => mock_wallet_call(&sent_txs, usize::MAX, method, params),
+            })
+        };
+        let da_service = BitcoinService {
+            commit_confirmations: 3,
+            ..get_mock_service(node_url)
+        }
+        .with_clock(VirtualClock::new(sleeps.clone()));
+
+        da_service.send_transaction(b"deep blob").await.unwrap();
+
+        // the reveal tx is withheld while the commit tx has 0, 1 and 2 confirmations
+        assert_eq!(*polls.lock().unwrap(), vec![1, 1, 1, 1]);
+        assert_eq!(sleeps.lock().unwrap().len(), 3);
+        let sent_txs = sent_txs.lock().unwrap();
+        assert_eq!(sent_txs.len(), 2);
+        assert_eq!(
+            sent_txs[1].input[0].previous_output.txid,
+            sent_txs[0].txid()
+        );
+    }
+
+    #[tokio::test]
+    async fn estimate_inscription_fee_before_sending() {
+        let sent_txs = Arc::new(Mutex::new(Vec::new()));
+        let da_service = get_mock_service(start_mock_wallet_node(sent_txs.clone()));
+
+        let blob = (0..100u32)
+            .flat_map(|i| blob_hash(&i.to_le_bytes()))
+            .collect::<Vec<_>>();
+        let estimate = da_service
+            .estimate_inscription_fee(&blob, &EnvelopeFields::default())
             .await
-            .expect("Failed to get block");
+            .unwrap();
+        assert!(sent_txs.lock().unwrap().is_empty());
+
+        // the fees paid out of the 1 BTC utxo at 1 sat/vB
+        da_service.send_transaction(&blob).await.unwrap();
+        let sent_txs = sent_txs.lock().unwrap();
+        let (commit_tx, reveal_tx) = (&sent_txs[0], &sent_txs[1]);
+        let commit_outputs = commit_tx
+            .output
+            .iter()
+            .map(|output| output.value)
+            .sum::<u64>();
+        let commit_fee = 100_000_000 - commit_outputs;
+        let reveal_fee = commit_tx.output[0].value - reveal_tx.output[0].value;
+        assert!(estimate.abs_diff(commit_fee + reveal_fee) <= 2);
     }
 
     #[tokio::test]
-    async fn get_block_at() {
-        let da_service = get_service().await;
+    async fn send_transaction_selects_funding_utxos() {
+        let utxo = |txid: &str, vout: u32, amount: f64| {
+            json!({
+                "txid": txid,
+                "vout": vout,
+                "address": "bcrt1qxuds94z3pqwqea2p4f4ev4f25s6uu7y3avljrl",
+                "scriptPubKey": "",
+                "amount": amount,
+                "confirmations": 100,
+                "spendable": true,
+                "solvable": true,
+            })
+        };
+        let dust = utxo(
+            "4cfbec13cf1510545f285cceceb6229bd7b6a918a8f6eba1dbee64d26226a3b7",
+            0,
+            0.000006,
+        );
+        let small = utxo(
+            "9f6e4b5a2c8d7e1f0a3b6c9d2e5f8a1b4c7d0e3f6a9b2c5d8e1f4a7b0c3d6e9f",
+            1,
+            0.0001,
+        );
+        let large = utxo(
+            "9f6e4b5a2c8d7e1f0a3b6c9d2e5f8a1b4c7d0e3f6a9b2c5d8e1f4a7b0c3d6e9f",
+            2,
+            0.5,
+        );
 
-        da_service
-            .get_block_at(132)
+        let mock_service = |utxos: serde_json::Value, sent_txs: Arc<Mutex<Vec<Transaction>>>| {
+            get_mock_service(start_mock_node(move |method, params| match method {
+                "listunspent" => Ok(utxos.clone()),
+                _ => mock_wallet_call(&sent_txs, usize::MAX, method, params),
+            }))
+        };
+
+        // the first utxo of the wallet is too small, the inscription is made from the largest one
+        let sent_txs = Arc::new(Mutex::new(Vec::new()));
+        let da_service = mock_service(json!([dust, small, large]), sent_txs.clone());
+        da_service.send_transaction(b"funded blob").await.unwrap();
+        let commit_tx = sent_txs.lock().unwrap()[0].clone();
+        assert_eq!(commit_tx.input.len(), 1);
+        assert_eq!(commit_tx.input[0].previous_output.vout, 2);
+
+        // no combination of utxos can fund the inscription
+        let sent_txs = Arc::new(Mutex::new(Vec::new()));
+        let da_service = mock_service(json!([dust]), sent_txs.clone());
+        let error = da_service
+            .send_transaction(b"unfunded blob")
             .await
-            .expect("Failed to get block");
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<BuilderError>(),
+            Some(BuilderError::InsufficientFunds { available: 600, .. })
+        ));
+        assert!(sent_txs.lock().unwrap().is_empty());
     }
 
     #[tokio::test]
-    async fn extract_relevant_txs() {
-        let da_service = get_service().await;
+    async fn send_transaction_on_satpoint() {
+        let sent_txs = Arc::new(Mutex::new(Vec::new()));
+        let da_service = get_mock_service(start_mock_wallet_node(sent_txs.clone()));
+        let outpoint = OutPoint::new(
+            Txid::from_str("9f6e4b5a2c8d7e1f0a3b6c9d2e5f8a1b4c7d0e3f6a9b2c5d8e1f4a7b0c3d6e9f")
+                .unwrap(),
+            1,
+        );
+        let satpoint = SatPoint {
+            outpoint,
+            offset: 10_000,
+        };
 
-        let block = da_service
-            .get_block_at(132)
+        da_service
+            .send_transaction_on_satpoint(b"rare blob", EnvelopeFields::default(), satpoint)
             .await
-            .expect("Failed to get block");
-        // panic!();
+            .unwrap();
+        let commit_tx = sent_txs.lock().unwrap()[0].clone();
+        let reveal_tx = sent_txs.lock().unwrap()[1].clone();
 
-        let txs = da_service.extract_relevant_txs(&block);
+        // the sats before the satpoint go back to the wallet, the commit output starts with it
+        assert_eq!(commit_tx.input[0].previous_output, outpoint);
+        assert_eq!(commit_tx.output[0].value, 10_000);
+        let commit_vout = reveal_tx.input[0].previous_output.vout;
+        assert_eq!(commit_vout, 1);
+        let input_values = vec![100_000_000; commit_tx.input.len()];
+        assert_eq!(
+            get_commit_satpoint(&commit_tx, commit_vout, &input_values),
+            Some(satpoint)
+        );
+        let inscription = parse_transaction(&reveal_tx, "sov-btc").unwrap();
+        assert_eq!(inscription.body, compress_blob(b"rare blob"));
 
-        for tx in txs {
-            println!("blob: {:?}", tx.blob);
+        // the satpoint must be in a spendable utxo
+        for satpoint in [
+            SatPoint {
+                outpoint,
+                offset: 100_000_000,
+            },
+            SatPoint {
+                outpoint: OutPoint::new(outpoint.txid, 7),
+                offset: 0,
+            },
+        ] {
+            let error = da_service
+                .send_transaction_on_satpoint(b"blob", EnvelopeFields::default(), satpoint)
+                .await
+                .unwrap_err();
+            assert!(matches!(
+                error.downcast_ref::<BitcoinServiceError>(),
+                Some(BitcoinServiceError::InvalidSatpoint { .. })
+            ));
+        }
+
+        // an additional input pays the commit fee, nothing can take the sats before the satpoint
+        let da_service = BitcoinService {
+            commit_fee_mode: CommitFeeMode::RequireAdditionalInput,
+            ..da_service
+        };
+        let error = da_service
+            .send_transaction_on_satpoint(b"blob", EnvelopeFields::default(), satpoint)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<BuilderError>(),
+            Some(BuilderError::UnalignedSatpoint { offset: 10_000 })
+        ));
+        assert_eq!(sent_txs.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn rollup_names_in_block() {
+        let da_service = get_mock_service("http://localhost:38332".to_string());
+        let inscription = |rollup_name: &str| {
+            get_mock_reveal_tx(rollup_name, b"named blob", &EnvelopeFields::default())
+        };
+
+        let txs = vec![
+            inscription("sov-btc"),
+            inscription("other-rollup"),
+            inscription("sov-btc"),
+            inscription("third-rollup"),
+        ];
+        let block = BitcoinBlock::from_txs(get_mock_header().header, 100, txs, "sov-btc");
+        assert_eq!(
+            da_service.rollup_names_in_block(&block),
+            vec!["other-rollup", "sov-btc", "third-rollup"]
+        );
+
+        let empty_block = BitcoinBlock::from_txs(get_mock_header().header, 100, vec![], "sov-btc");
+        assert!(da_service.rollup_names_in_block(&empty_block).is_empty());
+    }
+
+    #[tokio::test]
+    async fn fee_rate_override_skips_estimation() {
+        // the node cannot estimate fees, as is common on regtest
+        let sent_txs = Arc::new(Mutex::new(Vec::new()));
+        let node_url = {
+            let sent_txs = sent_txs.clone();
+            start_mock_node(move |method, params| match method {
+                "estimatesmartfee" => Err(RPCError {
+                    code: -32603,
+                    message: "Insufficient data or no feerate found".to_string(),
+                }),
+                _ => mock_wallet_call(&sent_txs, usize::MAX, method, params),
+            })
+        };
+        assert!(get_mock_service(node_url.clone())
+            .send_transaction(b"pinned fee")
+            .await
+            .is_err());
+
+        let da_service = BitcoinService {
+            fee_rate_override: Some(7.0),
+            ..get_mock_service(node_url)
+        };
+        da_service.send_transaction(b"pinned fee").await.unwrap();
+        let sent_txs = sent_txs.lock().unwrap();
+        let (commit_tx, reveal_tx) = (&sent_txs[0], &sent_txs[1]);
+        let reveal_fee = commit_tx.output[0].value - reveal_tx.output[0].value;
+        assert!(reveal_fee.abs_diff(7 * reveal_tx.vsize() as u64) <= 7);
+
+        // the override must be relayable
+        let with_override = |fee_rate: f64| {
+            BitcoinService::try_new(
+                DaServiceConfig {
+                    fee_sat_per_vbyte_override: Some(fee_rate),
+                    ..get_config()
+                },
+                RollupParams {
+                    rollup_name: "sov-btc".to_string(),
+                },
+            )
+        };
+        assert!(with_override(1.0).is_ok());
+        for fee_rate in [0.5, 0.0, -3.0, f64::NAN, f64::INFINITY] {
+            assert!(with_override(fee_rate).is_err());
         }
     }
 
     #[tokio::test]
-    async fn extract_relevant_txs_with_proof() {
-        let da_service = get_service().await;
+    async fn preflight_reports_failing_checks() {
+        let sent_txs = Arc::new(Mutex::new(Vec::new()));
+        let node_url = |genesis_network: bitcoin::Network| {
+            let sent_txs = sent_txs.clone();
+            start_mock_node(move |method, params| match method {
+                "getblockcount" => Ok(json!(100)),
+                "getblockhash" => Ok(json!(genesis_block(genesis_network).block_hash())),
+                _ => mock_wallet_call(&sent_txs, usize::MAX, method, params),
+            })
+        };
 
-        let block = da_service
-            .get_block_at(142)
+        let report = get_mock_service(node_url(bitcoin::Network::Regtest))
+            .preflight()
             .await
-            .expect("Failed to get block");
+            .unwrap();
+        assert!(report.is_ok());
+        assert_eq!(report.results.len(), 6);
+        assert_eq!(report.spendable_balance, Some(200_000_000));
 
-        let (txs, inclusion_proof, completeness_proof) =
-            da_service.extract_relevant_txs_with_proof(&block).await;
-        
-        // completeness proof
+        // a regtest service pointed at a testnet node
+        let report = get_mock_service(node_url(bitcoin::Network::Testnet))
+            .preflight()
+            .await
+            .unwrap();
+        assert!(!report.is_ok());
+        let failures = report.failures();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0, PreflightCheck::Network);
+        assert!(failures[0].1.contains("not on regtest"));
+        assert_eq!(report.spendable_balance, Some(200_000_000));
+    }
 
-        // create hash set of txs
-        let mut txs_to_check = txs
-            .iter()
-            .map(|blob| blob.hash)
-            .collect::<HashSet<_>>();
+    #[tokio::test]
+    async fn sequencer_address_is_checked_at_construction() {
+        let try_new = |address: Option<&str>| {
+            BitcoinService::try_new(
+                DaServiceConfig {
+                    address: address.map(str::to_string),
+                    ..get_config()
+                },
+                RollupParams {
+                    rollup_name: "sov-btc".to_string(),
+                },
+            )
+        };
 
-        // Check every 00 bytes tx that parsed correctly is in txs
-        let mut completeness_tx_hashes = completeness_proof.iter().map(|tx| {
-            let tx_hash = tx.txid().to_raw_hash().to_byte_array();
+        // a mainnet address on a regtest service
+        let error = try_new(Some("bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq")).unwrap_err();
+        assert!(error.to_string().contains("is not a regtest address"));
+        assert!(try_new(Some("not an address")).is_err());
 
-            // it must parsed correctly
-            let parsed_tx = parse_transaction(tx, &da_service.rollup_name);
-            if parsed_tx.is_ok() {
-                let blob = parsed_tx.unwrap().body;
-                let blob_hash: [u8; 32] = bitcoin::hashes::sha256d::Hash::hash(&blob).to_byte_array();
-                // it must be in txs
-                assert!(txs_to_check.remove(&blob_hash));
-            }
+        // without an address, the service can read blocks but not send blobs
+        let da_service = try_new(None).unwrap();
+        assert!(da_service.address.is_none());
+        assert!(try_new(Some("")).unwrap().address.is_none());
+        let error = da_service
+            .send_transaction(b"no address")
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "No sequencer address is configured");
+    }
 
-            tx_hash
-        })
-        .collect::<HashSet<_>>();
-        
+    #[tokio::test]
+    async fn separate_commit_and_reveal_fee_rates() {
+        // the fees paid by the commit and reveal txs of a blob, out of the 1 BTC utxo
+        let fees = |commit_fee_multiplier: f64, reveal_fee_multiplier: f64| async move {
+            let sent_txs = Arc::new(Mutex::new(Vec::new()));
+            let da_service = BitcoinService {
+                commit_fee_multiplier,
+                reveal_fee_multiplier,
+                ..get_mock_service(start_mock_wallet_node(sent_txs.clone()))
+            };
+            da_service.send_transaction(b"fee rates").await.unwrap();
 
-        // assert no extra txs than the ones in the completeness proof are left
-        assert!(txs_to_check.is_empty());
+            let sent_txs = sent_txs.lock().unwrap();
+            let (commit_tx, reveal_tx) = (&sent_txs[0], &sent_txs[1]);
+            let commit_outputs = commit_tx
+                .output
+                .iter()
+                .map(|output| output.value)
+                .sum::<u64>();
+            (
+                100_000_000 - commit_outputs,
+                commit_tx.output[0].value - reveal_tx.output[0].value,
+            )
+        };
 
-        // no 00 bytes left behind completeness proof
-        inclusion_proof.txs.iter().for_each(|tx_hash| {
-            if tx_hash[0..2] == [0, 0] {
-                assert!(completeness_tx_hashes.remove(tx_hash));
-            }
-        });
+        let (commit_fee, reveal_fee) = fees(1.0, 1.0).await;
+        let (boosted_commit_fee, boosted_reveal_fee) = fees(1.0, 3.0).await;
+        assert_eq!(boosted_commit_fee, commit_fee);
+        // the random number found for the reveal txid may take a byte more or less
+        assert!(boosted_reveal_fee.abs_diff(3 * reveal_fee) <= 9);
 
-        // assert all transactions are included in block
-        assert!(completeness_tx_hashes.is_empty());
+        let (boosted_commit_fee, boosted_reveal_fee) = fees(2.0, 1.0).await;
+        assert!(boosted_commit_fee.abs_diff(2 * commit_fee) <= 2);
+        assert!(boosted_reveal_fee.abs_diff(reveal_fee) <= 3);
 
-        println!("\n--- Completeness proof verified ---\n");
+        // multipliers must be positive
+        assert!(BitcoinService::try_new(
+            DaServiceConfig {
+                reveal_fee_multiplier: Some(0.0),
+                ..get_config()
+            },
+            RollupParams {
+                rollup_name: "sov-btc".to_string(),
+            },
+        )
+        .is_err());
+    }
 
-        let tx_root = block
-            .header
-            .header
-            .merkle_root
-            .to_raw_hash()
-            .to_byte_array();
+    #[tokio::test]
+    async fn send_transactions_packs_blobs() {
+        let sent_txs = Arc::new(Mutex::new(Vec::new()));
+        let da_service = get_mock_service(start_mock_wallet_node(sent_txs.clone()));
 
-        // Inclusion proof is all the txs in the block.
-        let tx_hashes = inclusion_proof
-            .txs
+        let blobs: [&[u8]; 3] = [b"first batch", b"second batch", b"third batch"];
+        let submitted = da_service.send_transactions(&blobs).await.unwrap();
+        assert_eq!(submitted.len(), 1);
+        assert!(da_service.send_transactions(&[]).await.is_err());
+
+        // a single commit and reveal pair for the three blobs
+        let sent_txs = sent_txs.lock().unwrap().clone();
+        assert_eq!(sent_txs.len(), 2);
+        let reveal_tx = sent_txs[1].clone();
+        assert_eq!(submitted[0].reveal_txid, reveal_tx.txid());
+        let inscriptions = parse_inscriptions(&reveal_tx, "sov-btc", ParseMode::Strict).unwrap();
+        assert_eq!(inscriptions.len(), 3);
+
+        // one blob per envelope, in order, proven like the blobs of single inscription txs
+        let mut header = get_mock_header();
+        header.header.merkle_root = TxMerkleNode::from_raw_hash(reveal_tx.txid().to_raw_hash());
+        let block = BitcoinBlock::from_txs(header.header, 100, vec![reveal_tx], "sov-btc");
+        let (txs, inclusion_proof, completeness_proof) =
+            da_service.extract_relevant_txs_with_proof(&block).await;
+        let extracted = txs
             .iter()
-            .map(|tx| Txid::from_slice(tx).unwrap())
+            .map(|tx| {
+                let mut blob = tx.blob.clone();
+                blob.advance(blob.total_len());
+                blob.accumulator().to_vec()
+            })
             .collect::<Vec<_>>();
+        assert_eq!(extracted, blobs);
+        assert_eq!(da_service.extract_relevant_txs(&block), txs);
+        assert_eq!(completeness_proof.len(), 1);
+        BitcoinVerifier::new(RollupParams {
+            rollup_name: "sov-btc".to_string(),
+        })
+        .verify_relevant_tx_list(&block.header, &txs, inclusion_proof, completeness_proof)
+        .expect("Packed blobs should be verified");
+    }
 
-        let root_from_inclusion = merkle_tree::calculate_root(tx_hashes.into_iter())
-            .unwrap()
-            .to_raw_hash()
-            .to_byte_array();
+    #[tokio::test]
+    async fn send_transaction_chunks_large_blobs() {
+        let sent_txs = Arc::new(Mutex::new(Vec::new()));
+        let da_service = BitcoinService {
+            chunk_size: 1000,
+            ..get_mock_service(start_mock_wallet_node(sent_txs.clone()))
+        };
 
-        // Check that the tx root in the block header matches the tx root in the inclusion proof.
-        assert_eq!(root_from_inclusion, tx_root);
+        // hashes do not compress, so the compressed blob needs two chunks
+        let blob = (0..50u32)
+            .flat_map(|i| blob_hash(&i.to_le_bytes()))
+            .collect::<Vec<_>>();
+        let submitted = da_service
+            .send_transaction_with_fields(&blob, EnvelopeFields::default())
+            .await
+            .unwrap();
+        assert_eq!(submitted.len(), 2);
 
-        println!("\n--- Inclusion proof verified ---\n");
+        // a commit and reveal pair per chunk, each signed over its own body and position
+        let sent_txs = sent_txs.lock().unwrap().clone();
+        assert_eq!(sent_txs.len(), 4);
+        let reveal_txs = vec![sent_txs[1].clone(), sent_txs[3].clone()];
+        let chunks = reveal_txs
+            .iter()
+            .map(|tx| parse_transaction(tx, "sov-btc").unwrap())
+            .collect::<Vec<_>>();
+        let blob_id = blob_hash(&compress_blob(&blob));
+        for (index, chunk) in chunks.iter().enumerate() {
+            assert_eq!(
+                chunk.chunk,
+                Some(ChunkInfo {
+                    blob_id,
+                    index: index as u16,
+                    total: 2,
+                })
+            );
+            assert!(chunk.body.len() <= 1000);
+            assert!(EcdsaAuthenticator.authenticate(
+                &chunk.signed_message(),
+                &chunk.signature,
+                &chunk.public_key
+            ));
+        }
+        assert_ne!(chunks[0].signature, chunks[1].signature);
 
-        println!("\n--- Extracted #{:?} txs ---\n", txs.len());
+        // the commit tx of the second chunk spends the output of the first reveal tx
+        assert!(sent_txs[2]
+            .input
+            .iter()
+            .any(|input| input.previous_output == OutPoint::new(reveal_txs[0].txid(), 0)));
+
+        let block_of = |txs: Vec<Transaction>| {
+            let txids = txs.iter().map(|tx| tx.txid()).collect::<Vec<_>>();
+            let mut header = get_mock_header();
+            header.header.merkle_root = TxMerkleNode::from_raw_hash(
+                merkle_tree::calculate_root(txids.into_iter())
+                    .unwrap()
+                    .to_raw_hash(),
+            );
+            BitcoinBlock::from_txs(header.header, 100, txs, "sov-btc")
+        };
+
+        // the chunks are extracted as the blob, and the verifier agrees
+        let block = block_of(reveal_txs.clone());
+        let (txs, inclusion_proof, completeness_proof) =
+            da_service.extract_relevant_txs_with_proof(&block).await;
+        assert_eq!(txs.len(), 1);
+        assert_eq!(txs[0].hash, blob_id);
+        assert!(txs[0].verify_signature(&EcdsaAuthenticator));
+        let mut extracted = txs[0].blob.clone();
+        extracted.advance(extracted.total_len());
+        assert_eq!(extracted.accumulator(), blob);
+        assert_eq!(completeness_proof.len(), 2);
+        BitcoinVerifier::new(RollupParams {
+            rollup_name: "sov-btc".to_string(),
+        })
+        .verify_relevant_tx_list(&block.header, &txs, inclusion_proof, completeness_proof)
+        .expect("Chunked blob should be verified");
+
+        // the signature of a chunk does not carry over to another body or position
+        let mut forged = txs[0].clone();
+        forged.chunk = chunks[1].chunk;
+        assert!(!forged.verify_signature(&EcdsaAuthenticator));
+
+        // chunks out of order within the block are reassembled, a missing one yields nothing
+        let reversed = block_of(reveal_txs.iter().rev().cloned().collect());
+        assert_eq!(da_service.extract_relevant_txs(&reversed), txs);
+        let missing = block_of(vec![reveal_txs[0].clone()]);
+        assert!(da_service.extract_relevant_txs(&missing).is_empty());
+
+        // chunks of the same blob id sent by others before the sequencer ones do not drop them:
+        // a copy of the signature over another body, and a chunk signed by another key
+        const OTHER_PRIVATE_KEY: &str =
+            "0C27E8EB3C7D2F4E1F3E6C7E0B1E9A6F7D2C3B4A5968778695A4B3C2D1E0F1A2"; // Test key, safe to publish
+        let first_fields = EnvelopeFields {
+            chunk: chunks[0].chunk,
+            ..EnvelopeFields::default()
+        };
+        let copied = build_mock_reveal_tx(
+            "sov-btc",
+            b"not the first chunk".to_vec(),
+            &chunks[0].signature,
+            &chunks[0].public_key,
+            &first_fields,
+            SEQUENCER_DA_PRIVATE_KEY,
+        );
+        let foreign = get_mock_reveal_tx_signed_by(
+            "sov-btc",
+            b"not the first chunk",
+            &first_fields,
+            OTHER_PRIVATE_KEY,
+        );
+        let contested = block_of(vec![
+            copied,
+            foreign,
+            reveal_txs[0].clone(),
+            reveal_txs[1].clone(),
+        ]);
+        assert_eq!(da_service.extract_relevant_txs(&contested), txs);
     }
 
     #[tokio::test]
-    async fn send_transaction() {
-        let da_service = get_service().await;
+    async fn metadata_round_trip() {
+        let da_service = get_mock_service("http://localhost:38332".to_string());
 
-        let blob = "01000000b60000002adbd76606f2bd4125080e6f44df7ba2d728409955c80b8438eb1828ddf23e3c12188eeac7ecf6323be0ed5668e21cc354fca90d8bca513d6c0a240c26afa7007b758bf2e7670fafaf6bf0015ce0ff5aa802306fc7e3f45762853ffc37180fe64a0000000001fea6ac5b8751120fb62fff67b54d2eac66aef307c7dde1d394dea1e09e43dd44c800000000000000135d23aee8cb15c890831ff36db170157acaac31df9bba6cd40e7329e608eabd0000000000000000";
-        da_service
-            .send_transaction(blob.as_bytes())
-            .await
-            .expect("Failed to send transaction");
+        let metadata = vec![
+            ("chain-id".to_string(), vec![0, 0, 0, 42]),
+            ("version".to_string(), b"3".to_vec()),
+            ("encoder".to_string(), b"borsh".to_vec()),
+            ("empty".to_string(), vec![]),
+        ];
+        let fields = EnvelopeFields {
+            metadata: metadata.clone(),
+            ..Default::default()
+        };
+        let mut tx = get_mock_reveal_tx("sov-btc", b"tagged blob", &fields);
+        // relevant looking, so that the verifier checks it
+        for lock_time in 1.. {
+            tx.lock_time = LockTime::from_consensus(lock_time);
+            if tx.txid().to_raw_hash().to_byte_array()[0..2] == [0, 0] {
+                break;
+            }
+        }
+
+        // the signed body is the one of the same blob without metadata
+        let inscription = parse_transaction(&tx, "sov-btc").unwrap();
+        let untagged_tx = get_mock_reveal_tx("sov-btc", b"tagged blob", &EnvelopeFields::default());
+        let untagged = parse_transaction(&untagged_tx, "sov-btc").unwrap();
+        assert_eq!(inscription.metadata, metadata);
+        assert_eq!(inscription.body, untagged.body);
+        assert_eq!(inscription.signature, untagged.signature);
+        assert!(untagged.metadata.is_empty());
+
+        // the entries are surfaced in order, along with the proof
+        let mut header = get_mock_header();
+        header.header.merkle_root = TxMerkleNode::from_raw_hash(tx.txid().to_raw_hash());
+        let block = BitcoinBlock::from_txs(header.header, 100, vec![tx], "sov-btc");
+        let (txs, inclusion_proof, completeness_proof) =
+            da_service.extract_relevant_txs_with_proof(&block).await;
+        assert_eq!(txs.len(), 1);
+        assert_eq!(txs[0].metadata, metadata);
+        assert_eq!(txs[0].hash, blob_hash(&untagged.body));
+        BitcoinVerifier::new(RollupParams {
+            rollup_name: "sov-btc".to_string(),
+        })
+        .verify_relevant_tx_list(&block.header, &txs, inclusion_proof, completeness_proof)
+        .expect("Metadata should be verified");
+
+        // the entries are bounded as a whole
+        let fields = EnvelopeFields {
+            metadata: vec![("big".to_string(), vec![0; MAX_METADATA_LEN])],
+            ..Default::default()
+        };
+        assert_eq!(
+            fields.validate(),
+            Err(BuilderError::MetadataTooLarge {
+                len: MAX_METADATA_LEN + 3,
+                limit: MAX_METADATA_LEN,
+            })
+        );
     }
 }
@@ -3,6 +3,8 @@ use sov_rollup_interface::da::{BlobReaderTrait, CountedBufReader};
 use sov_rollup_interface::Buf;
 
 use super::address::AddressWrapper;
+use crate::authenticator::BlobAuthenticator;
+use crate::helpers::{signed_message, ChunkInfo, Compression, NAMESPACE_ID_LEN};
 
 // BlobBuf is a wrapper around Vec<u8> to implement Buf
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -21,6 +23,33 @@ impl BlobWithSender {
             }),
             sender: AddressWrapper(sender.unwrap_or(Vec::new())),
             hash: hash.unwrap_or([0; 32]),
+            namespace_id: None,
+            compression: None,
+            label: None,
+            metadata: Vec::new(),
+            signature: None,
+            chunk: None,
+            signed_hash: None,
+        }
+    }
+
+    // Checks the signature of the sender over the blob hash, the namespace id and the compression
+    // header with the authenticator of the DA service. Blobs extracted with lazy signature
+    // verification are not authenticated until it holds. The blobs inscribed in chunks are checked
+    // with the signature of their first chunk, which must be of this blob.
+    pub fn verify_signature(&self, authenticator: &dyn BlobAuthenticator) -> bool {
+        if self.chunk.is_some_and(|chunk| chunk.blob_id != self.hash) {
+            return false;
+        }
+
+        match &self.signature {
+            Some(signature) => {
+                let hash = self.signed_hash.unwrap_or(self.hash);
+                let message =
+                    signed_message(&hash, self.namespace_id, self.compression, self.chunk);
+                authenticator.authenticate(&message, signature, &self.sender.0)
+            }
+            None => false,
         }
     }
 }
@@ -47,6 +76,25 @@ pub struct BlobWithSender {
     pub sender: AddressWrapper,
 
     pub blob: CountedBufReader<BlobBuf>,
+
+    pub namespace_id: Option<[u8; NAMESPACE_ID_LEN]>,
+
+    // compression header of the inscribed body, the blob is already decompressed
+    pub compression: Option<Compression>,
+
+    pub label: Option<String>,
+
+    // key-value entries of the envelope, they are not covered by the signature
+    pub metadata: Vec<(String, Vec<u8>)>,
+
+    // signature of the sender over the hash, kept to verify it on demand
+    pub signature: Option<Vec<u8>>,
+
+    // position of the first chunk of the blobs inscribed in chunks, the signature is that chunk's
+    pub chunk: Option<ChunkInfo>,
+
+    // hash of the body of that first chunk, which the signature covers instead of the blob hash
+    pub signed_hash: Option<[u8; 32]>,
 }
 
 impl BlobReaderTrait for BlobWithSender {
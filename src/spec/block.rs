@@ -1,10 +1,15 @@
+use bitcoin::block::Header;
+use bitcoin::consensus::{Decodable, Encodable};
 use bitcoin::hashes::Hash;
+use bitcoin::{Transaction, VarInt};
 use serde::{Deserialize, Serialize};
 use sov_rollup_interface::da::BlockHeaderTrait;
 use sov_rollup_interface::services::da::SlotData;
 
+use super::block_hash::BlockHashWrapper;
 use super::header::HeaderWrapper;
 use super::transaction::ExtendedTransaction;
+use crate::helpers::parsers::extend_transaction;
 use crate::verifier::ChainValidityCondition;
 
 // BitcoinBlock is a wrapper around Block to remove unnecessary fields and implement SlotData
@@ -14,6 +19,111 @@ pub struct BitcoinBlock {
     pub txdata: Vec<ExtendedTransaction>,
 }
 
+impl BitcoinBlock {
+    // Builds a block out of raw transactions, recovering the sender of the inscriptions relevant to
+    // the rollup the way the node client does. The header is kept as is, its merkle root is not checked.
+    // Lets blocks with known inscriptions be fed to extraction without a node.
+    pub fn from_txs(header: Header, height: u64, txs: Vec<Transaction>, rollup_name: &str) -> Self {
+        let txdata = txs
+            .into_iter()
+            .map(|transaction| extend_transaction(transaction, rollup_name))
+            .collect::<Vec<_>>();
+
+        Self {
+            header: HeaderWrapper {
+                header,
+                tx_count: txdata.len() as u32,
+                height,
+            },
+            txdata,
+        }
+    }
+
+    // Hash of the block this one builds on
+    pub fn prev_hash(&self) -> BlockHashWrapper {
+        self.header.prev_hash()
+    }
+
+    // Compact encoding for disk caches: the height, the block in consensus encoding, then the
+    // sender and blob hash recovered for every transaction
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.header.height.to_le_bytes().to_vec();
+
+        // same as the consensus encoding of the block, without cloning its transactions
+        self.header.header.consensus_encode(&mut bytes).unwrap();
+        VarInt(self.txdata.len() as u64)
+            .consensus_encode(&mut bytes)
+            .unwrap();
+        for tx in self.txdata.iter() {
+            tx.transaction.consensus_encode(&mut bytes).unwrap();
+        }
+
+        for tx in self.txdata.iter() {
+            match &tx.sender {
+                Some(sender) => {
+                    bytes.push(1);
+                    sender.consensus_encode(&mut bytes).unwrap();
+                }
+                None => bytes.push(0),
+            }
+            match &tx.blob_hash {
+                Some(blob_hash) => {
+                    bytes.push(1);
+                    bytes.extend_from_slice(blob_hash);
+                }
+                None => bytes.push(0),
+            }
+        }
+
+        bytes
+    }
+
+    // Decodes a block encoded by `to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, anyhow::Error> {
+        let mut reader = bytes;
+
+        let height = u64::consensus_decode(&mut reader)?;
+        let header = Header::consensus_decode(&mut reader)?;
+        let transactions = Vec::<Transaction>::consensus_decode(&mut reader)?;
+
+        let mut txdata = Vec::with_capacity(transactions.len());
+        for transaction in transactions {
+            let sender = match u8::consensus_decode(&mut reader)? {
+                0 => None,
+                1 => Some(Vec::<u8>::consensus_decode(&mut reader)?),
+                flag => return Err(anyhow::anyhow!("invalid sender flag {}", flag)),
+            };
+            let blob_hash = match u8::consensus_decode(&mut reader)? {
+                0 => None,
+                1 => Some(<[u8; 32]>::consensus_decode(&mut reader)?),
+                flag => return Err(anyhow::anyhow!("invalid blob hash flag {}", flag)),
+            };
+
+            txdata.push(ExtendedTransaction {
+                transaction,
+                sender,
+                blob_hash,
+            });
+        }
+
+        if !reader.is_empty() {
+            return Err(anyhow::anyhow!(
+                "{} trailing bytes after the block",
+                reader.len()
+            ));
+        }
+
+        Ok(Self {
+            header: HeaderWrapper {
+                header,
+                tx_count: txdata.len() as u32,
+                height,
+            },
+            txdata,
+        })
+    }
+}
+
 impl SlotData for BitcoinBlock {
     type BlockHeader = HeaderWrapper;
     type Cond = ChainValidityCondition;
@@ -39,3 +149,39 @@ impl SlotData for BitcoinBlock {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::blockdata::constants::genesis_block;
+
+    use super::BitcoinBlock;
+    use crate::helpers::builders::EnvelopeFields;
+    use crate::helpers::test_utils::get_mock_reveal_tx;
+
+    #[test]
+    fn bytes_round_trip() {
+        let genesis = genesis_block(bitcoin::Network::Regtest);
+        let block = BitcoinBlock::from_txs(
+            genesis.header,
+            840_000,
+            vec![
+                genesis.txdata[0].clone(),
+                get_mock_reveal_tx("sov-btc", b"cached", &EnvelopeFields::default()),
+            ],
+            "sov-btc",
+        );
+        assert!(block.txdata[0].sender.is_none());
+        assert!(block.txdata[1].sender.is_some());
+
+        let bytes = block.to_bytes();
+        let decoded = BitcoinBlock::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, block);
+        assert_eq!(decoded.header.height, 840_000);
+
+        // far smaller than the json of the block
+        assert!(bytes.len() * 2 < serde_json::to_vec(&block).unwrap().len());
+
+        assert!(BitcoinBlock::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(BitcoinBlock::from_bytes(&[bytes.clone(), vec![0]].concat()).is_err());
+    }
+}
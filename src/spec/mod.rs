@@ -25,6 +25,43 @@ pub struct RollupParams {
     pub rollup_name: String,
 }
 
+// A rollup name accepted besides the one of the rollup, e.g. its previous name during a rename.
+// Blobs under it are only extracted and verified in blocks within the bounds, both inclusive.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AuthorizedRollupName {
+    pub name: String,
+    pub from_height: Option<u64>,
+    pub until_height: Option<u64>,
+}
+
+impl AuthorizedRollupName {
+    pub fn is_active_at(&self, height: u64) -> bool {
+        self.from_height.map_or(true, |from| height >= from)
+            && self.until_height.map_or(true, |until| height <= until)
+    }
+
+    // Whether the name is only accepted in some blocks, the verifier then needs the height proven
+    pub fn has_height_bounds(&self) -> bool {
+        self.from_height.is_some() || self.until_height.is_some()
+    }
+}
+
+// Returns the rollup name followed by the authorized names active at the height
+pub fn rollup_names_at<'a>(
+    rollup_name: &'a str,
+    authorized_names: &'a [AuthorizedRollupName],
+    height: u64,
+) -> Vec<&'a str> {
+    core::iter::once(rollup_name)
+        .chain(
+            authorized_names
+                .iter()
+                .filter(|authorized| authorized.is_active_at(height))
+                .map(|authorized| authorized.name.as_str()),
+        )
+        .collect()
+}
+
 impl DaSpec for BitcoinSpec {
     type SlotHash = BlockHashWrapper;
 
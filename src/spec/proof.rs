@@ -1,7 +1,276 @@
+use std::io::Read;
+
+use bitcoin::consensus::{encode, Encodable};
+use bitcoin::hashes::Hash;
+use bitcoin::{merkle_tree, Transaction, Txid, VarInt};
 use serde::{Deserialize, Serialize};
 
+use super::header::HeaderWrapper;
+use crate::helpers::builders::compress_blob;
+use crate::helpers::Compression;
+use crate::verifier::ValidationError;
+
+// A block serializes to at most 4MB, neither proof of one can be larger. Bounds the decompression
+// of the proofs, a few bytes of brotli can expand to gigabytes.
+pub const MAX_PROOF_LEN: usize = 4_000_000;
+
 // Set of proofs for inclusion of a transaction in a block
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct InclusionMultiProof {
+    // txids (not wtxids) of all the transactions in the block, in block order
     pub txs: Vec<[u8; 32]>,
 }
+
+impl InclusionMultiProof {
+    // Encodes the proof for the wire: a compression byte followed by the concatenated txids,
+    // compressed as told. Txids are hashes, so compression mostly helps blocks with repeated txids.
+    pub fn to_bytes(&self, compression: Compression) -> Vec<u8> {
+        let txs = self.txs.concat();
+        let payload = match compression {
+            Compression::None => txs,
+            Compression::Brotli => compress_blob(&txs),
+        };
+
+        [vec![compression.to_byte()], payload].concat()
+    }
+
+    // Decodes a proof encoded by `to_bytes`, whatever its compression
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, anyhow::Error> {
+        let txs = decode_payload(bytes, "inclusion proof")?;
+
+        if txs.len() % 32 != 0 {
+            return Err(anyhow::anyhow!(
+                "inclusion proof of {} bytes is not a list of txids",
+                txs.len()
+            ));
+        }
+
+        Ok(Self {
+            txs: txs
+                .chunks_exact(32)
+                .map(|txid| txid.try_into().unwrap())
+                .collect(),
+        })
+    }
+}
+
+// Encodes a completeness proof for the wire: a compression byte followed by the consensus encoding
+// of the transactions, compressed as told. Unlike txids, the transactions compress well.
+pub fn completeness_proof_to_bytes(txs: &[Transaction], compression: Compression) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    VarInt(txs.len() as u64)
+        .consensus_encode(&mut encoded)
+        .unwrap();
+    for tx in txs {
+        tx.consensus_encode(&mut encoded).unwrap();
+    }
+
+    let payload = match compression {
+        Compression::None => encoded,
+        Compression::Brotli => compress_blob(&encoded),
+    };
+
+    [vec![compression.to_byte()], payload].concat()
+}
+
+// Decodes a completeness proof encoded by `completeness_proof_to_bytes`, whatever its compression
+pub fn completeness_proof_from_bytes(bytes: &[u8]) -> Result<Vec<Transaction>, anyhow::Error> {
+    let encoded = decode_payload(bytes, "completeness proof")?;
+
+    Ok(encode::deserialize(&encoded)?)
+}
+
+// Splits the compression byte of an encoded proof and decompresses the rest as told, up to
+// `MAX_PROOF_LEN` bytes
+fn decode_payload(bytes: &[u8], proof: &str) -> Result<Vec<u8>, anyhow::Error> {
+    let (flag, payload) = bytes
+        .split_first()
+        .ok_or_else(|| anyhow::anyhow!("{} is empty", proof))?;
+
+    let decoded = match Compression::from_byte(*flag) {
+        Some(Compression::None) => payload.to_vec(),
+        Some(Compression::Brotli) => {
+            let mut decompressed = Vec::new();
+            // one byte past the limit is enough to tell the proof is too large
+            brotli::Decompressor::new(payload, 4096)
+                .take(MAX_PROOF_LEN as u64 + 1)
+                .read_to_end(&mut decompressed)?;
+            decompressed
+        }
+        None => return Err(anyhow::anyhow!("unknown {} format {}", proof, flag)),
+    };
+
+    if decoded.len() > MAX_PROOF_LEN {
+        return Err(anyhow::anyhow!(
+            "{} is larger than {} bytes",
+            proof,
+            MAX_PROOF_LEN
+        ));
+    }
+
+    Ok(decoded)
+}
+
+// Inclusion and completeness proofs of the blobs extracted from a block
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ExtractionProof {
+    pub inclusion: InclusionMultiProof,
+    // transactions of the block whose txid starts with two zero bytes, in block order
+    pub completeness: Vec<Transaction>,
+}
+
+impl ExtractionProof {
+    pub fn into_parts(self) -> (InclusionMultiProof, Vec<Transaction>) {
+        (self.inclusion, self.completeness)
+    }
+
+    // Checks that the proofs are consistent with the header, without looking at the blobs:
+    // the inclusion proof commits to the merkle root of the header and the completeness proof
+    // holds exactly the transactions of the block whose txid starts with two zero bytes, after
+    // the coinbase when it proves the height of the block.
+    pub fn verify_against(&self, header: &HeaderWrapper) -> Result<(), ValidationError> {
+        let root = merkle_tree::calculate_root(
+            self.inclusion
+                .txs
+                .iter()
+                .map(|tx| Txid::from_byte_array(*tx)),
+        )
+        .ok_or(ValidationError::InvalidProof)?;
+        if root.to_raw_hash() != header.header.merkle_root.to_raw_hash() {
+            return Err(ValidationError::InvalidProof);
+        }
+
+        let relevant_txs = self.inclusion.txs.iter().filter(|tx| tx[0..2] == [0, 0]);
+        let completeness_txs = self
+            .completeness
+            .iter()
+            .map(|tx| tx.txid().to_raw_hash().to_byte_array());
+        // the coinbase may come first to prove the height of the block, even if not relevant
+        let height_proof = self.completeness.first().is_some_and(|tx| {
+            let txid = tx.txid().to_raw_hash().to_byte_array();
+            txid[0..2] != [0, 0] && self.inclusion.txs.first() == Some(&txid)
+        });
+        let completeness_txs = completeness_txs.skip(height_proof as usize);
+        if !relevant_txs.copied().eq(completeness_txs) {
+            return Err(ValidationError::InvalidProof);
+        }
+
+        Ok(())
+    }
+}
+
+impl From<(InclusionMultiProof, Vec<Transaction>)> for ExtractionProof {
+    fn from((inclusion, completeness): (InclusionMultiProof, Vec<Transaction>)) -> Self {
+        Self {
+            inclusion,
+            completeness,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::hashes::{sha256d, Hash};
+    use bitcoin::Transaction;
+
+    use super::{
+        completeness_proof_from_bytes, completeness_proof_to_bytes, InclusionMultiProof,
+        MAX_PROOF_LEN,
+    };
+    use crate::helpers::builders::{compress_blob, EnvelopeFields};
+    use crate::helpers::test_utils::get_mock_reveal_tx_with_body;
+    use crate::helpers::Compression;
+
+    #[test]
+    fn inclusion_proof_wire_round_trip() {
+        // txids of a block with thousands of transactions
+        let proof = InclusionMultiProof {
+            txs: (0..4000u32)
+                .map(|i| sha256d::Hash::hash(&i.to_le_bytes()).to_byte_array())
+                .collect(),
+        };
+
+        let raw = proof.to_bytes(Compression::None);
+        let compressed = proof.to_bytes(Compression::Brotli);
+        assert_eq!(InclusionMultiProof::from_bytes(&raw).unwrap(), proof);
+        assert_eq!(InclusionMultiProof::from_bytes(&compressed).unwrap(), proof);
+
+        // txids are uniformly random, brotli cannot shrink them but only adds a few bytes of framing
+        assert_eq!(raw.len(), 1 + 4000 * 32);
+        assert!(compressed.len() < raw.len() + 64);
+
+        // a proof with repeated txids compresses well
+        let repeated = InclusionMultiProof {
+            txs: vec![proof.txs[0]; 4000],
+        };
+        assert!(repeated.to_bytes(Compression::Brotli).len() < raw.len() / 100);
+
+        assert!(InclusionMultiProof::from_bytes(&[]).is_err());
+        assert!(InclusionMultiProof::from_bytes(&[2, 0]).is_err());
+        assert!(InclusionMultiProof::from_bytes(&raw[..raw.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn completeness_proof_wire_round_trip() {
+        // reveal txs carrying large batches of transfers, inscribed without compression
+        let txs = (0..5u32)
+            .map(|i| {
+                let body = (0..1000u32)
+                    .flat_map(|nonce| {
+                        format!("{{\"from\":{},\"nonce\":{},\"amount\":100}}", i, nonce)
+                            .into_bytes()
+                    })
+                    .collect::<Vec<_>>();
+                get_mock_reveal_tx_with_body("sov-btc", body, &EnvelopeFields::default())
+            })
+            .collect::<Vec<Transaction>>();
+
+        let raw = completeness_proof_to_bytes(&txs, Compression::None);
+        let compressed = completeness_proof_to_bytes(&txs, Compression::Brotli);
+        assert_eq!(completeness_proof_from_bytes(&raw).unwrap(), txs);
+        assert_eq!(completeness_proof_from_bytes(&compressed).unwrap(), txs);
+        assert_eq!(completeness_proof_from_bytes(&[0, 0]).unwrap(), vec![]);
+
+        // the witnesses dominate and compress well, unlike the txids of the inclusion proof
+        assert!(raw.len() > 5 * 30_000);
+        assert!(compressed.len() < raw.len() / 4);
+
+        assert!(completeness_proof_from_bytes(&[]).is_err());
+        assert!(completeness_proof_from_bytes(&[2, 0]).is_err());
+        assert!(completeness_proof_from_bytes(&raw[..raw.len() - 1]).is_err());
+        assert!(completeness_proof_from_bytes(&[raw.clone(), vec![0]].concat()).is_err());
+    }
+
+    #[test]
+    fn proof_decompression_is_bounded() {
+        // a tiny payload expanding past the size of any block
+        let bomb = [
+            vec![Compression::Brotli.to_byte()],
+            compress_blob(&vec![0; MAX_PROOF_LEN + 32]),
+        ]
+        .concat();
+        assert!(bomb.len() < 1000);
+
+        assert!(InclusionMultiProof::from_bytes(&bomb).is_err());
+        assert!(completeness_proof_from_bytes(&bomb).is_err());
+
+        // the limit applies without compression too
+        let raw = [
+            vec![Compression::None.to_byte()],
+            vec![0; MAX_PROOF_LEN + 32],
+        ]
+        .concat();
+        assert!(InclusionMultiProof::from_bytes(&raw).is_err());
+
+        // proofs up to the limit are still accepted
+        let proof = [
+            vec![Compression::Brotli.to_byte()],
+            compress_blob(&vec![0; MAX_PROOF_LEN]),
+        ]
+        .concat();
+        assert_eq!(
+            InclusionMultiProof::from_bytes(&proof).unwrap().txs.len(),
+            MAX_PROOF_LEN / 32
+        );
+    }
+}
@@ -0,0 +1,117 @@
+use core::time::Duration;
+use std::collections::HashMap;
+
+use bitcoin::{OutPoint, Transaction};
+use metrics::{gauge, histogram, increment_counter};
+
+// Counts a call to send a blob and, if it failed, the failure
+pub fn record_send(succeeded: bool) {
+    increment_counter!("da_send_transaction_total");
+    if !succeeded {
+        increment_counter!("da_send_transaction_failed");
+    }
+}
+
+// Records the fee paid by a commit and reveal pair, in sats
+pub fn record_inscription_fee(
+    commit_tx: &Transaction,
+    reveal_tx: &Transaction,
+    input_values: &HashMap<OutPoint, u64>,
+) {
+    histogram!(
+        "da_inscription_fee_sats",
+        inscription_fee(commit_tx, reveal_tx, input_values) as f64
+    );
+}
+
+pub fn record_get_block_latency(latency: Duration) {
+    histogram!("da_get_block_seconds", latency.as_secs_f64());
+}
+
+// Records how many blocks the tip is ahead of the last finalized block
+pub fn record_finality_lag(block_count: u64, finalized_height: u64) {
+    gauge!(
+        "da_finality_lag_blocks",
+        block_count.saturating_sub(finalized_height) as f64
+    );
+}
+
+// Fee of the pair: the value of the utxos spent by the commit tx less what both txs pay out,
+// except the commit outputs spent by the reveal tx. Utxos of unknown value are not counted.
+fn inscription_fee(
+    commit_tx: &Transaction,
+    reveal_tx: &Transaction,
+    input_values: &HashMap<OutPoint, u64>,
+) -> u64 {
+    let commit_txid = commit_tx.txid();
+    let spent = commit_tx
+        .input
+        .iter()
+        .filter_map(|input| input_values.get(&input.previous_output))
+        .sum::<u64>();
+    let unspent_commit_outputs = commit_tx
+        .output
+        .iter()
+        .enumerate()
+        .filter(|(vout, _)| {
+            !reveal_tx
+                .input
+                .iter()
+                .any(|input| input.previous_output == OutPoint::new(commit_txid, *vout as u32))
+        })
+        .map(|(_, output)| output.value)
+        .sum::<u64>();
+    let revealed = reveal_tx
+        .output
+        .iter()
+        .map(|output| output.value)
+        .sum::<u64>();
+
+    spent.saturating_sub(unspent_commit_outputs + revealed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use bitcoin::absolute::LockTime;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness};
+
+    use super::inscription_fee;
+
+    fn spend(previous_output: OutPoint, values: &[u64]) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output,
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            }],
+            output: values
+                .iter()
+                .map(|value| TxOut {
+                    value: *value,
+                    script_pubkey: ScriptBuf::new(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn fee_of_commit_and_reveal() {
+        let utxo = OutPoint::new(Txid::all_zeros(), 1);
+        // the commit tx pays 500 sats, its change is kept and its first output is revealed
+        let commit_tx = spend(utxo, &[10_000, 89_500]);
+        // the reveal tx pays 1_000 sats
+        let reveal_tx = spend(OutPoint::new(commit_tx.txid(), 0), &[9_000]);
+
+        let input_values = HashMap::from([(utxo, 100_000)]);
+        assert_eq!(
+            inscription_fee(&commit_tx, &reveal_tx, &input_values),
+            1_500
+        );
+    }
+}
@@ -1,7 +1,9 @@
 use std::collections::HashSet;
+use std::sync::Arc;
 
+use bitcoin::block::Header;
 use bitcoin::hashes::Hash;
-use bitcoin::{merkle_tree, Txid};
+use bitcoin::{merkle_tree, Block, Network, Txid};
 use borsh::{BorshDeserialize, BorshSerialize};
 use serde::{Deserialize, Serialize};
 use sov_rollup_interface::da::{DaSpec, DaVerifier};
@@ -9,12 +11,39 @@ use sov_rollup_interface::digest::Digest;
 use sov_rollup_interface::zk::ValidityCondition;
 use thiserror::Error;
 
-use crate::helpers::builders::decompress_blob;
-use crate::helpers::parsers::parse_transaction;
-use crate::spec::BitcoinSpec;
+use crate::authenticator::{BlobAuthenticator, EcdsaAuthenticator};
+use crate::helpers::builders::decompress_body;
+use crate::helpers::parsers::{parse_inscriptions, ChunkAssembler, ParseMode};
+use crate::helpers::{blob_hash, is_authorized_sequencer};
+use crate::spec::proof::{completeness_proof_from_bytes, InclusionMultiProof};
+use crate::spec::{rollup_names_at, AuthorizedRollupName, BitcoinSpec};
 
 pub struct BitcoinVerifier {
     pub rollup_name: String,
+    // names accepted besides the rollup name, e.g. during a rename
+    pub authorized_names: Vec<AuthorizedRollupName>,
+    // public keys the blobs must be signed with, any key when empty
+    pub authorized_sequencers: Vec<Vec<u8>>,
+    // checks the sequencer signature of the blobs, must be the authenticator of the DA service
+    pub authenticator: Arc<dyn BlobAuthenticator>,
+    // DANGEROUS: hashes of blobs accepted without checking their signature nor their sequencer,
+    // because a relay the operator trusts vouched for them. Anyone inscribing a blob with one of
+    // these hashes gets it accepted. Empty, and so off, unless set explicitly.
+    pub trusted_relay_blob_hashes: HashSet<[u8; 32]>,
+    // header the header chains given to `verify_relevant_tx_list_with_header_chain` start from,
+    // those are rejected when unset
+    pub header_checkpoint: Option<HeaderCheckpoint>,
+    // how the envelopes are parsed, must be the `envelope_parse_mode` of the DA service
+    pub parse_mode: ParseMode,
+}
+
+/// A block header trusted by the verifier, e.g. hardcoded by the rollup. It must be the first
+/// block of a difficulty period so that the targets of the following headers can be checked.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeaderCheckpoint {
+    pub network: Network,
+    pub height: u64,
+    pub header: Header,
 }
 
 // TODO: custom errors based on our implementation
@@ -23,6 +52,8 @@ pub enum ValidationError {
     InvalidTx,
     InvalidProof,
     InvalidBlock,
+    InvalidHeaderChain,
+    InsufficientWork,
 }
 
 #[derive(
@@ -58,6 +89,306 @@ impl ValidityCondition for ChainValidityCondition {
     }
 }
 
+impl BitcoinVerifier {
+    // Also accepts the blobs inscribed under the given names, within their height bounds
+    pub fn with_authorized_names(self, authorized_names: Vec<AuthorizedRollupName>) -> Self {
+        Self {
+            authorized_names,
+            ..self
+        }
+    }
+
+    // Only accepts the blobs signed with one of the given public keys, as extraction does when
+    // the DA service is configured with the same keys
+    pub fn with_authorized_sequencers(self, authorized_sequencers: Vec<Vec<u8>>) -> Self {
+        Self {
+            authorized_sequencers,
+            ..self
+        }
+    }
+
+    // Authenticates the blobs with the given authenticator instead of the default signature check,
+    // as extraction does when the DA service is built `with_authenticator`
+    pub fn with_authenticator(self, authenticator: impl BlobAuthenticator + 'static) -> Self {
+        Self {
+            authenticator: Arc::new(authenticator),
+            ..self
+        }
+    }
+
+    // Parses the envelopes with the given mode, as extraction does when the DA service is
+    // configured with the same `envelope_parse_mode`
+    pub fn with_parse_mode(self, parse_mode: ParseMode) -> Self {
+        Self { parse_mode, ..self }
+    }
+
+    // Accepts the blobs with the given hashes whoever signed them, as extraction does when the DA
+    // service is configured with the same `trusted_relay_blob_hashes`
+    pub fn with_trusted_relay_blobs(self, trusted_relay_blob_hashes: HashSet<[u8; 32]>) -> Self {
+        Self {
+            trusted_relay_blob_hashes,
+            ..self
+        }
+    }
+
+    // Checks the header chains given to `verify_relevant_tx_list_with_header_chain` from the given
+    // checkpoint
+    pub fn with_header_checkpoint(self, header_checkpoint: HeaderCheckpoint) -> Self {
+        Self {
+            header_checkpoint: Some(header_checkpoint),
+            ..self
+        }
+    }
+
+    // Same as `verify_relevant_tx_list`, for light clients that also need to know the block is on a
+    // proof-of-work chain. `header_chain` holds the headers between the header checkpoint and the
+    // block, oldest first.
+    pub fn verify_relevant_tx_list_with_header_chain(
+        &self,
+        block_header: &<BitcoinSpec as DaSpec>::BlockHeader,
+        header_chain: &[Header],
+        txs: &[<BitcoinSpec as DaSpec>::BlobTransaction],
+        inclusion_proof: <BitcoinSpec as DaSpec>::InclusionMultiProof,
+        completeness_proof: <BitcoinSpec as DaSpec>::CompletenessProof,
+    ) -> Result<<BitcoinSpec as DaSpec>::ValidityCondition, ValidationError> {
+        let checkpoint = self
+            .header_checkpoint
+            .as_ref()
+            .ok_or(ValidationError::InvalidHeaderChain)?;
+        verify_header_chain(checkpoint, header_chain, &block_header.header)?;
+
+        self.verify_relevant_tx_list(block_header, txs, inclusion_proof, completeness_proof)
+    }
+
+    // Same as `verify_relevant_tx_list`, also checking that the block carries the proof of work its
+    // header claims. The target itself is not checked, see `verify_header_pow`.
+    pub fn verify_relevant_tx_list_with_pow(
+        &self,
+        block_header: &<BitcoinSpec as DaSpec>::BlockHeader,
+        txs: &[<BitcoinSpec as DaSpec>::BlobTransaction],
+        inclusion_proof: <BitcoinSpec as DaSpec>::InclusionMultiProof,
+        completeness_proof: <BitcoinSpec as DaSpec>::CompletenessProof,
+    ) -> Result<<BitcoinSpec as DaSpec>::ValidityCondition, ValidationError> {
+        verify_header_pow(&block_header.header)?;
+
+        self.verify_relevant_tx_list(block_header, txs, inclusion_proof, completeness_proof)
+    }
+
+    // Same as `verify_relevant_tx_list`, with the inclusion proof as encoded by `InclusionMultiProof::to_bytes`
+    pub fn verify_relevant_tx_list_with_encoded_proof(
+        &self,
+        block_header: &<BitcoinSpec as DaSpec>::BlockHeader,
+        txs: &[<BitcoinSpec as DaSpec>::BlobTransaction],
+        inclusion_proof: &[u8],
+        completeness_proof: <BitcoinSpec as DaSpec>::CompletenessProof,
+    ) -> Result<<BitcoinSpec as DaSpec>::ValidityCondition, ValidationError> {
+        let inclusion_proof = InclusionMultiProof::from_bytes(inclusion_proof)
+            .map_err(|_| ValidationError::InvalidProof)?;
+
+        self.verify_relevant_tx_list(block_header, txs, inclusion_proof, completeness_proof)
+    }
+
+    // Same as `verify_relevant_tx_list_with_encoded_proof`, with the completeness proof also encoded,
+    // by `completeness_proof_to_bytes`
+    pub fn verify_relevant_tx_list_with_encoded_proofs(
+        &self,
+        block_header: &<BitcoinSpec as DaSpec>::BlockHeader,
+        txs: &[<BitcoinSpec as DaSpec>::BlobTransaction],
+        inclusion_proof: &[u8],
+        completeness_proof: &[u8],
+    ) -> Result<<BitcoinSpec as DaSpec>::ValidityCondition, ValidationError> {
+        let completeness_proof = completeness_proof_from_bytes(completeness_proof)
+            .map_err(|_| ValidationError::InvalidProof)?;
+
+        self.verify_relevant_tx_list_with_encoded_proof(
+            block_header,
+            txs,
+            inclusion_proof,
+            completeness_proof,
+        )
+    }
+}
+
+// Checks that the hash of the header meets the target encoded in its `bits`. A header can state an
+// easy target, whether the target is the one required at its height is up to the header chain.
+pub fn verify_header_pow(header: &Header) -> Result<(), ValidationError> {
+    header
+        .validate_pow(header.target())
+        .map(|_| ())
+        .map_err(|_| ValidationError::InsufficientWork)
+}
+
+// Parameters of the difficulty adjustment, as in Bitcoin Core's chainparams
+struct PowParams {
+    pow_limit: u32,
+    allow_min_difficulty_blocks: bool,
+    no_retargeting: bool,
+}
+
+const DIFFICULTY_ADJUSTMENT_INTERVAL: u64 = 2016;
+const POW_TARGET_SPACING: u32 = 10 * 60;
+const POW_TARGET_TIMESPAN: u32 = POW_TARGET_SPACING * DIFFICULTY_ADJUSTMENT_INTERVAL as u32;
+
+fn pow_params(network: Network) -> Option<PowParams> {
+    match network {
+        Network::Bitcoin => Some(PowParams {
+            pow_limit: 0x1d00ffff,
+            allow_min_difficulty_blocks: false,
+            no_retargeting: false,
+        }),
+        Network::Testnet => Some(PowParams {
+            pow_limit: 0x1d00ffff,
+            allow_min_difficulty_blocks: true,
+            no_retargeting: false,
+        }),
+        Network::Signet => Some(PowParams {
+            pow_limit: 0x1e0377ae,
+            allow_min_difficulty_blocks: false,
+            no_retargeting: false,
+        }),
+        Network::Regtest => Some(PowParams {
+            pow_limit: 0x207fffff,
+            allow_min_difficulty_blocks: true,
+            no_retargeting: true,
+        }),
+        _ => None,
+    }
+}
+
+// Expands compact bits to a big endian 256 bits target, None for negative or overflowing bits
+fn target_from_compact(bits: u32) -> Option<[u8; 32]> {
+    let size = (bits >> 24) as i64;
+    let mantissa = bits & 0x007fffff;
+    if bits & 0x00800000 != 0 && mantissa != 0 {
+        return None;
+    }
+
+    let mut target = [0u8; 32];
+    for (i, byte) in mantissa.to_be_bytes()[1..].iter().enumerate() {
+        // the byte is worth 256^position, the ones below 256^0 are shifted out
+        let position = size - 1 - i as i64;
+        if *byte == 0 || position < 0 {
+            continue;
+        }
+        if position >= 32 {
+            return None;
+        }
+        target[31 - position as usize] = *byte;
+    }
+    Some(target)
+}
+
+// Compresses a target to compact bits the way Bitcoin Core does, dropping the low bytes
+fn target_to_compact(target: &[u8; 32]) -> u32 {
+    let mut size = 32 - target.iter().take_while(|byte| **byte == 0).count() as u32;
+    let mut mantissa = 0u32;
+    for i in 1..=3 {
+        mantissa <<= 8;
+        if size >= i {
+            mantissa |= target[(32 + i - size) as usize - 1] as u32;
+        }
+    }
+    if mantissa & 0x00800000 != 0 {
+        mantissa >>= 8;
+        size += 1;
+    }
+    mantissa | size << 24
+}
+
+fn mul_target(target: &[u8; 32], factor: u32) -> Option<[u8; 32]> {
+    let mut result = [0u8; 32];
+    let mut carry = 0u64;
+    for (result, byte) in result.iter_mut().zip(target).rev() {
+        let value = *byte as u64 * factor as u64 + carry;
+        *result = value as u8;
+        carry = value >> 8;
+    }
+    (carry == 0).then_some(result)
+}
+
+fn div_target(target: &[u8; 32], divisor: u32) -> [u8; 32] {
+    let mut result = [0u8; 32];
+    let mut remainder = 0u64;
+    for (result, byte) in result.iter_mut().zip(target) {
+        let value = remainder << 8 | *byte as u64;
+        *result = (value / divisor as u64) as u8;
+        remainder = value % divisor as u64;
+    }
+    result
+}
+
+// The bits of the first block of a difficulty period, from the last block of the previous one
+fn next_period_bits(params: &PowParams, last_bits: u32, timespan: u32) -> Option<u32> {
+    if params.no_retargeting {
+        return Some(last_bits);
+    }
+
+    let timespan = timespan.clamp(POW_TARGET_TIMESPAN / 4, POW_TARGET_TIMESPAN * 4);
+    let pow_limit = target_from_compact(params.pow_limit)?;
+    let target = div_target(
+        &mul_target(&target_from_compact(last_bits)?, timespan)?,
+        POW_TARGET_TIMESPAN,
+    );
+    Some(target_to_compact(&target.min(pow_limit)))
+}
+
+// Checks that every header links to the previous one, starting from the trusted checkpoint, that
+// their bits are the ones the difficulty adjustment requires at their height and that all of them,
+// the block included, carry the proof of work required by their target. Without the checkpoint a
+// prover could forge the whole chain at the minimum difficulty.
+pub fn verify_header_chain(
+    checkpoint: &HeaderCheckpoint,
+    header_chain: &[Header],
+    block_header: &Header,
+) -> Result<(), ValidationError> {
+    let params = pow_params(checkpoint.network).ok_or(ValidationError::InvalidHeaderChain)?;
+    if checkpoint.height % DIFFICULTY_ADJUSTMENT_INTERVAL != 0 {
+        return Err(ValidationError::InvalidHeaderChain);
+    }
+    let pow_limit =
+        target_from_compact(params.pow_limit).ok_or(ValidationError::InvalidHeaderChain)?;
+
+    let mut prev_header = &checkpoint.header;
+    let mut height = checkpoint.height;
+    let mut period_start_time = checkpoint.header.time;
+    let mut period_bits = checkpoint.header.bits.to_consensus();
+    for header in header_chain.iter().chain(core::iter::once(block_header)) {
+        height += 1;
+        if header.prev_blockhash != prev_header.block_hash() {
+            return Err(ValidationError::InvalidHeaderChain);
+        }
+
+        let bits = header.bits.to_consensus();
+        match target_from_compact(bits) {
+            Some(target) if target != [0; 32] && target <= pow_limit => {}
+            _ => return Err(ValidationError::InvalidHeaderChain),
+        }
+
+        if height % DIFFICULTY_ADJUSTMENT_INTERVAL == 0 {
+            let timespan = prev_header.time.saturating_sub(period_start_time);
+            if Some(bits) != next_period_bits(&params, prev_header.bits.to_consensus(), timespan) {
+                return Err(ValidationError::InvalidHeaderChain);
+            }
+            period_start_time = header.time;
+            period_bits = bits;
+        } else {
+            // testnet allows a block at the minimum difficulty 20 minutes after the previous one
+            let min_difficulty = params.allow_min_difficulty_blocks
+                && bits == params.pow_limit
+                && header.time > prev_header.time.saturating_add(POW_TARGET_SPACING * 2);
+            if bits != period_bits && !min_difficulty {
+                return Err(ValidationError::InvalidHeaderChain);
+            }
+        }
+
+        verify_header_pow(header).map_err(|_| ValidationError::InvalidHeaderChain)?;
+
+        prev_header = header;
+    }
+
+    Ok(())
+}
+
 impl DaVerifier for BitcoinVerifier {
     type Spec = BitcoinSpec;
 
@@ -66,6 +397,12 @@ impl DaVerifier for BitcoinVerifier {
     fn new(params: <Self::Spec as DaSpec>::ChainParams) -> Self {
         Self {
             rollup_name: params.rollup_name,
+            authorized_names: Vec::new(),
+            authorized_sequencers: Vec::new(),
+            authenticator: Arc::new(EcdsaAuthenticator),
+            trusted_relay_blob_hashes: HashSet::new(),
+            header_checkpoint: None,
+            parse_mode: ParseMode::default(),
         }
     }
 
@@ -90,64 +427,202 @@ impl DaVerifier for BitcoinVerifier {
                 .to_byte_array(),
         };
 
+        // the block hash does not commit to the height of the header. When some names are only
+        // accepted at some heights, the prover puts the coinbase first in the completeness proof
+        // and the height is read from it (BIP34), the inclusion proof binds it to the block.
+        let mut completeness_txs = completeness_proof.as_slice();
+        if self
+            .authorized_names
+            .iter()
+            .any(AuthorizedRollupName::has_height_bounds)
+        {
+            let coinbase = completeness_txs
+                .first()
+                .filter(|tx| {
+                    inclusion_proof.txs.first() == Some(&tx.txid().to_raw_hash().to_byte_array())
+                })
+                .expect("coinbase proving the block height is missing from the completeness proof");
+
+            let height = Block {
+                header: block_header.header,
+                txdata: vec![coinbase.clone()],
+            }
+            .bip34_block_height()
+            .expect("coinbase does not commit to the block height");
+            assert_eq!(
+                height, block_header.height,
+                "block height does not match the coinbase"
+            );
+
+            // it is only part of the completeness proof proper when its txid starts with 00 bytes
+            if coinbase.txid().to_raw_hash().to_byte_array()[0..2] != [0, 0] {
+                completeness_txs = &completeness_txs[1..];
+            }
+        }
+
+        // names the blobs of the block may be inscribed under
+        let rollup_names = rollup_names_at(
+            &self.rollup_name,
+            &self.authorized_names,
+            block_header.height,
+        );
+
         // completeness proof
 
         // create hash set of txs
         let mut txs_to_check = txs
             .iter()
-            .map(|blob| blob.hash)
+            .map(|blob| (blob.sender.0.clone(), blob.hash))
             .collect::<HashSet<_>>();
 
         let mut prev_index_in_inclusion = 0;
 
-        // Check every 00 bytes tx that parsed correctly is in txs
-        let mut completeness_tx_hashes = completeness_proof.iter().enumerate().map(|(index_completeness, tx)| {
-            let tx_hash = tx.txid().to_raw_hash().to_byte_array();
-
-            // make sure it is 00 bytes
-            assert_eq!(tx_hash[0..2], [0, 0], "non-relevant tx found in completeness proof");
-
-            // make sure completeness txs are ordered same in inclusion proof
-            // this logic always start seaching from the last found index
-            // ordering should be preserved naturally
-            let mut is_found_in_block = false;
-            for i in prev_index_in_inclusion..inclusion_proof.txs.len() {
-                if inclusion_proof.txs[i] == tx_hash {
-                    is_found_in_block = true;
-                    prev_index_in_inclusion = i + 1;
-                    break;
-                }
-            }
+        // blobs inscribed more than once for redundancy are extracted once, at the first copy of
+        // their sender
+        let mut seen_blobs = HashSet::new();
+        let mut index_relevant = 0;
 
-            // assert tx is included in inclusion proof, thus in block
-            assert!(is_found_in_block, "tx in completeness proof is not found in DA block or order was not preserved");
+        // chunks of a blob are authenticated and reassembled, as extraction does
+        let mut chunks = ChunkAssembler::new(self.authenticator.as_ref());
 
-            // it must be parsed correctly
-            let parsed_tx = parse_transaction(tx, &self.rollup_name);
-            if parsed_tx.is_ok() {
-                let blob = parsed_tx.unwrap().body;
-                let blob_hash: [u8; 32] = bitcoin::hashes::sha256d::Hash::hash(&blob).to_byte_array();
-                // it must be in txs
-                assert!(txs_to_check.remove(&blob_hash), "blob in completeness proof is not found in txs");
-
-                // asserting txs order is preserved
-                assert_eq!(txs[index_completeness].hash, blob_hash, "order of transactions is not preserved");
-
-                // decompress the blob
-                let decompressed_blob = decompress_blob(&blob);
-
-                // read the supplied blob from txs
-                let mut blob_content = txs[index_completeness].blob.clone();
-                blob_content.advance(blob_content.total_len());
-                let blob_content = blob_content.accumulator();
+        // Check every 00 bytes tx that parsed correctly is in txs
+        let mut completeness_tx_hashes = completeness_txs
+            .iter()
+            .map(|tx| {
+                let tx_hash = tx.txid().to_raw_hash().to_byte_array();
+
+                // make sure it is 00 bytes
+                assert_eq!(
+                    tx_hash[0..2],
+                    [0, 0],
+                    "non-relevant tx found in completeness proof"
+                );
+
+                // make sure completeness txs are ordered same in inclusion proof
+                // this logic always start seaching from the last found index
+                // ordering should be preserved naturally
+                let mut is_found_in_block = false;
+                for i in prev_index_in_inclusion..inclusion_proof.txs.len() {
+                    if inclusion_proof.txs[i] == tx_hash {
+                        is_found_in_block = true;
+                        prev_index_in_inclusion = i + 1;
+                        break;
+                    }
+                }
 
-                // assert tx content is not modified
-                assert_eq!(blob_content, decompressed_blob, "blob content was modified");
-            }
+                // assert tx is included in inclusion proof, thus in block
+                assert!(
+                    is_found_in_block,
+                    "tx in completeness proof is not found in DA block or order was not preserved"
+                );
+
+                // a tx may pack several inscriptions, each checked like the blob of a single inscription tx
+                let inscriptions = rollup_names
+                    .iter()
+                    .find_map(|rollup_name| {
+                        parse_inscriptions(tx, rollup_name, self.parse_mode).ok()
+                    })
+                    .unwrap_or_default();
+                for inscription in inscriptions
+                    .into_iter()
+                    .filter_map(|inscription| chunks.push(inscription))
+                {
+                    // blobs vouched for by a trusted relay skip both checks below, as in extraction
+                    let trusted = self
+                        .trusted_relay_blob_hashes
+                        .contains(&blob_hash(&inscription.body));
+                    // it must be parsed correctly and signed by its sequencer, as extraction drops the others
+                    if !trusted
+                        && !self.authenticator.authenticate(
+                            &inscription.signed_message(),
+                            &inscription.signature,
+                            &inscription.public_key,
+                        )
+                    {
+                        continue;
+                    }
+                    // and signed with an authorized sequencer key, the embedded key alone proves nothing
+                    if !trusted
+                        && !is_authorized_sequencer(
+                            &self.authorized_sequencers,
+                            &inscription.public_key,
+                        )
+                    {
+                        continue;
+                    }
+                    // extraction skips the bodies that do not decompress
+                    let decompressed_blob =
+                        match decompress_body(&inscription.body, inscription.compression) {
+                            Ok(decompressed_blob) => decompressed_blob,
+                            Err(_) => continue,
+                        };
+                    if !seen_blobs
+                        .insert((inscription.public_key.clone(), blob_hash(&inscription.body)))
+                    {
+                        continue;
+                    }
+
+                    let blob = inscription.body;
+                    let blob_hash = blob_hash(&blob);
+
+                    // index of the blob in txs
+                    let index_completeness = index_relevant;
+                    index_relevant += 1;
+
+                    // it must be in txs
+                    assert!(
+                        txs_to_check.remove(&(inscription.public_key.clone(), blob_hash)),
+                        "blob in completeness proof is not found in txs"
+                    );
+
+                    // asserting txs order is preserved
+                    assert_eq!(
+                        txs[index_completeness].hash, blob_hash,
+                        "order of transactions is not preserved"
+                    );
+
+                    // assert the sender of the blob is not modified, copies of other senders are kept apart
+                    assert_eq!(
+                        txs[index_completeness].sender.0, inscription.public_key,
+                        "sender was modified"
+                    );
+
+                    // read the supplied blob from txs
+                    let mut blob_content = txs[index_completeness].blob.clone();
+                    blob_content.advance(blob_content.total_len());
+                    let blob_content = blob_content.accumulator();
+
+                    // assert tx content is not modified
+                    assert_eq!(blob_content, decompressed_blob, "blob content was modified");
+
+                    // assert the namespace of the blob is not modified
+                    assert_eq!(
+                        txs[index_completeness].namespace_id, inscription.namespace_id,
+                        "namespace id was modified"
+                    );
+
+                    // assert the compression header of the blob is not modified, the signature covers it
+                    assert_eq!(
+                        txs[index_completeness].compression, inscription.compression,
+                        "compression was modified"
+                    );
+
+                    // assert the label of the blob is not modified
+                    assert_eq!(
+                        txs[index_completeness].label, inscription.label,
+                        "label was modified"
+                    );
+
+                    // assert the metadata of the blob is not modified
+                    assert_eq!(
+                        txs[index_completeness].metadata, inscription.metadata,
+                        "metadata was modified"
+                    );
+                }
 
-            tx_hash
-        })
-        .collect::<HashSet<_>>();
+                tx_hash
+            })
+            .collect::<HashSet<_>>();
 
         // assert no extra txs than the ones in the completeness proof are left
         assert!(txs_to_check.is_empty(), "completeness proof is incorrect");
@@ -170,6 +645,8 @@ impl DaVerifier for BitcoinVerifier {
             .to_byte_array();
 
         // Inclusion proof is all the txs in the block.
+        // The merkle root in the header commits to txids, witness-stripped hashes, and not to wtxids.
+        // Completeness proof txs were looked up by txid above, and any wtxid would make the root mismatch below.
         let tx_hashes = inclusion_proof
             .txs
             .iter()
@@ -191,14 +668,53 @@ impl DaVerifier for BitcoinVerifier {
 #[cfg(test)]
 mod tests {
 
+    use bitcoin::{
+        absolute::LockTime,
+        block::{Header, Version},
+        blockdata::{
+            opcodes::{
+                all::{OP_CHECKSIG, OP_ENDIF, OP_IF},
+                OP_FALSE,
+            },
+            script,
+        },
+        consensus::Decodable,
+        hash_types::TxMerkleNode,
+        hashes::Hash,
+        script::{PushBytes, PushBytesBuf},
+        string::FromHexStr,
+        BlockHash, CompactTarget, Network, Transaction, Witness,
+    };
     use core::str::FromStr;
     use hex;
-    use bitcoin::{block::{Header, Version}, BlockHash, hash_types::TxMerkleNode, CompactTarget, string::FromHexStr, Transaction, consensus::Decodable, hashes::Hash};
-    use sov_rollup_interface::{da::{DaVerifier, DaSpec}, crypto::NoOpHasher};
-
-    use crate::{spec::{header::HeaderWrapper, blob::BlobWithSender, proof::InclusionMultiProof, transaction::ExtendedTransaction}, helpers::{parsers::{parse_transaction, recover_sender_and_hash_from_tx}, builders::decompress_blob}};
-
-    use super::BitcoinVerifier;
+    use sov_rollup_interface::{
+        crypto::NoOpHasher,
+        da::{DaSpec, DaVerifier},
+    };
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    use crate::{
+        authenticator::EcdsaAuthenticator,
+        helpers::{
+            builders::{decompress_blob, sign_blob_with_private_key},
+            parsers::{parse_transaction, recover_sender_and_hash_from_tx, ParseMode},
+            test_utils::SEQUENCER_DA_PRIVATE_KEY,
+            Compression,
+        },
+        spec::{
+            blob::BlobWithSender,
+            header::HeaderWrapper,
+            proof::{completeness_proof_to_bytes, InclusionMultiProof},
+            transaction::ExtendedTransaction,
+            AuthorizedRollupName,
+        },
+    };
+
+    use super::{
+        next_period_bits, pow_params, target_from_compact, target_to_compact, verify_header_chain,
+        verify_header_pow, BitcoinVerifier, HeaderCheckpoint, ValidationError,
+    };
 
     fn get_mock_txs() -> Vec<Transaction> {
         // relevant txs are on 6, 8, 10, 12 indices
@@ -223,7 +739,7 @@ mod tests {
         let blob = parsed_inscription.body;
 
         // Decompress the blob
-        let decompressed_blob = decompress_blob(&blob);
+        let decompressed_blob = decompress_blob(&blob).unwrap();
 
         BlobWithSender::new(
             decompressed_blob,
@@ -248,7 +764,8 @@ mod tests {
                 nonce: 0,
             },
             tx_count: 13,
-            height: 2,
+            // the height the coinbase commits to
+            height: 130,
         };
 
         let block_txs = get_mock_txs();
@@ -275,10 +792,46 @@ mod tests {
         (header, inclusion_proof, completeness_proof, txs)
     }
 
+    // The same reveal tx with its blob inscribed in a minimal envelope, without the random field.
+    // Only the witness changes, so the txid and the proofs stay the same.
+    fn without_random(tx: &Transaction) -> Transaction {
+        let body = parse_transaction(tx, "sov-btc").unwrap().body;
+        let (signature, public_key) =
+            sign_blob_with_private_key(&body, SEQUENCER_DA_PRIVATE_KEY).unwrap();
+
+        let mut builder = script::Builder::new()
+            .push_slice([2; 32])
+            .push_opcode(OP_CHECKSIG)
+            .push_opcode(OP_FALSE)
+            .push_opcode(OP_IF)
+            .push_slice([1])
+            .push_slice(b"sov-btc")
+            .push_slice([2])
+            .push_slice(<&PushBytes>::try_from(signature.as_slice()).unwrap())
+            .push_slice([3])
+            .push_slice(<&PushBytes>::try_from(public_key.as_slice()).unwrap())
+            .push_slice(PushBytesBuf::new());
+        for chunk in body.chunks(520) {
+            builder = builder.push_slice(<&PushBytes>::try_from(chunk).unwrap());
+        }
+        let reveal_script = builder.push_opcode(OP_ENDIF).into_script();
+
+        let mut tx = tx.clone();
+        tx.input[0].witness =
+            Witness::from_slice(&[vec![0; 64], reveal_script.to_bytes(), vec![0xc0; 33]]);
+        tx
+    }
+
     #[test]
     fn correct () {
         let verifier = BitcoinVerifier {
-            rollup_name: "sov-btc".to_string()
+            rollup_name: "sov-btc".to_string(),
+            authorized_names: vec![],
+            authorized_sequencers: vec![],
+            authenticator: Arc::new(EcdsaAuthenticator),
+            trusted_relay_blob_hashes: HashSet::new(),
+            header_checkpoint: None,
+            parse_mode: ParseMode::Strict,
         };
 
         let (
@@ -291,11 +844,150 @@ mod tests {
         assert!(verifier.verify_relevant_tx_list(&block_header, txs.as_slice(), inclusion_proof, completeness_proof).is_ok());
     }
 
+    #[test]
+    fn lenient_envelopes() {
+        let verifier = BitcoinVerifier {
+            rollup_name: "sov-btc".to_string(),
+            authorized_names: vec![],
+            authorized_sequencers: vec![],
+            authenticator: Arc::new(EcdsaAuthenticator),
+            trusted_relay_blob_hashes: HashSet::new(),
+            header_checkpoint: None,
+            parse_mode: ParseMode::Strict,
+        }
+        .with_parse_mode(ParseMode::Lenient);
+
+        let (block_header, inclusion_proof, mut completeness_proof, txs) = get_mock_data();
+
+        // extraction in lenient mode still returns the blob, so does the verifier in the same mode
+        completeness_proof[0] = without_random(&completeness_proof[0]);
+
+        verifier
+            .verify_relevant_tx_list(
+                &block_header,
+                txs.as_slice(),
+                inclusion_proof,
+                completeness_proof,
+            )
+            .unwrap();
+    }
+
+    #[test]
+    #[should_panic = "completeness proof is incorrect"]
+    fn lenient_envelopes_with_strict_verifier() {
+        let verifier = BitcoinVerifier {
+            rollup_name: "sov-btc".to_string(),
+            authorized_names: vec![],
+            authorized_sequencers: vec![],
+            authenticator: Arc::new(EcdsaAuthenticator),
+            trusted_relay_blob_hashes: HashSet::new(),
+            header_checkpoint: None,
+            parse_mode: ParseMode::Strict,
+        };
+
+        let (block_header, inclusion_proof, mut completeness_proof, txs) = get_mock_data();
+
+        // a strict verifier does not see the blob a lenient extraction returned
+        completeness_proof[0] = without_random(&completeness_proof[0]);
+
+        verifier
+            .verify_relevant_tx_list(
+                &block_header,
+                txs.as_slice(),
+                inclusion_proof,
+                completeness_proof,
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn correct_with_encoded_proof() {
+        let verifier = BitcoinVerifier {
+            rollup_name: "sov-btc".to_string(),
+            authorized_names: vec![],
+            authorized_sequencers: vec![],
+            authenticator: Arc::new(EcdsaAuthenticator),
+            trusted_relay_blob_hashes: HashSet::new(),
+            header_checkpoint: None,
+            parse_mode: ParseMode::Strict,
+        };
+
+        let (block_header, inclusion_proof, completeness_proof, txs) = get_mock_data();
+
+        for compression in [Compression::None, Compression::Brotli] {
+            let encoded_proof = inclusion_proof.to_bytes(compression);
+            assert!(verifier
+                .verify_relevant_tx_list_with_encoded_proof(
+                    &block_header,
+                    txs.as_slice(),
+                    &encoded_proof,
+                    completeness_proof.clone()
+                )
+                .is_ok());
+        }
+
+        assert_eq!(
+            verifier.verify_relevant_tx_list_with_encoded_proof(
+                &block_header,
+                txs.as_slice(),
+                &[2],
+                completeness_proof
+            ),
+            Err(ValidationError::InvalidProof)
+        );
+    }
+
+    #[test]
+    fn correct_with_encoded_proofs() {
+        let verifier = BitcoinVerifier {
+            rollup_name: "sov-btc".to_string(),
+            authorized_names: vec![],
+            authorized_sequencers: vec![],
+            authenticator: Arc::new(EcdsaAuthenticator),
+            trusted_relay_blob_hashes: HashSet::new(),
+            header_checkpoint: None,
+            parse_mode: ParseMode::Strict,
+        };
+
+        let (block_header, inclusion_proof, completeness_proof, txs) = get_mock_data();
+
+        for compression in [Compression::None, Compression::Brotli] {
+            let encoded_inclusion_proof = inclusion_proof.to_bytes(compression);
+            let encoded_completeness_proof =
+                completeness_proof_to_bytes(&completeness_proof, compression);
+            assert!(verifier
+                .verify_relevant_tx_list_with_encoded_proofs(
+                    &block_header,
+                    txs.as_slice(),
+                    &encoded_inclusion_proof,
+                    &encoded_completeness_proof
+                )
+                .is_ok());
+        }
+
+        let encoded_inclusion_proof = inclusion_proof.to_bytes(Compression::None);
+        assert_eq!(
+            verifier.verify_relevant_tx_list_with_encoded_proofs(
+                &block_header,
+                txs.as_slice(),
+                &encoded_inclusion_proof,
+                &[2]
+            ),
+            Err(ValidationError::InvalidProof)
+        );
+    }
+
     #[test]
     #[should_panic(expected = "inclusion proof is incorrect")]
     fn extra_tx_in_inclusion () {
         let verifier = BitcoinVerifier {
-            rollup_name: "sov-btc".to_string()
+            rollup_name: "sov-btc".to_string(),
+            authorized_names: vec![],
+            authorized_sequencers: vec![],
+            authenticator: Arc::new(EcdsaAuthenticator),
+            trusted_relay_blob_hashes: HashSet::new(),
+            header_checkpoint: None,
+            parse_mode: ParseMode::Strict,
         };
 
         let (
@@ -314,7 +1006,13 @@ mod tests {
     #[should_panic(expected = "tx in completeness proof is not found in DA block or order was not preserved")]
     fn missing_tx_in_inclusion () {
         let verifier = BitcoinVerifier {
-            rollup_name: "sov-btc".to_string()
+            rollup_name: "sov-btc".to_string(),
+            authorized_names: vec![],
+            authorized_sequencers: vec![],
+            authenticator: Arc::new(EcdsaAuthenticator),
+            trusted_relay_blob_hashes: HashSet::new(),
+            header_checkpoint: None,
+            parse_mode: ParseMode::Strict,
         };
 
         let (
@@ -329,11 +1027,52 @@ mod tests {
         verifier.verify_relevant_tx_list(&block_header, txs.as_slice(), inclusion_proof, completeness_proof).unwrap();
     }
 
+    #[test]
+    #[should_panic = "tx in completeness proof is not found in DA block or order was not preserved"]
+    fn extraneous_tx_in_completeness() {
+        let verifier = BitcoinVerifier {
+            rollup_name: "sov-btc".to_string(),
+            authorized_names: vec![],
+            authorized_sequencers: vec![],
+            authenticator: Arc::new(EcdsaAuthenticator),
+            trusted_relay_blob_hashes: HashSet::new(),
+            header_checkpoint: None,
+            parse_mode: ParseMode::Strict,
+        };
+
+        let (block_header, inclusion_proof, mut completeness_proof, txs) = get_mock_data();
+
+        // a relevant looking tx (txid starting with 00 bytes) that is not in the block
+        let mut extraneous_tx = completeness_proof[0].clone();
+        for lock_time in 1.. {
+            extraneous_tx.lock_time = LockTime::from_consensus(lock_time);
+            if extraneous_tx.txid().to_raw_hash().to_byte_array()[0..2] == [0, 0] {
+                break;
+            }
+        }
+        completeness_proof.push(extraneous_tx);
+
+        verifier
+            .verify_relevant_tx_list(
+                &block_header,
+                txs.as_slice(),
+                inclusion_proof,
+                completeness_proof,
+            )
+            .unwrap();
+    }
+
     #[test]
     #[should_panic = "tx in completeness proof is not found in DA block or order was not preserved"]
     fn empty_inclusion () {
         let verifier = BitcoinVerifier {
-            rollup_name: "sov-btc".to_string()
+            rollup_name: "sov-btc".to_string(),
+            authorized_names: vec![],
+            authorized_sequencers: vec![],
+            authenticator: Arc::new(EcdsaAuthenticator),
+            trusted_relay_blob_hashes: HashSet::new(),
+            header_checkpoint: None,
+            parse_mode: ParseMode::Strict,
         };
 
         let (
@@ -352,7 +1091,13 @@ mod tests {
     #[should_panic = "inclusion proof is incorrect"]
     fn break_order_of_inclusion () {
         let verifier = BitcoinVerifier {
-            rollup_name: "sov-btc".to_string()
+            rollup_name: "sov-btc".to_string(),
+            authorized_names: vec![],
+            authorized_sequencers: vec![],
+            authenticator: Arc::new(EcdsaAuthenticator),
+            trusted_relay_blob_hashes: HashSet::new(),
+            header_checkpoint: None,
+            parse_mode: ParseMode::Strict,
         };
 
         let (
@@ -371,7 +1116,13 @@ mod tests {
     #[should_panic(expected = "completeness proof is incorrect")]
     fn missing_tx_in_completeness_proof () {
         let verifier = BitcoinVerifier {
-            rollup_name: "sov-btc".to_string()
+            rollup_name: "sov-btc".to_string(),
+            authorized_names: vec![],
+            authorized_sequencers: vec![],
+            authenticator: Arc::new(EcdsaAuthenticator),
+            trusted_relay_blob_hashes: HashSet::new(),
+            header_checkpoint: None,
+            parse_mode: ParseMode::Strict,
         };
 
         let (
@@ -390,7 +1141,13 @@ mod tests {
     #[should_panic(expected = "completeness proof is incorrect")]
     fn empty_completeness_proof () {
         let verifier = BitcoinVerifier {
-            rollup_name: "sov-btc".to_string()
+            rollup_name: "sov-btc".to_string(),
+            authorized_names: vec![],
+            authorized_sequencers: vec![],
+            authenticator: Arc::new(EcdsaAuthenticator),
+            trusted_relay_blob_hashes: HashSet::new(),
+            header_checkpoint: None,
+            parse_mode: ParseMode::Strict,
         };
 
         let (
@@ -409,7 +1166,13 @@ mod tests {
     #[should_panic(expected = "non-relevant tx found in completeness proof")]
     fn non_relevant_tx_in_completeness_proof () {
         let verifier = BitcoinVerifier {
-            rollup_name: "sov-btc".to_string()
+            rollup_name: "sov-btc".to_string(),
+            authorized_names: vec![],
+            authorized_sequencers: vec![],
+            authenticator: Arc::new(EcdsaAuthenticator),
+            trusted_relay_blob_hashes: HashSet::new(),
+            header_checkpoint: None,
+            parse_mode: ParseMode::Strict,
         };
 
         let (
@@ -428,7 +1191,13 @@ mod tests {
     #[should_panic(expected = "order of transactions is not preserved")]
     fn break_completeness_proof_order () {
         let verifier = BitcoinVerifier {
-            rollup_name: "sov-btc".to_string()
+            rollup_name: "sov-btc".to_string(),
+            authorized_names: vec![],
+            authorized_sequencers: vec![],
+            authenticator: Arc::new(EcdsaAuthenticator),
+            trusted_relay_blob_hashes: HashSet::new(),
+            header_checkpoint: None,
+            parse_mode: ParseMode::Strict,
         };
 
         let (
@@ -447,7 +1216,13 @@ mod tests {
     #[should_panic(expected = "order of transactions is not preserved")]
     fn break_rel_tx_order () {
         let verifier = BitcoinVerifier {
-            rollup_name: "sov-btc".to_string()
+            rollup_name: "sov-btc".to_string(),
+            authorized_names: vec![],
+            authorized_sequencers: vec![],
+            authenticator: Arc::new(EcdsaAuthenticator),
+            trusted_relay_blob_hashes: HashSet::new(),
+            header_checkpoint: None,
+            parse_mode: ParseMode::Strict,
         };
 
         let (
@@ -466,7 +1241,13 @@ mod tests {
     #[should_panic = "tx in completeness proof is not found in DA block or order was not preserved"]
     fn break_rel_tx_and_completeness_proof_order () {
         let verifier = BitcoinVerifier {
-            rollup_name: "sov-btc".to_string()
+            rollup_name: "sov-btc".to_string(),
+            authorized_names: vec![],
+            authorized_sequencers: vec![],
+            authenticator: Arc::new(EcdsaAuthenticator),
+            trusted_relay_blob_hashes: HashSet::new(),
+            header_checkpoint: None,
+            parse_mode: ParseMode::Strict,
         };
 
         let (
@@ -487,7 +1268,13 @@ mod tests {
     #[should_panic(expected = "blob content was modified")]
     fn tamper_rel_tx_content () {
         let verifier = BitcoinVerifier {
-            rollup_name: "sov-btc".to_string()
+            rollup_name: "sov-btc".to_string(),
+            authorized_names: vec![],
+            authorized_sequencers: vec![],
+            authenticator: Arc::new(EcdsaAuthenticator),
+            trusted_relay_blob_hashes: HashSet::new(),
+            header_checkpoint: None,
+            parse_mode: ParseMode::Strict,
         };
 
         let (
@@ -507,4 +1294,496 @@ mod tests {
 
         verifier.verify_relevant_tx_list(&block_header, txs.as_slice(), inclusion_proof, completeness_proof).unwrap();
     }
-}
\ No newline at end of file
+
+    #[test]
+    #[should_panic(expected = "metadata was modified")]
+    fn tamper_rel_tx_metadata() {
+        let verifier = BitcoinVerifier {
+            rollup_name: "sov-btc".to_string(),
+            authorized_names: vec![],
+            authorized_sequencers: vec![],
+            authenticator: Arc::new(EcdsaAuthenticator),
+            trusted_relay_blob_hashes: HashSet::new(),
+            header_checkpoint: None,
+            parse_mode: ParseMode::Strict,
+        };
+
+        let (block_header, inclusion_proof, completeness_proof, mut txs) = get_mock_data();
+
+        // metadata is not signed, it must still match the envelope
+        txs[1].metadata = vec![("version".to_string(), b"2".to_vec())];
+
+        verifier
+            .verify_relevant_tx_list(
+                &block_header,
+                txs.as_slice(),
+                inclusion_proof,
+                completeness_proof,
+            )
+            .unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "inclusion proof is incorrect")]
+    fn wtxids_in_inclusion() {
+        let verifier = BitcoinVerifier {
+            rollup_name: "sov-btc".to_string(),
+            authorized_names: vec![],
+            authorized_sequencers: vec![],
+            authenticator: Arc::new(EcdsaAuthenticator),
+            trusted_relay_blob_hashes: HashSet::new(),
+            header_checkpoint: None,
+            parse_mode: ParseMode::Strict,
+        };
+
+        let (block_header, mut inclusion_proof, completeness_proof, txs) = get_mock_data();
+
+        // use wtxids for every tx that is not in the completeness proof
+        let block_txs = get_mock_txs();
+        for (index, tx) in block_txs.iter().enumerate() {
+            if tx.txid().to_raw_hash() != tx.wtxid().to_raw_hash()
+                && ![6, 8, 10, 12].contains(&index)
+            {
+                inclusion_proof.txs[index] = tx.wtxid().to_raw_hash().to_byte_array();
+            }
+        }
+        assert_ne!(
+            inclusion_proof.txs,
+            block_txs
+                .iter()
+                .map(|t| t.txid().to_raw_hash().to_byte_array())
+                .collect::<Vec<_>>()
+        );
+
+        verifier
+            .verify_relevant_tx_list(
+                &block_header,
+                txs.as_slice(),
+                inclusion_proof,
+                completeness_proof,
+            )
+            .unwrap();
+    }
+
+    // Mines headers on top of each other, the last one being the parent of the block
+    #[test]
+    fn authorized_rollup_name() {
+        // the blobs of the block are inscribed under the name the rollup is migrating from
+        let verifier = BitcoinVerifier {
+            rollup_name: "sov-btc-v2".to_string(),
+            authorized_names: vec![],
+            authorized_sequencers: vec![],
+            authenticator: Arc::new(EcdsaAuthenticator),
+            trusted_relay_blob_hashes: HashSet::new(),
+            header_checkpoint: None,
+            parse_mode: ParseMode::Strict,
+        }
+        .with_authorized_names(vec![AuthorizedRollupName {
+            name: "sov-btc".to_string(),
+            from_height: Some(100),
+            until_height: Some(200),
+        }]);
+
+        let (block_header, inclusion_proof, mut completeness_proof, txs) = get_mock_data();
+
+        // the coinbase proves the height the name is accepted at
+        completeness_proof.insert(0, get_mock_txs()[0].clone());
+
+        assert!(verifier
+            .verify_relevant_tx_list(
+                &block_header,
+                txs.as_slice(),
+                inclusion_proof,
+                completeness_proof
+            )
+            .is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "completeness proof is incorrect")]
+    fn authorized_rollup_name_out_of_bounds() {
+        // the migration window ended before the block
+        let verifier = BitcoinVerifier {
+            rollup_name: "sov-btc-v2".to_string(),
+            authorized_names: vec![AuthorizedRollupName {
+                name: "sov-btc".to_string(),
+                from_height: None,
+                until_height: Some(129),
+            }],
+            authorized_sequencers: vec![],
+            authenticator: Arc::new(EcdsaAuthenticator),
+            trusted_relay_blob_hashes: HashSet::new(),
+            header_checkpoint: None,
+            parse_mode: ParseMode::Strict,
+        };
+
+        let (block_header, inclusion_proof, mut completeness_proof, txs) = get_mock_data();
+        completeness_proof.insert(0, get_mock_txs()[0].clone());
+
+        verifier
+            .verify_relevant_tx_list(
+                &block_header,
+                txs.as_slice(),
+                inclusion_proof,
+                completeness_proof,
+            )
+            .unwrap();
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "coinbase proving the block height is missing from the completeness proof"
+    )]
+    fn authorized_rollup_name_without_height_proof() {
+        let verifier = BitcoinVerifier {
+            rollup_name: "sov-btc-v2".to_string(),
+            authorized_names: vec![AuthorizedRollupName {
+                name: "sov-btc".to_string(),
+                from_height: Some(100),
+                until_height: Some(200),
+            }],
+            authorized_sequencers: vec![],
+            authenticator: Arc::new(EcdsaAuthenticator),
+            trusted_relay_blob_hashes: HashSet::new(),
+            header_checkpoint: None,
+            parse_mode: ParseMode::Strict,
+        };
+
+        let (block_header, inclusion_proof, completeness_proof, txs) = get_mock_data();
+
+        verifier
+            .verify_relevant_tx_list(
+                &block_header,
+                txs.as_slice(),
+                inclusion_proof,
+                completeness_proof,
+            )
+            .unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "block height does not match the coinbase")]
+    fn authorized_rollup_name_at_forged_height() {
+        // the window ended, the prover claims the block is still in it
+        let verifier = BitcoinVerifier {
+            rollup_name: "sov-btc-v2".to_string(),
+            authorized_names: vec![AuthorizedRollupName {
+                name: "sov-btc".to_string(),
+                from_height: None,
+                until_height: Some(129),
+            }],
+            authorized_sequencers: vec![],
+            authenticator: Arc::new(EcdsaAuthenticator),
+            trusted_relay_blob_hashes: HashSet::new(),
+            header_checkpoint: None,
+            parse_mode: ParseMode::Strict,
+        };
+
+        let (mut block_header, inclusion_proof, mut completeness_proof, txs) = get_mock_data();
+        completeness_proof.insert(0, get_mock_txs()[0].clone());
+        block_header.height = 129;
+
+        verifier
+            .verify_relevant_tx_list(
+                &block_header,
+                txs.as_slice(),
+                inclusion_proof,
+                completeness_proof,
+            )
+            .unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "completeness proof is incorrect")]
+    fn unauthorized_sequencer() {
+        // the blobs are validly signed, but not by the authorized key
+        let verifier = BitcoinVerifier {
+            rollup_name: "sov-btc".to_string(),
+            authorized_names: vec![],
+            authorized_sequencers: vec![vec![2; 33]],
+            authenticator: Arc::new(EcdsaAuthenticator),
+            trusted_relay_blob_hashes: HashSet::new(),
+            header_checkpoint: None,
+            parse_mode: ParseMode::Strict,
+        };
+
+        let (block_header, inclusion_proof, completeness_proof, txs) = get_mock_data();
+
+        verifier
+            .verify_relevant_tx_list(
+                &block_header,
+                txs.as_slice(),
+                inclusion_proof,
+                completeness_proof,
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn trusted_relay_blobs_skip_sequencer_check() {
+        let (block_header, inclusion_proof, completeness_proof, txs) = get_mock_data();
+
+        // the blobs are not signed by the authorized key, but a trusted relay vouched for them
+        let verifier = BitcoinVerifier {
+            rollup_name: "sov-btc".to_string(),
+            authorized_names: vec![],
+            authorized_sequencers: vec![vec![2; 33]],
+            authenticator: Arc::new(EcdsaAuthenticator),
+            trusted_relay_blob_hashes: HashSet::new(),
+            header_checkpoint: None,
+            parse_mode: ParseMode::Strict,
+        }
+        .with_trusted_relay_blobs(txs.iter().map(|blob| blob.hash).collect());
+
+        assert!(verifier
+            .verify_relevant_tx_list(
+                &block_header,
+                txs.as_slice(),
+                inclusion_proof,
+                completeness_proof
+            )
+            .is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "completeness proof is incorrect")]
+    fn trusted_relay_blobs_only_cover_their_hashes() {
+        let (block_header, inclusion_proof, completeness_proof, txs) = get_mock_data();
+
+        // the last blob is not vouched for, it still needs an authorized signature
+        let verifier = BitcoinVerifier {
+            rollup_name: "sov-btc".to_string(),
+            authorized_names: vec![],
+            authorized_sequencers: vec![vec![2; 33]],
+            authenticator: Arc::new(EcdsaAuthenticator),
+            trusted_relay_blob_hashes: HashSet::new(),
+            header_checkpoint: None,
+            parse_mode: ParseMode::Strict,
+        }
+        .with_trusted_relay_blobs(txs[..txs.len() - 1].iter().map(|blob| blob.hash).collect());
+
+        verifier
+            .verify_relevant_tx_list(
+                &block_header,
+                txs.as_slice(),
+                inclusion_proof,
+                completeness_proof,
+            )
+            .unwrap();
+    }
+
+    fn get_header_chain(length: u32) -> Vec<Header> {
+        let mut header_chain: Vec<Header> = Vec::new();
+        for index in 0..length {
+            let mut header = Header {
+                version: Version::from_consensus(536870912),
+                prev_blockhash: header_chain
+                    .last()
+                    .map_or(BlockHash::all_zeros(), |prev| prev.block_hash()),
+                merkle_root: TxMerkleNode::all_zeros(),
+                time: 1694177000 + index,
+                bits: CompactTarget::from_hex_str_no_prefix("207fffff").unwrap(),
+                nonce: 0,
+            };
+            mine(&mut header);
+            header_chain.push(header);
+        }
+        header_chain
+    }
+
+    fn mine(header: &mut Header) {
+        while header.validate_pow(header.target()).is_err() {
+            header.nonce += 1;
+        }
+    }
+
+    #[test]
+    fn header_chain() {
+        let header_chain = get_header_chain(4);
+        let checkpoint = HeaderCheckpoint {
+            network: Network::Regtest,
+            height: 0,
+            header: header_chain[0],
+        };
+        let header_chain = header_chain[1..].to_vec();
+        let verifier = BitcoinVerifier {
+            rollup_name: "sov-btc".to_string(),
+            authorized_names: vec![],
+            authorized_sequencers: vec![],
+            authenticator: Arc::new(EcdsaAuthenticator),
+            trusted_relay_blob_hashes: HashSet::new(),
+            header_checkpoint: None,
+            parse_mode: ParseMode::Strict,
+        };
+
+        let (mut block_header, inclusion_proof, completeness_proof, txs) = get_mock_data();
+
+        // attach the block to the chain, its transactions are unchanged
+        block_header.header.prev_blockhash = header_chain.last().unwrap().block_hash();
+        mine(&mut block_header.header);
+
+        // there is nothing to check the chain against without a checkpoint
+        assert_eq!(
+            verifier.verify_relevant_tx_list_with_header_chain(
+                &block_header,
+                &header_chain,
+                &txs,
+                inclusion_proof.clone(),
+                completeness_proof.clone()
+            ),
+            Err(ValidationError::InvalidHeaderChain)
+        );
+
+        let verifier = verifier.with_header_checkpoint(checkpoint);
+        verifier
+            .verify_relevant_tx_list_with_header_chain(
+                &block_header,
+                &header_chain,
+                &txs,
+                inclusion_proof.clone(),
+                completeness_proof.clone(),
+            )
+            .unwrap();
+
+        // a broken prev hash link is rejected
+        let mut broken_header_chain = header_chain.clone();
+        broken_header_chain[1].prev_blockhash = BlockHash::all_zeros();
+        mine(&mut broken_header_chain[1]);
+        assert_eq!(
+            verifier.verify_relevant_tx_list_with_header_chain(
+                &block_header,
+                &broken_header_chain,
+                &txs,
+                inclusion_proof.clone(),
+                completeness_proof.clone()
+            ),
+            Err(ValidationError::InvalidHeaderChain)
+        );
+
+        // so is a chain that does not start at the checkpoint
+        assert_eq!(
+            verifier.verify_relevant_tx_list_with_header_chain(
+                &block_header,
+                &header_chain[1..],
+                &txs,
+                inclusion_proof.clone(),
+                completeness_proof.clone()
+            ),
+            Err(ValidationError::InvalidHeaderChain)
+        );
+
+        // and a header changing its target outside of a retarget, whatever its work
+        let mut weak_header = header_chain[0];
+        weak_header.bits = CompactTarget::from_consensus(0x1d00ffff);
+        assert_eq!(
+            verify_header_chain(&checkpoint, &[], &weak_header),
+            Err(ValidationError::InvalidHeaderChain)
+        );
+
+        // a chain forged at the regtest difficulty is rejected on mainnet, the target is above the pow limit
+        let mainnet_checkpoint = HeaderCheckpoint {
+            network: Network::Bitcoin,
+            ..checkpoint
+        };
+        assert_eq!(
+            verify_header_chain(&mainnet_checkpoint, &[], &header_chain[0]),
+            Err(ValidationError::InvalidHeaderChain)
+        );
+
+        // the checkpoint must open a difficulty period
+        let checkpoint = HeaderCheckpoint {
+            height: 1,
+            ..checkpoint
+        };
+        assert_eq!(
+            verify_header_chain(&checkpoint, &[], &header_chain[0]),
+            Err(ValidationError::InvalidHeaderChain)
+        );
+    }
+
+    #[test]
+    fn difficulty_adjustment() {
+        let mainnet = pow_params(Network::Bitcoin).unwrap();
+
+        assert_eq!(
+            target_to_compact(&target_from_compact(0x1b0404cb).unwrap()),
+            0x1b0404cb
+        );
+        assert_eq!(target_from_compact(0x01003456), Some([0; 32]));
+        assert_eq!(target_from_compact(0x04923456), None);
+        assert_eq!(target_from_compact(0xff123456), None);
+
+        // blocks mined on schedule keep the target
+        assert_eq!(
+            next_period_bits(&mainnet, 0x1b0404cb, 14 * 24 * 60 * 60),
+            Some(0x1b0404cb)
+        );
+        // twice as fast doubles the difficulty
+        assert_eq!(
+            next_period_bits(&mainnet, 0x1b0404cb, 7 * 24 * 60 * 60),
+            Some(0x1b020265)
+        );
+        // the adjustment is bounded to a factor 4 either way, and never goes below the pow limit
+        assert_eq!(next_period_bits(&mainnet, 0x1d00ffff, 0), Some(0x1c3fffc0));
+        assert_eq!(
+            next_period_bits(&mainnet, 0x1d00ffff, u32::MAX),
+            Some(0x1d00ffff)
+        );
+
+        // regtest does not retarget
+        assert_eq!(
+            next_period_bits(&pow_params(Network::Regtest).unwrap(), 0x207fffff, 0),
+            Some(0x207fffff)
+        );
+    }
+
+    #[test]
+    fn header_pow() {
+        let header = get_header_chain(1)[0];
+        assert_eq!(verify_header_pow(&header), Ok(()));
+
+        // the same header claiming the mainnet difficulty does not meet its target
+        let mut weak_header = header;
+        weak_header.bits = CompactTarget::from_consensus(0x1d00ffff);
+        assert_eq!(
+            verify_header_pow(&weak_header),
+            Err(ValidationError::InsufficientWork)
+        );
+    }
+
+    #[test]
+    fn relevant_tx_list_with_pow() {
+        let verifier = BitcoinVerifier {
+            rollup_name: "sov-btc".to_string(),
+            authorized_names: vec![],
+            authorized_sequencers: vec![],
+            authenticator: Arc::new(EcdsaAuthenticator),
+            trusted_relay_blob_hashes: HashSet::new(),
+            header_checkpoint: None,
+            parse_mode: ParseMode::Strict,
+        };
+
+        let (mut block_header, inclusion_proof, completeness_proof, txs) = get_mock_data();
+        mine(&mut block_header.header);
+        verifier
+            .verify_relevant_tx_list_with_pow(
+                &block_header,
+                &txs,
+                inclusion_proof.clone(),
+                completeness_proof.clone(),
+            )
+            .unwrap();
+
+        // a header without the work is rejected before the transactions are looked at
+        block_header.header.bits = CompactTarget::from_consensus(0x1d00ffff);
+        assert_eq!(
+            verifier.verify_relevant_tx_list_with_pow(
+                &block_header,
+                &txs,
+                inclusion_proof,
+                completeness_proof
+            ),
+            Err(ValidationError::InsufficientWork)
+        );
+    }
+}
@@ -0,0 +1,69 @@
+use core::time::Duration;
+
+use tracing::warn;
+use zeromq::{Socket, SocketRecv, SubSocket};
+
+// Topic of the notifications the node publishes with `-zmqpubhashblock` when its tip changes
+const HASHBLOCK_TOPIC: &str = "hashblock";
+const INITIAL_RECONNECT_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(60);
+
+// Subscription to the block notifications of a node. The socket is reconnected with exponential
+// backoff whenever it fails, so waiting for a notification only returns once one is received.
+pub struct BlockNotifications {
+    endpoint: String,
+    socket: Option<SubSocket>,
+}
+
+impl BlockNotifications {
+    pub fn new(endpoint: String) -> Self {
+        Self {
+            endpoint,
+            socket: None,
+        }
+    }
+
+    // Connects the socket unless it already is. Notifications sent once connected are queued,
+    // so a block announced between this and `next` is not missed.
+    pub async fn connect(&mut self) {
+        let mut backoff = INITIAL_RECONNECT_BACKOFF;
+        while self.socket.is_none() {
+            match self.try_connect().await {
+                Ok(socket) => self.socket = Some(socket),
+                Err(error) => {
+                    warn!(
+                        "Failed to subscribe to {}: {}, retrying in {:?}",
+                        self.endpoint, error, backoff
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_RECONNECT_BACKOFF);
+                }
+            }
+        }
+    }
+
+    // Waits for the next block notification, the hash it carries is not needed by the caller
+    pub async fn next(&mut self) {
+        loop {
+            self.connect().await;
+            let socket = self.socket.as_mut().expect("socket is connected");
+            match socket.recv().await {
+                Ok(_) => return,
+                Err(error) => {
+                    warn!(
+                        "Block notifications from {} failed: {}",
+                        self.endpoint, error
+                    );
+                    self.socket = None;
+                }
+            }
+        }
+    }
+
+    async fn try_connect(&self) -> Result<SubSocket, anyhow::Error> {
+        let mut socket = SubSocket::new();
+        socket.connect(&self.endpoint).await?;
+        socket.subscribe(HASHBLOCK_TOPIC).await?;
+        Ok(socket)
+    }
+}